@@ -1,6 +1,6 @@
 """Python API for RSN DB — Rust engine with optional official MemPalace integration."""
 
-from ._core import Database, Query, Record
+from ._core import Database, DatabaseLockedError, EncryptionKeyError, Query, Record
 from .ai_memory import MemoryTurn, SessionMemory
 from . import beginners
 from .easy import RsnDatabase, open_db
@@ -10,6 +10,8 @@ __version__ = "0.4.6"
 
 __all__ = [
     "Database",
+    "DatabaseLockedError",
+    "EncryptionKeyError",
     "Query",
     "Record",
     "RsnDatabase",
@@ -31,11 +31,8 @@ def quick_start(
 
 
 def insert_many(db: Database, table: str, rows: list[dict[str, Any]]) -> list[int]:
-    """Insert a list of dict rows; returns list of new row ids."""
-    ids: list[int] = []
-    for row in rows:
-        ids.append(db.insert(table, row))
-    return ids
+    """Insert a list of dict rows in one write; returns list of new row ids."""
+    return db.insert_many(table, rows)
 
 
 def records_to_dicts(records: list[Record]) -> list[dict[str, Any]]:
@@ -25,12 +25,30 @@ class RsnDatabase:
         palace_path: Optional[str] = None,
         enable_mempalace: bool = False,
         session_memory: bool = True,
+        strict_types: Optional[bool] = None,
+        journal: bool = False,
+        layout: str = "file",
+        compression_level: Optional[int] = None,
+        read_only: bool = False,
+        lock_timeout: float = 0.0,
+        background: bool = False,
+        history_size: int = 1000,
+        persist_history: bool = False,
     ) -> None:
         self._inner = Database(
             storage_path=storage_path,
             encryption_key=encryption_key,
             compression=compression,
             mode=mode,
+            strict_types=strict_types,
+            journal=journal,
+            layout=layout,
+            compression_level=compression_level,
+            read_only=read_only,
+            lock_timeout=lock_timeout,
+            background=background,
+            history_size=history_size,
+            persist_history=persist_history,
         )
         self._palace: Optional[MemPalaceBridge] = None
         self._memory: Optional[SessionMemory] = None
@@ -65,6 +83,15 @@ class RsnDatabase:
     def snapshot(self, dest: str) -> None:
         self._inner.snapshot(dest)
 
+    def close(self, flush: bool = True) -> None:
+        self._inner.close(flush)
+
+    def __enter__(self) -> "RsnDatabase":
+        return self
+
+    def __exit__(self, exc_type: Any, exc: Any, tb: Any) -> None:
+        self._inner.__exit__(exc_type, exc, tb)
+
     def remember(self, text: str, **kwargs: Any) -> str:
         if self._memory:
             role = kwargs.pop("role", "user")
@@ -105,6 +132,15 @@ def open_db(
     mode: str = "professional",
     palace_path: Optional[str] = None,
     mempalace: bool = False,
+    strict_types: Optional[bool] = None,
+    journal: bool = False,
+    layout: str = "file",
+    compression_level: Optional[int] = None,
+    read_only: bool = False,
+    lock_timeout: float = 0.0,
+    background: bool = False,
+    history_size: int = 1000,
+    persist_history: bool = False,
 ) -> Iterator[RsnDatabase]:
     db = RsnDatabase(
         storage_path,
@@ -113,9 +149,20 @@ def open_db(
         mode=mode,
         palace_path=palace_path,
         enable_mempalace=mempalace,
+        strict_types=strict_types,
+        journal=journal,
+        layout=layout,
+        compression_level=compression_level,
+        read_only=read_only,
+        lock_timeout=lock_timeout,
+        background=background,
+        history_size=history_size,
+        persist_history=persist_history,
     )
     try:
         yield db
     finally:
-        if storage_path:
-            db.save()
+        if not db.closed:
+            if storage_path and not read_only:
+                db.save()
+            db.close()
@@ -24,14 +24,63 @@ SECTIONS: tuple[tuple[str, tuple[HelpEntry, ...]], ...] = (
     (
         "Tables & data",
         (
-            HelpEntry("COUNT <table>", "Return the number of rows in a table."),
-            HelpEntry("SHOW TABLES", "List all tables (alias: TABLES)."),
+            HelpEntry(
+                "COUNT <table> [WHERE ...]",
+                "Return the number of rows in a table, or of those matching the conditions.",
+            ),
+            HelpEntry(
+                "CREATE TABLE [IF NOT EXISTS] <table> (<field> <type> ..., ...)",
+                "Define a table; each field takes REQUIRED, UNIQUE and DEFAULT <value> in any order.",
+            ),
+            HelpEntry(
+                "DELETE FROM <table> WHERE ...",
+                "Delete matching rows and return how many; use ALL instead of WHERE for every row.",
+            ),
+            HelpEntry(
+                "DESCRIBE <table> [FULL]",
+                "List field names; FULL adds types, constraints and metadata.",
+            ),
+            HelpEntry(
+                "INSERT INTO <table> (<cols>) VALUES (...)[, (...)]",
+                "Insert rows and return the new id(s); a JSON object works in place of the lists.",
+            ),
+            HelpEntry(
+                "SELECT <cols|*> FROM <table> [WHERE ...] [ORDER BY ...] [LIMIT n]",
+                "Query rows; WHERE takes =, !=, <, >, <=, >= and LIKE joined with AND.",
+            ),
+            HelpEntry(
+                "SHOW TABLES [FULL]",
+                "List all tables (alias: TABLES); FULL adds record and field counts.",
+            ),
             HelpEntry("TABLES", "Same as SHOW TABLES."),
+            HelpEntry(
+                "TRUNCATE <table> [RESET]",
+                "Delete every row but keep the schema; RESET restarts ids at 1.",
+            ),
+            HelpEntry(
+                "UPDATE <table> SET <field> = <value>[, ...] WHERE ...",
+                "Change matching rows and return how many; use ALL instead of WHERE for every row.",
+            ),
+            HelpEntry(
+                "VERIFY [RECOVER]",
+                "Check the database file; RECOVER keeps only the tables that are intact.",
+            ),
         ),
     ),
     (
         "GraphRAG (knowledge)",
         (
+            HelpEntry(
+                "GRAPH COMMUNITIES",
+                "List entity communities with their id, size and summary.",
+            ),
+            HelpEntry(
+                "GRAPH ENTITIES [LIKE <pattern>]",
+                "List entities by mentions, optionally filtered by a pattern.",
+            ),
+            HelpEntry("GRAPH FORGET <source>", "Remove everything ingested from one source."),
+            HelpEntry("GRAPH NEIGHBORS <entity>", "List related entities with their weights."),
+            HelpEntry("GRAPH SOURCES", "List ingested sources with their chunk counts."),
             HelpEntry("GRAPH_QUERY <text>", "Search ingested knowledge for related facts."),
             HelpEntry("INGEST <text>", "Add free-form text to the on-disk knowledge graph."),
         ),
@@ -40,8 +89,11 @@ SECTIONS: tuple[tuple[str, tuple[HelpEntry, ...]], ...] = (
         "Transactions",
         (
             HelpEntry("BATCH", "Start a batch; following writes are held until COMMIT."),
-            HelpEntry("COMMIT", "Apply all operations in the current batch."),
+            HelpEntry("COMMIT", "Apply the current batch all or nothing, saving once."),
+            HelpEntry("RELEASE <name>", "Forget a savepoint (and later ones); queued writes stay."),
             HelpEntry("ROLLBACK", "Discard the current batch without saving changes."),
+            HelpEntry("ROLLBACK TO <name>", "Drop only the writes queued after a savepoint."),
+            HelpEntry("SAVEPOINT <name>", "Mark a point in the batch to roll back to."),
         ),
     ),
     (
@@ -73,7 +73,7 @@ impl AliveState {
                     ),
                     format!(
                         "I'm {}% done pretending this is fine.",
-                        100 - (self.errors_total.min(100) as u64)
+                        100 - self.errors_total.min(100)
                     ),
                     format!(
                         "Streak: {} successes. Don't ruin it.",
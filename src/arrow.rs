@@ -0,0 +1,370 @@
+//! Just enough of the Arrow C data interface to hand a record batch to pyarrow without
+//! converting it row by row: nullable int64, float64, boolean and large-utf8 columns,
+//! exported as one struct array.
+
+use serde_json::Value;
+use std::ffi::{c_char, c_void, CString};
+use std::ptr;
+
+const FLAG_NULLABLE: i64 = 2;
+
+/// `struct ArrowSchema` of the C data interface.
+#[repr(C)]
+pub struct FfiSchema {
+    format: *const c_char,
+    name: *const c_char,
+    metadata: *const c_char,
+    flags: i64,
+    n_children: i64,
+    children: *mut *mut FfiSchema,
+    dictionary: *mut FfiSchema,
+    release: Option<unsafe extern "C" fn(*mut FfiSchema)>,
+    private_data: *mut c_void,
+}
+
+/// `struct ArrowArray` of the C data interface.
+#[repr(C)]
+pub struct FfiArray {
+    length: i64,
+    null_count: i64,
+    offset: i64,
+    n_buffers: i64,
+    n_children: i64,
+    buffers: *mut *const c_void,
+    children: *mut *mut FfiArray,
+    dictionary: *mut FfiArray,
+    release: Option<unsafe extern "C" fn(*mut FfiArray)>,
+    private_data: *mut c_void,
+}
+
+/// A consumer moves a structure out by copying it and clearing our `release`; whatever is
+/// still ours when it drops is released here.
+impl Drop for FfiSchema {
+    fn drop(&mut self) {
+        if let Some(release) = self.release {
+            unsafe { release(self) }
+        }
+    }
+}
+
+impl Drop for FfiArray {
+    fn drop(&mut self) {
+        if let Some(release) = self.release {
+            unsafe { release(self) }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Int64,
+    Float64,
+    Boolean,
+    /// Strings; any other JSON value is stored as its serialized text.
+    Utf8,
+}
+
+enum Values {
+    Int64(Vec<i64>),
+    Float64(Vec<f64>),
+    Boolean(Vec<u8>),
+    Utf8 { offsets: Vec<i64>, data: Vec<u8> },
+}
+
+/// One nullable column, filled a value at a time.
+pub struct Column {
+    name: String,
+    len: usize,
+    nulls: usize,
+    validity: Vec<u8>,
+    values: Values,
+}
+
+impl Column {
+    pub fn new(name: &str, kind: Kind, capacity: usize) -> Self {
+        let values = match kind {
+            Kind::Int64 => Values::Int64(Vec::with_capacity(capacity)),
+            Kind::Float64 => Values::Float64(Vec::with_capacity(capacity)),
+            Kind::Boolean => Values::Boolean(Vec::with_capacity(capacity.div_ceil(8))),
+            Kind::Utf8 => {
+                let mut offsets = Vec::with_capacity(capacity + 1);
+                offsets.push(0);
+                Values::Utf8 {
+                    offsets,
+                    data: Vec::new(),
+                }
+            }
+        };
+        Self {
+            name: name.to_string(),
+            len: 0,
+            nulls: 0,
+            validity: Vec::with_capacity(capacity.div_ceil(8)),
+            values,
+        }
+    }
+
+    /// Appends `value`, or a null when it is null or does not fit the column's kind.
+    pub fn push(&mut self, value: &Value) {
+        let valid = match &mut self.values {
+            Values::Int64(v) => {
+                let x = value.as_i64();
+                v.push(x.unwrap_or_default());
+                x.is_some()
+            }
+            Values::Float64(v) => {
+                let x = value.as_f64();
+                v.push(x.unwrap_or_default());
+                x.is_some()
+            }
+            Values::Boolean(bits) => {
+                let x = value.as_bool();
+                set_bit(bits, self.len, x.unwrap_or_default());
+                x.is_some()
+            }
+            Values::Utf8 { offsets, data } => {
+                match value {
+                    Value::Null => {}
+                    Value::String(s) => data.extend_from_slice(s.as_bytes()),
+                    other => data.extend_from_slice(other.to_string().as_bytes()),
+                }
+                offsets.push(data.len() as i64);
+                !value.is_null()
+            }
+        };
+        set_bit(&mut self.validity, self.len, valid);
+        self.len += 1;
+        if !valid {
+            self.nulls += 1;
+        }
+    }
+
+    fn format(&self) -> &'static str {
+        match self.values {
+            Values::Int64(_) => "l",
+            Values::Float64(_) => "g",
+            Values::Boolean(_) => "b",
+            Values::Utf8 { .. } => "U",
+        }
+    }
+}
+
+fn set_bit(bits: &mut Vec<u8>, i: usize, on: bool) {
+    if i.is_multiple_of(8) {
+        bits.push(0);
+    }
+    if on {
+        bits[i / 8] |= 1 << (i % 8);
+    }
+}
+
+/// Owned by an exported `FfiSchema`; freed by its release callback.
+struct SchemaPrivate {
+    format: CString,
+    name: CString,
+    children: Vec<*mut FfiSchema>,
+}
+
+/// Owned by an exported `FfiArray`; keeps the buffers alive until it is released.
+struct ArrayPrivate {
+    _column: Option<Column>,
+    buffers: Vec<*const c_void>,
+    children: Vec<*mut FfiArray>,
+}
+
+unsafe extern "C" fn release_schema(schema: *mut FfiSchema) {
+    let Some(schema) = schema.as_mut() else {
+        return;
+    };
+    let private = Box::from_raw(schema.private_data.cast::<SchemaPrivate>());
+    for child in private.children {
+        drop(Box::from_raw(child));
+    }
+    schema.release = None;
+}
+
+unsafe extern "C" fn release_array(array: *mut FfiArray) {
+    let Some(array) = array.as_mut() else {
+        return;
+    };
+    let private = Box::from_raw(array.private_data.cast::<ArrayPrivate>());
+    for child in private.children {
+        drop(Box::from_raw(child));
+    }
+    array.release = None;
+}
+
+fn export_schema(format: &str, name: &str, flags: i64, children: Vec<*mut FfiSchema>) -> FfiSchema {
+    let format = CString::new(format).expect("Arrow formats have no NUL bytes");
+    let name = CString::new(name.replace('\0', "")).expect("NUL bytes were removed");
+    let mut private = Box::new(SchemaPrivate {
+        format,
+        name,
+        children,
+    });
+    FfiSchema {
+        format: private.format.as_ptr(),
+        name: private.name.as_ptr(),
+        metadata: ptr::null(),
+        flags,
+        n_children: private.children.len() as i64,
+        children: private.children.as_mut_ptr(),
+        dictionary: ptr::null_mut(),
+        release: Some(release_schema),
+        private_data: Box::into_raw(private).cast(),
+    }
+}
+
+fn export_array(
+    len: usize,
+    nulls: usize,
+    column: Option<Column>,
+    buffers: Vec<*const c_void>,
+    children: Vec<*mut FfiArray>,
+) -> FfiArray {
+    let mut private = Box::new(ArrayPrivate {
+        _column: column,
+        buffers,
+        children,
+    });
+    FfiArray {
+        length: len as i64,
+        null_count: nulls as i64,
+        offset: 0,
+        n_buffers: private.buffers.len() as i64,
+        n_children: private.children.len() as i64,
+        buffers: private.buffers.as_mut_ptr(),
+        children: private.children.as_mut_ptr(),
+        dictionary: ptr::null_mut(),
+        release: Some(release_array),
+        private_data: Box::into_raw(private).cast(),
+    }
+}
+
+fn export_column(column: Column) -> (FfiSchema, FfiArray) {
+    let schema = export_schema(column.format(), &column.name, FLAG_NULLABLE, Vec::new());
+    // The buffers live on the heap, so their addresses survive moving `column` below.
+    let validity = if column.nulls == 0 {
+        ptr::null()
+    } else {
+        column.validity.as_ptr().cast()
+    };
+    let buffers = match &column.values {
+        Values::Int64(v) => vec![validity, v.as_ptr().cast()],
+        Values::Float64(v) => vec![validity, v.as_ptr().cast()],
+        Values::Boolean(v) => vec![validity, v.as_ptr().cast()],
+        Values::Utf8 { offsets, data } => {
+            vec![validity, offsets.as_ptr().cast(), data.as_ptr().cast()]
+        }
+    };
+    let (len, nulls) = (column.len, column.nulls);
+    let array = export_array(len, nulls, Some(column), buffers, Vec::new());
+    (schema, array)
+}
+
+/// A record batch in C data interface form. Pass `array_addr` and `schema_addr` to an
+/// importer such as `pyarrow.RecordBatch._import_from_c`; anything it did not take is
+/// released on drop.
+pub struct Batch {
+    array: Box<FfiArray>,
+    schema: Box<FfiSchema>,
+}
+
+impl Batch {
+    /// Every column must hold `rows` values.
+    pub fn new(columns: Vec<Column>, rows: usize) -> Self {
+        debug_assert!(columns.iter().all(|c| c.len == rows));
+        let (schemas, arrays): (Vec<_>, Vec<_>) = columns
+            .into_iter()
+            .map(|c| {
+                let (schema, array) = export_column(c);
+                (
+                    Box::into_raw(Box::new(schema)),
+                    Box::into_raw(Box::new(array)),
+                )
+            })
+            .unzip();
+        Self {
+            schema: Box::new(export_schema("+s", "", 0, schemas)),
+            array: Box::new(export_array(rows, 0, None, vec![ptr::null()], arrays)),
+        }
+    }
+
+    pub fn array_addr(&mut self) -> usize {
+        ptr::from_mut(self.array.as_mut()) as usize
+    }
+
+    pub fn schema_addr(&mut self) -> usize {
+        ptr::from_mut(self.schema.as_mut()) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::ffi::CStr;
+    use std::slice;
+
+    #[test]
+    fn batches_export_typed_nullable_columns() {
+        let rows = [
+            json!({"n": 1, "x": 1.5, "ok": true, "s": "ab"}),
+            json!({"n": null, "x": 2, "ok": null, "s": {"k": 1}}),
+            json!({"n": 3, "ok": false, "s": null}),
+        ];
+        let mut columns = vec![
+            Column::new("n", Kind::Int64, rows.len()),
+            Column::new("x", Kind::Float64, rows.len()),
+            Column::new("ok", Kind::Boolean, rows.len()),
+            Column::new("s", Kind::Utf8, rows.len()),
+        ];
+        for row in &rows {
+            for c in &mut columns {
+                c.push(row.get(&c.name).unwrap_or(&Value::Null));
+            }
+        }
+        let mut batch = Batch::new(columns, rows.len());
+
+        unsafe {
+            let schema = &*(batch.schema_addr() as *const FfiSchema);
+            assert_eq!(CStr::from_ptr(schema.format).to_str(), Ok("+s"));
+            let fields = slice::from_raw_parts(schema.children, 4);
+            let formats: Vec<_> = fields
+                .iter()
+                .map(|f| CStr::from_ptr((**f).format).to_str().unwrap())
+                .collect();
+            assert_eq!(formats, ["l", "g", "b", "U"]);
+            assert_eq!(CStr::from_ptr((*fields[2]).name).to_str(), Ok("ok"));
+
+            // Move the batch out as an importer would, then release it on its behalf.
+            let moved = ptr::read(batch.array_addr() as *const FfiArray);
+            batch.array.release = None;
+            assert_eq!((moved.length, moved.n_children), (3, 4));
+            let cols = slice::from_raw_parts(moved.children, 4);
+            let nulls: Vec<_> = cols.iter().map(|c| (**c).null_count).collect();
+            assert_eq!(nulls, [1, 1, 1, 1]);
+
+            let n = &*cols[0];
+            let buffers = slice::from_raw_parts(n.buffers, 2);
+            assert_eq!(*buffers[0].cast::<u8>(), 0b101);
+            assert_eq!(
+                slice::from_raw_parts(buffers[1].cast::<i64>(), 3),
+                [1, 0, 3]
+            );
+
+            let ok = &*cols[2];
+            let bits = *slice::from_raw_parts(ok.buffers, 2)[1].cast::<u8>();
+            assert_eq!(bits & 0b111, 0b001);
+
+            let s = &*cols[3];
+            let buffers = slice::from_raw_parts(s.buffers, 3);
+            let offsets = slice::from_raw_parts(buffers[1].cast::<i64>(), 4);
+            assert_eq!(offsets, [0, 2, 9, 9]);
+            let data = slice::from_raw_parts(buffers[2].cast::<u8>(), 9);
+            assert_eq!(data, b"ab{\"k\":1}");
+
+            drop(moved);
+        }
+    }
+}
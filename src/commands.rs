@@ -0,0 +1,437 @@
+/// One command `execute_sql` understands. The dispatcher only accepts words found here, so
+/// `HELP` and typo suggestions always describe what actually runs.
+pub struct Command {
+    /// First word of the command, upper case.
+    pub name: &'static str,
+    pub section: &'static str,
+    pub syntax: &'static str,
+    pub summary: &'static str,
+    pub details: &'static str,
+    pub examples: &'static [&'static str],
+}
+
+/// Grouped by section, sorted by name within each.
+pub const COMMANDS: &[Command] = &[
+    Command {
+        name: "CREATE",
+        section: "Schema",
+        syntax: "CREATE TABLE [IF NOT EXISTS] <table> (<field> <type> [REQUIRED] [UNIQUE] [INDEXED] [DEFAULT <value>], ...)",
+        summary: "Define a table.",
+        details: "Types are the ones create_table() accepts (STRING, INTEGER, FLOAT, BOOLEAN, JSON, \
+                  DATETIME, ARRAY<type>). Modifiers may come in any order; a DEFAULT may be a JSON \
+                  object or array. IF NOT EXISTS leaves an existing table alone instead of failing.",
+        examples: &[
+            "CREATE TABLE users (name STRING REQUIRED UNIQUE, age INTEGER DEFAULT 0, profile JSON)",
+            "CREATE TABLE IF NOT EXISTS tags (label TEXT UNIQUE)",
+        ],
+    },
+    Command {
+        name: "DESCRIBE",
+        section: "Schema",
+        syntax: "DESCRIBE <table> [FULL]",
+        summary: "List a table's field names; FULL adds types, constraints and metadata.",
+        details: "Without FULL the result is the sorted field names. FULL returns the same document \
+                  schema() gives.",
+        examples: &["DESCRIBE users", "DESCRIBE users FULL"],
+    },
+    Command {
+        name: "SHOW",
+        section: "Schema",
+        syntax: "SHOW TABLES [FULL]",
+        summary: "List all tables.",
+        details: "Returns the table names. FULL returns one dict per table, sorted by name, \
+                  with its record and field counts and whether any field is unique or \
+                  indexed. TABLES on its own does the same.",
+        examples: &["SHOW TABLES", "SHOW TABLES FULL"],
+    },
+    Command {
+        name: "TABLES",
+        section: "Schema",
+        syntax: "TABLES [FULL]",
+        summary: "Same as SHOW TABLES.",
+        details: "Returns the table names.",
+        examples: &["TABLES"],
+    },
+    Command {
+        name: "TRUNCATE",
+        section: "Schema",
+        syntax: "TRUNCATE <table> [RESET]",
+        summary: "Delete every row but keep the schema.",
+        details: "Removes all rows in one save. RESET also restarts ids at 1.",
+        examples: &["TRUNCATE sessions", "TRUNCATE sessions RESET"],
+    },
+    Command {
+        name: "COUNT",
+        section: "Data",
+        syntax: "COUNT <table> [WHERE <cond> [AND ...]]",
+        summary: "Return the number of rows in a table, or of those matching.",
+        details: "Conditions are the ones SELECT takes. Expired and soft-deleted rows are not \
+                  counted.",
+        examples: &["COUNT users", "COUNT users WHERE age > 30"],
+    },
+    Command {
+        name: "DELETE",
+        section: "Data",
+        syntax: "DELETE FROM <table> (WHERE <condition> [AND ...] | ALL)",
+        summary: "Delete matching rows and return how many.",
+        details: "Conditions are the ones SELECT takes. on_delete rules apply as in delete_where(). \
+                  Leaving out WHERE is refused unless ALL says every row should go.",
+        examples: &[
+            "DELETE FROM users WHERE age < 18",
+            "DELETE FROM sessions ALL",
+        ],
+    },
+    Command {
+        name: "INSERT",
+        section: "Data",
+        syntax: "INSERT INTO <table> (<field>, ...) VALUES (<value>, ...)[, (...)]",
+        summary: "Insert rows and return the new id(s).",
+        details: "Values are numbers, 'quoted text', TRUE, FALSE or NULL. A JSON object, or an \
+                  array of them, works in place of the column and value lists. Several rows are \
+                  inserted all or nothing.",
+        examples: &[
+            "INSERT INTO users (name, age) VALUES ('Ada', 36), ('Alan', 41)",
+            "INSERT INTO users {\"name\": \"Grace\", \"age\": 85}",
+        ],
+    },
+    Command {
+        name: "PRINT",
+        section: "Data",
+        syntax: "PRINT [TABLE | MARKDOWN | JSON] <select>",
+        summary: "Run a SELECT and show the rows as a text table.",
+        details: "TABLE (the default) draws an aligned ASCII table and MARKDOWN a Markdown one, \
+                  both with id first; JSON pretty-prints the rows. format_results() does the \
+                  same from Python.",
+        examples: &[
+            "PRINT SELECT * FROM users",
+            "PRINT MARKDOWN SELECT name, age FROM users ORDER BY age DESC LIMIT 5",
+        ],
+    },
+    Command {
+        name: "SELECT",
+        section: "Data",
+        syntax: "SELECT <fields|*> FROM <table> [WHERE <condition> [AND ...]] [ORDER BY <field> [ASC|DESC]] [LIMIT <n>]",
+        summary: "Query rows.",
+        details: "A condition is <field> <op> <value> with =, !=, <>, <, >, <=, >= or LIKE, where \
+                  LIKE takes % and _ wildcards. Table names match regardless of case; a name in \
+                  double quotes, `backticks` or [brackets] matches exactly and may be a \
+                  keyword.",
+        examples: &[
+            "SELECT * FROM users",
+            "SELECT name, age FROM users WHERE age >= 18 AND name LIKE 'A%' ORDER BY age DESC LIMIT 10",
+        ],
+    },
+    Command {
+        name: "UPDATE",
+        section: "Data",
+        syntax: "UPDATE <table> SET <field> = <value>[, ...] (WHERE <condition> [AND ...] | ALL)",
+        summary: "Change matching rows and return how many.",
+        details: "Each row is validated as update() would, all or nothing. Leaving out WHERE is \
+                  refused unless ALL says every row should change.",
+        examples: &[
+            "UPDATE users SET age = 37, active = TRUE WHERE name = 'Ada'",
+            "UPDATE users SET active = FALSE ALL",
+        ],
+    },
+    Command {
+        name: "BATCH",
+        section: "Transactions",
+        syntax: "BATCH",
+        summary: "Start a batch; later commands are queued until COMMIT.",
+        details: "Queued commands run nowhere until COMMIT. A batch cannot be nested; use \
+                  SAVEPOINT for stages.",
+        examples: &["BATCH"],
+    },
+    Command {
+        name: "COMMIT",
+        section: "Transactions",
+        syntax: "COMMIT",
+        summary: "Run the queued batch all or nothing, saving once.",
+        details: "If any queued command fails, everything the batch did is undone and the error \
+                  names the failing command and its position.",
+        examples: &["COMMIT"],
+    },
+    Command {
+        name: "RELEASE",
+        section: "Transactions",
+        syntax: "RELEASE <savepoint>",
+        summary: "Forget a savepoint and any set after it; queued commands stay.",
+        details: "Only valid inside a batch.",
+        examples: &["RELEASE loaded_users"],
+    },
+    Command {
+        name: "ROLLBACK",
+        section: "Transactions",
+        syntax: "ROLLBACK [TO <savepoint>]",
+        summary: "Discard the batch, or only what was queued after a savepoint.",
+        details: "Plain ROLLBACK ends the batch without running anything. ROLLBACK TO keeps the \
+                  batch and the savepoint open.",
+        examples: &["ROLLBACK", "ROLLBACK TO loaded_users"],
+    },
+    Command {
+        name: "SAVEPOINT",
+        section: "Transactions",
+        syntax: "SAVEPOINT <name>",
+        summary: "Mark a point in the batch to roll back to.",
+        details: "Names follow table-name rules and must be unique within the batch.",
+        examples: &["SAVEPOINT loaded_users"],
+    },
+    Command {
+        name: "GRAPH",
+        section: "Knowledge graph",
+        syntax: "GRAPH SOURCES | ENTITIES [LIKE <pattern>] | COMMUNITIES | NEIGHBORS <entity> | \
+                 FORGET <source>",
+        summary: "Inspect the knowledge graph, or forget one source.",
+        details: "SOURCES lists sources with their chunks, words, latest ingest time and top \
+                  entities; ENTITIES sorts by mentions, filtered by a case-insensitive LIKE \
+                  pattern; COMMUNITIES gives id, size, summary and top entities; NEIGHBORS lists \
+                  related entities with summed relation weights and the relation types, such \
+                  as -[works_for]-> or <-[acquired]-. FORGET removes a source's chunks with \
+                  the entities and relations only they added and returns the chunk count.",
+        examples: &[
+            "GRAPH SOURCES",
+            "GRAPH ENTITIES LIKE 'A%'",
+            "GRAPH NEIGHBORS Alice",
+            "GRAPH FORGET notes",
+        ],
+    },
+    Command {
+        name: "GRAPH_QUERY",
+        section: "Knowledge graph",
+        syntax: "GRAPH_QUERY <text>",
+        summary: "Search ingested knowledge for related facts.",
+        details: "Matches entities and text chunks from everything INGEST has stored: the best \
+                  3 chunks and the context of up to 2 communities the text names.",
+        examples: &["GRAPH_QUERY who works at RSN DB"],
+    },
+    Command {
+        name: "INGEST",
+        section: "Knowledge graph",
+        syntax: "INGEST <text>",
+        summary: "Add free-form text to the knowledge graph.",
+        details: "Entities and relations are extracted and saved with the database.",
+        examples: &["INGEST Alice works at RSN DB."],
+    },
+    Command {
+        name: "ALIAS",
+        section: "Shell",
+        syntax: "ALIAS <name> = <command>",
+        summary: "Define a shortcut for a command.",
+        details: "Names are case-insensitive; a built-in command of the same name wins.",
+        examples: &["ALIAS people = SELECT * FROM users"],
+    },
+    Command {
+        name: "ALIASES",
+        section: "Shell",
+        syntax: "ALIASES",
+        summary: "List aliases and what they expand to.",
+        details: "Sorted by name. list_aliases() returns the same mapping.",
+        examples: &["ALIASES"],
+    },
+    Command {
+        name: "CLEAR",
+        section: "Shell",
+        syntax: "CLEAR HISTORY",
+        summary: "Forget the command history.",
+        details: "With persist_history the stored history is wiped from disk as well.",
+        examples: &["CLEAR HISTORY"],
+    },
+    Command {
+        name: "HELP",
+        section: "Shell",
+        syntax: "HELP [<command>]",
+        summary: "List commands, or show usage and examples for one.",
+        details: "Commands are matched by their first word, so HELP SHOW covers SHOW TABLES.",
+        examples: &["HELP", "HELP SELECT"],
+    },
+    Command {
+        name: "HISTORY",
+        section: "Shell",
+        syntax: "HISTORY [<n>] | HISTORY SEARCH <term>",
+        summary: "Show recent commands, or those containing a term.",
+        details: "Most recent first, 10 unless <n> is given; SEARCH ignores case and lists \
+                  every match. Empty lines and HISTORY itself are left out. Only the last \
+                  history_size commands are kept, in memory unless the database was opened \
+                  with persist_history.",
+        examples: &["HISTORY", "HISTORY 25", "HISTORY SEARCH users"],
+    },
+    Command {
+        name: "STATS",
+        section: "Shell",
+        syntax: "STATS",
+        summary: "Show tables, records, graph size and session counters.",
+        details: "The same data as stats(): record counts per table, aliases, the graph as \
+                  graph_stats() counts it (index size, entity types, community sizes, top \
+                  entities), calls and errors since the database was opened, and \
+                  storage_stats() when something is on disk.",
+        examples: &["STATS"],
+    },
+    Command {
+        name: "UNALIAS",
+        section: "Shell",
+        syntax: "UNALIAS <name>",
+        summary: "Remove an alias.",
+        details: "The removal is saved, so the alias stays gone after reopening.",
+        examples: &["UNALIAS people"],
+    },
+    Command {
+        name: "VERIFY",
+        section: "Shell",
+        syntax: "VERIFY [RECOVER]",
+        summary: "Check the database file.",
+        details: "Reports what is damaged. RECOVER keeps only the tables that are intact.",
+        examples: &["VERIFY", "VERIFY RECOVER"],
+    },
+    Command {
+        name: "ACHIEVEMENT",
+        section: "Alive system",
+        syntax: "ACHIEVEMENT",
+        summary: "Show an unlocked achievement.",
+        details: "Mostly for Snarky mode.",
+        examples: &["ACHIEVEMENT"],
+    },
+    Command {
+        name: "MOOD",
+        section: "Alive system",
+        syntax: "MOOD",
+        summary: "Show the engine's current mood.",
+        details: "The mood follows how the session is going: successes lift it, errors sink it.",
+        examples: &["MOOD"],
+    },
+    Command {
+        name: "PULSE",
+        section: "Alive system",
+        syntax: "PULSE",
+        summary: "Print a heartbeat line.",
+        details: "Worded to suit the personality mode.",
+        examples: &["PULSE"],
+    },
+    Command {
+        name: "VITALS",
+        section: "Alive system",
+        syntax: "VITALS",
+        summary: "Show internal vitals as JSON.",
+        details: "Mood, streaks and activity counters.",
+        examples: &["VITALS"],
+    },
+    Command {
+        name: "WHY",
+        section: "Alive system",
+        syntax: "WHY ARE YOU SO <word>",
+        summary: "Ask about the attitude.",
+        details: "Needs the whole question.",
+        examples: &["WHY ARE YOU SO MEAN"],
+    },
+];
+
+/// The command whose name is `word`, ignoring case.
+pub fn find(word: &str) -> Option<&'static Command> {
+    COMMANDS.iter().find(|c| c.name.eq_ignore_ascii_case(word))
+}
+
+/// The command a mistyped `word` most likely meant; see `nearest`.
+pub fn closest(word: &str) -> Option<&'static Command> {
+    let name = nearest(word, COMMANDS.iter().map(|c| c.name))?;
+    find(name)
+}
+
+/// The candidate a mistyped `word` most likely meant, ignoring case: at most two edits
+/// away, and fewer than the word's own length so short noise does not match everything.
+/// Ties go to the first candidate.
+pub fn nearest<'a>(word: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let word = word.to_lowercase();
+    candidates
+        .into_iter()
+        .map(|c| (edit_distance(&word, &c.to_lowercase()), c))
+        .filter(|(d, _)| *d <= 2 && *d < word.chars().count())
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, c)| c)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let next = (diagonal + usize::from(ca != *cb))
+                .min(row[j] + 1)
+                .min(row[j + 1] + 1);
+            diagonal = row[j + 1];
+            row[j + 1] = next;
+        }
+    }
+    row[b.len()]
+}
+
+/// Every command with its summary and syntax, grouped by section.
+pub fn listing() -> String {
+    let width = COMMANDS.iter().map(|c| c.name.len()).max().unwrap_or(0);
+    let mut out = String::new();
+    let mut section = "";
+    for c in COMMANDS {
+        if c.section != section {
+            section = c.section;
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            out.push_str(section);
+            out.push('\n');
+        }
+        out.push_str(&format!("  {:width$}  {}\n", c.name, c.summary, width = width));
+        // Bare words need no syntax line.
+        if c.syntax != c.name {
+            out.push_str(&format!("  {:width$}    {}\n", "", c.syntax, width = width));
+        }
+    }
+    out.push_str("\nHELP <command> shows details and examples.");
+    out
+}
+
+/// Syntax, details and examples of one command.
+pub fn usage(c: &Command) -> String {
+    let mut out = format!("{}\n\n{}\n{}\n\nExamples:", c.syntax, c.summary, c.details);
+    for example in c.examples {
+        out.push_str("\n  ");
+        out.push_str(example);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registry_is_grouped_sorted_and_unique() {
+        let mut seen_sections: Vec<&str> = Vec::new();
+        for pair in COMMANDS.windows(2) {
+            if pair[0].section == pair[1].section {
+                assert!(pair[0].name < pair[1].name, "{} out of order", pair[1].name);
+            } else {
+                assert!(!seen_sections.contains(&pair[1].section));
+                seen_sections.push(pair[0].section);
+            }
+        }
+        for c in COMMANDS {
+            assert_eq!(c.name, c.name.to_ascii_uppercase());
+            assert!(c.syntax.starts_with(c.name), "{}", c.name);
+            assert!(!c.examples.is_empty(), "{}", c.name);
+        }
+    }
+
+    #[test]
+    fn typos_find_the_nearest_command() {
+        assert_eq!(closest("DELTE").map(|c| c.name), Some("DELETE"));
+        assert_eq!(closest("selcet").map(|c| c.name), Some("SELECT"));
+        assert_eq!(closest("comit").map(|c| c.name), Some("COMMIT"));
+        assert!(closest("X").is_none());
+        assert!(closest("FROBNICATE").is_none());
+        assert_eq!(nearest("usres", ["orders", "users"]), Some("users"));
+        assert_eq!(nearest("Users", ["users"]), Some("users"));
+        assert_eq!(nearest("ab", ["xy"]), None);
+        assert_eq!(find("help").map(|c| c.name), Some("HELP"));
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+}
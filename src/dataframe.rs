@@ -0,0 +1,298 @@
+//! Reads a DataFrame into columns of JSON values: through the `__dataframe__` interchange
+//! protocol when the object has it, and `to_dict("records")` otherwise. Missing values
+//! (NaN, NaT, `None`, masked entries) come out as null and datetimes as RFC 3339 text.
+
+use chrono::{DateTime, SecondsFormat};
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use serde_json::{Number, Value};
+use std::collections::HashMap;
+use std::slice;
+
+/// Column names with their values, in frame order.
+pub type Columns = Vec<(String, Vec<Value>)>;
+
+// `DtypeKind` of the interchange protocol.
+const INT: i64 = 0;
+const UINT: i64 = 1;
+const FLOAT: i64 = 2;
+const BOOL: i64 = 20;
+const STRING: i64 = 21;
+const DATETIME: i64 = 22;
+const CATEGORICAL: i64 = 23;
+
+// `ColumnNullType` of the interchange protocol.
+const USE_SENTINEL: i64 = 2;
+const USE_BITMASK: i64 = 3;
+const USE_BYTEMASK: i64 = 4;
+
+pub fn columns(df: &Bound<'_, PyAny>) -> PyResult<Columns> {
+    if df.hasattr("__dataframe__")? {
+        let frame = df.call_method0("__dataframe__")?;
+        let mut out = Vec::new();
+        for name in frame.call_method0("column_names")?.iter()? {
+            let name: String = name?.extract()?;
+            let column = frame.call_method1("get_column_by_name", (name.as_str(),))?;
+            out.push((name, column_values(&column)?));
+        }
+        return Ok(out);
+    }
+    if df.hasattr("to_dict")? {
+        return record_columns(&df.call_method1("to_dict", ("records",))?);
+    }
+    Err(PyTypeError::new_err(
+        "expected a DataFrame: an object with __dataframe__ or to_dict('records')",
+    ))
+}
+
+fn record_columns(records: &Bound<'_, PyAny>) -> PyResult<Columns> {
+    let mut out: Columns = Vec::new();
+    let mut slots = HashMap::new();
+    let mut rows = 0;
+    for record in records.iter()? {
+        let record = record?;
+        let record = record.downcast::<PyDict>()?;
+        for (key, cell) in record.iter() {
+            let name: String = key.extract()?;
+            let slot = *slots.entry(name.clone()).or_insert_with(|| {
+                out.push((name, vec![Value::Null; rows]));
+                out.len() - 1
+            });
+            out[slot].1.push(record_cell(&cell)?);
+        }
+        rows += 1;
+        for (_, values) in &mut out {
+            values.resize(rows, Value::Null);
+        }
+    }
+    Ok(out)
+}
+
+fn record_cell(cell: &Bound<'_, PyAny>) -> PyResult<Value> {
+    if cell.get_type().name()? == "NAType" || cell.ne(cell).unwrap_or(false) {
+        // pandas.NA, and anything unequal to itself: NaN and NaT.
+        return Ok(Value::Null);
+    }
+    if cell.hasattr("isoformat")? {
+        let text: String = cell.call_method0("isoformat")?.extract()?;
+        return Ok(Value::String(text));
+    }
+    if cell.hasattr("item")? {
+        // numpy scalars; `item` gives the plain Python value.
+        return record_cell(&cell.call_method0("item")?);
+    }
+    crate::py_to_json(cell.clone())
+}
+
+/// One interchange buffer, borrowed for as long as `_owner` is alive.
+struct Buffer<'py> {
+    _owner: Bound<'py, PyAny>,
+    bytes: &'py [u8],
+    bits: usize,
+    format: String,
+}
+
+impl<'py> Buffer<'py> {
+    /// Reads the `(buffer, dtype)` pair stored under `key`, if there is one.
+    fn get(buffers: &Bound<'py, PyAny>, key: &str) -> PyResult<Option<Self>> {
+        let entry = buffers.get_item(key)?;
+        if entry.is_none() {
+            return Ok(None);
+        }
+        let (owner, dtype): (Bound<'py, PyAny>, Bound<'py, PyAny>) = entry.extract()?;
+        let (_, bits, format, endianness): (i64, usize, String, String) = dtype.extract()?;
+        if endianness == ">" {
+            return Err(PyValueError::new_err(
+                "big-endian DataFrame buffers are not supported",
+            ));
+        }
+        let ptr: usize = owner.getattr("ptr")?.extract()?;
+        let len: usize = owner.getattr("bufsize")?.extract()?;
+        let bytes = if len == 0 {
+            &[][..]
+        } else {
+            // SAFETY: the protocol promises `bufsize` readable bytes at `ptr` while the
+            // buffer object lives, and `_owner` keeps it alive.
+            unsafe { slice::from_raw_parts(ptr as *const u8, len) }
+        };
+        Ok(Some(Self {
+            _owner: owner,
+            bytes,
+            bits,
+            format,
+        }))
+    }
+
+    /// Checks that `count` elements fit before any is read.
+    fn holds(&self, count: usize) -> PyResult<()> {
+        if (count * self.bits).div_ceil(8) > self.bytes.len() {
+            return Err(PyValueError::new_err(
+                "DataFrame buffer is shorter than its column",
+            ));
+        }
+        Ok(())
+    }
+
+    fn bit(&self, i: usize) -> bool {
+        self.bytes[i / 8] >> (i % 8) & 1 == 1
+    }
+
+    fn word(&self, i: usize) -> [u8; 8] {
+        let width = self.bits / 8;
+        let mut out = [0; 8];
+        out[..width].copy_from_slice(&self.bytes[i * width..(i + 1) * width]);
+        out
+    }
+
+    fn int(&self, i: usize) -> i64 {
+        let word = self.word(i);
+        match self.bits {
+            8 => word[0] as i8 as i64,
+            16 => i16::from_ne_bytes([word[0], word[1]]) as i64,
+            32 => i32::from_ne_bytes([word[0], word[1], word[2], word[3]]) as i64,
+            _ => i64::from_ne_bytes(word),
+        }
+    }
+
+    fn uint(&self, i: usize) -> u64 {
+        let word = self.word(i);
+        match self.bits {
+            8 => word[0] as u64,
+            16 => u16::from_ne_bytes([word[0], word[1]]) as u64,
+            32 => u32::from_ne_bytes([word[0], word[1], word[2], word[3]]) as u64,
+            _ => u64::from_ne_bytes(word),
+        }
+    }
+
+    fn float(&self, i: usize) -> f64 {
+        let word = self.word(i);
+        match self.bits {
+            32 => f32::from_ne_bytes([word[0], word[1], word[2], word[3]]) as f64,
+            _ => f64::from_ne_bytes(word),
+        }
+    }
+}
+
+fn column_values(column: &Bound<'_, PyAny>) -> PyResult<Vec<Value>> {
+    let mut out = Vec::new();
+    for chunk in column.call_method0("get_chunks")?.iter()? {
+        out.extend(chunk_values(&chunk?)?);
+    }
+    Ok(out)
+}
+
+fn chunk_values(column: &Bound<'_, PyAny>) -> PyResult<Vec<Value>> {
+    let size: usize = column.call_method0("size")?.extract()?;
+    let offset: usize = column.getattr("offset")?.extract()?;
+    let (kind, ..): (i64, usize, String, String) = column.getattr("dtype")?.extract()?;
+    let (null_kind, null_value): (i64, Bound<'_, PyAny>) =
+        column.getattr("describe_null")?.extract()?;
+    let buffers = column.call_method0("get_buffers")?;
+    let data = Buffer::get(&buffers, "data")?
+        .ok_or_else(|| PyValueError::new_err("DataFrame column has no data buffer"))?;
+    let validity = Buffer::get(&buffers, "validity")?;
+    let end = offset + size;
+    data.holds(if kind == STRING { 0 } else { end })?;
+    if let Some(mask) = &validity {
+        mask.holds(end)?;
+    }
+    let sentinel: Option<i64> = (null_kind == USE_SENTINEL)
+        .then(|| null_value.extract())
+        .transpose()?;
+    // For the mask kinds, `null_value` is the mask entry that marks a null.
+    let null_mark: i64 = null_value.extract().unwrap_or(0);
+    let masked = |i: usize| match (null_kind, &validity) {
+        (USE_BITMASK, Some(mask)) => mask.bit(i) as i64 == null_mark,
+        (USE_BYTEMASK, Some(mask)) => mask.bytes[i] as i64 == null_mark,
+        _ => false,
+    };
+
+    if kind == CATEGORICAL {
+        let described = column.getattr("describe_categorical")?;
+        let categories = described.get_item("categories")?;
+        if categories.is_none() {
+            return Err(PyValueError::new_err(
+                "categorical DataFrame column has no categories",
+            ));
+        }
+        let categories = column_values(&categories)?;
+        return (offset..end)
+            .map(|i| {
+                let code = data.int(i);
+                if masked(i) || sentinel == Some(code) || code < 0 {
+                    return Ok(Value::Null);
+                }
+                categories
+                    .get(code as usize)
+                    .cloned()
+                    .ok_or_else(|| PyValueError::new_err("categorical code outside its categories"))
+            })
+            .collect();
+    }
+
+    if kind == STRING {
+        let offsets = Buffer::get(&buffers, "offsets")?
+            .ok_or_else(|| PyValueError::new_err("string DataFrame column has no offsets"))?;
+        offsets.holds(end + 1)?;
+        return (offset..end)
+            .map(|i| {
+                if masked(i) {
+                    return Ok(Value::Null);
+                }
+                let (start, stop) = (offsets.int(i) as usize, offsets.int(i + 1) as usize);
+                let text = data
+                    .bytes
+                    .get(start..stop)
+                    .ok_or_else(|| PyValueError::new_err("string offsets outside the data"))?;
+                Ok(Value::String(String::from_utf8_lossy(text).into_owned()))
+            })
+            .collect();
+    }
+
+    (offset..end)
+        .map(|i| {
+            if masked(i) || matches!(kind, INT | DATETIME) && sentinel == Some(data.int(i)) {
+                return Ok(Value::Null);
+            }
+            // NaN has no JSON number, so it turns into null here whatever `describe_null` says.
+            Ok(match kind {
+                BOOL if data.bits == 1 => Value::Bool(data.bit(i)),
+                BOOL => Value::Bool(data.bytes[i] != 0),
+                INT => Value::from(data.int(i)),
+                UINT => Value::from(data.uint(i)),
+                FLOAT => Number::from_f64(data.float(i)).map_or(Value::Null, Value::Number),
+                DATETIME => datetime(&data.format, data.int(i))?,
+                _ => {
+                    return Err(PyValueError::new_err(format!(
+                        "unsupported DataFrame column type (dtype kind {})",
+                        kind
+                    )))
+                }
+            })
+        })
+        .collect()
+}
+
+/// Interchange datetimes are Arrow format strings: `tdD` (days), `tdm` (milliseconds) or
+/// `ts<unit>:<tz>` with unit s, m, u or n.
+fn datetime(format: &str, raw: i64) -> PyResult<Value> {
+    let nanos = match format.get(..3) {
+        Some("tdD") => raw.checked_mul(86_400_000_000_000),
+        Some("tdm") | Some("tsm") => raw.checked_mul(1_000_000),
+        Some("tss") => raw.checked_mul(1_000_000_000),
+        Some("tsu") => raw.checked_mul(1_000),
+        Some("tsn") => Some(raw),
+        _ => {
+            return Err(PyValueError::new_err(format!(
+                "unsupported DataFrame datetime format '{}'",
+                format
+            )))
+        }
+    };
+    Ok(nanos
+        .map(DateTime::from_timestamp_nanos)
+        .map_or(Value::Null, |dt| {
+            Value::String(dt.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+        }))
+}
@@ -0,0 +1,271 @@
+use serde_json::{Map, Number, Value};
+
+/// Expressions for computed fields: field names, string and number literals,
+/// `+ - * /` on numbers, parentheses and `concat(...)`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Field(String),
+    Literal(Value),
+    Neg(Box<Expr>),
+    Binary(Box<Expr>, char, Box<Expr>),
+    Concat(Vec<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(f64, bool),
+    Str(String),
+    Sym(char),
+}
+
+fn tokenize(src: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = src.chars().collect();
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            out.push(Token::Ident(chars[start..i].iter().collect()));
+        } else if c.is_ascii_digit() || c == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            let n = text
+                .parse::<f64>()
+                .map_err(|_| format!("bad number `{}`", text))?;
+            out.push(Token::Number(n, !text.contains('.')));
+        } else if c == '"' || c == '\'' {
+            let start = i + 1;
+            i = start;
+            while i < chars.len() && chars[i] != c {
+                i += 1;
+            }
+            if i == chars.len() {
+                return Err("unterminated string literal".to_string());
+            }
+            out.push(Token::Str(chars[start..i].iter().collect()));
+            i += 1;
+        } else if "+-*/(),".contains(c) {
+            out.push(Token::Sym(c));
+            i += 1;
+        } else {
+            return Err(format!("unexpected character `{}`", c));
+        }
+    }
+    Ok(out)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn eat(&mut self, sym: char) -> bool {
+        if self.peek() == Some(&Token::Sym(sym)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, sym: char) -> Result<(), String> {
+        if self.eat(sym) {
+            Ok(())
+        } else {
+            Err(format!("expected `{}`", sym))
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        let mut left = self.term()?;
+        while let Some(Token::Sym(op @ ('+' | '-'))) = self.peek().cloned() {
+            self.pos += 1;
+            left = Expr::Binary(Box::new(left), op, Box::new(self.term()?));
+        }
+        Ok(left)
+    }
+
+    fn term(&mut self) -> Result<Expr, String> {
+        let mut left = self.unary()?;
+        while let Some(Token::Sym(op @ ('*' | '/'))) = self.peek().cloned() {
+            self.pos += 1;
+            left = Expr::Binary(Box::new(left), op, Box::new(self.unary()?));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.eat('-') {
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        let token = self
+            .peek()
+            .cloned()
+            .ok_or_else(|| "unexpected end of expression".to_string())?;
+        self.pos += 1;
+        match token {
+            Token::Number(n, true) if n.abs() < i64::MAX as f64 => {
+                Ok(Expr::Literal(Value::from(n as i64)))
+            }
+            Token::Number(n, _) => Ok(Expr::Literal(float(n))),
+            Token::Str(s) => Ok(Expr::Literal(Value::String(s))),
+            Token::Sym('(') => {
+                let inner = self.expr()?;
+                self.expect(')')?;
+                Ok(inner)
+            }
+            Token::Ident(name) if self.eat('(') => {
+                if !name.eq_ignore_ascii_case("concat") {
+                    return Err(format!("unknown function `{}`", name));
+                }
+                let mut args = Vec::new();
+                if !self.eat(')') {
+                    loop {
+                        args.push(self.expr()?);
+                        if self.eat(')') {
+                            break;
+                        }
+                        self.expect(',')?;
+                    }
+                }
+                Ok(Expr::Concat(args))
+            }
+            Token::Ident(name) => Ok(Expr::Field(name)),
+            Token::Sym(c) => Err(format!("unexpected `{}`", c)),
+        }
+    }
+}
+
+pub fn parse(src: &str) -> Result<Expr, String> {
+    let mut parser = Parser {
+        tokens: tokenize(src)?,
+        pos: 0,
+    };
+    let expr = parser.expr()?;
+    if parser.pos != parser.tokens.len() {
+        return Err("trailing input after expression".to_string());
+    }
+    Ok(expr)
+}
+
+impl Expr {
+    /// Field names the expression reads.
+    pub fn fields(&self) -> Vec<&str> {
+        match self {
+            Self::Field(f) => vec![f.as_str()],
+            Self::Literal(_) => Vec::new(),
+            Self::Neg(inner) => inner.fields(),
+            Self::Binary(l, _, r) => {
+                let mut out = l.fields();
+                out.extend(r.fields());
+                out
+            }
+            Self::Concat(args) => args.iter().flat_map(Expr::fields).collect(),
+        }
+    }
+
+    /// Evaluates against `record`; anything that can't be computed yields null.
+    pub fn eval(&self, record: &Map<String, Value>) -> Value {
+        match self {
+            Self::Field(f) => record.get(f).cloned().unwrap_or(Value::Null),
+            Self::Literal(v) => v.clone(),
+            Self::Neg(inner) => match inner.eval(record) {
+                Value::Number(n) => match n.as_i64() {
+                    Some(i) => i.checked_neg().map(Value::from).unwrap_or(Value::Null),
+                    None => float(-n.as_f64().unwrap_or(f64::NAN)),
+                },
+                _ => Value::Null,
+            },
+            Self::Binary(l, op, r) => arith(&l.eval(record), *op, &r.eval(record)),
+            Self::Concat(args) => Value::String(
+                args.iter()
+                    .map(|a| match a.eval(record) {
+                        Value::Null => String::new(),
+                        Value::String(s) => s,
+                        other => other.to_string(),
+                    })
+                    .collect(),
+            ),
+        }
+    }
+}
+
+fn float(n: f64) -> Value {
+    Number::from_f64(n)
+        .map(Value::Number)
+        .unwrap_or(Value::Null)
+}
+
+fn arith(l: &Value, op: char, r: &Value) -> Value {
+    let (Value::Number(a), Value::Number(b)) = (l, r) else {
+        return Value::Null;
+    };
+    if let (Some(x), Some(y), true) = (a.as_i64(), b.as_i64(), op != '/') {
+        let out = match op {
+            '+' => x.checked_add(y),
+            '-' => x.checked_sub(y),
+            _ => x.checked_mul(y),
+        };
+        return out.map(Value::from).unwrap_or(Value::Null);
+    }
+    let (Some(x), Some(y)) = (a.as_f64(), b.as_f64()) else {
+        return Value::Null;
+    };
+    match op {
+        '+' => float(x + y),
+        '-' => float(x - y),
+        '*' => float(x * y),
+        _ if y == 0.0 => Value::Null,
+        _ => float(x / y),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn row(v: Value) -> Map<String, Value> {
+        v.as_object().cloned().unwrap_or_default()
+    }
+
+    #[test]
+    fn arithmetic_respects_precedence() {
+        let e = parse("price * quantity + 1").unwrap();
+        assert_eq!(e.eval(&row(json!({"price": 3, "quantity": 4}))), json!(13));
+        let e = parse("-(a - b) / 2").unwrap();
+        assert_eq!(e.eval(&row(json!({"a": 1, "b": 4}))), json!(1.5));
+        assert_eq!(e.fields(), ["a", "b"]);
+        assert!(parse("1 / 0").unwrap().eval(&Map::new()).is_null());
+    }
+
+    #[test]
+    fn concat_and_parse_errors() {
+        let e = parse("concat(first, ' ', last)").unwrap();
+        let r = row(json!({"first": "Ada", "last": "Lovelace"}));
+        assert_eq!(e.eval(&r), json!("Ada Lovelace"));
+        assert!(parse("price *").is_err());
+        assert!(parse("upper(name)").is_err());
+        assert!(parse("concat('x'").is_err());
+        assert!(parse("a b").is_err());
+    }
+}
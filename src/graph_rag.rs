@@ -1,20 +1,34 @@
-use petgraph::graph::UnGraph;
+use petgraph::graph::{NodeIndex, UnGraph};
+use petgraph::visit::EdgeRef;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::sync::LazyLock;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TextChunk {
     pub id: String,
     pub text: String,
+    #[serde(default = "unknown_source")]
     pub source: String,
+    /// The entities `ingest` counted a mention of from this chunk, so forgetting it can take
+    /// them back. `None` on chunks stored before this was tracked; their entities are
+    /// extracted again from the text when needed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entities: Option<Vec<String>>,
+    /// When `ingest` stored the chunk, in epoch milliseconds; `None` on imported chunks and
+    /// on those stored before this was recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ingested_at: Option<i64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Entity {
     pub name: String,
+    #[serde(default = "concept")]
     pub entity_type: String,
+    #[serde(default = "one")]
     pub mentions: usize,
 }
 
@@ -23,7 +37,29 @@ pub struct Relation {
     pub source: String,
     pub target: String,
     pub relation_type: String,
+    #[serde(default = "unit_weight")]
     pub weight: f32,
+    /// The chunk `ingest` extracted this relation from; `None` on imported relations and
+    /// on those stored before this was tracked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunk: Option<String>,
+}
+
+// Defaults for graph data written elsewhere, as `ingest` would have filled them in.
+fn unknown_source() -> String {
+    "unknown".to_string()
+}
+
+fn concept() -> String {
+    "CONCEPT".to_string()
+}
+
+fn one() -> usize {
+    1
+}
+
+fn unit_weight() -> f32 {
+    1.0
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -31,6 +67,9 @@ pub struct Community {
     pub id: usize,
     pub entities: Vec<String>,
     pub summary: String,
+    /// Its most connected entities, as the summary names them.
+    #[serde(default)]
+    pub top_entities: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -39,35 +78,468 @@ pub struct GraphRagData {
     pub entities: HashMap<String, Entity>,
     pub relations: Vec<Relation>,
     pub communities: Vec<Community>,
+    /// The ids of the chunks mentioning each entity, rebuilt with the search index.
+    #[serde(default)]
+    pub entity_chunks: HashMap<String, HashSet<String>>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+/// A graph as `import_graph` reads and `export_graph_json` writes it: lists of chunks,
+/// entities and relations in place of the maps `GraphRagData` keeps them in.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GraphDump {
+    #[serde(default)]
+    pub chunks: Vec<TextChunk>,
+    #[serde(default)]
+    pub entities: Vec<Entity>,
+    #[serde(default)]
+    pub relations: Vec<Relation>,
+}
+
+/// What `GraphRagEngine::import` took in; `created` are the entities it added for relations
+/// that named them.
+#[derive(Debug, Default)]
+pub struct ImportSummary {
+    pub chunks: usize,
+    pub entities: usize,
+    pub relations: usize,
+    pub created: Vec<String>,
+}
+
+/// What an ingest added: chunks not stored before, chunks skipped because one with the
+/// same source and text already was, and the entities the graph did not know.
+#[derive(Debug, Default, PartialEq)]
+pub struct IngestCounts {
+    pub new_chunks: usize,
+    pub duplicate_chunks: usize,
+    pub new_entities: Vec<String>,
+}
+
+/// An entity related to the one `neighbors` was asked about.
+#[derive(Debug, PartialEq)]
+pub struct Neighbor {
+    pub entity: String,
+    /// Summed weight of the relations between the two.
+    pub weight: f32,
+    /// Their relation types as seen from the entity asked about, sorted: `-[type]->` to the
+    /// neighbor, `<-[type]-` from it, and `-[CO_OCCURS]-` either way.
+    pub relations: Vec<String>,
+}
+
+/// An entity `neighbors_within` reached, with the entity one hop nearer it was reached
+/// from and the summed weight of the relations between the two.
+#[derive(Debug, PartialEq)]
+pub struct Reached {
+    pub entity: String,
+    pub via: String,
+    pub weight: f32,
+}
+
+/// One step of a `shortest_path`: (source, relation type, target), typed relations the
+/// way they were extracted and co-occurrence in path order.
+pub type PathStep = (String, String, String);
+
+/// The relations between two entities as one edge of an `EntityGraph`: their summed
+/// weight and the relation that says most about the pair (typed over co-occurrence,
+/// then the heaviest).
+struct Link<'a> {
+    weight: f32,
+    relation: &'a Relation,
+}
+
+/// The entities and relations as a petgraph graph, built on demand by the methods that
+/// walk it.
+struct EntityGraph<'a> {
+    graph: UnGraph<&'a str, Link<'a>>,
+    nodes: HashMap<&'a str, NodeIndex>,
+}
+
+/// What `forget_source` removed: the source's chunks, the entities no remaining chunk (or
+/// import) mentions, and the relations left without a chunk or an entity to stand on.
+#[derive(Debug, Default, PartialEq)]
+pub struct ForgetCounts {
+    pub chunks: usize,
+    pub entities: usize,
+    pub relations: usize,
+}
+
+/// One ingested source as `sources` describes it.
+#[derive(Debug, PartialEq)]
+pub struct SourceStats {
+    pub source: String,
+    pub chunks: usize,
+    /// Words across its chunks, so sentences repeated by a chunk overlap count again.
+    pub words: usize,
+    /// When its most recent chunk was ingested, if any chunk recorded it.
+    pub ingested_at: Option<i64>,
+    /// Up to `TOP_SOURCE_ENTITIES` entities by how many of its chunks mention them.
+    pub top_entities: Vec<String>,
+}
+
+/// How many entities `sources` lists for each source.
+const TOP_SOURCE_ENTITIES: usize = 5;
+
+/// The size and shape of the graph as `stats` reports it, from what is already in memory.
+#[derive(Debug, PartialEq)]
+pub struct GraphStats {
+    pub chunks: usize,
+    /// Words the search index holds across all chunks, stopwords left out.
+    pub indexed_words: usize,
+    /// Distinct words in the search index.
+    pub vocabulary: usize,
+    pub entities: usize,
+    pub entity_types: BTreeMap<String, usize>,
+    pub relations: usize,
+    pub relation_weight: f32,
+    /// Entities in each community, smallest first.
+    pub community_sizes: Vec<usize>,
+    /// Up to `TOP_STATS_ENTITIES` entities with their mentions, as `top_entities` sorts them.
+    pub top_entities: Vec<(String, usize)>,
+}
+
+/// How many entities `stats` lists.
+const TOP_STATS_ENTITIES: usize = 10;
+
+/// What `reingest_with` changed: chunks of the old text it dropped, chunks of the new text
+/// it stored and those the two share, and the entities that appeared or disappeared.
+#[derive(Debug, Default, PartialEq)]
+pub struct ReingestCounts {
+    pub removed_chunks: usize,
+    pub added_chunks: usize,
+    pub unchanged_chunks: usize,
+    pub new_entities: Vec<String>,
+    pub dropped_entities: Vec<String>,
+}
+
+/// Chunks `graph_query` returns when not told otherwise.
+pub const DEFAULT_TOP_K: usize = 3;
+
+/// Most community summaries a query response carries.
+const MAX_QUERY_COMMUNITIES: usize = 2;
+
+/// English function words kept out of the index and never taken for entities, even when a
+/// sentence starts with them.
+const STOPWORDS: &str = "\
+    a about above after again against all also although am an and any are as at be because been \
+    before being below between both but by can could did do does doing down during each either \
+    every few for from further had has have having he her here hers herself him himself his how \
+    however i if in into is it its itself just me meanwhile more moreover most my myself neither \
+    no nor not now of off on once only or other our ours ourselves out over own same she should so \
+    some such than that the their theirs them themselves then there therefore these they this \
+    those though through thus to too under until up very was we were what when where whether which \
+    while who whom why with would yet you your yours yourself yourselves";
+
+static STOPWORD_SET: LazyLock<HashSet<&'static str>> =
+    LazyLock::new(|| STOPWORDS.split_whitespace().collect());
+
+/// Titles that make the name after them a PERSON.
+const HONORIFICS: &[&str] = &[
+    "Mr", "Mrs", "Ms", "Mx", "Miss", "Dr", "Prof", "Sir", "Dame", "Lord", "Lady", "Rev",
+];
+
+/// Last words that make a name an ORG.
+const ORG_SUFFIXES: &[&str] = &["Inc", "Ltd", "Corp", "Corporation", "Co", "Company", "Plc"];
+
+/// Common given names: a run of capitalized words opening with one is taken for a PERSON.
+const FIRST_NAMES: &str = "\
+    adam alan alice amanda amy andrew angela anna anne anthony barbara ben benjamin betty bob \
+    brian carol catherine charles chris christopher daniel david deborah dennis donald dorothy \
+    edward elizabeth emily emma eric frank gary george grace hannah harry helen henry jack james \
+    jane janet jason jennifer jessica joan john jonathan joseph joshua julia karen kate kenneth \
+    kevin laura linda lisa margaret maria mark martha mary matthew michael nancy nicholas olivia \
+    patricia paul peter rachel rebecca richard robert ronald ruth sam samuel sandra sarah scott \
+    sophie stephen steven susan thomas timothy tom victoria william";
+
+static FIRST_NAME_SET: LazyLock<HashSet<&'static str>> =
+    LazyLock::new(|| FIRST_NAMES.split_whitespace().collect());
+
+static NAME_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\b[A-Z][a-z]+(?:\s+[A-Z][a-z]+)*\b").expect("name pattern is valid")
+});
+
+static ACRONYM_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\b[A-Z]{2,}(?:\s+[A-Z]{2,})*\b").expect("acronym pattern is valid")
+});
+
+/// ISO and slashed dates, and a month name with a day, a year or both.
+static DATE_RE: LazyLock<Regex> = LazyLock::new(|| {
+    let month = "(?:January|February|March|April|May|June|July|August|September|October|\
+                 November|December)";
+    let pattern = format!(
+        r"(?x)\b(?:
+            \d{{4}}-\d{{2}}-\d{{2}} | \d{{1,2}}/\d{{1,2}}/\d{{2,4}}
+            | {m}\s+\d{{1,2}}(?:st|nd|rd|th)?(?:,?\s+\d{{4}})?
+            | \d{{1,2}}(?:st|nd|rd|th)?\s+{m}(?:\s+\d{{4}})?
+            | {m}\s+\d{{4}}
+        )\b",
+        m = month
+    );
+    Regex::new(&pattern).expect("date pattern is valid")
+});
+
+/// A whole word that is a number; only those with a separator, decimals, currency or
+/// percent sign count, as bare integers are mostly noise.
+static NUMBER_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^[$€£]?(?:\d{1,3}(?:,\d{3})+|\d+)(?:\.\d+)?%?$").expect("number pattern is valid")
+});
+
+/// Relation types for the verb phrase between two entities, first match wins; passives
+/// come before the active forms they contain. Any other pair of entities CO_OCCURS.
+const RELATION_PATTERNS: &[(&str, &str)] = &[
+    (r"\b(?:acquired|bought|purchased) by\b", "acquired_by"),
+    (r"\b(?:founded|started|established) by\b", "founded_by"),
+    (r"\b(?:owned) by\b", "owned_by"),
+    (r"\b(?:led|headed|run|managed) by\b", "led_by"),
+    (
+        r"\b(?:acquired|acquires|bought|buys|purchased|took over|takes over)\b",
+        "acquired",
+    ),
+    (
+        r"\b(?:works?|worked|working) (?:for|at)\b|\bemployed by\b|\bjoined\b",
+        "works_for",
+    ),
+    (
+        r"\b(?:founded|co-founded|founds|started|established)\b",
+        "founded",
+    ),
+    (
+        r"\b(?:leads|led|heads|headed|runs|ran|manages|managed)\b",
+        "leads",
+    ),
+    (
+        r"\b(?:located|based|headquartered|situated|lives?|lived) in\b",
+        "located_in",
+    ),
+    (r"\bpart of\b|\bbelongs? to\b", "part_of"),
+    (r"\b(?:owns|owned)\b", "owns"),
+    (r"\b(?:met|meets|knows|knew)\b", "met"),
+    (r"\b(?:visited|visits|travell?ed to|went to)\b", "visited"),
+    (r"\b(?:married|marries)\b", "married"),
+    (r"\b(?:wrote|writes|authored)\b", "wrote"),
+];
+
+/// Most words between two entities for the phrase to count as the verb relating them.
+const MAX_RELATION_WORDS: usize = 5;
+
+static RELATION_RES: LazyLock<Vec<(Regex, &'static str)>> = LazyLock::new(|| {
+    RELATION_PATTERNS
+        .iter()
+        .map(|(p, kind)| (Regex::new(p).expect("relation pattern is valid"), *kind))
+        .collect()
+});
+
+/// Most relations listed under each community in a query response.
+const MAX_CONTEXT_RELATIONS: usize = 5;
+
+/// BM25 parameters when none are set: the usual k1 and b.
+pub const DEFAULT_BM25_K1: f32 = 1.2;
+pub const DEFAULT_BM25_B: f32 = 0.75;
+
+/// Character budget of a chunk when an ingest does not give one.
+pub const DEFAULT_CHUNK_SIZE: usize = 500;
+
+/// Community summaries when not configured: the entities they name and the sentences they
+/// quote.
+pub const DEFAULT_SUMMARY_ENTITIES: usize = 3;
+pub const DEFAULT_SUMMARY_SENTENCES: usize = 2;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GraphRagEngine {
     pub data: GraphRagData,
+    /// Chunking for ingests that do not choose their own: at most `chunk_size` characters
+    /// per chunk, each opening with the last `chunk_overlap` sentences of the one before.
+    #[serde(default = "default_chunk_size")]
+    pub chunk_size: usize,
+    #[serde(default)]
+    pub chunk_overlap: usize,
+    /// How queries that do not choose rank chunks, and BM25's term-frequency saturation
+    /// (`bm25_k1`) and length normalization (`bm25_b`).
+    #[serde(default)]
+    pub ranking: Ranking,
+    #[serde(default = "default_bm25_k1")]
+    pub bm25_k1: f32,
+    #[serde(default = "default_bm25_b")]
+    pub bm25_b: f32,
+    /// Lowercase words ignored on top of `STOPWORDS`, by the index and by entity extraction.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub extra_stopwords: BTreeSet<String>,
+    /// How many top entities each community summary names, and at most how many sentences
+    /// it quotes.
+    #[serde(default = "default_summary_entities")]
+    pub summary_entities: usize,
+    #[serde(default = "default_summary_sentences")]
+    pub summary_sentences: usize,
     #[serde(skip)]
     tfidf_index: HashMap<String, HashMap<String, f32>>, // word -> {chunk_id -> score}
+    #[serde(skip)]
+    term_counts: HashMap<String, HashMap<String, usize>>, // word -> {chunk_id -> count}
+    #[serde(skip)]
+    doc_lens: HashMap<String, usize>, // chunk_id -> words
+    #[serde(skip)]
+    avg_doc_len: f32,
+}
+
+fn default_chunk_size() -> usize {
+    DEFAULT_CHUNK_SIZE
+}
+
+fn default_bm25_k1() -> f32 {
+    DEFAULT_BM25_K1
+}
+
+fn default_summary_entities() -> usize {
+    DEFAULT_SUMMARY_ENTITIES
+}
+
+fn default_summary_sentences() -> usize {
+    DEFAULT_SUMMARY_SENTENCES
+}
+
+fn default_bm25_b() -> f32 {
+    DEFAULT_BM25_B
+}
+
+/// How `query` scores chunks: TF-IDF with term frequency relative to chunk length, or
+/// Okapi BM25, which saturates repeated terms and weighs length against the average chunk.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Ranking {
+    #[default]
+    TfIdf,
+    Bm25,
+}
+
+impl Ranking {
+    pub(crate) fn from_str(raw: &str) -> Option<Self> {
+        match raw.to_lowercase().as_str() {
+            "tfidf" => Some(Self::TfIdf),
+            "bm25" => Some(Self::Bm25),
+            _ => None,
+        }
+    }
+}
+
+impl Default for GraphRagEngine {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl GraphRagEngine {
     pub fn new() -> Self {
         Self {
             data: GraphRagData::default(),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            chunk_overlap: 0,
+            ranking: Ranking::TfIdf,
+            bm25_k1: DEFAULT_BM25_K1,
+            bm25_b: DEFAULT_BM25_B,
+            extra_stopwords: BTreeSet::new(),
+            summary_entities: DEFAULT_SUMMARY_ENTITIES,
+            summary_sentences: DEFAULT_SUMMARY_SENTENCES,
             tfidf_index: HashMap::new(),
+            term_counts: HashMap::new(),
+            doc_lens: HashMap::new(),
+            avg_doc_len: 0.0,
         }
     }
 
-    pub fn ingest(&mut self, text: &str, source: &str) {
-        let chunks = self.chunk_text(text, source);
-        let mut new_entities = 0;
-        for chunk in chunks {
+    /// Adds the chunks of `text` not already stored, with their entities and relations;
+    /// a chunk seen before (same source and text) counts for nothing the second time.
+    pub fn ingest(&mut self, text: &str, source: &str) -> IngestCounts {
+        let now = crate::now_millis();
+        self.ingest_with(text, source, self.chunk_size, self.chunk_overlap, now)
+    }
+
+    /// `ingest` with its own chunking, stamping new chunks with `ingested_at` (epoch
+    /// milliseconds); `chunk_size` must be positive.
+    pub fn ingest_with(
+        &mut self,
+        text: &str,
+        source: &str,
+        chunk_size: usize,
+        chunk_overlap: usize,
+        ingested_at: i64,
+    ) -> IngestCounts {
+        let chunks = Self::chunk_text(text, source, chunk_size, chunk_overlap);
+        let counts = self.add_chunks(chunks, ingested_at);
+        if counts.new_chunks > 0 {
+            self.rebuild_index();
+        }
+        if !counts.new_entities.is_empty() || self.data.communities.is_empty() {
+            self.detect_communities();
+        }
+        counts
+    }
+
+    /// Replaces what `source` holds with the chunks of `text`, as `forget_source` then
+    /// `ingest_with` would, rebuilding the search index and communities once. Chunks whose
+    /// text is unchanged are kept as they were, ingest time included.
+    pub fn reingest_with(
+        &mut self,
+        text: &str,
+        source: &str,
+        chunk_size: usize,
+        chunk_overlap: usize,
+        ingested_at: i64,
+    ) -> ReingestCounts {
+        let chunks = Self::chunk_text(text, source, chunk_size, chunk_overlap);
+        let wanted: HashSet<String> = chunks.iter().map(|c| c.id.clone()).collect();
+        let known: HashSet<String> = self.data.entities.keys().cloned().collect();
+        let removed = self.remove_chunks(|c| c.source == source && !wanted.contains(&c.id));
+        let added = self.add_chunks(chunks, ingested_at);
+        if removed.chunks > 0 || added.new_chunks > 0 {
+            self.rebuild_index();
+            self.detect_communities();
+        }
+        let mut new_entities: Vec<String> = self
+            .data
+            .entities
+            .keys()
+            .filter(|name| !known.contains(*name))
+            .cloned()
+            .collect();
+        new_entities.sort();
+        let mut dropped_entities: Vec<String> = known
+            .into_iter()
+            .filter(|name| !self.data.entities.contains_key(name))
+            .collect();
+        dropped_entities.sort();
+        ReingestCounts {
+            removed_chunks: removed.chunks,
+            added_chunks: added.new_chunks,
+            unchanged_chunks: wanted.len() - added.new_chunks,
+            new_entities,
+            dropped_entities,
+        }
+    }
+
+    /// Stores the chunks not already stored with their entities and relations, without
+    /// rebuilding the index or communities.
+    fn add_chunks(&mut self, chunks: Vec<TextChunk>, ingested_at: i64) -> IngestCounts {
+        let mut counts = IngestCounts::default();
+        for mut chunk in chunks {
+            if self.data.chunks.contains_key(&chunk.id) {
+                counts.duplicate_chunks += 1;
+                continue;
+            }
+            counts.new_chunks += 1;
             let extracted_entities = self.extract_entities(&chunk.text);
-            let extracted_relations = self.extract_relations(&chunk.text, &extracted_entities);
+            let mut extracted_relations = self.extract_relations(&chunk.text, &extracted_entities);
+            let mut names: Vec<String> =
+                extracted_entities.iter().map(|e| e.name.clone()).collect();
+            names.sort();
+            chunk.entities = Some(names);
+            chunk.ingested_at = Some(ingested_at);
+            for rel in &mut extracted_relations {
+                rel.chunk = Some(chunk.id.clone());
+            }
 
             for ent in extracted_entities {
                 self.data.entities.entry(ent.name.clone())
                     .and_modify(|e| e.mentions += 1)
                     .or_insert_with(|| {
-                        new_entities += 1;
+                        counts.new_entities.push(ent.name.clone());
                         ent
                     });
             }
@@ -78,136 +550,455 @@ impl GraphRagEngine {
 
             self.data.chunks.insert(chunk.id.clone(), chunk);
         }
-        self.rebuild_tfidf();
-        if new_entities > 0 || self.data.communities.is_empty() {
-            self.detect_communities();
+        counts
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.chunks.is_empty()
+            && self.data.entities.is_empty()
+            && self.data.relations.is_empty()
+            && self.data.communities.is_empty()
+    }
+
+    /// Folds another graph into this one: chunks are keyed by id and entity mentions add up.
+    /// Chunks this graph already holds were counted when they were added, so their mentions
+    /// and relations are left out, as `add_chunks` leaves out a duplicate chunk.
+    pub fn merge(&mut self, mut other: GraphRagData) {
+        let known: HashSet<String> = other
+            .chunks
+            .keys()
+            .filter(|id| self.data.chunks.contains_key(*id))
+            .cloned()
+            .collect();
+        for id in &known {
+            for name in self.chunk_entities(&other.chunks[id]) {
+                if let Some(ent) = other.entities.get_mut(&name) {
+                    ent.mentions = ent.mentions.saturating_sub(1);
+                }
+            }
         }
+        other.chunks.retain(|id, _| !known.contains(id));
+        other
+            .relations
+            .retain(|rel| rel.chunk.as_ref().is_none_or(|id| !known.contains(id)));
+        self.data.chunks.extend(other.chunks);
+        for (name, ent) in other.entities {
+            if ent.mentions == 0 {
+                continue;
+            }
+            self.data
+                .entities
+                .entry(name)
+                .and_modify(|e| e.mentions += ent.mentions)
+                .or_insert(ent);
+        }
+        self.data.relations.extend(other.relations);
+        self.rebuild_index();
+        self.detect_communities();
     }
 
-    fn chunk_text(&self, text: &str, source: &str) -> Vec<TextChunk> {
-        let mut chunks = Vec::new();
-        let sentences: Vec<&str> = text.split_inclusive(&['.', '!', '?'][..]).collect();
+    /// The graph as a `GraphDump`: chunks by id, entities by name, relations as stored.
+    pub fn dump(&self) -> GraphDump {
+        let mut chunks: Vec<TextChunk> = self.data.chunks.values().cloned().collect();
+        chunks.sort_by(|a, b| a.id.cmp(&b.id));
+        let mut entities: Vec<Entity> = self.data.entities.values().cloned().collect();
+        entities.sort_by(|a, b| a.name.cmp(&b.name));
+        GraphDump {
+            chunks,
+            entities,
+            relations: self.data.relations.clone(),
+        }
+    }
 
-        let chunk_size = 500;
-        let mut current_chunk = String::new();
-        let mut count = 0;
+    /// Merges graph data built elsewhere, as `merge` does: chunks replace those with the
+    /// same id and entity mentions add up. Relations are appended, or with
+    /// `merge_relations` add their weight to a stored relation with the same source, target
+    /// and type. A relation naming an entity found in neither graph is an error unless
+    /// `auto_create_entities`, which adds it as a CONCEPT. Nothing changes on error.
+    pub fn import(
+        &mut self,
+        dump: GraphDump,
+        auto_create_entities: bool,
+        merge_relations: bool,
+    ) -> Result<ImportSummary, String> {
+        if let Some(chunk) = dump.chunks.iter().find(|c| c.id.is_empty()) {
+            return Err(format!("chunk from '{}' has an empty id", chunk.source));
+        }
+        if dump.entities.iter().any(|e| e.name.trim().is_empty()) {
+            return Err("entity names cannot be empty".to_string());
+        }
+        let mut known: HashSet<&str> = self.data.entities.keys().map(String::as_str).collect();
+        known.extend(dump.entities.iter().map(|e| e.name.as_str()));
+        let mut created = Vec::new();
+        for rel in &dump.relations {
+            for end in [&rel.source, &rel.target] {
+                if known.contains(end.as_str()) || created.contains(end) {
+                    continue;
+                }
+                if !auto_create_entities {
+                    return Err(format!(
+                        "relation '{}' -> '{}' names unknown entity '{}'; pass \
+                         auto_create_entities=True to add it",
+                        rel.source, rel.target, end
+                    ));
+                }
+                created.push(end.clone());
+            }
+        }
 
-        for sent in sentences {
-            if current_chunk.len() + sent.len() > chunk_size && !current_chunk.is_empty() {
-                let id = format!("{:x}", Sha256::digest(format!("{}_{}", source, count).as_bytes()))[..12].to_string();
-                chunks.push(TextChunk {
-                    id,
-                    text: current_chunk.clone(),
-                    source: source.to_string(),
-                });
-                current_chunk.clear();
-                count += 1;
+        let summary = ImportSummary {
+            chunks: dump.chunks.len(),
+            entities: dump.entities.len(),
+            relations: dump.relations.len(),
+            created,
+        };
+        for mut chunk in dump.chunks {
+            // Imported chunks brought their entities along rather than adding mentions.
+            chunk.entities.get_or_insert_with(Vec::new);
+            self.data.chunks.insert(chunk.id.clone(), chunk);
+        }
+        let made = summary.created.iter().map(|name| Entity {
+            name: name.clone(),
+            entity_type: concept(),
+            mentions: 1,
+        });
+        for ent in dump.entities.into_iter().chain(made) {
+            self.data
+                .entities
+                .entry(ent.name.clone())
+                .and_modify(|e| e.mentions += ent.mentions)
+                .or_insert(ent);
+        }
+        let key = |r: &Relation| (r.source.clone(), r.target.clone(), r.relation_type.clone());
+        let mut slots: HashMap<_, usize> = match merge_relations {
+            true => self
+                .data
+                .relations
+                .iter()
+                .enumerate()
+                .map(|(i, r)| (key(r), i))
+                .collect(),
+            false => HashMap::new(),
+        };
+        for rel in dump.relations {
+            if !merge_relations {
+                self.data.relations.push(rel);
+                continue;
+            }
+            match slots.get(&key(&rel)) {
+                Some(&i) => self.data.relations[i].weight += rel.weight,
+                None => {
+                    slots.insert(key(&rel), self.data.relations.len());
+                    self.data.relations.push(rel);
+                }
             }
-            current_chunk.push_str(sent);
         }
+        self.rebuild_index();
+        self.detect_communities();
+        Ok(summary)
+    }
 
-        if !current_chunk.is_empty() {
-            let id = format!(
-                "{:x}",
-                Sha256::digest(format!("{}_{}", source, count).as_bytes())
-            )[..12]
-                .to_string();
-            chunks.push(TextChunk {
-                id,
-                text: current_chunk,
-                source: source.to_string(),
-            });
+    /// Splits `text` into chunks of whole sentences, at most `chunk_size` characters each
+    /// unless the overlap alone leaves no room. A sentence longer than `chunk_size` is cut
+    /// into pieces first, at whitespace where it can be. Each chunk after the first opens
+    /// with the last `chunk_overlap` sentences of the one before, fewer when they would
+    /// not fit beside the next sentence.
+    fn chunk_text(
+        text: &str,
+        source: &str,
+        chunk_size: usize,
+        chunk_overlap: usize,
+    ) -> Vec<TextChunk> {
+        let pieces: Vec<&str> = text
+            .split_inclusive(&['.', '!', '?'][..])
+            .flat_map(|sentence| split_long(sentence, chunk_size))
+            .collect();
+        let width = |piece: &&str| piece.chars().count();
+
+        let mut texts = Vec::new();
+        let mut current: Vec<&str> = Vec::new();
+        // Sentences in `current` that the previous chunk did not already hold.
+        let mut fresh = 0;
+        for piece in pieces {
+            let used: usize = current.iter().map(width).sum();
+            if fresh > 0 && used + width(&piece) > chunk_size {
+                texts.push(current.concat());
+                let keep = current.len().saturating_sub(chunk_overlap);
+                current.drain(..keep);
+                while current.iter().map(width).sum::<usize>() + width(&piece) > chunk_size
+                    && !current.is_empty()
+                {
+                    current.remove(0);
+                }
+                fresh = 0;
+            }
+            current.push(piece);
+            fresh += 1;
         }
-        chunks
+        if fresh > 0 {
+            texts.push(current.concat());
+        }
+
+        texts
+            .into_iter()
+            .map(|text| TextChunk {
+                id: chunk_id(source, &text),
+                text,
+                source: source.to_string(),
+                entities: None,
+                ingested_at: None,
+            })
+            .collect()
+    }
+
+    /// Whether `word` is a stopword, built in or added, in any case.
+    fn is_stopword(&self, word: &str) -> bool {
+        let word = word.to_lowercase();
+        STOPWORD_SET.contains(word.as_str()) || self.extra_stopwords.contains(&word)
+    }
+
+    /// The words of `text` as the index keys them: lowercased, with leading and trailing
+    /// punctuation trimmed, and without stopwords.
+    fn tokens(&self, text: &str) -> Vec<String> {
+        text.split_whitespace()
+            .map(|w| {
+                w.trim_matches(|c: char| !c.is_alphanumeric())
+                    .to_lowercase()
+            })
+            .filter(|w| !w.is_empty() && !self.is_stopword(w))
+            .collect()
     }
 
+    /// Dates, numbers and acronyms (as DATE, NUMBER and ORG), then runs of capitalized
+    /// words, less any stopwords they open or close with. The word that starts a sentence
+    /// only counts when the chunk also capitalizes it mid-sentence, the graph already knows
+    /// it or it is a given name opening a longer run, so "Yesterday" is not mistaken for a
+    /// name. Runs after a title or opening with a given name are PERSON, those ending in a
+    /// company suffix ORG, and the rest CONCEPT.
     fn extract_entities(&self, text: &str) -> Vec<Entity> {
         let mut entities = HashMap::new();
-        let Ok(re) = Regex::new(r"\b[A-Z][a-z]+(?:\s+[A-Z][a-z]+)*\b") else {
-            return Vec::new();
+        let mut add = |name: &str, entity_type: &str| {
+            entities.entry(name.to_string()).or_insert_with(|| Entity {
+                name: name.to_string(),
+                entity_type: entity_type.to_string(),
+                mentions: 1,
+            });
         };
-        for mat in re.find_iter(text) {
-            let name = mat.as_str().to_string();
+        let mut dates = Vec::new();
+        for mat in DATE_RE.find_iter(text) {
+            add(mat.as_str(), "DATE");
+            dates.push(mat.range());
+        }
+        for word in text.split_whitespace() {
+            let word = word
+                .trim_start_matches(|c: char| "\"'([{".contains(c))
+                .trim_end_matches(|c: char| "\"')]},;:.!?".contains(c));
+            if NUMBER_RE.is_match(word) && word.contains(['.', ',', '%', '$', '€', '£']) {
+                add(word, "NUMBER");
+            }
+        }
+        for mat in ACRONYM_RE.find_iter(text) {
+            if mat.len() > 2 && !self.is_stopword(mat.as_str()) {
+                add(mat.as_str(), "ORG");
+            }
+        }
+
+        let mut candidates = Vec::new();
+        for mat in NAME_RE.find_iter(text) {
+            if dates
+                .iter()
+                .any(|d| d.start < mat.end() && mat.start() < d.end)
+            {
+                continue;
+            }
+            let mut name = mat.as_str();
+            let before = text[..mat.start()].trim_end();
+            let title = before.split_whitespace().last().unwrap_or_default();
+            let mut person = HONORIFICS.contains(&title.trim_end_matches('.'));
+            let mut initial = !person && starts_sentence(before);
+            if let Some(title) = name
+                .split_whitespace()
+                .next()
+                .filter(|w| HONORIFICS.contains(w))
+            {
+                name = name[title.len()..].trim_start();
+                person = true;
+                initial = false;
+            }
+            while let Some(first) = name
+                .split_whitespace()
+                .next()
+                .filter(|w| self.is_stopword(w))
+            {
+                name = name[first.len()..].trim_start();
+                initial = false;
+            }
+            while let Some(last) = name
+                .split_whitespace()
+                .last()
+                .filter(|w| self.is_stopword(w))
+            {
+                name = name[..name.len() - last.len()].trim_end();
+            }
+            candidates.push((name, initial, person));
+        }
+        let mid_sentence: HashSet<&str> = candidates
+            .iter()
+            .flat_map(|(name, initial, _)| name.split_whitespace().skip(*initial as usize))
+            .collect();
+        let known = |name: &str| self.data.entities.contains_key(name);
+        for (mut name, initial, person) in candidates {
+            let first = name.split_whitespace().next().unwrap_or_default();
+            let given = name.len() > first.len() && is_first_name(first);
+            if initial && !given && !known(name) && !known(first) && !mid_sentence.contains(first) {
+                name = name[first.len()..].trim_start();
+            }
             if name.len() > 2 {
-                entities.entry(name.clone()).or_insert(Entity {
-                    name,
-                    entity_type: "CONCEPT".to_string(),
-                    mentions: 1,
-                });
+                add(name, name_type(name, person));
             }
         }
         entities.into_values().collect()
     }
 
+    /// One relation for each pair of `entities` in `text`, in the order they first appear.
+    /// When one directly follows the other in a sentence with a verb phrase from
+    /// `RELATION_PATTERNS` between them, the pair takes that phrase's type, pointing from the
+    /// first to the second; otherwise it CO_OCCURS.
     fn extract_relations(&self, text: &str, entities: &[Entity]) -> Vec<Relation> {
-        let mut relations = Vec::new();
-        for i in 0..entities.len() {
-            for j in i + 1..entities.len() {
-                let e1 = &entities[i];
-                let e2 = &entities[j];
-                if text.contains(&e1.name) && text.contains(&e2.name) {
-                    relations.push(Relation {
-                        source: e1.name.clone(),
-                        target: e2.name.clone(),
-                        relation_type: "CO_OCCURS".to_string(),
-                        weight: 1.0,
-                    });
+        let mut mentions: Vec<(usize, usize, &str)> = entities
+            .iter()
+            .flat_map(|e| {
+                text.match_indices(e.name.as_str())
+                    .map(|(at, name)| (at, at + name.len(), name))
+            })
+            .collect();
+        // Earliest first, and the longer name where two start together ("Jane Doe" over
+        // "Jane"); names inside another mention are dropped.
+        mentions.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)));
+        let mut end = 0;
+        mentions.retain(|&(start, stop, _)| {
+            let outside = start >= end;
+            end = end.max(stop);
+            outside
+        });
+
+        let mut typed: HashMap<(&str, &str), &str> = HashMap::new();
+        for pair in mentions.windows(2) {
+            let ((_, stop, first), (start, _, second)) = (pair[0], pair[1]);
+            if first != second {
+                if let Some(kind) = verb_relation(&text[stop..start]) {
+                    typed.entry((first, second)).or_insert(kind);
                 }
             }
         }
+
+        let mut order: Vec<&str> = Vec::new();
+        for &(_, _, name) in &mentions {
+            if !order.contains(&name) {
+                order.push(name);
+            }
+        }
+        let mut relations = Vec::new();
+        for (i, &a) in order.iter().enumerate() {
+            for &b in &order[i + 1..] {
+                let (source, target, kind) = match (typed.get(&(a, b)), typed.get(&(b, a))) {
+                    (Some(kind), _) => (a, b, *kind),
+                    (None, Some(kind)) => (b, a, *kind),
+                    (None, None) => (a, b, "CO_OCCURS"),
+                };
+                relations.push(Relation {
+                    source: source.to_string(),
+                    target: target.to_string(),
+                    relation_type: kind.to_string(),
+                    weight: 1.0,
+                    chunk: None,
+                });
+            }
+        }
         relations
     }
 
-    pub fn rebuild_tfidf(&mut self) {
+    /// Rebuilds the search index: TF-IDF scores, and the term counts and chunk lengths BM25
+    /// scores from at query time. `entity_chunks` is rebuilt along with it.
+    pub fn rebuild_index(&mut self) {
+        let mut entity_chunks: HashMap<String, HashSet<String>> = HashMap::new();
+        for chunk in self.data.chunks.values() {
+            for name in self.chunk_entities(chunk) {
+                entity_chunks
+                    .entry(name)
+                    .or_default()
+                    .insert(chunk.id.clone());
+            }
+        }
+        self.data.entity_chunks = entity_chunks;
+
         let mut doc_counts: HashMap<String, usize> = HashMap::new();
         let num_docs = self.data.chunks.len();
-        if num_docs == 0 { return; }
+        self.tfidf_index.clear();
+        self.term_counts.clear();
+        self.doc_lens.clear();
+        self.avg_doc_len = 0.0;
+        if num_docs == 0 {
+            return;
+        }
 
-        let mut chunk_lowered = HashMap::new();
+        let mut chunk_tokens = HashMap::new();
 
         for (cid, chunk) in &self.data.chunks {
-            let lower = chunk.text.to_lowercase();
-            let words: HashSet<_> = lower.split_whitespace().collect();
-            for word in words {
-                *doc_counts.entry(word.to_string()).or_insert(0) += 1;
+            let words = self.tokens(&chunk.text);
+            for word in words.iter().collect::<HashSet<_>>() {
+                *doc_counts.entry(word.clone()).or_insert(0) += 1;
             }
-            chunk_lowered.insert(cid.clone(), lower);
+            chunk_tokens.insert(cid.clone(), words);
         }
 
-        self.tfidf_index.clear();
-        for (cid, lower) in chunk_lowered {
-            let words: Vec<_> = lower.split_whitespace().collect();
+        for (cid, words) in chunk_tokens {
             let mut word_counts = HashMap::new();
             for word in &words {
-                *word_counts.entry(*word).or_insert(0) += 1;
+                *word_counts.entry(word.as_str()).or_insert(0) += 1;
             }
+            self.doc_lens.insert(cid.clone(), words.len());
 
             for (word, count) in word_counts {
                 let tf = count as f32 / words.len() as f32;
                 let idf = ((num_docs as f32) / (*doc_counts.get(word).unwrap_or(&1) as f32)).ln();
                 self.tfidf_index
                     .entry(word.to_string())
-                    .or_insert_with(HashMap::new)
+                    .or_default()
                     .insert(cid.clone(), tf * idf);
+                self.term_counts
+                    .entry(word.to_string())
+                    .or_default()
+                    .insert(cid.clone(), count);
             }
         }
+        self.avg_doc_len = self.doc_lens.values().sum::<usize>() as f32 / num_docs as f32;
     }
 
-    pub fn detect_communities(&mut self) {
-        let mut graph = UnGraph::<String, f32>::new_undirected();
-        let mut nodes = HashMap::new();
-
-        for ent in self.data.entities.keys() {
-            let idx = graph.add_node(ent.clone());
-            nodes.insert(ent.clone(), idx);
-        }
-
-        for rel in &self.data.relations {
-            if let (Some(&u), Some(&v)) = (nodes.get(&rel.source), nodes.get(&rel.target)) {
-                graph.add_edge(u, v, rel.weight);
-            }
-        }
+    /// Okapi BM25 score of `word` for each chunk holding it.
+    fn bm25_scores(&self, word: &str) -> HashMap<&String, f32> {
+        let Some(counts) = self.term_counts.get(word) else {
+            return HashMap::new();
+        };
+        let (k1, b) = (self.bm25_k1, self.bm25_b);
+        let num_docs = self.doc_lens.len() as f32;
+        let df = counts.len() as f32;
+        let idf = ((num_docs - df + 0.5) / (df + 0.5) + 1.0).ln();
+        // Empty chunks index nothing, so the average is positive whenever a word matched.
+        let avg = self.avg_doc_len.max(1.0);
+        counts
+            .iter()
+            .map(|(cid, count)| {
+                let tf = *count as f32;
+                let len = self.doc_lens.get(cid).copied().unwrap_or(0) as f32;
+                let norm = k1 * (1.0 - b + b * len / avg);
+                (cid, idf * tf * (k1 + 1.0) / (tf + norm))
+            })
+            .collect()
+    }
 
+    pub fn detect_communities(&mut self) {
+        let EntityGraph { graph, .. } = self.entity_graph();
         let mut partition = Vec::new();
         let mut visited = HashSet::new();
         for node in graph.node_indices() {
@@ -218,7 +1009,7 @@ impl GraphRagEngine {
             let mut stack = vec![node];
             while let Some(n) = stack.pop() {
                 if visited.insert(n) {
-                    component.push(graph[n].clone());
+                    component.push(graph[n].to_string());
                     for neighbor in graph.neighbors(n) {
                         stack.push(neighbor);
                     }
@@ -230,48 +1021,151 @@ impl GraphRagEngine {
         self.data.communities = partition
             .into_iter()
             .enumerate()
-            .map(|(i, entities)| {
-                let summary = format!(
-                    "Community of {} entities including {}.",
-                    entities.len(),
-                    entities
+            .map(|(i, entities)| Community {
+                id: i,
+                entities,
+                summary: String::new(),
+                top_entities: Vec::new(),
+            })
+            .collect();
+        self.summarize_communities();
+    }
+
+    /// Ranks each community's entities by weighted degree (the summed weight of their
+    /// relations, then mentions, then name) and keeps the top `summary_entities`. Its
+    /// summary names them, then quotes up to `summary_sentences` sentences mentioning the
+    /// most of them (two at least, when there are two), earliest ingested first.
+    pub fn summarize_communities(&mut self) {
+        let mut degree: HashMap<&str, f32> = HashMap::new();
+        for rel in &self.data.relations {
+            if rel.source != rel.target {
+                *degree.entry(&rel.source).or_insert(0.0) += rel.weight;
+                *degree.entry(&rel.target).or_insert(0.0) += rel.weight;
+            }
+        }
+        let weight = |name: &str| degree.get(name).copied().unwrap_or(0.0);
+        let mentions = |name: &str| self.data.entities.get(name).map_or(0, |e| e.mentions);
+        let tops: Vec<Vec<String>> = self
+            .data
+            .communities
+            .iter()
+            .map(|c| {
+                let mut ranked: Vec<&String> = c.entities.iter().collect();
+                ranked.sort_by(|a, b| {
+                    weight(b)
+                        .partial_cmp(&weight(a))
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                        .then_with(|| mentions(b).cmp(&mentions(a)))
+                        .then_with(|| a.cmp(b))
+                });
+                ranked
+                    .into_iter()
+                    .take(self.summary_entities)
+                    .cloned()
+                    .collect()
+            })
+            .collect();
+
+        // Sentences naming top entities, by community: how many they name, and the text.
+        let owner: HashMap<&str, usize> = tops
+            .iter()
+            .enumerate()
+            .flat_map(|(i, top)| top.iter().map(move |name| (name.as_str(), i)))
+            .collect();
+        let mut quotes: Vec<Vec<(usize, &str)>> = vec![Vec::new(); tops.len()];
+        let mut chunks: Vec<&TextChunk> = self.data.chunks.values().collect();
+        chunks.sort_by(|a, b| (a.ingested_at, &a.id).cmp(&(b.ingested_at, &b.id)));
+        for chunk in chunks {
+            let named: BTreeSet<usize> = self
+                .chunk_entities(chunk)
+                .iter()
+                .filter_map(|name| owner.get(name.as_str()).copied())
+                .collect();
+            if named.is_empty() {
+                continue;
+            }
+            for sentence in chunk.text.split_inclusive(['.', '!', '?']).map(str::trim) {
+                for &i in &named {
+                    let count = tops[i]
                         .iter()
-                        .take(3)
-                        .cloned()
-                        .collect::<Vec<_>>()
-                        .join(", ")
-                );
-                Community {
-                    id: i,
-                    entities,
-                    summary,
+                        .filter(|n| sentence.contains(n.as_str()))
+                        .count();
+                    let seen = quotes[i].iter().any(|(_, s)| *s == sentence);
+                    if count >= tops[i].len().min(2) && !seen {
+                        quotes[i].push((count, sentence));
+                    }
                 }
+            }
+        }
+
+        let summaries: Vec<Vec<&str>> = quotes
+            .into_iter()
+            .map(|mut quotes| {
+                // Stable, so equally good sentences stay in ingest order.
+                quotes.sort_by_key(|(count, _)| std::cmp::Reverse(*count));
+                quotes
+                    .into_iter()
+                    .take(self.summary_sentences)
+                    .map(|(_, s)| s)
+                    .collect()
             })
             .collect();
+        let communities = self.data.communities.iter_mut().zip(tops);
+        for ((community, top), quoted) in communities.zip(summaries) {
+            community.summary = summary_text(&top, community.entities.len(), &quoted);
+            community.top_entities = top;
+        }
     }
 
-    pub fn query(&self, query: &str) -> String {
+    /// The `top_k` chunks (all of them for 0) scoring at least `min_score` for `query` under
+    /// `ranking`, best first, followed by the context of up to `MAX_QUERY_COMMUNITIES`
+    /// communities with an entity the query names. Unless `entity_types` is empty, only
+    /// chunks mentioning an entity of one of those types are returned.
+    pub fn query(
+        &self,
+        query: &str,
+        top_k: usize,
+        min_score: f32,
+        ranking: Ranking,
+        entity_types: &[String],
+    ) -> String {
         let lower_query = query.to_lowercase();
-        let query_words: Vec<_> = lower_query.split_whitespace().collect();
+        let query_words = self.tokens(query);
         let mut scores = HashMap::new();
 
-        for word in query_words {
-            if let Some(chunk_scores) = self.tfidf_index.get(word) {
-                for (cid, score) in chunk_scores {
-                    *scores.entry(cid).or_insert(0.0) += score;
-                }
+        for word in &query_words {
+            let chunk_scores: Vec<(&String, f32)> = match ranking {
+                Ranking::TfIdf => match self.tfidf_index.get(word) {
+                    Some(chunk_scores) => chunk_scores.iter().map(|(c, s)| (c, *s)).collect(),
+                    None => Vec::new(),
+                },
+                Ranking::Bm25 => self.bm25_scores(word).into_iter().collect(),
+            };
+            for (cid, score) in chunk_scores {
+                *scores.entry(cid).or_insert(0.0) += score;
             }
         }
 
-        let mut sorted_chunks: Vec<_> = scores.into_iter().collect();
-        sorted_chunks.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        let mut sorted_chunks: Vec<_> = scores
+            .into_iter()
+            .filter(|(_, score)| *score >= min_score)
+            .filter(|(cid, _)| entity_types.is_empty() || self.mentions_type(cid, entity_types))
+            .collect();
+        sorted_chunks.sort_by(|a, b| {
+            b.1.partial_cmp(&a.1)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| a.0.cmp(b.0))
+        });
 
         if sorted_chunks.is_empty() {
             return "No relevant information found.".to_string();
         }
+        if top_k > 0 {
+            sorted_chunks.truncate(top_k);
+        }
 
-        let mut response = format!("--- GraphRAG Results ---\n");
-        for (cid, _) in sorted_chunks.iter().take(3) {
+        let mut response = "--- GraphRAG Results ---\n".to_string();
+        for (cid, _) in &sorted_chunks {
             if let Some(chunk) = self.data.chunks.get(*cid) {
                 response.push_str(&format!(
                     "\n[Chunk ID: {} | Source: {}]\n{}\n",
@@ -280,37 +1174,1301 @@ impl GraphRagEngine {
             }
         }
 
-        for comm in &self.data.communities {
-            if comm
-                .entities
+        let named = self.data.communities.iter().filter(|comm| {
+            comm.entities
                 .iter()
-                .any(|e| query.to_lowercase().contains(&e.to_lowercase()))
-            {
-                response.push_str(&format!("\n[Community Context: {}]\n", comm.summary));
+                .any(|e| lower_query.contains(&e.to_lowercase()))
+        });
+        for comm in named.take(MAX_QUERY_COMMUNITIES) {
+            response.push_str(&format!("\n[Community Context: {}]\n", comm.summary));
+            for line in self.context_relations(comm, &lower_query) {
+                response.push_str(&line);
+                response.push('\n');
             }
         }
 
         response
     }
+
+    /// Up to `MAX_CONTEXT_RELATIONS` relations of `community` touching an entity the query
+    /// names, as `Source -[type]-> Target` lines: typed ones before CO_OCCURS, then by
+    /// summed weight.
+    fn context_relations(&self, community: &Community, lower_query: &str) -> Vec<String> {
+        let members: HashSet<&str> = community.entities.iter().map(String::as_str).collect();
+        let named = |e: &str| lower_query.contains(&e.to_lowercase());
+        let mut weights: HashMap<(&str, &str, &str), f32> = HashMap::new();
+        for rel in &self.data.relations {
+            if members.contains(rel.source.as_str()) && (named(&rel.source) || named(&rel.target)) {
+                let key = (
+                    rel.source.as_str(),
+                    rel.target.as_str(),
+                    rel.relation_type.as_str(),
+                );
+                *weights.entry(key).or_insert(0.0) += rel.weight;
+            }
+        }
+        let mut relations: Vec<_> = weights.into_iter().collect();
+        relations.sort_by(|(a, wa), (b, wb)| {
+            (a.2 == "CO_OCCURS")
+                .cmp(&(b.2 == "CO_OCCURS"))
+                .then(wb.partial_cmp(wa).unwrap_or(std::cmp::Ordering::Equal))
+                .then(a.cmp(b))
+        });
+        relations
+            .into_iter()
+            .take(MAX_CONTEXT_RELATIONS)
+            .map(|((source, target, kind), _)| format!("{} -[{}]-> {}", source, kind, target))
+            .collect()
+    }
+
+    /// Whether chunk `id` mentions an entity whose type is one of `entity_types`.
+    fn mentions_type(&self, id: &str, entity_types: &[String]) -> bool {
+        self.data.chunks.get(id).is_some_and(|chunk| {
+            self.chunk_entities(chunk).iter().any(|name| {
+                self.data
+                    .entities
+                    .get(name)
+                    .is_some_and(|e| entity_types.contains(&e.entity_type))
+            })
+        })
+    }
+
+    /// Every ingested source with its chunks, words, latest ingest and top entities, sorted
+    /// by source. Chunks stored before entities were recorded have theirs extracted again.
+    pub fn sources(&self) -> Vec<SourceStats> {
+        let mut by_source: HashMap<&str, Vec<&TextChunk>> = HashMap::new();
+        for chunk in self.data.chunks.values() {
+            by_source
+                .entry(chunk.source.as_str())
+                .or_default()
+                .push(chunk);
+        }
+        let mut sources: Vec<SourceStats> = by_source
+            .into_iter()
+            .map(|(source, chunks)| {
+                let mut counts: HashMap<String, usize> = HashMap::new();
+                for chunk in &chunks {
+                    for name in self.chunk_entities(chunk) {
+                        *counts.entry(name).or_insert(0) += 1;
+                    }
+                }
+                let mut top: Vec<(String, usize)> = counts.into_iter().collect();
+                top.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+                SourceStats {
+                    source: source.to_string(),
+                    chunks: chunks.len(),
+                    words: chunks
+                        .iter()
+                        .map(|c| c.text.split_whitespace().count())
+                        .sum(),
+                    ingested_at: chunks.iter().filter_map(|c| c.ingested_at).max(),
+                    top_entities: top
+                        .into_iter()
+                        .take(TOP_SOURCE_ENTITIES)
+                        .map(|(name, _)| name)
+                        .collect(),
+                }
+            })
+            .collect();
+        sources.sort_by(|a, b| a.source.cmp(&b.source));
+        sources
+    }
+
+    /// Counts for monitoring what ingests produce, read off the data and the search index
+    /// as they stand.
+    pub fn stats(&self) -> GraphStats {
+        let mut entity_types = BTreeMap::new();
+        for entity in self.data.entities.values() {
+            *entity_types.entry(entity.entity_type.clone()).or_insert(0) += 1;
+        }
+        let mut community_sizes: Vec<usize> = self
+            .data
+            .communities
+            .iter()
+            .map(|c| c.entities.len())
+            .collect();
+        community_sizes.sort_unstable();
+        GraphStats {
+            chunks: self.data.chunks.len(),
+            indexed_words: self.doc_lens.values().sum(),
+            vocabulary: self.tfidf_index.len(),
+            entities: self.data.entities.len(),
+            entity_types,
+            relations: self.data.relations.len(),
+            relation_weight: self
+                .data
+                .relations
+                .iter()
+                .fold(0.0, |sum, r| sum + r.weight),
+            community_sizes,
+            top_entities: self
+                .top_entities(|_| true)
+                .into_iter()
+                .take(TOP_STATS_ENTITIES)
+                .map(|e| (e.name.clone(), e.mentions))
+                .collect(),
+        }
+    }
+
+    /// Entities by mentions, most first (ties by name), keeping those `keep` accepts.
+    pub fn top_entities(&self, keep: impl Fn(&str) -> bool) -> Vec<&Entity> {
+        let mut entities: Vec<&Entity> = self
+            .data
+            .entities
+            .values()
+            .filter(|e| keep(&e.name))
+            .collect();
+        entities.sort_by(|a, b| {
+            b.mentions
+                .cmp(&a.mentions)
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        entities
+    }
+
+    /// Sets the type of the entity called `name`; false when there is none.
+    pub fn set_entity_type(&mut self, name: &str, entity_type: &str) -> bool {
+        match self.data.entities.get_mut(name) {
+            Some(entity) => {
+                entity.entity_type = entity_type.to_string();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The entities `chunk` mentions, extracted again when it predates recording them.
+    fn chunk_entities(&self, chunk: &TextChunk) -> Vec<String> {
+        match &chunk.entities {
+            Some(names) => names.clone(),
+            None => self
+                .extract_entities(&chunk.text)
+                .into_iter()
+                .map(|e| e.name)
+                .collect(),
+        }
+    }
+
+    /// Entities whose name, or a word in it, starts with `pattern`, or that match it as a
+    /// LIKE pattern when it holds `%` or `_`, ignoring case. By mentions as `top_entities`
+    /// sorts them, at most `limit` (all for 0).
+    pub fn search_entities(&self, pattern: &str, limit: usize) -> Vec<&Entity> {
+        let pattern = pattern.trim().to_lowercase();
+        let like = pattern.contains(['%', '_']);
+        let mut found = self.top_entities(|name| {
+            let name = name.to_lowercase();
+            match like {
+                true => crate::sql::like(&pattern, &name),
+                false => {
+                    name.split_whitespace().any(|w| w.starts_with(&pattern))
+                        || name.starts_with(&pattern)
+                }
+            }
+        });
+        if limit > 0 {
+            found.truncate(limit);
+        }
+        found
+    }
+
+    /// The ids of the chunks mentioning entity `name`, sorted.
+    pub fn entity_chunk_ids(&self, name: &str) -> Vec<String> {
+        let mut ids: Vec<String> = self
+            .data
+            .entity_chunks
+            .get(name)
+            .map(|ids| ids.iter().cloned().collect())
+            .unwrap_or_default();
+        ids.sort();
+        ids
+    }
+
+    /// The stored name of `entity`, matched without regard to case.
+    pub fn entity_name(&self, entity: &str) -> Option<&str> {
+        match self.data.entities.get_key_value(entity) {
+            Some((name, _)) => Some(name),
+            None => self
+                .data
+                .entities
+                .keys()
+                .find(|name| name.eq_ignore_ascii_case(entity))
+                .map(String::as_str),
+        }
+    }
+
+    /// Entities related to `entity` with the summed weight and the types of their
+    /// relations, heaviest first (ties by name). `None` if the graph has no such entity.
+    pub fn neighbors(&self, entity: &str) -> Option<Vec<Neighbor>> {
+        let name = self.entity_name(entity)?;
+        let mut found: HashMap<&str, (f32, BTreeSet<String>)> = HashMap::new();
+        for rel in &self.data.relations {
+            let kind = rel.relation_type.as_str();
+            let (other, arrow) = match (rel.source == name, rel.target == name) {
+                (true, false) => (&rel.target, format!("-[{}]->", kind)),
+                (false, true) => (&rel.source, format!("<-[{}]-", kind)),
+                _ => continue,
+            };
+            // Co-occurrence has no direction, whichever way it was stored.
+            let arrow = match kind {
+                "CO_OCCURS" => format!("-[{}]-", kind),
+                _ => arrow,
+            };
+            let (weight, relations) = found.entry(other.as_str()).or_default();
+            *weight += rel.weight;
+            relations.insert(arrow);
+        }
+        let mut neighbors: Vec<Neighbor> = found
+            .into_iter()
+            .map(|(n, (weight, relations))| Neighbor {
+                entity: n.to_string(),
+                weight,
+                relations: relations.into_iter().collect(),
+            })
+            .collect();
+        neighbors.sort_by(|a, b| {
+            b.weight
+                .partial_cmp(&a.weight)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| a.entity.cmp(&b.entity))
+        });
+        Some(neighbors)
+    }
+
+    /// The graph `detect_communities`, `neighbors_within` and `shortest_path` walk: one node
+    /// per entity and one edge per related pair.
+    fn entity_graph(&self) -> EntityGraph<'_> {
+        let mut graph = UnGraph::new_undirected();
+        let mut nodes = HashMap::new();
+        for name in self.data.entities.keys() {
+            nodes.insert(name.as_str(), graph.add_node(name.as_str()));
+        }
+        let mut edges = HashMap::new();
+        for rel in &self.data.relations {
+            let (Some(&u), Some(&v)) = (
+                nodes.get(rel.source.as_str()),
+                nodes.get(rel.target.as_str()),
+            ) else {
+                continue;
+            };
+            if u == v {
+                continue;
+            }
+            let pair = (u.min(v), u.max(v));
+            let Some(&edge) = edges.get(&pair) else {
+                let link = Link {
+                    weight: rel.weight,
+                    relation: rel,
+                };
+                edges.insert(pair, graph.add_edge(u, v, link));
+                continue;
+            };
+            let link = &mut graph[edge];
+            link.weight += rel.weight;
+            let typed = |r: &Relation| r.relation_type != "CO_OCCURS";
+            if (typed(rel), rel.weight) > (typed(link.relation), link.relation.weight) {
+                link.relation = rel;
+            }
+        }
+        EntityGraph { graph, nodes }
+    }
+
+    /// Entities within `depth` hops of `entity`, grouped by hop (the first group is one hop
+    /// away), following only links of at least `min_weight`. Each is reached via its
+    /// heaviest link from the hop before; a group is sorted heaviest first, ties by name.
+    /// `None` if the graph has no such entity.
+    pub fn neighbors_within(
+        &self,
+        entity: &str,
+        depth: usize,
+        min_weight: f32,
+    ) -> Option<Vec<Vec<Reached>>> {
+        let name = self.entity_name(entity)?;
+        let EntityGraph { graph, nodes } = self.entity_graph();
+        let start = nodes[name];
+        let mut seen = HashSet::from([start]);
+        let mut frontier = vec![start];
+        let mut hops = Vec::new();
+        while hops.len() < depth && !frontier.is_empty() {
+            let mut next: HashMap<NodeIndex, (NodeIndex, f32)> = HashMap::new();
+            for &node in &frontier {
+                for edge in graph.edges(node) {
+                    let other = edge.target();
+                    let weight = edge.weight().weight;
+                    if weight < min_weight || seen.contains(&other) {
+                        continue;
+                    }
+                    let best = next.entry(other).or_insert((node, weight));
+                    if weight > best.1 {
+                        *best = (node, weight);
+                    }
+                }
+            }
+            seen.extend(next.keys());
+            frontier = next.keys().copied().collect();
+            let mut reached: Vec<Reached> = next
+                .into_iter()
+                .map(|(n, (via, weight))| Reached {
+                    entity: graph[n].to_string(),
+                    via: graph[via].to_string(),
+                    weight,
+                })
+                .collect();
+            reached.sort_by(|a, b| {
+                b.weight
+                    .partial_cmp(&a.weight)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+                    .then_with(|| a.entity.cmp(&b.entity))
+            });
+            if !reached.is_empty() {
+                hops.push(reached);
+            }
+        }
+        Some(hops)
+    }
+
+    /// The cheapest path of at most `max_hops` links from entity `from` to `to`, a link
+    /// costing the inverse of its weight, so that strongly related entities are near. Empty
+    /// from an entity to itself; `None` if either is unknown or no such path exists.
+    pub fn shortest_path(&self, from: &str, to: &str, max_hops: usize) -> Option<Vec<PathStep>> {
+        let (from, to) = (self.entity_name(from)?, self.entity_name(to)?);
+        let EntityGraph { graph, nodes } = self.entity_graph();
+        let (start, goal) = (nodes[from], nodes[to]);
+        // Bellman-Ford cut off after `max_hops` rounds: `best[hop][n]` is the cheapest cost of
+        // reaching `n` in at most `hop` links and the (node, hop) it was reached from.
+        type Best = Option<(f32, Option<(NodeIndex, usize)>)>;
+        let mut best: Vec<Vec<Best>> = vec![vec![None; graph.node_count()]];
+        best[0][start.index()] = Some((0.0, None));
+        for hop in 1..=max_hops.min(graph.node_count()) {
+            let mut round = best[hop - 1].clone();
+            for edge in graph.edge_references() {
+                let weight = edge.weight().weight;
+                if weight <= 0.0 {
+                    continue;
+                }
+                for (u, v) in [
+                    (edge.source(), edge.target()),
+                    (edge.target(), edge.source()),
+                ] {
+                    let Some((cost, _)) = best[hop - 1][u.index()] else {
+                        continue;
+                    };
+                    let cost = cost + 1.0 / weight;
+                    if round[v.index()].is_none_or(|(c, _)| cost < c) {
+                        round[v.index()] = Some((cost, Some((u, hop - 1))));
+                    }
+                }
+            }
+            let settled = round == best[hop - 1];
+            best.push(round);
+            if settled {
+                break;
+            }
+        }
+        let mut at = (goal, best.len() - 1);
+        best[at.1][goal.index()]?;
+        let mut steps = Vec::new();
+        while let Some((_, Some(prev))) = best[at.1][at.0.index()] {
+            let link = &graph[graph.find_edge(prev.0, at.0)?];
+            let rel = link.relation;
+            steps.push(match rel.relation_type.as_str() {
+                "CO_OCCURS" => (graph[prev.0], "CO_OCCURS", graph[at.0]),
+                kind => (rel.source.as_str(), kind, rel.target.as_str()),
+            });
+            at = prev;
+        }
+        steps.reverse();
+        Some(
+            steps
+                .into_iter()
+                .map(|(s, kind, t)| (s.to_string(), kind.to_string(), t.to_string()))
+                .collect(),
+        )
+    }
+
+    /// Entities with at least `min_mentions` mentions, by name, and the relations between
+    /// them with the weights of repeated (source, target, type) triples summed, for the
+    /// exports below.
+    fn export_view(&self, min_mentions: usize) -> (Vec<&Entity>, Vec<ExportEdge<'_>>) {
+        let mut entities: Vec<&Entity> = self
+            .data
+            .entities
+            .values()
+            .filter(|e| e.mentions >= min_mentions)
+            .collect();
+        entities.sort_by(|a, b| a.name.cmp(&b.name));
+        let kept: HashSet<&str> = entities.iter().map(|e| e.name.as_str()).collect();
+        let mut weights: HashMap<(&str, &str, &str), f32> = HashMap::new();
+        for rel in &self.data.relations {
+            if kept.contains(rel.source.as_str()) && kept.contains(rel.target.as_str()) {
+                let key = (
+                    rel.source.as_str(),
+                    rel.target.as_str(),
+                    rel.relation_type.as_str(),
+                );
+                *weights.entry(key).or_insert(0.0) += rel.weight;
+            }
+        }
+        let mut edges: Vec<_> = weights
+            .into_iter()
+            .map(|((s, t, kind), w)| (s, t, kind, w))
+            .collect();
+        edges.sort_by(|a, b| (a.0, a.1, a.2).cmp(&(b.0, b.1, b.2)));
+        (entities, edges)
+    }
+
+    /// The entity graph as a GraphML document for Gephi and the like: entities are nodes
+    /// named by their `id`, with `entity_type` and `mentions`, and relations are undirected
+    /// edges with `relation_type` and `weight`. Entities with fewer than `min_mentions`
+    /// mentions are left out, with their relations.
+    pub fn export_graphml(&self, min_mentions: usize) -> String {
+        let (entities, edges) = self.export_view(min_mentions);
+        let mut out = String::from(GRAPHML_HEAD);
+        for e in entities {
+            out.push_str(&format!("    <node id=\"{}\">\n", xml_escape(&e.name)));
+            out.push_str(&format!(
+                "      <data key=\"entity_type\">{}</data>\n",
+                xml_escape(&e.entity_type)
+            ));
+            out.push_str(&format!(
+                "      <data key=\"mentions\">{}</data>\n    </node>\n",
+                e.mentions
+            ));
+        }
+        for (source, target, kind, weight) in edges {
+            out.push_str(&format!(
+                "    <edge source=\"{}\" target=\"{}\">\n",
+                xml_escape(source),
+                xml_escape(target)
+            ));
+            out.push_str(&format!(
+                "      <data key=\"relation_type\">{}</data>\n",
+                xml_escape(kind)
+            ));
+            out.push_str(&format!(
+                "      <data key=\"weight\">{}</data>\n    </edge>\n",
+                weight
+            ));
+        }
+        out.push_str("  </graph>\n</graphml>\n");
+        out
+    }
+
+    /// The same graph as `export_graphml` in Graphviz DOT, an undirected `graph`.
+    pub fn export_dot(&self, min_mentions: usize) -> String {
+        let (entities, edges) = self.export_view(min_mentions);
+        let mut out = String::from("graph knowledge {\n");
+        for e in entities {
+            out.push_str(&format!(
+                "  {} [entity_type={}, mentions={}];\n",
+                dot_quote(&e.name),
+                dot_quote(&e.entity_type),
+                e.mentions
+            ));
+        }
+        for (source, target, kind, weight) in edges {
+            out.push_str(&format!(
+                "  {} -- {} [relation_type={}, weight={}];\n",
+                dot_quote(source),
+                dot_quote(target),
+                dot_quote(kind),
+                weight
+            ));
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    /// Removes every chunk ingested from `source`. Their entity mentions are taken back,
+    /// dropping entities left with none, and the relations they produced go too, along with
+    /// any whose entities are gone. Chunks and relations from before provenance was stored
+    /// are matched by extracting their entities and relations again.
+    pub fn forget_source(&mut self, source: &str) -> ForgetCounts {
+        let removed = self.remove_chunks(|c| c.source == source);
+        if removed.chunks > 0 {
+            self.rebuild_index();
+            self.detect_communities();
+        }
+        removed
+    }
+
+    /// `forget_source` for the chunks `pick` accepts, leaving the index and communities as
+    /// they were.
+    fn remove_chunks(&mut self, pick: impl Fn(&TextChunk) -> bool) -> ForgetCounts {
+        let (gone, kept): (HashMap<_, _>, HashMap<_, _>) = std::mem::take(&mut self.data.chunks)
+            .into_iter()
+            .partition(|(_, c)| pick(c));
+        self.data.chunks = kept;
+        if gone.is_empty() {
+            return ForgetCounts::default();
+        }
+        // Untracked relations the forgotten chunks produced, by how many of each to drop.
+        let mut untracked: HashMap<(String, String, String), usize> = HashMap::new();
+        for chunk in gone.values() {
+            let names = match &chunk.entities {
+                Some(names) => names.clone(),
+                None => {
+                    let entities = self.extract_entities(&chunk.text);
+                    for rel in self.extract_relations(&chunk.text, &entities) {
+                        let key = (rel.source, rel.target, rel.relation_type);
+                        *untracked.entry(key).or_insert(0) += 1;
+                    }
+                    entities.into_iter().map(|e| e.name).collect()
+                }
+            };
+            for name in names {
+                if let Some(entity) = self.data.entities.get_mut(&name) {
+                    entity.mentions = entity.mentions.saturating_sub(1);
+                }
+            }
+        }
+        let (entities, relations) = (self.data.entities.len(), self.data.relations.len());
+        self.data.entities.retain(|_, e| e.mentions > 0);
+        let known = &self.data.entities;
+        self.data.relations.retain(|rel| {
+            let produced = match &rel.chunk {
+                Some(chunk) => gone.contains_key(chunk),
+                None => {
+                    let key = (
+                        rel.source.clone(),
+                        rel.target.clone(),
+                        rel.relation_type.clone(),
+                    );
+                    match untracked.get_mut(&key) {
+                        Some(left) if *left > 0 => {
+                            *left -= 1;
+                            true
+                        }
+                        _ => false,
+                    }
+                }
+            };
+            !produced && known.contains_key(&rel.source) && known.contains_key(&rel.target)
+        });
+        ForgetCounts {
+            chunks: gone.len(),
+            entities: entities - self.data.entities.len(),
+            relations: relations - self.data.relations.len(),
+        }
+    }
+}
+
+/// Whether text ending in `before` leaves the next word at the start of a sentence.
+fn starts_sentence(before: &str) -> bool {
+    let before = before.trim_end_matches(|c: char| c.is_whitespace() || "\"'(“‘".contains(c));
+    before.is_empty() || before.ends_with(['.', '!', '?'])
+}
+
+/// The relation type of the verb phrase in `between`, the text separating two entities;
+/// `None` across a sentence break or when it is too long to be one phrase.
+fn verb_relation(between: &str) -> Option<&'static str> {
+    let between = between.to_lowercase();
+    if between.contains(['.', '!', '?', ';'])
+        || between.split_whitespace().count() > MAX_RELATION_WORDS
+    {
+        return None;
+    }
+    RELATION_RES
+        .iter()
+        .find(|(re, _)| re.is_match(&between))
+        .map(|(_, kind)| *kind)
+}
+
+/// "About A, B and C (5 entities). " followed by the quoted sentences.
+fn summary_text(top: &[String], size: usize, quoted: &[&str]) -> String {
+    let names = match top {
+        [] => String::new(),
+        [only] => only.clone(),
+        [rest @ .., last] => format!("{} and {}", rest.join(", "), last),
+    };
+    let noun = if size == 1 { "entity" } else { "entities" };
+    let mut summary = format!("About {} ({} {}).", names, size, noun);
+    for sentence in quoted {
+        summary.push(' ');
+        summary.push_str(sentence);
+        if !sentence.ends_with(['.', '!', '?']) {
+            summary.push('.');
+        }
+    }
+    summary
+}
+
+fn is_first_name(word: &str) -> bool {
+    FIRST_NAME_SET.contains(word.to_lowercase().as_str())
+}
+
+/// The type of a run of capitalized words: ORG when it ends in a company suffix, PERSON
+/// after a title (`person`) or when it opens with a given name, CONCEPT otherwise.
+fn name_type(name: &str, person: bool) -> &'static str {
+    let words: Vec<&str> = name.split_whitespace().collect();
+    match words.as_slice() {
+        [.., last] if words.len() > 1 && ORG_SUFFIXES.contains(last) => "ORG",
+        _ if person => "PERSON",
+        [first, _, ..] if is_first_name(first) => "PERSON",
+        _ => "CONCEPT",
+    }
+}
+
+/// A chunk's id: the start of the SHA-256 of its source and text, so the same passage
+/// ingested again under the same source lands on the same id.
+fn chunk_id(source: &str, text: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(source.as_bytes());
+    hasher.update([0]);
+    hasher.update(text.as_bytes());
+    format!("{:x}", hasher.finalize())[..16].to_string()
+}
+
+/// `(source, target, relation type, summed weight)`.
+type ExportEdge<'a> = (&'a str, &'a str, &'a str, f32);
+
+/// `sentence` cut into pieces of at most `limit` characters, each ending at the last
+/// whitespace that fits or, with none, wherever the limit falls.
+fn split_long(sentence: &str, limit: usize) -> Vec<&str> {
+    let mut pieces = Vec::new();
+    let mut rest = sentence;
+    while rest.chars().count() > limit {
+        let cut = rest
+            .char_indices()
+            .nth(limit)
+            .map_or(rest.len(), |(i, _)| i);
+        let cut = match rest[..cut].rfind(char::is_whitespace) {
+            Some(space) if space > 0 => {
+                space + rest[space..].chars().next().map_or(1, char::len_utf8)
+            }
+            _ => cut,
+        };
+        pieces.push(&rest[..cut]);
+        rest = &rest[cut..];
+    }
+    pieces.push(rest);
+    pieces
+}
+
+const GRAPHML_HEAD: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<graphml xmlns="http://graphml.graphdrawing.org/xmlns">
+  <key id="entity_type" for="node" attr.name="entity_type" attr.type="string"/>
+  <key id="mentions" for="node" attr.name="mentions" attr.type="long"/>
+  <key id="relation_type" for="edge" attr.name="relation_type" attr.type="string"/>
+  <key id="weight" for="edge" attr.name="weight" attr.type="double"/>
+  <graph id="knowledge" edgedefault="undirected">
+"#;
+
+/// Text for an XML attribute or element. Line breaks and tabs are written as character
+/// references so attribute values keep them; other control characters, which XML 1.0
+/// cannot hold, are dropped.
+fn xml_escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            '\n' => out.push_str("&#10;"),
+            '\r' => out.push_str("&#13;"),
+            '\t' => out.push_str("&#9;"),
+            c if (c as u32) < 0x20 => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// `text` as a double-quoted DOT ID.
+fn dot_quote(text: &str) -> String {
+    let escaped = text
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace("\r\n", "\\n")
+        .replace(['\n', '\r'], "\\n");
+    format!("\"{}\"", escaped)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::BTreeMap;
 
     #[test]
     fn ingest_and_query_finds_content() {
         let mut engine = GraphRagEngine::new();
         engine.ingest("Alice engineers RSN DB in Rust.", "doc");
-        let out = engine.query("Alice Rust");
+        let out = engine.query("Alice Rust", DEFAULT_TOP_K, 0.0, Ranking::TfIdf, &[]);
         assert!(out.contains("Alice") || !out.contains("No relevant"));
     }
 
+    #[test]
+    fn queries_keep_top_k_chunks_above_min_score() {
+        let mut engine = GraphRagEngine::new();
+        for i in 0..5 {
+            engine.ingest(&format!("Rust note {}.", i), &format!("doc{}", i));
+        }
+        engine.ingest("Python only here.", "other");
+        let hits = |top_k, min_score| {
+            engine
+                .query("rust", top_k, min_score, Ranking::TfIdf, &[])
+                .matches("[Chunk ID:")
+                .count()
+        };
+        assert_eq!(hits(DEFAULT_TOP_K, 0.0), 3);
+        assert_eq!(hits(0, 0.0), 5);
+        assert_eq!(hits(2, 0.0), 2);
+        assert_eq!(hits(0, 1.0), 0);
+        assert!(engine
+            .query("rust", 0, 1.0, Ranking::TfIdf, &[])
+            .contains("No relevant"));
+    }
+
+    #[test]
+    fn sentence_starts_and_stopwords_are_not_entities() {
+        let mut engine = GraphRagEngine::new();
+        let text = "The data. However, the data held. Later Alice met Bob.";
+        engine.ingest(text, "notes");
+        let mut names: Vec<&str> = engine.data.entities.keys().map(String::as_str).collect();
+        names.sort();
+        assert_eq!(names, ["Alice", "Bob"]);
+        // "Yesterday" opens its sentence and appears nowhere else; Carol is named mid-way.
+        engine.ingest("Yesterday Carol called. Carol is fine.", "diary");
+        assert!(engine.entity_name("Yesterday").is_none());
+        assert!(engine.entity_name("Carol").is_some());
+
+        // "data." and "Data" index as "data", and stopwords are not indexed at all.
+        assert_eq!(engine.term_counts["data"].values().sum::<usize>(), 2);
+        assert!(!engine.term_counts.contains_key("data."));
+        assert!(!engine.term_counts.contains_key("the"));
+        engine.extra_stopwords.insert("data".to_string());
+        engine.rebuild_index();
+        assert!(engine
+            .query("data", 0, 0.0, Ranking::TfIdf, &[])
+            .contains("No relevant"));
+    }
+
+    #[test]
+    fn entities_are_typed_by_heuristics() {
+        let mut engine = GraphRagEngine::new();
+        let text = "Dr. Jane Doe joined Acme Corp on March 5, 2024. Sarah Connor and NASA paid \
+                    $1,200 for 15% of it. It beats Quantum Computing.";
+        engine.ingest(text, "news");
+        let types: BTreeMap<&str, &str> = engine
+            .data
+            .entities
+            .values()
+            .map(|e| (e.name.as_str(), e.entity_type.as_str()))
+            .collect();
+        let expected = BTreeMap::from([
+            ("$1,200", "NUMBER"),
+            ("15%", "NUMBER"),
+            ("Acme Corp", "ORG"),
+            ("Jane Doe", "PERSON"),
+            ("March 5, 2024", "DATE"),
+            ("NASA", "ORG"),
+            ("Quantum Computing", "CONCEPT"),
+            ("Sarah Connor", "PERSON"),
+        ]);
+        assert_eq!(types, expected);
+
+        engine.ingest("The cargo tool ships with rust.", "docs");
+        let only = |engine: &GraphRagEngine, types: &[&str]| {
+            let types: Vec<String> = types.iter().map(|t| t.to_string()).collect();
+            engine.query("rust paid", 0, 0.0, Ranking::TfIdf, &types)
+        };
+        assert!(only(&engine, &[]).contains("Source: docs"));
+        let orgs = only(&engine, &["ORG"]);
+        assert!(orgs.contains("Source: news") && !orgs.contains("Source: docs"));
+        assert!(engine.set_entity_type("NASA", "AGENCY"));
+        assert!(!engine.set_entity_type("Nobody", "PERSON"));
+        assert!(only(&engine, &["AGENCY", "PLACE"]).contains("Source: news"));
+        assert!(only(&engine, &["PLACE"]).contains("No relevant"));
+    }
+
+    #[test]
+    fn verb_phrases_type_relations_in_text_order() {
+        let mut engine = GraphRagEngine::new();
+        let text = "In 2020 Acme Corp acquired Beta Ltd. Beta Ltd was founded by Sarah Connor. \
+                    Sarah Connor lives in Paris with Alice.";
+        engine.ingest(text, "news");
+        assert_eq!(engine.data.relations.len(), 10);
+        let typed: BTreeSet<(&str, &str, &str)> = engine
+            .data
+            .relations
+            .iter()
+            .filter(|r| r.relation_type != "CO_OCCURS")
+            .map(|r| {
+                (
+                    r.source.as_str(),
+                    r.target.as_str(),
+                    r.relation_type.as_str(),
+                )
+            })
+            .collect();
+        let expected = BTreeSet::from([
+            ("Acme Corp", "Beta Ltd", "acquired"),
+            ("Beta Ltd", "Sarah Connor", "founded_by"),
+            ("Sarah Connor", "Paris", "located_in"),
+        ]);
+        assert_eq!(typed, expected);
+
+        assert_eq!(verb_relation(" works for "), Some("works_for"));
+        assert_eq!(verb_relation(" met. Then "), None);
+        assert_eq!(verb_relation(" said that everyone in the room knew "), None);
+
+        let out = engine.query("Sarah Connor", DEFAULT_TOP_K, 0.0, Ranking::TfIdf, &[]);
+        let context = out.split("[Community Context: ").nth(1).unwrap_or_default();
+        let lines: Vec<&str> = context.lines().skip(1).collect();
+        assert_eq!(
+            lines,
+            [
+                "Beta Ltd -[founded_by]-> Sarah Connor",
+                "Sarah Connor -[located_in]-> Paris",
+                "Acme Corp -[CO_OCCURS]-> Sarah Connor",
+                "Sarah Connor -[CO_OCCURS]-> Alice",
+            ]
+        );
+    }
+
+    #[test]
+    fn community_summaries_name_top_entities_and_quote_sentences() {
+        let mut engine = GraphRagEngine::new();
+        let texts = [
+            ("In Paris, Alice met Bob.", "a"),
+            ("Bob lives in Paris.", "b"),
+            ("Alice visited Rome.", "c"),
+            ("Then Carol called Bob.", "d"),
+            ("Then Erin emailed Frank.", "e"),
+        ];
+        for (text, source) in texts {
+            engine.ingest(text, source);
+        }
+        let community = |engine: &GraphRagEngine, name: &str| {
+            let found = engine
+                .data
+                .communities
+                .iter()
+                .find(|c| c.entities.iter().any(|e| e == name));
+            found.map(|c| (c.top_entities.join(", "), c.summary.clone()))
+        };
+        // Bob has the most relations; Alice and Paris tie on them and on mentions.
+        assert_eq!(
+            community(&engine, "Rome").unwrap(),
+            (
+                "Bob, Alice, Paris".to_string(),
+                "About Bob, Alice and Paris (5 entities). In Paris, Alice met Bob. Bob lives \
+                 in Paris."
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            community(&engine, "Erin").unwrap().1,
+            "About Erin and Frank (2 entities). Then Erin emailed Frank."
+        );
+
+        engine.summary_entities = 1;
+        engine.summary_sentences = 0;
+        engine.summarize_communities();
+        assert_eq!(
+            community(&engine, "Rome").unwrap().1,
+            "About Bob (5 entities)."
+        );
+    }
+
+    #[test]
+    fn entities_are_found_by_prefix_or_pattern_with_their_chunks() {
+        let mut engine = GraphRagEngine::new();
+        engine.ingest("In Paris, Alice met Bob.", "notes");
+        engine.ingest("Again Alice visited Paris with Alan.", "diary");
+        let names = |found: Vec<&Entity>| -> Vec<String> {
+            found.into_iter().map(|e| e.name.clone()).collect()
+        };
+        assert_eq!(names(engine.search_entities("al", 0)), ["Alice", "Alan"]);
+        assert_eq!(names(engine.search_entities("AL", 1)), ["Alice"]);
+        assert_eq!(names(engine.search_entities("%i%", 0)), ["Alice", "Paris"]);
+        assert!(engine.search_entities("zed", 0).is_empty());
+
+        let mut ids: Vec<String> = engine.data.chunks.keys().cloned().collect();
+        ids.sort();
+        assert_eq!(engine.entity_chunk_ids("Paris"), ids);
+        engine.forget_source("notes");
+        assert_eq!(engine.entity_chunk_ids("Paris").len(), 1);
+        assert!(engine.entity_chunk_ids("Bob").is_empty());
+
+        // Graphs saved before the map was kept get it back from their chunks.
+        let mut json = serde_json::to_value(&engine).unwrap();
+        json["data"]
+            .as_object_mut()
+            .unwrap()
+            .remove("entity_chunks");
+        let mut old: GraphRagEngine = serde_json::from_value(json).unwrap();
+        assert!(old.data.entity_chunks.is_empty());
+        old.rebuild_index();
+        let alan = engine.entity_chunk_ids("Alan");
+        assert_eq!(old.entity_chunk_ids("Alan"), alan);
+    }
+
+    #[test]
+    fn entities_are_walked_by_hop_and_joined_by_the_strongest_path() {
+        let mut engine = GraphRagEngine::new();
+        for name in ["Alice", "Acme", "Apollo", "Mars", "Zed"] {
+            engine.data.entities.insert(
+                name.to_string(),
+                Entity {
+                    name: name.to_string(),
+                    entity_type: "CONCEPT".to_string(),
+                    mentions: 1,
+                },
+            );
+        }
+        for (source, kind, target, weight) in [
+            ("Alice", "works_for", "Acme", 1.0),
+            ("Apollo", "CO_OCCURS", "Acme", 1.0),
+            ("Alice", "CO_OCCURS", "Apollo", 0.2),
+            ("Apollo", "CO_OCCURS", "Mars", 1.0),
+        ] {
+            engine.data.relations.push(Relation {
+                source: source.to_string(),
+                target: target.to_string(),
+                relation_type: kind.to_string(),
+                weight,
+                chunk: None,
+            });
+        }
+        let step = |s: &str, kind: &str, t: &str| (s.to_string(), kind.to_string(), t.to_string());
+
+        let path = engine.shortest_path("alice", "Apollo", 6).unwrap();
+        assert_eq!(
+            path,
+            [
+                step("Alice", "works_for", "Acme"),
+                step("Acme", "CO_OCCURS", "Apollo")
+            ]
+        );
+        let direct = engine.shortest_path("Alice", "Apollo", 1).unwrap();
+        assert_eq!(direct, [step("Alice", "CO_OCCURS", "Apollo")]);
+        assert_eq!(engine.shortest_path("Alice", "Alice", 6), Some(Vec::new()));
+        assert_eq!(engine.shortest_path("Alice", "Zed", 6), None);
+        assert_eq!(engine.shortest_path("Alice", "Nobody", 6), None);
+
+        let hops = |depth, min_weight| -> Vec<Vec<(String, String)>> {
+            let hops = engine.neighbors_within("alice", depth, min_weight).unwrap();
+            hops.into_iter()
+                .map(|hop| hop.into_iter().map(|r| (r.entity, r.via)).collect())
+                .collect()
+        };
+        let pair = |e: &str, via: &str| (e.to_string(), via.to_string());
+        assert_eq!(
+            hops(2, 0.0),
+            [
+                vec![pair("Acme", "Alice"), pair("Apollo", "Alice")],
+                vec![pair("Mars", "Apollo")]
+            ]
+        );
+        assert_eq!(
+            hops(5, 0.5),
+            [
+                vec![pair("Acme", "Alice")],
+                vec![pair("Apollo", "Acme")],
+                vec![pair("Mars", "Apollo")]
+            ]
+        );
+        assert_eq!(hops(1, 0.0).len(), 1);
+        assert!(engine.neighbors_within("Nobody", 1, 0.0).is_none());
+    }
+
+    #[test]
+    fn stats_count_the_index_entity_types_and_community_sizes() {
+        let mut engine = GraphRagEngine::new();
+        let empty = engine.stats();
+        assert_eq!((empty.chunks, empty.vocabulary), (0, 0));
+        assert_eq!(empty.relation_weight.to_string(), "0");
+        assert!(empty.community_sizes.is_empty() && empty.top_entities.is_empty());
+
+        engine.ingest(
+            "In Paris, Alice met Bob. Dr. Carol Smith joined Acme Corp.",
+            "notes",
+        );
+        engine.ingest("Later Bob visited Rome.", "diary");
+        engine.ingest("We saw Dave.", "misc");
+        let stats = engine.stats();
+        assert_eq!(stats.chunks, 3);
+        assert_eq!(stats.indexed_words, engine.doc_lens.values().sum::<usize>());
+        assert_eq!(stats.vocabulary, engine.tfidf_index.len());
+        assert_eq!(stats.entities, 7);
+        let types: Vec<(&str, usize)> = stats
+            .entity_types
+            .iter()
+            .map(|(t, n)| (t.as_str(), *n))
+            .collect();
+        assert_eq!(types, [("CONCEPT", 5), ("ORG", 1), ("PERSON", 1)]);
+        assert_eq!(stats.relations, engine.data.relations.len());
+        assert_eq!(stats.relation_weight, stats.relations as f32);
+        assert_eq!(stats.community_sizes, [1, 6]);
+        assert_eq!(stats.top_entities[0], ("Bob".to_string(), 2));
+        assert_eq!(stats.top_entities.len(), 7);
+    }
+
+    /// The first chunk id a query response lists.
+    fn top_hit(engine: &GraphRagEngine, ranking: Ranking) -> String {
+        let out = engine.query("rust", 1, 0.0, ranking, &[]);
+        let source = out.split("Source: ").nth(1).unwrap_or_default();
+        source.split(']').next().unwrap_or_default().to_string()
+    }
+
+    #[test]
+    fn bm25_stops_rewarding_short_chunks_tfidf_favours() {
+        let mut engine = GraphRagEngine::new();
+        engine.ingest("rust rust rust rust cargo", "repeats");
+        engine.ingest("rust", "single");
+        engine.ingest("python java go", "other");
+        engine.ingest("ruby perl", "more");
+        // TF-IDF divides by chunk length, so one word out of one beats four out of five;
+        // BM25 lets the repeats count for more than the length costs.
+        assert_eq!(top_hit(&engine, Ranking::TfIdf), "single");
+        assert_eq!(top_hit(&engine, Ranking::Bm25), "repeats");
+        // Normalizing fully for length (b = 1) tips it back to the shorter chunk.
+        engine.bm25_b = 1.0;
+        assert_eq!(top_hit(&engine, Ranking::Bm25), "single");
+    }
+
+    #[test]
+    fn reingesting_a_chunk_counts_it_once() {
+        let mut engine = GraphRagEngine::new();
+        let text = "Alice met Bob in Paris. Bob lives in Paris.";
+        let first = engine.ingest(text, "notes");
+        assert_eq!((first.new_chunks, first.duplicate_chunks), (1, 0));
+        let relations = engine.data.relations.len();
+        let again = engine.ingest(text, "notes");
+        assert_eq!((again.new_chunks, again.duplicate_chunks), (0, 1));
+        assert_eq!(engine.data.entities["Paris"].mentions, 1);
+        assert_eq!(engine.data.relations.len(), relations);
+
+        // A different document under the same label no longer overwrites the first.
+        engine.ingest("Carol met Dave.", "notes");
+        engine.ingest(text, "diary");
+        assert_eq!(engine.data.chunks.len(), 3);
+        assert_eq!(engine.data.entities["Paris"].mentions, 2);
+    }
+
+    #[test]
+    fn merging_the_same_graph_twice_counts_it_once() {
+        let mut source = GraphRagEngine::new();
+        source.ingest("Alice met Bob in Paris. Bob lives in Paris.", "notes");
+        let mut engine = GraphRagEngine::new();
+        engine.merge(source.data.clone());
+        engine.merge(source.data.clone());
+        assert_eq!(engine.data.chunks.len(), source.data.chunks.len());
+        assert_eq!(engine.data.relations.len(), source.data.relations.len());
+        assert_eq!(engine.data.entities["Paris"].mentions, 1);
+
+        // Only the chunk the second graph adds is counted.
+        source.ingest("Later, Carol met Dave in Paris.", "diary");
+        engine.merge(source.data.clone());
+        assert_eq!(engine.data.chunks.len(), 2);
+        assert_eq!(engine.data.relations.len(), source.data.relations.len());
+        assert_eq!(engine.data.entities["Paris"].mentions, 2);
+        assert_eq!(engine.data.entities["Carol"].mentions, 1);
+    }
+
+    #[test]
+    fn reingesting_a_source_swaps_only_changed_chunks() {
+        let mut engine = GraphRagEngine::new();
+        let (old, new) = (
+            "Then Alice met Bob. Then Carol met Dave.",
+            "Then Alice met Bob. Then Erin met Frank.",
+        );
+        engine.ingest_with(old, "notes", 22, 0, 1);
+        engine.ingest("Then Alice visited Paris.", "diary");
+        let changes = engine.reingest_with(new, "notes", 22, 0, 2);
+        assert_eq!(
+            changes,
+            ReingestCounts {
+                removed_chunks: 1,
+                added_chunks: 1,
+                unchanged_chunks: 1,
+                new_entities: vec!["Erin".to_string(), "Frank".to_string()],
+                dropped_entities: vec!["Carol".to_string(), "Dave".to_string()],
+            }
+        );
+        assert_eq!(engine.data.entities["Alice"].mentions, 2);
+        let stamps: HashSet<_> = engine.data.chunks.values().map(|c| c.ingested_at).collect();
+        assert!(stamps.contains(&Some(1)) && stamps.contains(&Some(2)));
+        assert!(engine
+            .query("Carol", DEFAULT_TOP_K, 0.0, Ranking::TfIdf, &[])
+            .contains("No relevant"));
+        assert!(!engine
+            .query("Erin", DEFAULT_TOP_K, 0.0, Ranking::TfIdf, &[])
+            .contains("No relevant"));
+    }
+
+    #[test]
+    fn forgetting_a_source_rederives_the_graph() {
+        let mut engine = GraphRagEngine::new();
+        engine.ingest("In Paris, Alice met Bob.", "notes");
+        engine.ingest("Again Alice visited Paris.", "diary");
+        let sources: Vec<_> = engine
+            .sources()
+            .into_iter()
+            .map(|s| (s.source, s.chunks))
+            .collect();
+        assert_eq!(
+            sources,
+            [("diary".to_string(), 1), ("notes".to_string(), 1)]
+        );
+        assert_eq!(engine.top_entities(|_| true)[0].name, "Alice");
+        assert_eq!(
+            engine.neighbors("alice").unwrap()[0],
+            Neighbor {
+                entity: "Paris".to_string(),
+                weight: 2.0,
+                relations: vec!["-[CO_OCCURS]-".to_string(), "-[visited]->".to_string()],
+            }
+        );
+
+        assert_eq!(
+            engine.forget_source("notes"),
+            ForgetCounts {
+                chunks: 1,
+                entities: 1,
+                relations: 3,
+            }
+        );
+        assert_eq!(engine.forget_source("notes"), ForgetCounts::default());
+        assert!(engine.entity_name("Bob").is_none());
+        assert_eq!(engine.data.entities["Alice"].mentions, 1);
+        let neighbors = engine.neighbors("Alice").unwrap();
+        assert_eq!(neighbors.len(), 1);
+        assert_eq!(
+            (neighbors[0].entity.as_str(), neighbors[0].weight),
+            ("Paris", 1.0)
+        );
+        assert_eq!(neighbors[0].relations, ["-[visited]->"]);
+        assert!(engine
+            .query("Bob", DEFAULT_TOP_K, 0.0, Ranking::TfIdf, &[])
+            .contains("No relevant"));
+    }
+
+    #[test]
+    fn graph_exports_escape_names_and_filter_by_mentions() {
+        let mut engine = GraphRagEngine::new();
+        for (name, mentions) in [("Say \"Hi\" <&>", 2), ("Zoë\n", 3), ("Rare", 1)] {
+            engine.data.entities.insert(
+                name.to_string(),
+                Entity {
+                    name: name.to_string(),
+                    entity_type: "CONCEPT".to_string(),
+                    mentions,
+                },
+            );
+        }
+        for target in ["Zoë\n", "Zoë\n", "Rare"] {
+            engine.data.relations.push(Relation {
+                source: "Say \"Hi\" <&>".to_string(),
+                target: target.to_string(),
+                relation_type: "CO_OCCURS".to_string(),
+                weight: 1.5,
+                chunk: None,
+            });
+        }
+
+        let graphml = engine.export_graphml(2);
+        assert!(graphml.contains("<node id=\"Say &quot;Hi&quot; &lt;&amp;&gt;\">"));
+        assert!(graphml.contains("<node id=\"Zoë&#10;\">"));
+        assert!(!graphml.contains("Rare"));
+        assert_eq!(graphml.matches("<edge ").count(), 1);
+        assert!(graphml.contains("<data key=\"weight\">3</data>"));
+        assert!(graphml.ends_with("</graph>\n</graphml>\n"));
+
+        let dot = engine.export_dot(2);
+        assert!(dot.contains(
+            "  \"Say \\\"Hi\\\" <&>\" -- \"Zoë\\n\" [relation_type=\"CO_OCCURS\", weight=3];"
+        ));
+        assert_eq!(engine.export_dot(1).matches(" -- ").count(), 2);
+    }
+
+    #[test]
+    fn imports_merge_mentions_and_check_relation_entities() {
+        let mut engine = GraphRagEngine::new();
+        engine.ingest("Then Alice met Bob.", "notes");
+        let dump = |json: serde_json::Value| serde_json::from_value::<GraphDump>(json).unwrap();
+
+        let err = engine
+            .import(
+                dump(serde_json::json!({
+                    "entities": [{"name": "Alice", "mentions": 2}],
+                    "relations": [{"source": "Alice", "target": "Carol", "relation_type": "KNOWS"}],
+                })),
+                false,
+                false,
+            )
+            .unwrap_err();
+        assert!(err.contains("unknown entity 'Carol'"));
+        assert_eq!(engine.data.entities["Alice"].mentions, 1);
+
+        let knows = serde_json::json!({
+            "entities": [{"name": "Alice", "mentions": 2}],
+            "relations": [{"source": "Alice", "target": "Carol", "relation_type": "KNOWS", "weight": 2.0}],
+        });
+        let summary = engine.import(dump(knows.clone()), true, false).unwrap();
+        assert_eq!(summary.created, ["Carol"]);
+        assert_eq!(engine.data.entities["Alice"].mentions, 3);
+        assert_eq!(engine.data.entities["Carol"].entity_type, "CONCEPT");
+        assert_eq!(engine.data.communities.len(), 1);
+
+        engine.import(dump(knows), false, true).unwrap();
+        let weights: Vec<f32> = engine
+            .data
+            .relations
+            .iter()
+            .filter(|r| r.relation_type == "KNOWS")
+            .map(|r| r.weight)
+            .collect();
+        assert_eq!(weights, [4.0]);
+        assert!(serde_json::from_value::<GraphDump>(serde_json::json!({"nodes": []})).is_err());
+    }
+
     #[test]
     fn chunk_text_splits_long_input() {
-        let engine = GraphRagEngine::new();
         let long = "Word. ".repeat(400);
-        let chunks = engine.chunk_text(&long, "src");
+        let chunks = GraphRagEngine::chunk_text(&long, "src", DEFAULT_CHUNK_SIZE, 0);
         assert!(!chunks.is_empty());
     }
+
+    #[test]
+    fn chunks_overlap_by_whole_sentences() {
+        let text: String = (0..10)
+            .map(|i| format!("Sentence {:02} here. ", i))
+            .collect();
+        let texts = |size, overlap| -> Vec<String> {
+            GraphRagEngine::chunk_text(&text, "src", size, overlap)
+                .into_iter()
+                .map(|c| c.text)
+                .collect()
+        };
+        // Each sentence is 18 characters with its space.
+        assert_eq!(texts(40, 0).len(), 5);
+        let overlapped = texts(60, 1);
+        assert_eq!(overlapped.len(), 5);
+        assert!(
+            overlapped[0].starts_with("Sentence 00")
+                && overlapped[0].ends_with("Sentence 02 here.")
+        );
+        assert!(overlapped[1].starts_with(" Sentence 02 here."));
+        assert!(overlapped[4].ends_with("Sentence 09 here. "));
+        // An overlap wider than the chunk still moves on by a sentence at a time.
+        let crowded = texts(40, 5);
+        assert_eq!(crowded.len(), 9);
+        assert!(crowded.iter().all(|t| t.chars().count() <= 40));
+    }
+
+    #[test]
+    fn sentences_longer_than_a_chunk_are_cut() {
+        let words = "lorem ipsum ".repeat(850);
+        let chunks = GraphRagEngine::chunk_text(&words, "src", 500, 0);
+        assert_eq!(chunks.len(), 21);
+        assert!(chunks.iter().all(|c| c.text.chars().count() <= 500));
+        assert!(chunks.iter().all(|c| c.text.ends_with(' ')));
+        let solid = "x".repeat(10_000);
+        let chunks = GraphRagEngine::chunk_text(&solid, "src", 500, 2);
+        assert_eq!(chunks.len(), 20);
+        assert_eq!(chunks.iter().map(|c| c.text.len()).sum::<usize>(), 10_000);
+    }
 }
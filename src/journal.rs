@@ -0,0 +1,120 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+const MAGIC: &[u8; 8] = b"RSNJRNL1";
+const HEADER_LEN: usize = 16;
+const CHECKSUM_LEN: usize = 8;
+
+/// One logged mutation. Records and table definitions travel as JSON text because
+/// bincode cannot round-trip `serde_json::Value`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Entry {
+    /// `created_at` is the row's insert time in TTL tables, which replay keeps.
+    Insert {
+        table: String,
+        id: u64,
+        record: String,
+        created_at: Option<i64>,
+    },
+    Update {
+        table: String,
+        id: u64,
+        record: String,
+    },
+    Delete {
+        table: String,
+        id: u64,
+    },
+    CreateTable {
+        name: String,
+        table: String,
+    },
+    /// A `graph_ingest` with the chunking and timestamp it ran with.
+    Ingest {
+        text: String,
+        source: String,
+        chunk_size: usize,
+        chunk_overlap: usize,
+        ingested_at: i64,
+    },
+    /// A `graph_reingest`: the source's chunks replaced by those of `text`.
+    Reingest {
+        text: String,
+        source: String,
+        chunk_size: usize,
+        chunk_overlap: usize,
+        ingested_at: i64,
+    },
+}
+
+/// Journal file header: magic plus the snapshot epoch the entries apply on top of.
+pub fn header(epoch: u64) -> Vec<u8> {
+    let mut out = MAGIC.to_vec();
+    out.extend_from_slice(&epoch.to_le_bytes());
+    out
+}
+
+fn checksum(payload: &[u8]) -> [u8; CHECKSUM_LEN] {
+    let digest = Sha256::digest(payload);
+    let mut out = [0u8; CHECKSUM_LEN];
+    out.copy_from_slice(&digest[..CHECKSUM_LEN]);
+    out
+}
+
+/// Length- and checksum-prefixed frame for one encoded entry.
+pub fn frame(payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(4 + CHECKSUM_LEN + payload.len());
+    out.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    out.extend_from_slice(&checksum(payload));
+    out.extend_from_slice(payload);
+    out
+}
+
+/// Epoch and intact entry payloads of a journal file. Reading stops at the first truncated
+/// or mismatching frame, which is what a crash mid-append leaves behind.
+pub fn parse(bytes: &[u8]) -> Option<(u64, Vec<&[u8]>)> {
+    if bytes.len() < HEADER_LEN || &bytes[..MAGIC.len()] != MAGIC {
+        return None;
+    }
+    let epoch = u64::from_le_bytes(bytes[MAGIC.len()..HEADER_LEN].try_into().ok()?);
+    let mut frames = Vec::new();
+    let mut rest = &bytes[HEADER_LEN..];
+    while rest.len() >= 4 + CHECKSUM_LEN {
+        let len = u32::from_le_bytes(rest[..4].try_into().ok()?) as usize;
+        let body = &rest[4 + CHECKSUM_LEN..];
+        if body.len() < len {
+            break;
+        }
+        let payload = &body[..len];
+        if checksum(payload) != rest[4..4 + CHECKSUM_LEN] {
+            break;
+        }
+        frames.push(payload);
+        rest = &body[len..];
+    }
+    Some((epoch, frames))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn torn_and_corrupt_tails_are_dropped() {
+        let mut file = header(7);
+        file.extend(frame(b"one"));
+        file.extend(frame(b"two"));
+        let (epoch, frames) = parse(&file).unwrap();
+        assert_eq!((epoch, frames), (7, vec![&b"one"[..], &b"two"[..]]));
+
+        let mut torn = file.clone();
+        torn.extend(&frame(b"three")[..6]);
+        assert_eq!(parse(&torn).unwrap().1.len(), 2);
+
+        let mut flipped = file.clone();
+        let last = flipped.len() - 1;
+        flipped[last] ^= 0xff;
+        assert_eq!(parse(&flipped).unwrap().1, vec![&b"one"[..]]);
+        assert!(parse(b"not a journal").is_none());
+    }
+}
@@ -1,36 +1,101 @@
 pub mod alive;
+#[cfg(feature = "arrow")]
+pub mod arrow;
+pub mod commands;
+pub mod dataframe;
+pub mod expr;
 pub mod graph_rag;
+pub mod journal;
+pub mod lock;
 pub mod personality;
+mod pymethods;
+pub mod render;
 pub mod snark_pool;
+pub mod sql;
+pub mod writer;
 
 const MAX_RECURSION_DEPTH: usize = 64;
 const MAX_COMMAND_LENGTH: usize = 4096;
 const MAX_BATCH_OPS: usize = 512;
+const DEFAULT_HISTORY_SIZE: usize = 1000;
 const MAX_INGEST_TEXT_BYTES: usize = 2 * 1024 * 1024;
 const MAX_JSONL_IMPORT_BYTES: u64 = 10 * 1024 * 1024;
 const MAX_JSONL_IMPORT_LINES: usize = 100_000;
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const MAX_CSV_IMPORT_BYTES: u64 = 10 * 1024 * 1024;
+const MAX_CSV_IMPORT_ROWS: usize = 100_000;
+const MAX_GRAPH_IMPORT_BYTES: u64 = 64 * 1024 * 1024;
+const MAX_IMPORT_REPORTED_ERRORS: usize = 5;
+const MAX_META_VALUE_BYTES: usize = 4096;
+const SCHEMA_FORMAT_VERSION: u64 = 1;
+const DUMP_FORMAT_VERSION: u64 = 1;
+const JOURNAL_CHECKPOINT_ENTRIES: usize = 1000;
+const MANIFEST_FILE: &str = "manifest.rsn";
+/// zstd level of `export_encrypted` bundles and of databases opened without `compression_level`.
+const DEFAULT_ZSTD_LEVEL: i32 = 3;
+/// Header after the checksum: magic, format version, compression tag, flags.
+const FILE_MAGIC: &[u8; 5] = b"RSNDB";
+/// Encrypted files follow the flags with the KDF iteration count, salt and key verifier,
+/// and are authenticated with an HMAC instead of SHA-256. Every header ends with the
+/// payload length, so truncation is detected and trailing bytes are ignored.
+const FILE_FORMAT_VERSION: u8 = 4;
+const FILE_HEADER_LEN: usize = 8;
+const PAYLOAD_LEN_LEN: usize = 8;
+const FLAG_ENCRYPTED: u8 = 1;
+const KDF_ITERATIONS: u32 = 100_000;
+const KDF_SALT_LEN: usize = 16;
+const KEY_CHECK_LEN: usize = 16;
+/// `export_encrypted` bundles: magic, format version, KDF iteration count (u32 LE), salt
+/// and key verifier, then the AES-GCM nonce and ciphertext of the zstd-compressed dump.
+const BUNDLE_MAGIC: &[u8; 8] = b"RSNBNDL\0";
+const BUNDLE_FORMAT_VERSION: u8 = 1;
+const BUNDLE_HEADER_LEN: usize = BUNDLE_MAGIC.len() + 1 + 4 + KDF_SALT_LEN + KEY_CHECK_LEN;
+/// Readers refuse more KDF rounds than this, so a forged header cannot stall them.
+const MAX_BUNDLE_KDF_ITERATIONS: u32 = 100 * KDF_ITERATIONS;
+const KEY_IGNORED_WARNING: &str =
+    "encryption_key was given but the database is not encrypted; it will be encrypted on the next write";
+const GRAPH_FILE: &str = "graph.rsn";
 
 use aes_gcm::{
     aead::{Aead, KeyInit},
     Aes256Gcm, Nonce,
 };
-use graph_rag::GraphRagEngine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine as _;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, SecondsFormat, Utc};
+use expr::Expr;
+use flate2::bufread::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression as GzLevel;
+use graph_rag::{
+    Entity, ForgetCounts, GraphDump, GraphRagEngine, GraphStats, Neighbor, Ranking, SourceStats,
+    DEFAULT_TOP_K,
+};
+use hmac::{Hmac, Mac};
 use lz4_flex::{compress_prepend_size, decompress_size_prepended};
 use personality::{Mode, Personality};
-use pyo3::exceptions::{PyIOError, PyKeyError, PyRuntimeError, PyValueError};
+use pyo3::create_exception;
+use pyo3::exceptions::{
+    PyIOError, PyKeyError, PyRuntimeError, PyTypeError, PyUserWarning, PyValueError,
+};
 use pyo3::prelude::*;
 use pyo3::types::{PyDict, PyList};
 use rand::{thread_rng, Rng};
+use regex::Regex;
 use rusqlite::types::{Value as SqlValue, ValueRef};
 use rusqlite::Connection;
 use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
 use serde_json::{Map, Value};
 use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::cell::Cell;
 use std::cmp::Ordering;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::fs;
-use std::io::{BufRead, BufReader};
-use std::path::{Component, PathBuf};
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
 use thiserror::Error;
 use zstd::stream::{decode_all, encode_all};
 
@@ -52,9 +117,87 @@ enum DbError {
     UnknownField(String),
     #[error("invalid identifier `{0}`")]
     InvalidIdentifier(String),
+    #[error("field `{field}` violates constraint `{constraint}`")]
+    ConstraintViolation { field: String, constraint: String },
+    #[error("invalid constraint on field `{field}`: {reason}")]
+    InvalidConstraint { field: String, reason: String },
+    #[error("schema type mismatch for field `{field}` at index {index}: expected `{expected}`")]
+    ElementTypeMismatch {
+        field: String,
+        index: usize,
+        expected: String,
+    },
+    #[error("field `{field}` references unknown table `{table}`")]
+    UnknownReference { field: String, table: String },
+    #[error("field `{field}` references missing record {value} in table `{table}`")]
+    DanglingReference {
+        field: String,
+        table: String,
+        value: String,
+    },
+    #[error("record id `{id}` in `{table}` is still referenced by `{dependent}`")]
+    ReferencedRecord {
+        table: String,
+        id: u64,
+        dependent: String,
+    },
+    #[error("record `{id}` in `{table}` is at version {actual}, expected {expected}")]
+    VersionConflict {
+        table: String,
+        id: u64,
+        expected: u64,
+        actual: u64,
+    },
+    #[error("record `{id}` in `{table}` does not hold the expected value for `{field}`")]
+    PreconditionFailed {
+        table: String,
+        id: u64,
+        field: String,
+    },
+    #[error("table `{0}` is not versioned")]
+    NotVersioned(String),
+    #[error("field `{0}` is computed and cannot be written")]
+    ComputedField(String),
+    #[error("metadata value for `{0}` exceeds {MAX_META_VALUE_BYTES} bytes")]
+    MetadataTooLarge(String),
+    #[error("table `{0}` does not use soft delete")]
+    NoSoftDelete(String),
+    #[error("upsert key `{0}` must be a unique or indexed field")]
+    UpsertKey(String),
+    #[error("upsert key `{0}` matches more than one record")]
+    AmbiguousUpsert(String),
+    #[error("match is ambiguous: {0} records found")]
+    AmbiguousMatch(usize),
+    #[error("sequence value {value} would collide with existing ids (next free id is {floor})")]
+    SequenceCollision { value: u64, floor: u64 },
+    #[error("record id `{id}` already exists in `{table}`")]
+    DuplicateId { table: String, id: u64 },
+    #[error("cannot convert field `{field}` to `{expected}` for record ids {ids:?}")]
+    ConversionFailed {
+        field: String,
+        expected: String,
+        ids: Vec<u64>,
+    },
 }
 
 type DbResult<T> = Result<T, DbError>;
+type HmacSha256 = Hmac<Sha256>;
+/// Records by id as exports write them, produced one at a time so a whole table never sits
+/// in memory twice.
+type ExportRows<'t> = Box<dyn Iterator<Item = (u64, Cow<'t, Map<String, Value>>)> + Send + 't>;
+
+create_exception!(
+    _core,
+    EncryptionKeyError,
+    PyValueError,
+    "The encryption key is missing or does not match the database."
+);
+create_exception!(
+    _core,
+    DatabaseLockedError,
+    PyIOError,
+    "Another process holds the database open for writing."
+);
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
 enum CompressionAlgo {
@@ -63,20 +206,386 @@ enum CompressionAlgo {
     None,
 }
 
+impl CompressionAlgo {
+    fn tag(self) -> u8 {
+        match self {
+            Self::None => 0,
+            Self::Zstd => 1,
+            Self::Lz4 => 2,
+        }
+    }
+    fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "zstd" => Some(Self::Zstd),
+            "lz4" => Some(Self::Lz4),
+            "none" => Some(Self::None),
+            _ => None,
+        }
+    }
+    fn name(self) -> &'static str {
+        match self {
+            Self::Zstd => "zstd",
+            Self::Lz4 => "lz4",
+            Self::None => "none",
+        }
+    }
+    fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            0 => Some(Self::None),
+            1 => Some(Self::Zstd),
+            2 => Some(Self::Lz4),
+            _ => None,
+        }
+    }
+}
+
+/// `File` keeps the whole engine in one blob; `Directory` gives every table, the graph and
+/// a manifest their own file so a write only touches what changed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum StorageLayout {
+    File,
+    Directory,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct FieldDef {
     field_type: FieldType,
     required: bool,
     unique: bool,
+    #[serde(default)]
+    default: Option<Value>,
+    #[serde(default)]
+    constraints: FieldConstraints,
+    #[serde(default)]
+    references: Option<String>,
+    #[serde(default)]
+    on_delete: OnDelete,
+    #[serde(default)]
+    indexed: bool,
+    #[serde(default)]
+    description: Option<String>,
+    /// Unique fields accept any number of nulls unless this is set, which allows at most one
+    /// explicit null. Missing values never count.
+    #[serde(default)]
+    strict_unique_nulls: bool,
+    /// Expression evaluated at read time; computed fields are never stored.
+    #[serde(default)]
+    computed: Option<String>,
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+/// What happens to a referencing row when the record it points at is deleted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+enum OnDelete {
+    #[default]
+    Restrict,
+    Cascade,
+    SetNull,
+}
+
+impl OnDelete {
+    fn label(self) -> &'static str {
+        match self {
+            Self::Restrict => "restrict",
+            Self::Cascade => "cascade",
+            Self::SetNull => "set_null",
+        }
+    }
+    fn from_str(raw: &str) -> Option<Self> {
+        match raw.to_lowercase().as_str() {
+            "restrict" => Some(Self::Restrict),
+            "cascade" => Some(Self::Cascade),
+            "set_null" => Some(Self::SetNull),
+            _ => None,
+        }
+    }
+}
+
+/// What an import does with a row whose unique value another record already holds, and
+/// `merge` with a row that matches one here by its key field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OnConflict {
+    Error,
+    Skip,
+    Replace,
+}
+
+impl OnConflict {
+    fn from_str(raw: &str) -> Option<Self> {
+        match raw.to_lowercase().as_str() {
+            "error" => Some(Self::Error),
+            "skip" => Some(Self::Skip),
+            "replace" => Some(Self::Replace),
+            _ => None,
+        }
+    }
+}
+
+/// What `export_sqlite` does when the destination file already has the table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExportMode {
+    Fail,
+    Replace,
+    Append,
+}
+
+impl ExportMode {
+    fn from_str(raw: &str) -> Option<Self> {
+        match raw.to_lowercase().as_str() {
+            "fail" => Some(Self::Fail),
+            "replace" => Some(Self::Replace),
+            "append" => Some(Self::Append),
+            _ => None,
+        }
+    }
+}
+
+/// How `format_results` and `PRINT` lay results out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ResultStyle {
+    Table,
+    Markdown,
+    Json,
+}
+
+impl ResultStyle {
+    fn from_str(raw: &str) -> Option<Self> {
+        match raw.to_lowercase().as_str() {
+            "table" => Some(Self::Table),
+            "markdown" => Some(Self::Markdown),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+}
+
+/// The file format `export_graph` writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum GraphFormat {
+    GraphMl,
+    Dot,
+}
+
+impl GraphFormat {
+    fn from_str(raw: &str) -> Option<Self> {
+        match raw.to_lowercase().as_str() {
+            "graphml" => Some(Self::GraphMl),
+            "dot" => Some(Self::Dot),
+            _ => None,
+        }
+    }
+}
+
+/// One row read by an import: its line (or row number), and its payload or why it could
+/// not be read.
+type ImportRow = (usize, Result<Map<String, Value>, String>);
+
+/// How a table differs from a baseline version of it, as `diff_table` reports it. Rows
+/// pair up by id or by a key field's value; `modified` holds `(id, baseline id)` pairs.
+#[derive(Debug, Default, PartialEq)]
+struct TableDiff {
+    /// Stored fields of both schemas, the ones rows are compared on.
+    fields: Vec<String>,
+    fields_added: Vec<String>,
+    fields_removed: Vec<String>,
+    added: Vec<u64>,
+    removed: Vec<u64>,
+    modified: Vec<(u64, u64)>,
+}
+
+/// Rows removed or detached by a delete, counted per table.
+#[derive(Debug, Default, PartialEq, Serialize)]
+struct DeleteSummary {
+    deleted: BTreeMap<String, usize>,
+    nulled: BTreeMap<String, usize>,
+}
+
+/// What a dumped row carries besides its fields: its version, when it was inserted into a
+/// TTL table and when it was soft-deleted. A plain insert would reset all three.
+#[derive(Debug, Default)]
+struct RowMarks {
+    version: Option<u64>,
+    created_at: Option<i64>,
+    deleted_at: Option<i64>,
+}
+
+/// In-place edits for array and json list fields.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ArrayOp {
+    Push,
+    Pull,
+    AddUnique,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+struct FieldConstraints {
+    min: Option<f64>,
+    max: Option<f64>,
+    min_length: Option<usize>,
+    max_length: Option<usize>,
+    pattern: Option<String>,
+}
+
+impl FieldDef {
+    fn new(field_type: FieldType) -> Self {
+        Self {
+            field_type,
+            required: false,
+            unique: false,
+            default: None,
+            constraints: FieldConstraints::default(),
+            references: None,
+            on_delete: OnDelete::Restrict,
+            indexed: false,
+            description: None,
+            strict_unique_nulls: false,
+            computed: None,
+        }
+    }
+    /// Rejects constraint sets that can never be satisfied or don't apply to the field type.
+    fn validate_constraints(&self, field: &str) -> DbResult<()> {
+        let c = &self.constraints;
+        let invalid = |reason: &str| DbError::InvalidConstraint {
+            field: field.to_string(),
+            reason: reason.to_string(),
+        };
+        let numeric = matches!(self.field_type, FieldType::Integer | FieldType::Float);
+        if (c.min.is_some() || c.max.is_some()) && !numeric {
+            return Err(invalid("min/max require an integer or float field"));
+        }
+        let textual = c.min_length.is_some() || c.max_length.is_some() || c.pattern.is_some();
+        if textual && self.field_type != FieldType::String {
+            return Err(invalid(
+                "length and pattern constraints require a string field",
+            ));
+        }
+        if let (Some(lo), Some(hi)) = (c.min, c.max) {
+            if hi < lo {
+                return Err(invalid("max is less than min"));
+            }
+        }
+        if let (Some(lo), Some(hi)) = (c.min_length, c.max_length) {
+            if hi < lo {
+                return Err(invalid("max_length is less than min_length"));
+            }
+        }
+        if let Some(p) = &c.pattern {
+            Regex::new(p).map_err(|e| invalid(&format!("bad pattern: {}", e)))?;
+        }
+        if self.references.is_some() && self.field_type != FieldType::Integer {
+            return Err(invalid("references require an integer field"));
+        }
+        if self.strict_unique_nulls && !self.unique {
+            return Err(invalid("strict_unique_nulls requires a unique field"));
+        }
+        if self.on_delete != OnDelete::Restrict && self.references.is_none() {
+            return Err(invalid("on_delete requires a references field"));
+        }
+        if self.on_delete == OnDelete::SetNull && self.required {
+            return Err(invalid("on_delete=set_null requires an optional field"));
+        }
+        if let Some(src) = &self.computed {
+            let stored_only = self.required
+                || self.unique
+                || self.indexed
+                || self.default.is_some()
+                || self.references.is_some()
+                || *c != FieldConstraints::default();
+            if stored_only {
+                return Err(invalid(
+                    "computed fields cannot be required, unique, indexed, defaulted, constrained or references",
+                ));
+            }
+            expr::parse(src).map_err(|e| invalid(&format!("bad expression: {}", e)))?;
+        }
+        Ok(())
+    }
+    /// Key tracked in the unique cache for `value`, if it takes part in uniqueness at all.
+    fn unique_key(&self, value: Option<&Value>) -> Option<String> {
+        let v = value?;
+        if !self.unique || (v.is_null() && !self.strict_unique_nulls) {
+            return None;
+        }
+        Some(v.to_string())
+    }
+    /// Schema entry as shown by `Database.schema`; unset options are left out.
+    fn describe(&self) -> Value {
+        let mut out = Map::new();
+        out.insert("type".to_string(), Value::String(self.field_type.label()));
+        out.insert("required".to_string(), Value::Bool(self.required));
+        out.insert("unique".to_string(), Value::Bool(self.unique));
+        if self.strict_unique_nulls {
+            out.insert("strict_unique_nulls".to_string(), Value::Bool(true));
+        }
+        out.insert("indexed".to_string(), Value::Bool(self.indexed));
+        if let Some(default) = &self.default {
+            out.insert("default".to_string(), default.clone());
+        }
+        if let Ok(Value::Object(constraints)) = serde_json::to_value(&self.constraints) {
+            out.extend(constraints.into_iter().filter(|(_, v)| !v.is_null()));
+        }
+        if let Some(target) = &self.references {
+            out.insert("references".to_string(), Value::String(target.clone()));
+            out.insert("on_delete".to_string(), self.on_delete.label().into());
+        }
+        if let Some(src) = &self.computed {
+            out.insert("computed".to_string(), Value::String(src.clone()));
+        }
+        if let Some(description) = &self.description {
+            out.insert(
+                "description".to_string(),
+                Value::String(description.clone()),
+            );
+        }
+        Value::Object(out)
+    }
+    fn check_constraints(
+        &self,
+        field: &str,
+        value: &Value,
+        pattern: Option<&Regex>,
+    ) -> DbResult<()> {
+        let c = &self.constraints;
+        let violation = |constraint: String| DbError::ConstraintViolation {
+            field: field.to_string(),
+            constraint,
+        };
+        if let Some(n) = value.as_f64() {
+            if let Some(lo) = c.min.filter(|lo| n < *lo) {
+                return Err(violation(format!("min={}", lo)));
+            }
+            if let Some(hi) = c.max.filter(|hi| n > *hi) {
+                return Err(violation(format!("max={}", hi)));
+            }
+        }
+        if let Some(text) = value.as_str() {
+            let len = text.chars().count();
+            if let Some(lo) = c.min_length.filter(|lo| len < *lo) {
+                return Err(violation(format!("min_length={}", lo)));
+            }
+            if let Some(hi) = c.max_length.filter(|hi| len > *hi) {
+                return Err(violation(format!("max_length={}", hi)));
+            }
+            if let (Some(raw), Some(re)) = (&c.pattern, pattern) {
+                if !re.is_match(text) {
+                    return Err(violation(format!("pattern={}", raw)));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Serialized externally tagged, so scalar variants keep their plain-string form in old files
+/// while `Array` is written as `{"Array": <element>}`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 enum FieldType {
     String,
     Integer,
     Float,
     Boolean,
     Json,
+    DateTime,
+    Array(Box<FieldType>),
 }
 
 impl FieldType {
@@ -87,16 +596,48 @@ impl FieldType {
             "float" | "double" | "number" => Some(Self::Float),
             "boolean" | "bool" => Some(Self::Boolean),
             "json" | "object" => Some(Self::Json),
-            _ => None,
+            "datetime" | "timestamp" | "date" => Some(Self::DateTime),
+            "array" | "list" => Some(Self::Array(Box::new(Self::Json))),
+            // The `array<elem>` spelling is what `label` (and so `export_schema`) emits.
+            lower => {
+                let elem = lower.strip_prefix("array<")?.strip_suffix('>')?;
+                Self::from_str(elem)
+                    .filter(|e| !matches!(e, Self::Array(_)))
+                    .map(|e| Self::Array(Box::new(e)))
+            }
         }
     }
-    fn label(&self) -> &'static str {
+    /// The closest type for a SQLite column declared as `declared`, by SQLite's own affinity
+    /// rules plus the common BOOLEAN and DATE/TIME spellings. BLOB columns become strings
+    /// holding base64. None for NUMERIC affinity and untyped columns, which may hold anything.
+    fn from_sqlite(declared: &str) -> Option<Self> {
+        let upper = declared.to_ascii_uppercase();
+        let has = |part: &str| upper.contains(part);
+        if has("INT") {
+            Some(Self::Integer)
+        } else if has("CHAR") || has("CLOB") || has("TEXT") || has("BLOB") {
+            Some(Self::String)
+        } else if has("REAL") || has("FLOA") || has("DOUB") {
+            Some(Self::Float)
+        } else if has("BOOL") {
+            Some(Self::Boolean)
+        } else if has("DATE") || has("TIME") {
+            Some(Self::DateTime)
+        } else if has("JSON") {
+            Some(Self::Json)
+        } else {
+            None
+        }
+    }
+    fn label(&self) -> String {
         match self {
-            Self::String => "string",
-            Self::Integer => "integer",
-            Self::Float => "float",
-            Self::Boolean => "boolean",
-            Self::Json => "json",
+            Self::String => "string".to_string(),
+            Self::Integer => "integer".to_string(),
+            Self::Float => "float".to_string(),
+            Self::Boolean => "boolean".to_string(),
+            Self::Json => "json".to_string(),
+            Self::DateTime => "datetime".to_string(),
+            Self::Array(elem) => format!("array<{}>", elem.label()),
         }
     }
     fn sql_label(&self) -> &'static str {
@@ -106,6 +647,8 @@ impl FieldType {
             Self::Float => "REAL",
             Self::Boolean => "INTEGER",
             Self::Json => "TEXT",
+            Self::DateTime => "TEXT",
+            Self::Array(_) => "TEXT",
         }
     }
     fn matches(&self, value: &Value) -> bool {
@@ -115,6 +658,12 @@ impl FieldType {
             Self::Float => value.is_number(),
             Self::Boolean => value.is_boolean(),
             Self::Json => true,
+            Self::DateTime => value
+                .as_str()
+                .is_some_and(|s| normalize_datetime(s).as_deref() == Some(s)),
+            Self::Array(elem) => value
+                .as_array()
+                .is_some_and(|items| items.iter().all(|v| elem.matches(v))),
         }
     }
     fn coerce(&self, value: Value) -> Option<Value> {
@@ -128,7 +677,7 @@ impl FieldType {
             (Self::Float, Value::String(s)) => s
                 .parse::<f64>()
                 .ok()
-                .and_then(|f| serde_json::Number::from_f64(f))
+                .and_then(serde_json::Number::from_f64)
                 .map(Value::Number),
             (Self::Boolean, Value::String(s)) => match s.to_lowercase().as_str() {
                 "true" | "1" | "yes" => Some(Value::Bool(true)),
@@ -136,968 +685,5500 @@ impl FieldType {
                 _ => None,
             },
             (Self::String, v) => Some(Value::String(v.to_string())),
+            (Self::DateTime, Value::String(s)) => normalize_datetime(&s).map(Value::String),
+            (Self::DateTime, Value::Number(n)) => n
+                .as_i64()
+                .and_then(|secs| DateTime::<Utc>::from_timestamp(secs, 0))
+                .map(|dt| Value::String(dt.to_rfc3339_opts(SecondsFormat::AutoSi, true))),
+            (Self::Array(elem), Value::Array(items)) => items
+                .into_iter()
+                .map(|v| elem.coerce_element(v))
+                .collect::<Option<Vec<_>>>()
+                .map(Value::Array),
             _ => None,
         }
     }
+    fn coerce_element(&self, value: Value) -> Option<Value> {
+        if value.is_null() && *self != Self::Json {
+            return None;
+        }
+        self.coerce(value)
+    }
+    /// Like `coerce`, but reports the field (and offending array index) on failure.
+    fn conform(&self, field: &str, value: Value) -> DbResult<Value> {
+        if let (Self::Array(elem), Value::Array(items)) = (self, &value) {
+            if let Some(index) = items
+                .iter()
+                .position(|v| elem.coerce_element(v.clone()).is_none())
+            {
+                return Err(DbError::ElementTypeMismatch {
+                    field: field.to_string(),
+                    index,
+                    expected: elem.label(),
+                });
+            }
+        }
+        self.coerce(value).ok_or_else(|| DbError::TypeMismatch {
+            field: field.to_string(),
+            expected: self.label(),
+        })
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct Table {
     schema: HashMap<String, FieldDef>,
-    records: HashMap<u64, Map<String, Value>>,
+    records: BTreeMap<u64, Map<String, Value>>,
     next_id: u64,
     #[serde(skip)]
     unique_cache: HashMap<String, HashSet<String>>,
+    #[serde(skip)]
+    pattern_cache: HashMap<String, Regex>,
+    #[serde(skip)]
+    computed: HashMap<String, Expr>,
+    /// When set, every record carries a version bumped on each update (see `versions`).
+    #[serde(default)]
+    versioned: bool,
+    #[serde(default)]
+    versions: HashMap<u64, u64>,
+    /// Rows older than this are hidden from reads and purged on the next write or persist.
+    #[serde(default)]
+    ttl_seconds: Option<u64>,
+    /// Insert time of each row in epoch milliseconds, tracked only while `ttl_seconds` is set.
+    #[serde(default)]
+    created_at: HashMap<u64, i64>,
+    /// Opt-in soft delete: rows are marked in `deleted_at` (epoch milliseconds) and hidden
+    /// from reads until restored or purged.
+    #[serde(default)]
+    soft_delete: bool,
+    /// Lets soft-deleted rows give up their unique values instead of keeping them reserved.
+    #[serde(default)]
+    soft_delete_releases_unique: bool,
+    #[serde(default)]
+    deleted_at: HashMap<u64, i64>,
+    /// Free-form table metadata (descriptions, owners, units...) persisted with the file.
+    #[serde(default)]
+    meta: Map<String, Value>,
+    /// Secondary indexes over indexed and unique fields: field -> serialized value -> record ids.
+    #[serde(skip)]
+    indexes: HashMap<String, HashMap<String, BTreeSet<u64>>>,
 }
 
 impl Table {
     fn new(schema: HashMap<String, FieldDef>) -> Self {
-        Self {
+        let mut table = Self {
             schema,
-            records: HashMap::new(),
+            records: BTreeMap::new(),
             next_id: 1,
+            versioned: false,
+            versions: HashMap::new(),
+            ttl_seconds: None,
+            created_at: HashMap::new(),
+            soft_delete: false,
+            soft_delete_releases_unique: false,
+            deleted_at: HashMap::new(),
+            meta: Map::new(),
             unique_cache: HashMap::new(),
+            pattern_cache: HashMap::new(),
+            computed: HashMap::new(),
+            indexes: HashMap::new(),
+        };
+        table.compile_patterns();
+        table.compile_computed();
+        table.rebuild_indexes();
+        table
+    }
+    /// A table with this one's schema and settings but no rows.
+    fn emptied(&self) -> Self {
+        let mut table = Self::new(self.schema.clone());
+        table.meta = self.meta.clone();
+        table.versioned = self.versioned;
+        table.ttl_seconds = self.ttl_seconds;
+        table.soft_delete = self.soft_delete;
+        table.soft_delete_releases_unique = self.soft_delete_releases_unique;
+        table
+    }
+    fn compile_patterns(&mut self) {
+        self.pattern_cache = self
+            .schema
+            .iter()
+            .filter_map(|(f, def)| {
+                let re = Regex::new(def.constraints.pattern.as_deref()?).ok()?;
+                Some((f.clone(), re))
+            })
+            .collect();
+    }
+    fn compile_computed(&mut self) {
+        self.computed = self
+            .schema
+            .iter()
+            .filter_map(|(f, def)| Some((f.clone(), expr::parse(def.computed.as_deref()?).ok()?)))
+            .collect();
+    }
+    /// Computed fields may only read stored fields of this table.
+    fn check_computed(&self) -> DbResult<()> {
+        for (field, e) in &self.computed {
+            for dep in e.fields() {
+                let stored = self.schema.get(dep).is_some_and(|d| d.computed.is_none());
+                if !stored {
+                    return Err(DbError::InvalidConstraint {
+                        field: field.clone(),
+                        reason: format!("expression reads `{}`, which is not a stored field", dep),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+    /// `record` with every computed field filled in, coerced to its declared type.
+    fn materialize<'r>(&self, record: &'r Map<String, Value>) -> Cow<'r, Map<String, Value>> {
+        if self.computed.is_empty() {
+            return Cow::Borrowed(record);
+        }
+        let mut out = record.clone();
+        for (field, e) in &self.computed {
+            let value = e.eval(record);
+            let value = match self.schema.get(field) {
+                Some(def) if !value.is_null() && !def.field_type.matches(&value) => {
+                    def.field_type.coerce(value).unwrap_or(Value::Null)
+                }
+                _ => value,
+            };
+            out.insert(field.clone(), value);
         }
+        Cow::Owned(out)
     }
+    /// With `strict_types` set, values must already match their declared type instead of
+    /// being coerced.
     fn validate_payload(
         &self,
         payload: &mut Map<String, Value>,
         updating: Option<u64>,
+        strict_types: bool,
     ) -> DbResult<()> {
         for field in payload.keys() {
-            if !self.schema.contains_key(field) {
-                return Err(DbError::UnknownField(field.clone()));
+            match self.schema.get(field) {
+                None => return Err(DbError::UnknownField(field.clone())),
+                Some(def) if def.computed.is_some() => {
+                    return Err(DbError::ComputedField(field.clone()))
+                }
+                Some(_) => {}
             }
         }
         for (field, def) in &self.schema {
             if let Some(value) = payload.get_mut(field) {
                 if !value.is_null() && !def.field_type.matches(value) {
-                    if let Some(coerced) = def.field_type.coerce(value.clone()) {
-                        *value = coerced;
-                    } else {
+                    if strict_types {
                         return Err(DbError::TypeMismatch {
                             field: field.clone(),
-                            expected: def.field_type.label().to_string(),
+                            expected: def.field_type.label(),
                         });
                     }
+                    *value = def.field_type.conform(field, value.take())?;
+                }
+                if !value.is_null() {
+                    def.check_constraints(field, value, self.pattern_cache.get(field))?;
                 }
             } else if def.required {
                 return Err(DbError::MissingField(field.clone()));
             }
-            if def.unique {
-                if let Some(candidate) = payload.get(field) {
-                    let serialized = candidate.to_string();
-                    if let Some(set) = self.unique_cache.get(field) {
-                        if set.contains(&serialized) {
-                            if let Some(rid) = updating {
-                                if let Some(old_record) = self.records.get(&rid) {
-                                    if let Some(old_val) = old_record.get(field) {
-                                        if old_val == candidate {
-                                            continue;
-                                        }
-                                    }
+            if let Some(serialized) = def.unique_key(payload.get(field)) {
+                if let Some(set) = self.unique_cache.get(field) {
+                    if set.contains(&serialized) {
+                        if let Some(rid) = updating {
+                            if let Some(old_record) = self.records.get(&rid) {
+                                if old_record.get(field) == payload.get(field) {
+                                    continue;
                                 }
                             }
-                            return Err(DbError::UniqueViolation(field.clone()));
                         }
+                        return Err(DbError::UniqueViolation(field.clone()));
                     }
                 }
             }
         }
         Ok(())
     }
-    fn insert(&mut self, mut payload: Map<String, Value>) -> DbResult<u64> {
-        self.validate_payload(&mut payload, None)?;
-        for (f, def) in &self.schema {
-            if def.unique {
-                if let Some(val) = payload.get(f) {
-                    self.unique_cache
-                        .entry(f.clone())
-                        .or_insert_with(HashSet::new)
-                        .insert(val.to_string());
+    /// Rebuilds everything `serde(skip)` leaves empty after deserializing.
+    fn rebuild_caches(&mut self) {
+        self.rebuild_unique_cache();
+        self.compile_patterns();
+        self.compile_computed();
+        self.rebuild_indexes();
+        self.next_id = self.next_id.max(self.min_next_id());
+    }
+    fn rebuild_unique_cache(&mut self) {
+        self.unique_cache.clear();
+        for (id, record) in &self.records {
+            if self.soft_delete_releases_unique && self.deleted_at.contains_key(id) {
+                continue;
+            }
+            for (f, def) in &self.schema {
+                if let Some(key) = def.unique_key(record.get(f)) {
+                    self.unique_cache.entry(f.clone()).or_default().insert(key);
                 }
             }
         }
-        let id = self.next_id;
-        self.next_id += 1;
-        self.records.insert(id, payload);
-        Ok(id)
     }
-    fn delete(&mut self, rid: u64) -> DbResult<()> {
-        let old = self
-            .records
-            .remove(&rid)
-            .ok_or(DbError::MissingRecord(rid))?;
-        for (f, def) in &self.schema {
-            if def.unique {
-                if let Some(val) = old.get(f) {
-                    if let Some(set) = self.unique_cache.get_mut(f) {
-                        set.remove(&val.to_string());
+    fn rebuild_indexes(&mut self) {
+        self.indexes = self
+            .schema
+            .iter()
+            .filter(|(_, def)| def.indexed || def.unique)
+            .map(|(f, _)| (f.clone(), HashMap::new()))
+            .collect();
+        let records = std::mem::take(&mut self.records);
+        for (id, record) in &records {
+            self.index_record(*id, record);
+        }
+        self.records = records;
+    }
+    fn index_record(&mut self, id: u64, record: &Map<String, Value>) {
+        for (f, index) in &mut self.indexes {
+            if let Some(val) = record.get(f) {
+                index.entry(val.to_string()).or_default().insert(id);
+            }
+        }
+    }
+    fn unindex_record(&mut self, id: u64, record: &Map<String, Value>) {
+        for (f, index) in &mut self.indexes {
+            if let Some(key) = record.get(f).map(Value::to_string) {
+                if let Some(ids) = index.get_mut(&key) {
+                    ids.remove(&id);
+                    if ids.is_empty() {
+                        index.remove(&key);
                     }
                 }
             }
         }
-        Ok(())
     }
-    fn update(&mut self, rid: u64, patch: Map<String, Value>) -> DbResult<()> {
-        let mut merged = self
-            .records
-            .get(&rid)
-            .cloned()
-            .ok_or(DbError::MissingRecord(rid))?;
-        for (k, v) in patch {
-            merged.insert(k, v);
+    /// Id of the visible record whose `field` equals `value`, looked up through the field's
+    /// index; soft-deleted and expired rows never match.
+    fn find_by(&self, field: &str, value: &Value, now: i64) -> DbResult<Option<u64>> {
+        let def = self
+            .schema
+            .get(field)
+            .ok_or_else(|| DbError::UnknownField(field.to_string()))?;
+        let index = self
+            .indexes
+            .get(field)
+            .ok_or_else(|| DbError::UpsertKey(field.to_string()))?;
+        let key = if def.field_type.matches(value) {
+            value.clone()
+        } else {
+            def.field_type.conform(field, value.clone())?
+        };
+        let Some(ids) = index.get(&key.to_string()) else {
+            return Ok(None);
+        };
+        let mut live = ids.iter().copied().filter(|id| !self.hidden(*id, now));
+        let found = live.next();
+        if live.next().is_some() {
+            return Err(DbError::AmbiguousUpsert(field.to_string()));
         }
-        self.validate_payload(&mut merged, Some(rid))?;
-        for (f, def) in &self.schema {
-            if def.unique {
-                if let Some(old_record) = self.records.get(&rid) {
-                    if let Some(old_val) = old_record.get(f) {
-                        if let Some(set) = self.unique_cache.get_mut(f) {
-                            set.remove(&old_val.to_string());
-                        }
-                    }
-                }
-                if let Some(new_val) = merged.get(f) {
-                    self.unique_cache
-                        .entry(f.clone())
-                        .or_insert_with(HashSet::new)
-                        .insert(new_val.to_string());
-                }
+        Ok(found)
+    }
+    /// Ids of visible rows sharing the same values for `fields`, one ascending group per
+    /// key with more than one row. Without `nulls_equal`, rows with a null key never match.
+    fn duplicate_groups(
+        &self,
+        fields: &[String],
+        nulls_equal: bool,
+        now: i64,
+    ) -> DbResult<Vec<Vec<u64>>> {
+        if let Some(f) = fields.iter().find(|f| !self.schema.contains_key(*f)) {
+            return Err(DbError::UnknownField(f.clone()));
+        }
+        let mut groups: HashMap<String, Vec<u64>> = HashMap::new();
+        for (id, record) in &self.records {
+            if self.hidden(*id, now) {
+                continue;
             }
+            let record = self.materialize(record);
+            let key: Vec<&Value> = fields
+                .iter()
+                .map(|f| record.get(f).unwrap_or(&Value::Null))
+                .collect();
+            if !nulls_equal && key.iter().any(|v| v.is_null()) {
+                continue;
+            }
+            let key = serde_json::to_string(&key).unwrap_or_default();
+            groups.entry(key).or_default().push(*id);
         }
-        self.records.insert(rid, merged);
-        Ok(())
+        let mut out: Vec<Vec<u64>> = groups.into_values().filter(|g| g.len() > 1).collect();
+        out.sort_unstable();
+        Ok(out)
     }
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct Engine {
-    tables: HashMap<String, Table>,
-    aliases: HashMap<String, String>,
-    graph_rag: GraphRagEngine,
-    alive: alive::AliveState,
-}
-
-impl Engine {
-    fn new() -> Self {
-        Self {
-            tables: HashMap::new(),
-            aliases: HashMap::new(),
-            graph_rag: GraphRagEngine::new(),
-            alive: alive::AliveState::default(),
+    /// Smallest `next_id` that cannot hand out an id already in use.
+    fn min_next_id(&self) -> u64 {
+        self.records.keys().next_back().map_or(1, |id| id + 1)
+    }
+    /// Sets `next_id` to `value`, or to just past the highest id when `None`.
+    fn reset_sequence(&mut self, value: Option<u64>) -> DbResult<u64> {
+        let floor = self.min_next_id();
+        let value = value.unwrap_or(floor);
+        if value < floor {
+            return Err(DbError::SequenceCollision { value, floor });
         }
+        self.next_id = value;
+        Ok(value)
     }
-    fn rebuild_cache(&mut self) {
-        self.graph_rag.rebuild_tfidf();
-        for table in self.tables.values_mut() {
-            table.unique_cache.clear();
-            for record in table.records.values() {
-                for (f, def) in &table.schema {
-                    if def.unique {
-                        if let Some(val) = record.get(f) {
-                            table
-                                .unique_cache
-                                .entry(f.clone())
-                                .or_insert_with(HashSet::new)
-                                .insert(val.to_string());
-                        }
-                    }
+    fn version(&self, rid: u64) -> Option<u64> {
+        self.versioned
+            .then(|| self.versions.get(&rid).copied().unwrap_or(1))
+    }
+    fn is_expired(&self, rid: u64, now: i64) -> bool {
+        let (Some(ttl), Some(created)) = (self.ttl_seconds, self.created_at.get(&rid)) else {
+            return false;
+        };
+        now.saturating_sub(*created) >= (ttl as i64).saturating_mul(1000)
+    }
+    /// Expired or soft-deleted: left out of reads unless asked for.
+    fn hidden(&self, rid: u64, now: i64) -> bool {
+        self.deleted_at.contains_key(&rid) || self.is_expired(rid, now)
+    }
+    fn live_count(&self, now: i64) -> usize {
+        self.records
+            .keys()
+            .filter(|id| !self.hidden(**id, now))
+            .count()
+    }
+    /// The stored record unless it is missing or soft-deleted; writes go through this.
+    fn live_record(&self, rid: u64) -> DbResult<&Map<String, Value>> {
+        self.records
+            .get(&rid)
+            .filter(|_| !self.deleted_at.contains_key(&rid))
+            .ok_or(DbError::MissingRecord(rid))
+    }
+    fn unique_keys(&self, rid: u64) -> Vec<(String, String)> {
+        let Some(record) = self.records.get(&rid) else {
+            return Vec::new();
+        };
+        self.schema
+            .iter()
+            .filter_map(|(f, def)| Some((f.clone(), def.unique_key(record.get(f))?)))
+            .collect()
+    }
+    /// The visible record whose value of unique field `field` clashes with `value`.
+    fn unique_holder(&self, field: &str, value: &Value, now: i64) -> Option<u64> {
+        let def = self.schema.get(field)?;
+        let key = def.unique_key(Some(&def.field_type.coerce(value.clone())?))?;
+        self.records
+            .iter()
+            .filter(|(id, _)| !self.hidden(**id, now))
+            .find(|(_, r)| def.unique_key(r.get(field)).as_ref() == Some(&key))
+            .map(|(id, _)| *id)
+    }
+    /// Hides `rid` as of `now`; the caller checks the table opted into soft delete.
+    fn mark_deleted(&mut self, rid: u64, now: i64) -> DbResult<()> {
+        self.live_record(rid)?;
+        if self.soft_delete_releases_unique {
+            for (f, key) in self.unique_keys(rid) {
+                if let Some(set) = self.unique_cache.get_mut(&f) {
+                    set.remove(&key);
                 }
             }
         }
+        self.deleted_at.insert(rid, now);
+        Ok(())
     }
-    fn create_table(&mut self, name: &str, schema: HashMap<String, FieldDef>) -> DbResult<()> {
-        if self.tables.contains_key(name) {
-            return Err(DbError::TableExists(name.to_string()));
+    /// Clears a soft-delete mark, reclaiming unique values if they were released.
+    fn restore(&mut self, rid: u64) -> DbResult<()> {
+        if !self.deleted_at.contains_key(&rid) {
+            return Err(DbError::MissingRecord(rid));
         }
-        self.tables.insert(name.to_string(), Table::new(schema));
+        if self.soft_delete_releases_unique {
+            let keys = self.unique_keys(rid);
+            if let Some((f, _)) = keys.iter().find(|(f, key)| {
+                self.unique_cache
+                    .get(f)
+                    .is_some_and(|set| set.contains(key))
+            }) {
+                return Err(DbError::UniqueViolation(f.clone()));
+            }
+            for (f, key) in keys {
+                self.unique_cache.entry(f).or_default().insert(key);
+            }
+        }
+        self.deleted_at.remove(&rid);
         Ok(())
     }
-}
-
-#[pyclass]
-struct Record {
-    #[pyo3(get)]
-    id: u64,
-    #[pyo3(get)]
-    data: PyObject,
-}
+    /// The rows past their TTL, for `Engine::purge_expired` to delete.
+    fn expired_ids(&self, now: i64) -> Vec<u64> {
+        if self.ttl_seconds.is_none() {
+            return Vec::new();
+        }
+        self.records
+            .keys()
+            .copied()
+            .filter(|id| self.is_expired(*id, now))
+            .collect()
+    }
+    /// Sets or, when `value` is `None`, removes one table metadata entry.
+    fn set_meta(&mut self, key: &str, value: Option<Value>) -> DbResult<()> {
+        match value {
+            Some(v) => {
+                if v.to_string().len() > MAX_META_VALUE_BYTES {
+                    return Err(DbError::MetadataTooLarge(key.to_string()));
+                }
+                self.meta.insert(key.to_string(), v);
+            }
+            None => {
+                self.meta.remove(key);
+            }
+        }
+        Ok(())
+    }
+    fn describe(&self) -> Value {
+        let fields: Map<String, Value> = self
+            .schema
+            .iter()
+            .map(|(f, def)| (f.clone(), def.describe()))
+            .collect();
+        let mut out = Map::new();
+        out.insert("fields".to_string(), Value::Object(fields));
+        out.insert("meta".to_string(), Value::Object(self.meta.clone()));
+        if self.versioned {
+            out.insert("versioned".to_string(), Value::Bool(true));
+        }
+        if let Some(ttl) = self.ttl_seconds {
+            out.insert("ttl_seconds".to_string(), Value::from(ttl));
+        }
+        if self.soft_delete {
+            out.insert("soft_delete".to_string(), Value::Bool(true));
+        }
+        if self.soft_delete_releases_unique {
+            out.insert("soft_delete_releases_unique".to_string(), Value::Bool(true));
+        }
+        Value::Object(out)
+    }
+    /// One entry of `SHOW TABLES FULL`.
+    fn overview(&self, name: &str, now: i64) -> Value {
+        serde_json::json!({
+            "name": name,
+            "records": self.live_count(now),
+            "fields": self.schema.len(),
+            "unique": self.schema.values().any(|def| def.unique),
+            "indexed": self.schema.values().any(|def| def.indexed),
+        })
+    }
+    fn create_index(&mut self, field: &str) -> DbResult<()> {
+        let def = self
+            .schema
+            .get_mut(field)
+            .ok_or_else(|| DbError::UnknownField(field.to_string()))?;
+        def.indexed = true;
+        self.rebuild_indexes();
+        Ok(())
+    }
+    /// Coerces every stored value of `field` to `new_type`. In strict mode any failure aborts
+    /// without touching the table; otherwise unconvertible values are nulled and their ids returned.
+    fn change_field_type(
+        &mut self,
+        field: &str,
+        new_type: FieldType,
+        strict: bool,
+    ) -> DbResult<Vec<u64>> {
+        let def = self
+            .schema
+            .get(field)
+            .ok_or_else(|| DbError::UnknownField(field.to_string()))?;
+        FieldDef {
+            field_type: new_type.clone(),
+            ..def.clone()
+        }
+        .validate_constraints(field)?;
+        let mut converted = Vec::new();
+        let mut failed = Vec::new();
+        for (id, record) in &self.records {
+            if let Some(value) = record.get(field).filter(|v| !v.is_null()) {
+                match new_type.coerce(value.clone()) {
+                    Some(c) => converted.push((*id, c)),
+                    None => failed.push(*id),
+                }
+            }
+        }
+        failed.sort_unstable();
+        if strict && !failed.is_empty() {
+            return Err(DbError::ConversionFailed {
+                field: field.to_string(),
+                expected: new_type.label(),
+                ids: failed,
+            });
+        }
+        let mut seen = HashSet::new();
+        let mut keys = converted
+            .iter()
+            .filter_map(|(_, v)| def.unique_key(Some(v)));
+        if !keys.all(|k| seen.insert(k)) {
+            return Err(DbError::UniqueViolation(field.to_string()));
+        }
+        for (id, value) in converted {
+            if let Some(record) = self.records.get_mut(&id) {
+                record.insert(field.to_string(), value);
+            }
+        }
+        for id in &failed {
+            if let Some(record) = self.records.get_mut(id) {
+                record.insert(field.to_string(), Value::Null);
+            }
+        }
+        if let Some(def) = self.schema.get_mut(field) {
+            def.field_type = new_type;
+        }
+        self.rebuild_unique_cache();
+        self.rebuild_indexes();
+        Ok(failed)
+    }
+    fn apply_defaults(&self, payload: &mut Map<String, Value>) {
+        for (field, def) in &self.schema {
+            if let Some(default) = &def.default {
+                if !payload.contains_key(field) {
+                    payload.insert(field.clone(), default.clone());
+                }
+            }
+        }
+    }
+    fn prepare_insert(
+        &self,
+        mut payload: Map<String, Value>,
+        strict_types: bool,
+    ) -> DbResult<Map<String, Value>> {
+        self.apply_defaults(&mut payload);
+        self.validate_payload(&mut payload, None, strict_types)?;
+        Ok(payload)
+    }
+    /// Stores an already-validated payload under the next id.
+    fn store(&mut self, payload: Map<String, Value>) -> u64 {
+        let id = self.next_id;
+        self.store_at(id, payload);
+        id
+    }
+    /// `store` under a given id, as journal replay needs.
+    fn store_at(&mut self, id: u64, payload: Map<String, Value>) {
+        for (f, def) in &self.schema {
+            if let Some(key) = def.unique_key(payload.get(f)) {
+                self.unique_cache.entry(f.clone()).or_default().insert(key);
+            }
+        }
+        self.next_id = self.next_id.max(id + 1);
+        if self.versioned {
+            self.versions.insert(id, 1);
+        }
+        if self.ttl_seconds.is_some() {
+            self.created_at.insert(id, now_millis());
+        }
+        self.index_record(id, &payload);
+        self.records.insert(id, payload);
+    }
+    /// Single-table insert without reference checks; callers go through `Engine::insert`.
+    #[cfg(test)]
+    fn insert(&mut self, payload: Map<String, Value>) -> DbResult<u64> {
+        let payload = self.prepare_insert(payload, false)?;
+        Ok(self.store(payload))
+    }
+    fn delete(&mut self, rid: u64) -> DbResult<()> {
+        let old = self
+            .records
+            .remove(&rid)
+            .ok_or(DbError::MissingRecord(rid))?;
+        self.unindex_record(rid, &old);
+        self.versions.remove(&rid);
+        self.created_at.remove(&rid);
+        // Released values may already belong to another row by now.
+        let released = self.deleted_at.remove(&rid).is_some() && self.soft_delete_releases_unique;
+        if released {
+            return Ok(());
+        }
+        for (f, def) in &self.schema {
+            if let Some(key) = def.unique_key(old.get(f)) {
+                if let Some(set) = self.unique_cache.get_mut(f) {
+                    set.remove(&key);
+                }
+            }
+        }
+        Ok(())
+    }
+    fn prepare_update(
+        &self,
+        rid: u64,
+        patch: Map<String, Value>,
+        strict_types: bool,
+    ) -> DbResult<Map<String, Value>> {
+        let mut merged = self.live_record(rid)?.clone();
+        for (k, v) in patch {
+            merged.insert(k, v);
+        }
+        self.validate_payload(&mut merged, Some(rid), strict_types)?;
+        Ok(merged)
+    }
+    /// Validates `payload` as the complete new contents of `rid`; fields it omits are dropped.
+    fn prepare_replace(
+        &self,
+        rid: u64,
+        mut payload: Map<String, Value>,
+        strict_types: bool,
+    ) -> DbResult<Map<String, Value>> {
+        self.live_record(rid)?;
+        self.apply_defaults(&mut payload);
+        self.validate_payload(&mut payload, Some(rid), strict_types)?;
+        Ok(payload)
+    }
+    /// `rid` with `fields` removed outright rather than nulled; required fields cannot go.
+    fn prepare_unset(&self, rid: u64, fields: &[String]) -> DbResult<Map<String, Value>> {
+        let mut record = self.live_record(rid)?.clone();
+        for field in fields {
+            match self.schema.get(field) {
+                None => return Err(DbError::UnknownField(field.clone())),
+                Some(def) if def.computed.is_some() => {
+                    return Err(DbError::ComputedField(field.clone()))
+                }
+                Some(def) if def.required => return Err(DbError::MissingField(field.clone())),
+                Some(_) => {
+                    record.remove(field);
+                }
+            }
+        }
+        Ok(record)
+    }
+    /// Swaps in an already-validated record, keeping unique caches and indexes in step.
+    fn store_update(&mut self, rid: u64, merged: Map<String, Value>) {
+        for (f, def) in &self.schema {
+            let old_key = self
+                .records
+                .get(&rid)
+                .and_then(|r| def.unique_key(r.get(f)));
+            if let Some(key) = old_key {
+                if let Some(set) = self.unique_cache.get_mut(f) {
+                    set.remove(&key);
+                }
+            }
+            if let Some(key) = def.unique_key(merged.get(f)) {
+                self.unique_cache.entry(f.clone()).or_default().insert(key);
+            }
+        }
+        if let Some(old_record) = self.records.remove(&rid) {
+            self.unindex_record(rid, &old_record);
+        }
+        if self.versioned {
+            *self.versions.entry(rid).or_insert(1) += 1;
+        }
+        self.index_record(rid, &merged);
+        self.records.insert(rid, merged);
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Engine {
+    tables: HashMap<String, Table>,
+    aliases: HashMap<String, String>,
+    /// Stored in a file of its own and read on first use (see `Database::graph`); files
+    /// from before the split carry it inline.
+    #[serde(default, skip_serializing)]
+    graph_rag: GraphRagEngine,
+    /// Whether `graph_rag` holds the stored graph rather than a placeholder.
+    #[serde(skip)]
+    graph_loaded: bool,
+    alive: alive::AliveState,
+    /// Reject values that only match their field type after coercion (`"42"` for an integer).
+    #[serde(default)]
+    strict_types: bool,
+    /// Bumped at each journaled checkpoint; only a journal stamped with the same epoch replays.
+    #[serde(default)]
+    journal_epoch: u64,
+    /// SHA-256 of each table's JSON as written to a single-file database, so `verify` can
+    /// tell which tables of a damaged file are still intact.
+    #[serde(default)]
+    checksums: BTreeMap<String, String>,
+    /// Commands typed at the prompt, kept only when the database was opened with
+    /// `persist_history` since they may quote sensitive literals.
+    #[serde(default, skip_serializing_if = "VecDeque::is_empty")]
+    history: VecDeque<String>,
+}
+
+/// A single-file document split into its parts without deserializing them, so `verify`
+/// can check each table on its own.
+#[derive(Deserialize)]
+struct RawEngine<'a> {
+    #[serde(borrow)]
+    tables: HashMap<String, &'a RawValue>,
+    #[serde(borrow, default)]
+    graph_rag: Option<&'a RawValue>,
+    #[serde(default)]
+    aliases: HashMap<String, String>,
+    #[serde(default)]
+    alive: alive::AliveState,
+    #[serde(default)]
+    strict_types: bool,
+    #[serde(default)]
+    checksums: BTreeMap<String, String>,
+}
+
+/// Last write of one directory-layout file.
+#[derive(Debug, Clone, Copy)]
+struct WrittenPart {
+    digest: [u8; 32],
+    raw: usize,
+    stored: usize,
+}
+
+/// Engine state outside tables and graph, stored as `manifest.rsn` in the directory layout.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Manifest {
+    /// Table name to its file inside the directory.
+    tables: BTreeMap<String, String>,
+    aliases: HashMap<String, String>,
+    alive: alive::AliveState,
+    strict_types: bool,
+    journal_epoch: u64,
+    #[serde(default, skip_serializing_if = "VecDeque::is_empty")]
+    history: VecDeque<String>,
+}
+
+impl Engine {
+    fn new() -> Self {
+        Self {
+            tables: HashMap::new(),
+            aliases: HashMap::new(),
+            graph_rag: GraphRagEngine::new(),
+            graph_loaded: true,
+            alive: alive::AliveState::default(),
+            strict_types: false,
+            journal_epoch: 0,
+            checksums: BTreeMap::new(),
+            history: VecDeque::new(),
+        }
+    }
+    /// Recomputes `checksums` from the tables as they are about to be serialized.
+    fn refresh_checksums(&mut self) -> serde_json::Result<()> {
+        self.checksums.clear();
+        for (name, t) in &self.tables {
+            let mut hasher = Sha256::new();
+            serde_json::to_writer(&mut hasher, t)?;
+            let digest = format!("{:x}", hasher.finalize());
+            self.checksums.insert(name.clone(), digest);
+        }
+        Ok(())
+    }
+    fn rebuild_cache(&mut self) {
+        self.graph_rag.rebuild_index();
+        for table in self.tables.values_mut() {
+            table.rebuild_caches();
+        }
+    }
+    /// Re-applies one journaled mutation on top of the snapshot it was logged against.
+    fn replay(&mut self, entry: journal::Entry) -> Result<(), String> {
+        match entry {
+            journal::Entry::Insert {
+                table,
+                id,
+                record,
+                created_at,
+            } => {
+                let record = serde_json::from_str(&record).map_err(|e| e.to_string())?;
+                self.purge_expired(&table, now_millis())
+                    .map_err(|e| e.to_string())?;
+                let t = self.table_mut(&table).map_err(|e| e.to_string())?;
+                t.store_at(id, record);
+                if let Some(created_at) = created_at {
+                    t.created_at.insert(id, created_at);
+                }
+            }
+            journal::Entry::Update { table, id, record } => {
+                let record = serde_json::from_str(&record).map_err(|e| e.to_string())?;
+                self.purge_expired(&table, now_millis())
+                    .map_err(|e| e.to_string())?;
+                let t = self.table_mut(&table).map_err(|e| e.to_string())?;
+                t.store_update(id, record);
+            }
+            journal::Entry::Delete { table, id } => {
+                self.delete(&table, id).map_err(|e| e.to_string())?;
+            }
+            journal::Entry::CreateTable { name, table } => {
+                let mut table: Table = serde_json::from_str(&table).map_err(|e| e.to_string())?;
+                table.rebuild_caches();
+                self.create_table(&name, table).map_err(|e| e.to_string())?;
+            }
+            journal::Entry::Ingest {
+                text,
+                source,
+                chunk_size,
+                chunk_overlap,
+                ingested_at,
+            } => {
+                self.graph_rag
+                    .ingest_with(&text, &source, chunk_size, chunk_overlap, ingested_at);
+            }
+            journal::Entry::Reingest {
+                text,
+                source,
+                chunk_size,
+                chunk_overlap,
+                ingested_at,
+            } => {
+                self.graph_rag.reingest_with(
+                    &text,
+                    &source,
+                    chunk_size,
+                    chunk_overlap,
+                    ingested_at,
+                );
+            }
+        }
+        Ok(())
+    }
+    fn table_mut(&mut self, name: &str) -> DbResult<&mut Table> {
+        self.tables
+            .get_mut(name)
+            .ok_or_else(|| DbError::MissingTable(name.to_string()))
+    }
+    fn create_table(&mut self, name: &str, table: Table) -> DbResult<()> {
+        if self.tables.contains_key(name) {
+            return Err(DbError::TableExists(name.to_string()));
+        }
+        table.check_computed()?;
+        for (field, def) in &table.schema {
+            if let Some(target) = &def.references {
+                if target != name && !self.tables.contains_key(target) {
+                    return Err(DbError::UnknownReference {
+                        field: field.clone(),
+                        table: target.clone(),
+                    });
+                }
+            }
+        }
+        self.tables.insert(name.to_string(), table);
+        Ok(())
+    }
+    /// Removes every row of `name` under the `on_delete` rules of the fields referencing
+    /// them, as `delete_many` would: nothing changes while a restrict reference holds one.
+    fn truncate_table(&mut self, name: &str, reset_ids: bool) -> DbResult<usize> {
+        let ids: Vec<u64> = self.table_mut(name)?.records.keys().copied().collect();
+        self.delete_rows(name, &ids)?;
+        let t = self.table_mut(name)?;
+        t.records.clear();
+        t.versions.clear();
+        t.created_at.clear();
+        t.deleted_at.clear();
+        t.rebuild_unique_cache();
+        t.rebuild_indexes();
+        if reset_ids {
+            t.next_id = 1;
+        }
+        Ok(ids.len())
+    }
+    /// Duplicates `src` under `dest`, optionally with its rows. With `preserve_ids` off the
+    /// copied rows are renumbered from 1 in id order. Fields referencing `src` itself point
+    /// at `dest` in the copy, following the renumbering.
+    fn copy_table(
+        &mut self,
+        src: &str,
+        dest: &str,
+        with_data: bool,
+        preserve_ids: bool,
+    ) -> DbResult<usize> {
+        if self.tables.contains_key(dest) {
+            return Err(DbError::TableExists(dest.to_string()));
+        }
+        let source = self
+            .tables
+            .get(src)
+            .ok_or_else(|| DbError::MissingTable(src.to_string()))?;
+        let mut copy = source.emptied();
+        let self_refs: Vec<String> = copy
+            .schema
+            .iter_mut()
+            .filter(|(_, def)| def.references.as_deref() == Some(src))
+            .map(|(field, def)| {
+                def.references = Some(dest.to_string());
+                field.clone()
+            })
+            .collect();
+        if with_data {
+            if preserve_ids {
+                copy.records = source.records.clone();
+                copy.versions = source.versions.clone();
+                copy.created_at = source.created_at.clone();
+                copy.deleted_at = source.deleted_at.clone();
+                copy.reset_sequence(Some(source.next_id.max(copy.min_next_id())))?;
+            } else {
+                for (n, (id, record)) in (1u64..).zip(&source.records) {
+                    copy.records.insert(n, record.clone());
+                    if let Some(v) = source.versions.get(id) {
+                        copy.versions.insert(n, *v);
+                    }
+                    if let Some(c) = source.created_at.get(id) {
+                        copy.created_at.insert(n, *c);
+                    }
+                    if let Some(d) = source.deleted_at.get(id) {
+                        copy.deleted_at.insert(n, *d);
+                    }
+                }
+                copy.next_id = copy.records.len() as u64 + 1;
+                let renumbered: HashMap<u64, u64> =
+                    source.records.keys().copied().zip(1..).collect();
+                for record in copy.records.values_mut() {
+                    for field in &self_refs {
+                        let Some(value) = record.get_mut(field) else {
+                            continue;
+                        };
+                        if let Some(n) = value.as_u64().and_then(|v| renumbered.get(&v)) {
+                            *value = Value::from(*n);
+                        }
+                    }
+                }
+            }
+            copy.rebuild_unique_cache();
+            copy.rebuild_indexes();
+        }
+        let copied = copy.records.len();
+        self.tables.insert(dest.to_string(), copy);
+        Ok(copied)
+    }
+    /// Creates several tables at once, ordering them so referenced tables come first.
+    /// Fails without creating anything on a name clash or an unresolvable reference.
+    fn create_tables(&mut self, mut pending: Vec<(String, Table)>) -> DbResult<Vec<String>> {
+        let mut known: HashSet<String> = self.tables.keys().cloned().collect();
+        for (name, _) in &pending {
+            if !known.insert(name.clone()) {
+                return Err(DbError::TableExists(name.clone()));
+            }
+        }
+        let mut ready: HashSet<String> = self.tables.keys().cloned().collect();
+        let mut ordered = Vec::new();
+        while !pending.is_empty() {
+            let (now, later): (Vec<_>, Vec<_>) = pending.into_iter().partition(|(name, t)| {
+                t.schema.values().all(|def| {
+                    def.references
+                        .as_ref()
+                        .is_none_or(|target| target == name || ready.contains(target))
+                })
+            });
+            if now.is_empty() {
+                let (field, table) = later
+                    .iter()
+                    .flat_map(|(name, t)| t.schema.iter().map(move |(f, def)| (name, f, def)))
+                    .find_map(|(name, f, def)| {
+                        let target = def.references.as_ref()?;
+                        (target != name && !ready.contains(target))
+                            .then(|| (f.clone(), target.clone()))
+                    })
+                    .unwrap_or_default();
+                return Err(DbError::UnknownReference { field, table });
+            }
+            ready.extend(now.iter().map(|(name, _)| name.clone()));
+            ordered.extend(now);
+            pending = later;
+        }
+        let mut created = Vec::new();
+        for (name, table) in ordered {
+            self.create_table(&name, table)?;
+            created.push(name);
+        }
+        Ok(created)
+    }
+    /// Ensures every non-null reference field in `record` points at an existing row.
+    /// Fails on a reference to a row that is missing, soft-deleted or expired.
+    fn check_references(&self, table: &str, record: &Map<String, Value>) -> DbResult<()> {
+        self.check_reference_targets(table, record, Some(now_millis()))
+    }
+    /// `check_references`, accepting hidden targets when `now` is `None`: loaded rows may
+    /// point at rows that were soft-deleted where they came from.
+    fn check_reference_targets(
+        &self,
+        table: &str,
+        record: &Map<String, Value>,
+        now: Option<i64>,
+    ) -> DbResult<()> {
+        let Some(t) = self.tables.get(table) else {
+            return Err(DbError::MissingTable(table.to_string()));
+        };
+        for (field, def) in &t.schema {
+            let Some(target) = &def.references else {
+                continue;
+            };
+            let Some(value) = record.get(field).filter(|v| !v.is_null()) else {
+                continue;
+            };
+            let exists = value
+                .as_u64()
+                .zip(self.tables.get(target))
+                .is_some_and(|(id, tt)| {
+                    tt.records.contains_key(&id) && !now.is_some_and(|now| tt.hidden(id, now))
+                });
+            if !exists {
+                return Err(DbError::DanglingReference {
+                    field: field.clone(),
+                    table: target.clone(),
+                    value: value.to_string(),
+                });
+            }
+        }
+        Ok(())
+    }
+    /// Stores dumped `(table, id, record)` rows under their own ids, validating each like an
+    /// insert. References are checked once every row is in, so rows may point forward.
+    fn load_rows(
+        &mut self,
+        rows: Vec<(String, u64, Map<String, Value>, RowMarks)>,
+    ) -> Result<(), (String, u64, DbError)> {
+        let strict = self.strict_types;
+        let mut loaded = Vec::with_capacity(rows.len());
+        for (table, id, payload, marks) in rows {
+            let t = self.table_mut(&table).map_err(|e| (table.clone(), id, e))?;
+            if t.records.contains_key(&id) {
+                let e = DbError::DuplicateId {
+                    table: table.clone(),
+                    id,
+                };
+                return Err((table, id, e));
+            }
+            let prepared = t
+                .prepare_insert(payload, strict)
+                .map_err(|e| (table.clone(), id, e))?;
+            t.store_at(id, prepared);
+            if let Some(version) = marks.version.filter(|_| t.versioned) {
+                t.versions.insert(id, version);
+            }
+            if let Some(at) = marks.created_at.filter(|_| t.ttl_seconds.is_some()) {
+                t.created_at.insert(id, at);
+            }
+            if let Some(at) = marks.deleted_at {
+                // Marked as soon as it is in, so a unique value it released stays free.
+                t.mark_deleted(id, at).map_err(|e| (table.clone(), id, e))?;
+            }
+            loaded.push((table, id));
+        }
+        for (table, id) in loaded {
+            let record = &self.tables[&table].records[&id];
+            self.check_reference_targets(&table, record, None)
+                .map_err(|e| (table.clone(), id, e))?;
+        }
+        Ok(())
+    }
+    /// Lists `(table, field, record id)` triples whose reference points at `table`/`rid`.
+    fn dependents(&self, table: &str, rid: u64) -> Vec<(String, String, u64)> {
+        let mut found = Vec::new();
+        for (tname, t) in &self.tables {
+            for (field, def) in &t.schema {
+                if def.references.as_deref() != Some(table) {
+                    continue;
+                }
+                for (id, record) in &t.records {
+                    if record.get(field).and_then(Value::as_u64) == Some(rid) {
+                        found.push((tname.clone(), field.clone(), *id));
+                    }
+                }
+            }
+        }
+        found.sort();
+        found
+    }
+    fn insert(&mut self, table: &str, payload: Map<String, Value>) -> DbResult<u64> {
+        let strict = self.strict_types;
+        self.purge_expired(table, now_millis())?;
+        let prepared = self.table_mut(table)?.prepare_insert(payload, strict)?;
+        self.check_references(table, &prepared)?;
+        Ok(self.table_mut(table)?.store(prepared))
+    }
+    /// `insert` under a given id, as imports that keep their ids need. The id counter moves
+    /// past it.
+    fn insert_at(&mut self, table: &str, id: u64, payload: Map<String, Value>) -> DbResult<u64> {
+        let strict = self.strict_types;
+        self.purge_expired(table, now_millis())?;
+        let t = self.table_mut(table)?;
+        if t.records.contains_key(&id) {
+            return Err(DbError::DuplicateId {
+                table: table.to_string(),
+                id,
+            });
+        }
+        let prepared = t.prepare_insert(payload, strict)?;
+        self.check_references(table, &prepared)?;
+        self.table_mut(table)?.store_at(id, prepared);
+        Ok(id)
+    }
+    /// Inserts every payload or none: on the first failure the rows already stored are
+    /// removed again and the id counter rewound.
+    fn insert_many(
+        &mut self,
+        table: &str,
+        payloads: Vec<Map<String, Value>>,
+    ) -> DbResult<Vec<u64>> {
+        let next_id = self.table_mut(table)?.next_id;
+        let mut ids = Vec::with_capacity(payloads.len());
+        for payload in payloads {
+            match self.insert(table, payload) {
+                Ok(id) => ids.push(id),
+                Err(e) => {
+                    self.rewind_inserts(table, ids, next_id)?;
+                    return Err(e);
+                }
+            }
+        }
+        Ok(ids)
+    }
+    /// Undoes a run of inserts: removes `ids` again and puts the id counter back to
+    /// `next_id`, its value before the first of them.
+    fn rewind_inserts(&mut self, table: &str, ids: Vec<u64>, next_id: u64) -> DbResult<()> {
+        let t = self.table_mut(table)?;
+        for id in ids {
+            let _ = t.delete(id);
+        }
+        t.next_id = next_id;
+        Ok(())
+    }
+    /// Replaces the record holding `payload`'s value of unique field `field` with `payload`,
+    /// as an import does on a conflict. Returns the record's id.
+    fn replace_holder(
+        &mut self,
+        table: &str,
+        field: &str,
+        payload: Map<String, Value>,
+    ) -> DbResult<u64> {
+        let value = payload.get(field).unwrap_or(&Value::Null);
+        let rid = self
+            .table_mut(table)?
+            .unique_holder(field, value, now_millis())
+            .ok_or_else(|| DbError::UniqueViolation(field.to_string()))?;
+        self.replace(table, rid, payload)?;
+        Ok(rid)
+    }
+    /// Patches the record whose `on` field matches the payload's, or inserts the payload when
+    /// none does. Returns the id and whether it was created.
+    fn upsert(
+        &mut self,
+        table: &str,
+        payload: Map<String, Value>,
+        on: &str,
+    ) -> DbResult<(u64, bool)> {
+        self.purge_expired(table, now_millis())?;
+        let t = self.table_mut(table)?;
+        let value = payload
+            .get(on)
+            .filter(|v| !v.is_null())
+            .ok_or_else(|| DbError::MissingField(on.to_string()))?;
+        match t.find_by(on, value, now_millis())? {
+            Some(rid) => {
+                self.update(table, rid, payload)?;
+                Ok((rid, false))
+            }
+            None => Ok((self.insert(table, payload)?, true)),
+        }
+    }
+    /// `upsert` for each payload in turn; the table is restored if any of them fails.
+    fn upsert_many(
+        &mut self,
+        table: &str,
+        payloads: Vec<Map<String, Value>>,
+        on: &str,
+    ) -> DbResult<Vec<(u64, bool)>> {
+        let backup = self.table_mut(table)?.clone();
+        let mut out = Vec::with_capacity(payloads.len());
+        for payload in payloads {
+            match self.upsert(table, payload, on) {
+                Ok(hit) => out.push(hit),
+                Err(e) => {
+                    self.tables.insert(table.to_string(), backup);
+                    return Err(e);
+                }
+            }
+        }
+        Ok(out)
+    }
+    fn update(&mut self, table: &str, rid: u64, patch: Map<String, Value>) -> DbResult<()> {
+        let strict = self.strict_types;
+        self.purge_expired(table, now_millis())?;
+        let merged = self.table_mut(table)?.prepare_update(rid, patch, strict)?;
+        self.check_references(table, &merged)?;
+        self.table_mut(table)?.store_update(rid, merged);
+        Ok(())
+    }
+    fn replace(&mut self, table: &str, rid: u64, payload: Map<String, Value>) -> DbResult<()> {
+        let strict = self.strict_types;
+        self.purge_expired(table, now_millis())?;
+        let record = self
+            .table_mut(table)?
+            .prepare_replace(rid, payload, strict)?;
+        self.check_references(table, &record)?;
+        self.table_mut(table)?.store_update(rid, record);
+        Ok(())
+    }
+    fn unset(&mut self, table: &str, rid: u64, fields: &[String]) -> DbResult<()> {
+        self.purge_expired(table, now_millis())?;
+        let t = self.table_mut(table)?;
+        let record = t.prepare_unset(rid, fields)?;
+        t.store_update(rid, record);
+        Ok(())
+    }
+    fn soft_delete(&mut self, table: &str, rid: u64) -> DbResult<()> {
+        let t = self.table_mut(table)?;
+        if !t.soft_delete {
+            return Err(DbError::NoSoftDelete(table.to_string()));
+        }
+        t.mark_deleted(rid, now_millis())
+    }
+    /// Permanently deletes rows soft-deleted at least `older_than` seconds ago, applying
+    /// `on_delete` rules as a normal delete would.
+    fn purge_deleted(&mut self, table: &str, older_than: u64) -> DbResult<DeleteSummary> {
+        let t = self.table_mut(table)?;
+        if !t.soft_delete {
+            return Err(DbError::NoSoftDelete(table.to_string()));
+        }
+        let cutoff = now_millis().saturating_sub((older_than as i64).saturating_mul(1000));
+        let mut ids: Vec<u64> = t
+            .deleted_at
+            .iter()
+            .filter(|(_, at)| **at <= cutoff)
+            .map(|(id, _)| *id)
+            .collect();
+        ids.sort_unstable();
+        self.delete_many(table, &ids)
+    }
+    fn delete(&mut self, table: &str, rid: u64) -> DbResult<DeleteSummary> {
+        self.delete_many(table, &[rid])
+    }
+    /// Optimistic-concurrency guard: fails unless `rid` is still at `expected`.
+    fn check_version(&self, table: &str, rid: u64, expected: Option<u64>) -> DbResult<()> {
+        let Some(expected) = expected else {
+            return Ok(());
+        };
+        let t = self
+            .tables
+            .get(table)
+            .ok_or_else(|| DbError::MissingTable(table.to_string()))?;
+        if !t.records.contains_key(&rid) {
+            return Err(DbError::MissingRecord(rid));
+        }
+        let actual = t
+            .version(rid)
+            .ok_or_else(|| DbError::NotVersioned(table.to_string()))?;
+        if actual != expected {
+            return Err(DbError::VersionConflict {
+                table: table.to_string(),
+                id: rid,
+                expected,
+                actual,
+            });
+        }
+        Ok(())
+    }
+    /// Adds `delta` to a numeric field through the normal update path, so min/max constraints
+    /// and versions apply. A missing or null value starts from zero when `default_zero` is set.
+    fn increment(
+        &mut self,
+        table: &str,
+        rid: u64,
+        field: &str,
+        delta: &Value,
+        default_zero: bool,
+    ) -> DbResult<Value> {
+        self.purge_expired(table, now_millis())?;
+        let t = self.table_mut(table)?;
+        let record = t.live_record(rid)?;
+        let def = t
+            .schema
+            .get(field)
+            .ok_or_else(|| DbError::UnknownField(field.to_string()))?;
+        if def.computed.is_some() {
+            return Err(DbError::ComputedField(field.to_string()));
+        }
+        let current = match record.get(field) {
+            Some(v) if !v.is_null() => v.clone(),
+            _ if default_zero => Value::from(0),
+            _ => return Err(DbError::MissingField(field.to_string())),
+        };
+        let mismatch = || DbError::TypeMismatch {
+            field: field.to_string(),
+            expected: def.field_type.label(),
+        };
+        let next = match def.field_type {
+            FieldType::Integer => {
+                let (Some(a), Some(b)) = (current.as_i64(), delta.as_i64()) else {
+                    return Err(mismatch());
+                };
+                a.checked_add(b)
+                    .map(Value::from)
+                    .ok_or_else(|| DbError::ConstraintViolation {
+                        field: field.to_string(),
+                        constraint: "overflow".to_string(),
+                    })?
+            }
+            FieldType::Float => {
+                let (Some(a), Some(b)) = (current.as_f64(), delta.as_f64()) else {
+                    return Err(mismatch());
+                };
+                serde_json::Number::from_f64(a + b)
+                    .map(Value::Number)
+                    .ok_or_else(mismatch)?
+            }
+            _ => {
+                return Err(DbError::TypeMismatch {
+                    field: field.to_string(),
+                    expected: "integer or float".to_string(),
+                })
+            }
+        };
+        let patch = Map::from_iter([(field.to_string(), next.clone())]);
+        self.update(table, rid, patch)?;
+        Ok(next)
+    }
+    /// Applies `op` with `value` to a list field, treating a missing value as empty.
+    /// Returns the new length.
+    fn array_op(
+        &mut self,
+        table: &str,
+        rid: u64,
+        field: &str,
+        op: ArrayOp,
+        value: Value,
+    ) -> DbResult<usize> {
+        self.purge_expired(table, now_millis())?;
+        let t = self.table_mut(table)?;
+        let record = t.live_record(rid)?;
+        let def = t
+            .schema
+            .get(field)
+            .ok_or_else(|| DbError::UnknownField(field.to_string()))?;
+        let not_array = || DbError::TypeMismatch {
+            field: field.to_string(),
+            expected: "array".to_string(),
+        };
+        let value = match &def.field_type {
+            FieldType::Array(elem) => {
+                elem.coerce_element(value)
+                    .ok_or_else(|| DbError::TypeMismatch {
+                        field: field.to_string(),
+                        expected: elem.label(),
+                    })?
+            }
+            FieldType::Json => value,
+            _ => return Err(not_array()),
+        };
+        let mut items = match record.get(field) {
+            None | Some(Value::Null) => Vec::new(),
+            Some(Value::Array(items)) => items.clone(),
+            Some(_) => return Err(not_array()),
+        };
+        match op {
+            ArrayOp::Push => items.push(value),
+            ArrayOp::Pull => items.retain(|v| *v != value),
+            ArrayOp::AddUnique if !items.contains(&value) => items.push(value),
+            ArrayOp::AddUnique => {}
+        }
+        let len = items.len();
+        let patch = Map::from_iter([(field.to_string(), Value::Array(items))]);
+        self.update(table, rid, patch)?;
+        Ok(len)
+    }
+    /// Returns the single record equal to `matches` on every field, or inserts `matches` over
+    /// `defaults` when there is none. The flag tells whether a record was created.
+    fn find_or_create(
+        &mut self,
+        table: &str,
+        matches: Map<String, Value>,
+        defaults: Map<String, Value>,
+    ) -> DbResult<(u64, bool)> {
+        self.purge_expired(table, now_millis())?;
+        let t = self.table_mut(table)?;
+        let mut query = Query::new(table.to_string());
+        for (field, value) in &matches {
+            let def = t
+                .schema
+                .get(field)
+                .ok_or_else(|| DbError::UnknownField(field.clone()))?;
+            let value = if value.is_null() || def.field_type.matches(value) {
+                value.clone()
+            } else {
+                def.field_type.conform(field, value.clone())?
+            };
+            query.filters.push(Filter::Eq(field.clone(), value));
+        }
+        let found = query.rows(t);
+        match found.as_slice() {
+            [(id, _)] => Ok((*id, false)),
+            [] => {
+                let mut payload = defaults;
+                payload.extend(matches);
+                Ok((self.insert(table, payload)?, true))
+            }
+            rows => Err(DbError::AmbiguousMatch(rows.len())),
+        }
+    }
+    /// `update` with the same patch for each of `ids`; the table is restored if any of them
+    /// fails.
+    fn update_many(
+        &mut self,
+        table: &str,
+        ids: &[u64],
+        patch: &Map<String, Value>,
+    ) -> DbResult<()> {
+        let backup = self.table_mut(table)?.clone();
+        for id in ids {
+            if let Err(e) = self.update(table, *id, patch.clone()) {
+                self.tables.insert(table.to_string(), backup);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+    /// `increment` for each of `ids`; the table is restored if any of them fails.
+    fn increment_many(
+        &mut self,
+        table: &str,
+        ids: &[u64],
+        field: &str,
+        delta: &Value,
+        default_zero: bool,
+    ) -> DbResult<()> {
+        let backup = self.table_mut(table)?.clone();
+        for id in ids {
+            if let Err(e) = self.increment(table, *id, field, delta, default_zero) {
+                self.tables.insert(table.to_string(), backup);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+    /// Compare-and-set guard: fails unless every `expected` field equals the stored value.
+    /// A missing field compares as null.
+    fn check_expected(&self, table: &str, rid: u64, expected: &Map<String, Value>) -> DbResult<()> {
+        let t = self
+            .tables
+            .get(table)
+            .ok_or_else(|| DbError::MissingTable(table.to_string()))?;
+        let record = t.materialize(t.live_record(rid)?);
+        for (field, want) in expected {
+            let def = t
+                .schema
+                .get(field)
+                .ok_or_else(|| DbError::UnknownField(field.clone()))?;
+            let want = match want {
+                Value::Null => Value::Null,
+                v if def.field_type.matches(v) => v.clone(),
+                v => def.field_type.conform(field, v.clone())?,
+            };
+            if *record.get(field).unwrap_or(&Value::Null) != want {
+                return Err(DbError::PreconditionFailed {
+                    table: table.to_string(),
+                    id: rid,
+                    field: field.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+    /// Deletes `table`'s expired rows the way `delete` would, so the `on_delete` rules of
+    /// rows referencing them apply. One that a restrict reference holds on to stays, hidden,
+    /// until the reference goes. Returns how many expired rows went.
+    fn purge_expired(&mut self, table: &str, now: i64) -> DbResult<usize> {
+        let expired = self.table_mut(table)?.expired_ids(now);
+        if expired.is_empty() || self.delete_rows(table, &expired).is_ok() {
+            return Ok(expired.len());
+        }
+        for id in &expired {
+            if self.tables[table].records.contains_key(id) {
+                let _ = self.delete_rows(table, &[*id]);
+            }
+        }
+        let left = &self.tables[table].records;
+        Ok(expired.iter().filter(|id| !left.contains_key(id)).count())
+    }
+    /// `purge_expired` for every table, before a snapshot is written.
+    fn purge_all_expired(&mut self, now: i64) {
+        let names: Vec<String> = self.tables.keys().cloned().collect();
+        for name in names {
+            let _ = self.purge_expired(&name, now);
+        }
+    }
+    /// Deletes `ids` from `table` and applies every referencing field's `on_delete` rule.
+    /// Nothing is touched unless the whole cascade can be applied.
+    fn delete_many(&mut self, table: &str, ids: &[u64]) -> DbResult<DeleteSummary> {
+        self.purge_expired(table, now_millis())?;
+        self.delete_rows(table, ids)
+    }
+    /// `delete_many` without purging expired rows first.
+    fn delete_rows(&mut self, table: &str, ids: &[u64]) -> DbResult<DeleteSummary> {
+        let t = self
+            .tables
+            .get(table)
+            .ok_or_else(|| DbError::MissingTable(table.to_string()))?;
+        if let Some(id) = ids.iter().find(|id| !t.records.contains_key(id)) {
+            return Err(DbError::MissingRecord(*id));
+        }
+        let mut doomed: BTreeSet<(String, u64)> =
+            ids.iter().map(|id| (table.to_string(), *id)).collect();
+        let mut frontier: Vec<(String, u64)> = doomed.iter().cloned().collect();
+        let mut detached = Vec::new();
+        // `doomed` holds every row already visited, so cycles end without a depth cap.
+        while !frontier.is_empty() {
+            let mut next = Vec::new();
+            for (tname, rid) in &frontier {
+                for (dt, field, did) in self.dependents(tname, *rid) {
+                    if self.tables[&dt].schema[&field].on_delete == OnDelete::Cascade {
+                        if doomed.insert((dt.clone(), did)) {
+                            next.push((dt, did));
+                        }
+                    } else {
+                        detached.push((tname.clone(), *rid, dt, field, did));
+                    }
+                }
+            }
+            frontier = next;
+        }
+
+        let mut nulls = BTreeSet::new();
+        for (tname, rid, dt, field, did) in detached {
+            if doomed.contains(&(dt.clone(), did)) {
+                continue;
+            }
+            if self.tables[&dt].schema[&field].on_delete == OnDelete::Restrict {
+                return Err(DbError::ReferencedRecord {
+                    table: tname,
+                    id: rid,
+                    dependent: format!("{}.{}", dt, field),
+                });
+            }
+            nulls.insert((dt, did, field));
+        }
+
+        let mut summary = DeleteSummary::default();
+        let mut nulled_rows = BTreeSet::new();
+        for (dt, did, field) in nulls {
+            let t = self.table_mut(&dt)?;
+            let mut merged = t.records[&did].clone();
+            merged.insert(field, Value::Null);
+            t.store_update(did, merged);
+            if nulled_rows.insert((dt.clone(), did)) {
+                *summary.nulled.entry(dt).or_default() += 1;
+            }
+        }
+        for (dt, did) in doomed {
+            self.table_mut(&dt)?.delete(did)?;
+            *summary.deleted.entry(dt).or_default() += 1;
+        }
+        Ok(summary)
+    }
+}
+
+#[pyclass]
+struct Record {
+    #[pyo3(get)]
+    id: u64,
+    #[pyo3(get)]
+    data: PyObject,
+    /// Current version in versioned tables, `None` elsewhere.
+    #[pyo3(get)]
+    version: Option<u64>,
+}
 #[pymethods]
 impl Record {
     fn __repr__(&self, py: Python<'_>) -> PyResult<String> {
+        let version = self
+            .version
+            .map(|v| format!(", version={}", v))
+            .unwrap_or_default();
         Ok(format!(
-            "Record(id={}, data={})",
+            "Record(id={}, data={}{})",
             self.id,
-            self.data.bind(py).repr()?
+            self.data.bind(py).repr()?,
+            version
         ))
     }
 }
 
-#[pyclass]
-#[derive(Clone)]
-struct Query {
-    table: String,
-    filters: Vec<(String, Value)>,
-    order_by: Option<(String, bool)>,
-    limit: Option<usize>,
+#[derive(Clone)]
+enum Filter {
+    Eq(String, Value),
+    Between(String, Value, Value),
+    Contains(String, Value),
+    Null(String),
+    Missing(String),
+    /// `!=` and the ordering comparisons of a SQL `WHERE`.
+    Compare(String, sql::Op, Value),
+    Like(String, String),
+}
+
+impl Filter {
+    fn field(&self) -> &str {
+        match self {
+            Self::Eq(f, _)
+            | Self::Between(f, _, _)
+            | Self::Contains(f, _)
+            | Self::Null(f)
+            | Self::Missing(f)
+            | Self::Compare(f, _, _)
+            | Self::Like(f, _) => f,
+        }
+    }
+    /// Whether `record` passes, comparing as a field of `field_type` when the schema has one.
+    fn matches(&self, record: &Map<String, Value>, field_type: Option<&FieldType>) -> bool {
+        match self {
+            Self::Eq(f, expected) => record.get(f) == Some(expected),
+            Self::Between(f, low, high) => record.get(f).is_some_and(|v| {
+                same_kind(v, low)
+                    && same_kind(v, high)
+                    && field_cmp(field_type, v, low) != Ordering::Less
+                    && field_cmp(field_type, v, high) != Ordering::Greater
+            }),
+            Self::Contains(f, needle) => match (record.get(f), needle) {
+                (Some(Value::Array(items)), _) => items.contains(needle),
+                (Some(Value::String(hay)), Value::String(sub)) => hay.contains(sub.as_str()),
+                _ => false,
+            },
+            Self::Null(f) => record.get(f).is_some_and(Value::is_null),
+            Self::Missing(f) => !record.contains_key(f),
+            Self::Compare(f, sql::Op::Ne, v) => record.get(f).is_some_and(|x| x != v),
+            Self::Compare(f, op, v) => record
+                .get(f)
+                .is_some_and(|x| same_kind(x, v) && op.accepts(field_cmp(field_type, x, v))),
+            Self::Like(f, pattern) => record
+                .get(f)
+                .and_then(Value::as_str)
+                .is_some_and(|text| sql::like(pattern, text)),
+        }
+    }
+    /// Normalizes literal operands so they compare against stored values of `field_type`.
+    fn normalized(&self, field_type: &FieldType) -> Self {
+        let operand_type = match (self, field_type) {
+            (Self::Contains(..), FieldType::Array(elem)) => elem.as_ref(),
+            _ => field_type,
+        };
+        if *operand_type != FieldType::DateTime {
+            return self.clone();
+        }
+        let norm = |v: &Value| operand_type.coerce(v.clone()).unwrap_or_else(|| v.clone());
+        match self {
+            Self::Eq(f, v) => Self::Eq(f.clone(), norm(v)),
+            Self::Between(f, lo, hi) => Self::Between(f.clone(), norm(lo), norm(hi)),
+            Self::Contains(f, v) => Self::Contains(f.clone(), norm(v)),
+            Self::Compare(f, op, v) => Self::Compare(f.clone(), *op, norm(v)),
+            Self::Null(_) | Self::Missing(_) | Self::Like(..) => self.clone(),
+        }
+    }
+}
+
+/// A parsed `WHERE` clause as the filters `Query` applies.
+fn sql_filters(conditions: Vec<sql::Condition>) -> Vec<Filter> {
+    conditions
+        .into_iter()
+        .map(|c| match (c.op, c.value) {
+            (sql::Op::Eq, v) => Filter::Eq(c.field, v),
+            (sql::Op::Like, Value::String(p)) => Filter::Like(c.field, p),
+            (op, v) => Filter::Compare(c.field, op, v),
+        })
+        .collect()
+}
+
+#[pyclass]
+#[derive(Clone)]
+struct Query {
+    table: String,
+    filters: Vec<Filter>,
+    order_by: Option<(String, bool)>,
+    limit: Option<usize>,
+    with_deleted: bool,
+    /// Fields kept in each result, in this order; `None` keeps them all.
+    columns: Option<Vec<String>>,
+}
+
+impl Query {
+    /// Ids narrowed by the first equality filter on an indexed field, if any.
+    fn candidates(t: &Table, filters: &[Filter]) -> Option<Vec<u64>> {
+        filters.iter().find_map(|f| match f {
+            Filter::Eq(field, value) => t.indexes.get(field).map(|index| {
+                index
+                    .get(&value.to_string())
+                    .map(|ids| ids.iter().copied().collect())
+                    .unwrap_or_default()
+            }),
+            _ => None,
+        })
+    }
+    /// Rows of `t` matching the filters, ordered and truncated as requested.
+    fn rows<'t>(&self, t: &'t Table) -> Vec<(u64, Cow<'t, Map<String, Value>>)> {
+        let mut rows: Vec<_> = self.matching(t).collect();
+        if let Some((f, d)) = &self.order_by {
+            let field_type = t.schema.get(f).map(|def| &def.field_type);
+            rows.sort_by(|(_, l), (_, r)| {
+                let lv = l.get(f).unwrap_or(&Value::Null);
+                let rv = r.get(f).unwrap_or(&Value::Null);
+                let c = field_cmp(field_type, lv, rv);
+                if *d {
+                    c.reverse()
+                } else {
+                    c
+                }
+            });
+        }
+        if let Some(l) = self.limit {
+            rows.truncate(l);
+        }
+        rows
+    }
+    /// Fails on a `select` field `t` does not have.
+    fn check_columns(&self, t: &Table) -> PyResult<()> {
+        match self
+            .columns
+            .iter()
+            .flatten()
+            .find(|c| *c != "id" && !t.schema.contains_key(*c))
+        {
+            Some(unknown) => Err(PyKeyError::new_err(format!(
+                "table '{}' has no column '{}'",
+                self.table, unknown
+            ))),
+            None => Ok(()),
+        }
+    }
+    /// Fields a column-wise result shows after `id`: the `select` fields in their order, or
+    /// every schema field by name.
+    fn output_fields(&self, t: &Table) -> PyResult<Vec<String>> {
+        self.check_columns(t)?;
+        Ok(match &self.columns {
+            Some(columns) => columns.iter().filter(|c| *c != "id").cloned().collect(),
+            None => {
+                let mut fields: Vec<String> = t.schema.keys().cloned().collect();
+                fields.sort();
+                fields
+            }
+        })
+    }
+    /// Drops the fields `select` left out of `row`.
+    fn project(&self, row: &mut Cow<'_, Map<String, Value>>) {
+        if let Some(columns) = &self.columns {
+            row.to_mut().retain(|field, _| columns.contains(field));
+        }
+    }
+    /// Number of rows of `t` matching the filters; ordering and `take` do not apply.
+    fn count(&self, t: &Table) -> usize {
+        self.matching(t).count()
+    }
+    /// Live rows of `t` passing every filter, in storage order.
+    fn matching<'t>(
+        &self,
+        t: &'t Table,
+    ) -> impl Iterator<Item = (u64, Cow<'t, Map<String, Value>>)> + 't {
+        let filters: Vec<Filter> = self
+            .filters
+            .iter()
+            .map(|f| match t.schema.get(f.field()) {
+                Some(def) => f.normalized(&def.field_type),
+                None => f.clone(),
+            })
+            .collect();
+        let types: Vec<Option<&FieldType>> = filters
+            .iter()
+            .map(|f| t.schema.get(f.field()).map(|def| &def.field_type))
+            .collect();
+        let scan: Box<dyn Iterator<Item = (&u64, &Map<String, Value>)>> =
+            match Self::candidates(t, &filters) {
+                Some(ids) => Box::new(
+                    ids.into_iter()
+                        .filter_map(|id| t.records.get_key_value(&id)),
+                ),
+                None => Box::new(t.records.iter()),
+            };
+        let now = now_millis();
+        let with_deleted = self.with_deleted;
+        scan.filter(move |(id, _)| !t.is_expired(**id, now))
+            .filter(move |(id, _)| with_deleted || !t.deleted_at.contains_key(id))
+            .map(|(id, r)| (*id, t.materialize(r)))
+            .filter(move |(_, r)| {
+                filters
+                    .iter()
+                    .zip(&types)
+                    .all(|(f, field_type)| f.matches(r, *field_type))
+            })
+    }
+}
+
+#[pyclass]
+struct Database {
+    engine: Engine,
+    storage_path: Option<PathBuf>,
+    /// Key used for writing, derived from `passphrase` with `kdf_salt`.
+    encryption_key: Option<[u8; 32]>,
+    passphrase: Option<String>,
+    kdf_salt: [u8; KDF_SALT_LEN],
+    compression: CompressionAlgo,
+    /// zstd level used by the next write; ignored by the other algorithms.
+    compression_level: i32,
+    /// Serialized and on-disk byte counts of the last persist.
+    last_write: Option<(usize, usize)>,
+    /// Persists that wrote something vs. found the files already up to date.
+    persists_written: u64,
+    persists_skipped: u64,
+    /// Public method calls since open, and how many of them raised; reported by `stats`.
+    ops_executed: Cell<u64>,
+    errors_returned: Cell<u64>,
+    /// Public methods currently running, so one calling another is counted once.
+    calls_open: Cell<u32>,
+    personality: Personality,
+    /// Commands run at the prompt, oldest first; with `persist_history` they live in the
+    /// engine instead (see `history`).
+    command_history: VecDeque<String>,
+    /// Entries kept before the oldest is evicted.
+    history_size: usize,
+    persist_history: bool,
+    batch_mode: bool,
+    batch_ops: Vec<String>,
+    /// Savepoints of the open batch in creation order, each with the number of operations
+    /// queued before it.
+    savepoints: Vec<(String, usize)>,
+    /// Engine state at `transaction()` entry; while set, `persist` is deferred.
+    tx_snapshot: Option<Box<Engine>>,
+    /// `dirty` and `queued` at `transaction()` entry, put back on rollback.
+    tx_dirty: (bool, bool),
+    /// With autosave off, mutations only mark the database dirty until `flush()`.
+    autosave: bool,
+    dirty: bool,
+    /// The current state is queued on the background writer; `dirty` clears once the
+    /// writer reports it written.
+    queued: bool,
+    /// Append small mutations to a checksummed journal beside the file instead of rewriting
+    /// the whole snapshot; see `persist`.
+    journal: bool,
+    journal_entries: usize,
+    /// Journal entry for the mutation about to be persisted, staged by the method making it.
+    pending_entry: Option<journal::Entry>,
+    layout: StorageLayout,
+    /// Digest of the JSON last written to each file, so unchanged files (or, in the
+    /// directory layout, unchanged tables) are not rewritten.
+    written: HashMap<String, WrittenPart>,
+    /// The graph is only re-serialized after an ingest or a change that rewrites every file.
+    graph_dirty: bool,
+    /// Directory layout: tables whose file was missing or unreadable at load, with the reason.
+    /// Their files are kept until the table is recreated.
+    load_errors: BTreeMap<String, String>,
+    /// Opened with a shared lock: changes stay in memory and `save`/`flush` refuse.
+    read_only: bool,
+    /// Key of the process-wide lock on `<storage_path>.lock`, released by `close` or drop.
+    lock: Option<PathBuf>,
+    /// Background mode: persists queue snapshots here instead of writing inline.
+    writer: Option<writer::Writer>,
+    /// Set by `close`; every later call raises instead of touching stale state.
+    closed: bool,
+    /// `graph_set_entity_classifier` callback, asked to type each new graph entity.
+    entity_classifier: Option<PyObject>,
+}
+
+impl Drop for Database {
+    fn drop(&mut self) {
+        let _ = self.drain_writer();
+        if self.dirty && self.storage_path.is_some() {
+            Python::with_gil(|py| self.warn_unsaved(py, "dropped"));
+        }
+        // Finish queued writes while the file is still locked.
+        self.writer = None;
+        if let Some(key) = self.lock.take() {
+            lock::release(&key);
+        }
+    }
+}
+
+/// Context manager returned by `Database.transaction()`.
+#[pyclass]
+struct Transaction {
+    db: Py<Database>,
+}
+
+impl Database {
+    /// Parses and runs one statement of the command language (see `sql`).
+    fn run_statement(&mut self, py: Python<'_>, sql: &str, depth: usize) -> PyResult<PyObject> {
+        let statement = sql::parse(sql).map_err(|e| {
+            if depth == 0 {
+                self.engine.alive.on_error();
+            }
+            match self.personality.mode() {
+                Mode::Snarky => PyValueError::new_err(self.personality.error(&e)),
+                _ => PyValueError::new_err(e),
+            }
+        })?;
+        if !matches!(
+            statement,
+            sql::Statement::Select(_) | sql::Statement::Count(_)
+        ) {
+            self.check_writable()?;
+        }
+        match statement {
+            sql::Statement::Select(select) => self.select(py, select),
+            sql::Statement::Insert(insert) => self.insert_rows(py, insert),
+            sql::Statement::Update(update) => self.update_rows(update).map(|n| n.into_py(py)),
+            sql::Statement::Delete(delete) => self.delete_rows(delete).map(|n| n.into_py(py)),
+            sql::Statement::CreateTable(create) => self.create_table_sql(py, create),
+            sql::Statement::Count(count) => self.count_rows(count).map(|n| n.into_py(py)),
+        }
+    }
+    /// Runs a `SELECT` through the same machinery as `query()`; with a column list, each
+    /// record's data holds only those fields.
+    fn select(&self, py: Python<'_>, select: sql::Select) -> PyResult<PyObject> {
+        let query = self.select_query(select)?;
+        let t = &self.engine.tables[&query.table];
+        let mut records = Vec::new();
+        for (id, mut r) in query.rows(t) {
+            query.project(&mut r);
+            records.push(Record {
+                id,
+                version: t.version(id),
+                data: json_to_py(py, &Value::Object(r.into_owned()))?,
+            });
+        }
+        Ok(records.into_py(py))
+    }
+    /// The `Query` a `SELECT` runs, with its table resolved and its columns checked.
+    fn select_query(&self, select: sql::Select) -> PyResult<Query> {
+        let table = self.resolve_table(&select.table)?;
+        let query = Query {
+            filters: sql_filters(select.conditions),
+            order_by: select.order_by,
+            limit: select.limit,
+            columns: select.columns,
+            ..Query::new(table)
+        };
+        query.check_columns(&self.engine.tables[&query.table])?;
+        Ok(query)
+    }
+    /// `query`'s rows as text, for `format_results` and `PRINT`.
+    fn render_results(
+        &self,
+        query: &Query,
+        style: ResultStyle,
+        max_width: Option<usize>,
+    ) -> PyResult<String> {
+        self.check_open()?;
+        if max_width == Some(0) {
+            return Err(PyValueError::new_err("max_width must be at least 1"));
+        }
+        let t = self.engine.tables.get(&query.table).ok_or_else(|| {
+            PyKeyError::new_err(format!("table '{}' does not exist", query.table))
+        })?;
+        let fields = query.output_fields(t)?;
+        let rows = query.rows(t);
+        if style == ResultStyle::Json {
+            let rows: Vec<Value> = rows
+                .iter()
+                .map(|(id, r)| {
+                    let mut row = Map::new();
+                    row.insert("id".to_string(), Value::from(*id));
+                    for field in &fields {
+                        let value = r.get(field).cloned().unwrap_or(Value::Null);
+                        row.insert(field.clone(), value);
+                    }
+                    Value::Object(row)
+                })
+                .collect();
+            return serde_json::to_string_pretty(&rows)
+                .map_err(|e| PyValueError::new_err(e.to_string()));
+        }
+        let mut ids = render::Column {
+            header: "id".to_string(),
+            cells: Vec::with_capacity(rows.len()),
+            numeric: true,
+        };
+        let mut columns: Vec<_> = fields
+            .iter()
+            .map(|field| render::Column {
+                header: field.clone(),
+                cells: Vec::with_capacity(rows.len()),
+                numeric: matches!(
+                    t.schema[field].field_type,
+                    FieldType::Integer | FieldType::Float
+                ),
+            })
+            .collect();
+        for (id, r) in &rows {
+            ids.cells.push(id.to_string());
+            for (field, column) in fields.iter().zip(&mut columns) {
+                column
+                    .cells
+                    .push(csv_cell(r.get(field).unwrap_or(&Value::Null)));
+            }
+        }
+        columns.insert(0, ids);
+        Ok(match style {
+            ResultStyle::Markdown => render::markdown(&columns, max_width),
+            _ => render::ascii(&columns, max_width),
+        })
+    }
+    /// `INSERT`: one row goes through `insert`'s path and returns its id; several are
+    /// inserted all or nothing with one persist and return their ids.
+    fn insert_rows(&mut self, py: Python<'_>, insert: sql::Insert) -> PyResult<PyObject> {
+        let table = self.resolve_table(&insert.table)?;
+        let mut rows = insert.rows;
+        if rows.len() == 1 {
+            let id = self
+                .engine
+                .insert(&table, rows.remove(0))
+                .map_err(convert_db_error)?;
+            self.stage_row(&table, id, true);
+            self.persist()?;
+            return Ok(id.into_py(py));
+        }
+        let ids = self
+            .engine
+            .insert_many(&table, rows)
+            .map_err(convert_db_error)?;
+        self.persist()?;
+        Ok(ids.into_py(py))
+    }
+    /// Registers a table built by `create_table` or `CREATE TABLE`, journals it and persists.
+    fn add_table(&mut self, name: String, table: Table) -> PyResult<PyObject> {
+        self.engine
+            .create_table(&name, table)
+            .map_err(convert_db_error)?;
+        if self.journal {
+            let table = serde_json::to_string(&self.engine.tables[&name])
+                .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+            self.pending_entry = Some(journal::Entry::CreateTable {
+                name: name.clone(),
+                table,
+            });
+        }
+        self.persist()?;
+        Python::with_gil(|py| {
+            Ok(if self.personality.is_professional() {
+                py.None()
+            } else {
+                self.personality
+                    .success(&format!("Table '{}' created.", name))
+                    .into_py(py)
+            })
+        })
+    }
+    /// `GRAPH SOURCES | ENTITIES [LIKE <pattern>] | COMMUNITIES | NEIGHBORS <entity> |
+    /// FORGET <source>`, each answered with plain Python data.
+    fn graph_command(&mut self, py: Python<'_>, args: &[&str]) -> PyResult<PyObject> {
+        let usage = "GRAPH format: GRAPH SOURCES | ENTITIES [LIKE <pattern>] | COMMUNITIES | \
+                     NEIGHBORS <entity> | FORGET <source>";
+        let Some((sub, rest)) = args.split_first() else {
+            return Err(PyValueError::new_err(usage));
+        };
+        let rest = rest.join(" ");
+        let out = match sub.to_ascii_uppercase().as_str() {
+            "SOURCES" if rest.is_empty() => {
+                let sources = self.graph()?.sources();
+                sources.into_iter().map(source_json).collect()
+            }
+            "ENTITIES" => {
+                let pattern = match rest.split_once(' ') {
+                    None if rest.is_empty() => None,
+                    Some((like, pattern)) if like.eq_ignore_ascii_case("LIKE") => {
+                        Some(pattern.trim().trim_matches('\'').to_lowercase())
+                    }
+                    _ => return Err(PyValueError::new_err(usage)),
+                };
+                self.graph()?
+                    .top_entities(|name| {
+                        pattern
+                            .as_ref()
+                            .is_none_or(|p| sql::like(p, &name.to_lowercase()))
+                    })
+                    .into_iter()
+                    .map(entity_json)
+                    .collect()
+            }
+            "COMMUNITIES" if rest.is_empty() => self
+                .graph()?
+                .data
+                .communities
+                .iter()
+                .map(|c| {
+                    serde_json::json!({
+                        "id": c.id,
+                        "size": c.entities.len(),
+                        "summary": c.summary,
+                        "top_entities": c.top_entities,
+                    })
+                })
+                .collect(),
+            "NEIGHBORS" if !rest.is_empty() => {
+                let graph = self.graph()?;
+                graph
+                    .neighbors(&rest)
+                    .ok_or_else(|| unknown_entity(graph, &rest))?
+                    .into_iter()
+                    .map(neighbor_json)
+                    .collect()
+            }
+            "FORGET" if !rest.is_empty() => {
+                let removed = self.forget_graph_source(&rest)?;
+                return Ok(removed.chunks.into_py(py));
+            }
+            _ => return Err(PyValueError::new_err(usage)),
+        };
+        json_to_py(py, &Value::Array(out))
+    }
+    /// `COUNT`: live rows, or those matching the conditions, counted without building
+    /// records.
+    fn count_rows(&self, count: sql::Count) -> PyResult<usize> {
+        let table = self.resolve_table(&count.table)?;
+        let t = &self.engine.tables[&table];
+        if count.conditions.is_empty() {
+            return Ok(t.live_count(now_millis()));
+        }
+        let query = Query {
+            filters: sql_filters(count.conditions),
+            ..Query::new(table)
+        };
+        Ok(query.count(t))
+    }
+    /// `UPDATE`: the patch goes through `update`'s validation for every matching row, all or
+    /// nothing, with one persist. Returns the number of rows changed.
+    fn update_rows(&mut self, update: sql::Update) -> PyResult<usize> {
+        let table = self.resolve_table(&update.table)?;
+        let t = &self.engine.tables[&table];
+        let query = Query {
+            filters: sql_filters(update.conditions),
+            ..Query::new(table)
+        };
+        let ids: Vec<u64> = query.rows(t).into_iter().map(|(id, _)| id).collect();
+        self.engine
+            .update_many(&query.table, &ids, &update.set)
+            .map_err(convert_db_error)?;
+        self.persist()?;
+        Ok(ids.len())
+    }
+    /// `DELETE`: matching rows go through `delete_many`, so `on_delete` rules apply as in
+    /// `delete_where`, with one persist. Returns the number of rows deleted from the table.
+    fn delete_rows(&mut self, delete: sql::Delete) -> PyResult<usize> {
+        let table = self.resolve_table(&delete.table)?;
+        let t = &self.engine.tables[&table];
+        let query = Query {
+            filters: sql_filters(delete.conditions),
+            ..Query::new(table)
+        };
+        let ids: Vec<u64> = query.rows(t).into_iter().map(|(id, _)| id).collect();
+        self.engine
+            .delete_many(&query.table, &ids)
+            .map_err(convert_db_error)?;
+        self.persist()?;
+        Ok(ids.len())
+    }
+    /// Stored name of a table the command language named. Bare names match regardless of
+    /// case and must not fit two tables; quoted names match exactly.
+    fn resolve_table(&self, table: &sql::TableRef) -> PyResult<String> {
+        let mut found: Vec<&String> = self
+            .engine
+            .tables
+            .keys()
+            .filter(|name| match table.quoted {
+                true => **name == table.name,
+                false => name.eq_ignore_ascii_case(&table.name),
+            })
+            .collect();
+        found.sort();
+        match found[..] {
+            [] => Err(self.unknown_table(&table.name)),
+            [name] => Ok(name.clone()),
+            _ => Err(PyValueError::new_err(format!(
+                "table name '{}' is ambiguous between {}; quote it, as in `{}`, to match exactly",
+                table.name,
+                found
+                    .iter()
+                    .map(|name| format!("'{}'", name))
+                    .collect::<Vec<_>>()
+                    .join(" and "),
+                found[0]
+            ))),
+        }
+    }
+    /// `KeyError` for a table the command language named, suggesting the closest existing
+    /// table when there is one.
+    fn unknown_table(&self, name: &str) -> PyErr {
+        let mut tables: Vec<&str> = self.engine.tables.keys().map(String::as_str).collect();
+        tables.sort_unstable();
+        PyKeyError::new_err(match commands::nearest(name, tables) {
+            Some(near) => format!("table '{}' does not exist; did you mean '{}'?", name, near),
+            None => format!("table '{}' does not exist", name),
+        })
+    }
+    fn insert_record(&mut self, table: String, payload: Bound<'_, PyDict>) -> PyResult<PyObject> {
+        self.check_open()?;
+        self.check_writable()?;
+        validate_identifier(&table).map_err(convert_db_error)?;
+        let mut data = Map::new();
+        for (k, v) in payload.iter() {
+            data.insert(k.extract::<String>()?, py_to_json(v)?);
+        }
+        let id = self.engine.insert(&table, data).map_err(convert_db_error)?;
+        self.stage_row(&table, id, true);
+        self.persist()?;
+        Python::with_gil(|py| {
+            Ok(if self.personality.is_professional() {
+                id.into_py(py)
+            } else {
+                self.personality
+                    .success(&format!("Row inserted into '{}' (id: {}).", table, id))
+                    .into_py(py)
+            })
+        })
+    }
+    fn insert_records(
+        &mut self,
+        table: String,
+        payloads: Vec<Bound<'_, PyDict>>,
+    ) -> PyResult<Vec<u64>> {
+        self.check_open()?;
+        self.check_writable()?;
+        validate_identifier(&table).map_err(convert_db_error)?;
+        let mut rows = Vec::with_capacity(payloads.len());
+        for payload in payloads {
+            let mut data = Map::new();
+            for (k, v) in payload.iter() {
+                data.insert(k.extract::<String>()?, py_to_json(v)?);
+            }
+            rows.push(data);
+        }
+        let ids = self
+            .engine
+            .insert_many(&table, rows)
+            .map_err(convert_db_error)?;
+        self.persist()?;
+        Ok(ids)
+    }
+    /// Rows and fields an export writes: every stored record (computed fields filled in)
+    /// in id order under the sorted schema fields for a table name, or what `query` returns,
+    /// in its order and limited to its `select` fields, for a `Query`.
+    fn export_rows(&self, source: &Bound<'_, PyAny>) -> PyResult<(ExportRows<'_>, Vec<String>)> {
+        if let Ok(query) = source.downcast::<Query>() {
+            let query = query.borrow().clone();
+            let t = self.engine.tables.get(&query.table).ok_or_else(|| {
+                PyKeyError::new_err(format!("table '{}' does not exist", query.table))
+            })?;
+            let fields = query.output_fields(t)?;
+            let rows = query.rows(t).into_iter().map(move |(id, mut r)| {
+                query.project(&mut r);
+                (id, r)
+            });
+            return Ok((Box::new(rows), fields));
+        }
+        let table: String = source.extract()?;
+        let t = self
+            .engine
+            .tables
+            .get(&table)
+            .ok_or_else(|| PyKeyError::new_err("missing table"))?;
+        let mut fields: Vec<String> = t.schema.keys().cloned().collect();
+        fields.sort();
+        let now = now_millis();
+        let rows = t
+            .records
+            .iter()
+            .filter(move |(id, _)| !t.hidden(**id, now))
+            .map(|(id, r)| (*id, t.materialize(r)));
+        Ok((Box::new(rows), fields))
+    }
+    /// Inserts `rows` into `table` as one unit for the `import_*` methods. A row clashing with
+    /// a unique value is handled per `on_conflict`; any other failing row fails the import,
+    /// or with `skip_invalid` is left out and reported. A failed import puts the table back
+    /// as it was; otherwise it is persisted once.
+    ///
+    /// With `preserve_ids` each row is stored under its own `id`, and a row whose id is
+    /// taken is handled per `on_conflict` too, `"replace"` overwriting the record there.
+    #[allow(clippy::too_many_arguments)]
+    fn import_rows(
+        &mut self,
+        py: Python<'_>,
+        table: &str,
+        label: &str,
+        rows: Vec<ImportRow>,
+        on_conflict: OnConflict,
+        skip_invalid: bool,
+        preserve_ids: bool,
+    ) -> PyResult<PyObject> {
+        let before = self
+            .engine
+            .tables
+            .get(table)
+            .ok_or_else(|| PyKeyError::new_err("missing table"))?
+            .clone();
+        let (mut inserted, mut replaced) = (0, 0);
+        let mut skipped = Vec::new();
+        let mut failed = Vec::new();
+        for (line, row) in rows {
+            let row = row.and_then(|mut payload| match preserve_ids {
+                true => import_id(payload.remove("id")).map(|id| (payload, Some(id))),
+                false => Ok((payload, None)),
+            });
+            let (payload, id) = match row {
+                Ok(row) => row,
+                Err(error) if skip_invalid => {
+                    skipped.push((line, error));
+                    continue;
+                }
+                Err(error) => {
+                    failed.push((line, error));
+                    continue;
+                }
+            };
+            let kept = (on_conflict == OnConflict::Replace).then(|| payload.clone());
+            let result = match id {
+                Some(id) => self.engine.insert_at(table, id, payload),
+                None => self.engine.insert(table, payload),
+            };
+            match result {
+                Ok(_) => inserted += 1,
+                Err(DbError::UniqueViolation(field)) => match (on_conflict, kept) {
+                    (OnConflict::Replace, Some(payload)) => {
+                        match self.engine.replace_holder(table, &field, payload) {
+                            Ok(_) => replaced += 1,
+                            Err(e) => failed.push((line, e.to_string())),
+                        }
+                    }
+                    (OnConflict::Skip, _) => {
+                        skipped.push((line, DbError::UniqueViolation(field).to_string()))
+                    }
+                    _ => failed.push((line, DbError::UniqueViolation(field).to_string())),
+                },
+                Err(e @ DbError::DuplicateId { id, .. }) => match (on_conflict, kept) {
+                    (OnConflict::Replace, Some(payload)) => {
+                        match self.engine.replace(table, id, payload) {
+                            Ok(()) => replaced += 1,
+                            Err(e) => failed.push((line, e.to_string())),
+                        }
+                    }
+                    (OnConflict::Skip, _) => skipped.push((line, e.to_string())),
+                    _ => failed.push((line, e.to_string())),
+                },
+                Err(e) if skip_invalid => skipped.push((line, e.to_string())),
+                Err(e) => failed.push((line, e.to_string())),
+            }
+        }
+        if !failed.is_empty() {
+            self.engine.tables.insert(table.to_string(), before);
+            let mut report = failed
+                .iter()
+                .take(MAX_IMPORT_REPORTED_ERRORS)
+                .map(|(line, error)| format!("line {}: {}", line, error))
+                .collect::<Vec<_>>()
+                .join("; ");
+            if failed.len() > MAX_IMPORT_REPORTED_ERRORS {
+                report.push_str(&format!(
+                    "; and {} more",
+                    failed.len() - MAX_IMPORT_REPORTED_ERRORS
+                ));
+            }
+            return Err(PyValueError::new_err(format!(
+                "{} {} row(s) failed, nothing was imported: {}",
+                failed.len(),
+                label,
+                report
+            )));
+        }
+        if inserted + replaced > 0 {
+            self.persist()?;
+        }
+        let skipped = skipped
+            .into_iter()
+            .map(|(line, error)| serde_json::json!({"line": line, "error": error}))
+            .collect::<Vec<_>>();
+        json_to_py(
+            py,
+            &serde_json::json!({"inserted": inserted, "replaced": replaced, "skipped": skipped}),
+        )
+    }
+    /// The `dump_json` document. With `only`, just those tables' schema, rows and id
+    /// sequences, without aliases or the graph.
+    fn dump_document(&mut self, only: Option<&BTreeSet<String>>) -> PyResult<Value> {
+        let mut tables = Map::new();
+        let mut sequences = Map::new();
+        for (name, t) in &self.engine.tables {
+            if only.is_some_and(|only| !only.contains(name)) {
+                continue;
+            }
+            // `$` keys never clash with field names, which are identifiers.
+            let rows = t
+                .records
+                .iter()
+                .map(|(id, r)| {
+                    let mut row = r.clone();
+                    row.insert("$id".to_string(), Value::from(*id));
+                    if let Some(version) = t.version(*id) {
+                        row.insert("$version".to_string(), Value::from(version));
+                    }
+                    if let Some(at) = t.created_at.get(id) {
+                        row.insert("$created_at".to_string(), Value::from(*at));
+                    }
+                    if let Some(at) = t.deleted_at.get(id) {
+                        row.insert("$deleted_at".to_string(), Value::from(*at));
+                    }
+                    Value::Object(row)
+                })
+                .collect();
+            tables.insert(name.clone(), Value::Array(rows));
+            sequences.insert(name.clone(), Value::from(t.next_id));
+        }
+        let mut schema = self.schema_document();
+        if let (Some(only), Some(Value::Object(specs))) = (only, schema.get_mut("tables")) {
+            specs.retain(|name, _| only.contains(name));
+        }
+        let mut doc = serde_json::json!({
+            "version": DUMP_FORMAT_VERSION,
+            "schema": schema,
+            "tables": tables,
+            "sequences": sequences,
+        });
+        if only.is_none() {
+            self.graph()?;
+            doc["aliases"] = serde_json::json!(self.engine.aliases);
+            doc["graph"] = serde_json::json!(self.engine.graph_rag.data);
+        }
+        Ok(doc)
+    }
+    /// Body of `load_json` and `import_encrypted`.
+    fn load_document(
+        &mut self,
+        py: Python<'_>,
+        mut doc: Map<String, Value>,
+        merge: bool,
+    ) -> PyResult<()> {
+        let version = doc.get("version").and_then(Value::as_u64).unwrap_or(1);
+        if version > DUMP_FORMAT_VERSION {
+            return Err(PyValueError::new_err(format!(
+                "unsupported dump format version {}",
+                version
+            )));
+        }
+        let mut engine = if merge {
+            self.graph()?;
+            self.engine.clone()
+        } else {
+            Engine {
+                alive: self.engine.alive.clone(),
+                strict_types: self.engine.strict_types,
+                journal_epoch: self.engine.journal_epoch,
+                history: self.engine.history.clone(),
+                ..Engine::new()
+            }
+        };
+
+        let schema = doc
+            .remove("schema")
+            .ok_or_else(|| PyValueError::new_err("dump requires schema"))?;
+        let schema = json_to_py(py, &schema)?.into_bound(py);
+        let mut pending = Vec::new();
+        if let Some(specs) = schema.downcast::<PyDict>()?.get_item("tables")? {
+            for (name, spec) in specs.downcast::<PyDict>()?.iter() {
+                let name = name.extract::<String>()?;
+                validate_identifier(&name).map_err(convert_db_error)?;
+                if !engine.tables.contains_key(&name) {
+                    pending.push((name, parse_table_spec(spec.downcast::<PyDict>()?)?));
+                }
+            }
+        }
+        engine.create_tables(pending).map_err(convert_db_error)?;
+
+        let mut rows = Vec::new();
+        if let Some(Value::Object(tables)) = doc.remove("tables") {
+            for (table, records) in tables {
+                let Value::Array(records) = records else {
+                    return Err(PyValueError::new_err(format!(
+                        "rows of `{}` must be a list",
+                        table
+                    )));
+                };
+                for record in records {
+                    let Value::Object(mut record) = record else {
+                        return Err(PyValueError::new_err(format!(
+                            "rows of `{}` must be objects",
+                            table
+                        )));
+                    };
+                    let id = record.remove("$id").as_ref().and_then(Value::as_u64);
+                    let id = id.ok_or_else(|| {
+                        PyValueError::new_err(format!("row of `{}` has no id", table))
+                    })?;
+                    let marks = RowMarks {
+                        version: record.remove("$version").and_then(|v| v.as_u64()),
+                        created_at: record.remove("$created_at").and_then(|v| v.as_i64()),
+                        deleted_at: record.remove("$deleted_at").and_then(|v| v.as_i64()),
+                    };
+                    rows.push((table.clone(), id, record, marks));
+                }
+            }
+        }
+        engine.load_rows(rows).map_err(|(table, id, e)| {
+            PyValueError::new_err(format!("record {} of `{}`: {}", id, table, e))
+        })?;
+        if let Some(Value::Object(sequences)) = doc.remove("sequences") {
+            for (name, next) in sequences {
+                if let (Some(t), Some(next)) = (engine.tables.get_mut(&name), next.as_u64()) {
+                    t.next_id = t.next_id.max(next);
+                }
+            }
+        }
+        if let Some(aliases) = doc.remove("aliases") {
+            let aliases: HashMap<String, String> = serde_json::from_value(aliases)
+                .map_err(|e| PyValueError::new_err(format!("invalid aliases: {}", e)))?;
+            engine.aliases.extend(aliases);
+        }
+        if let Some(graph) = doc.remove("graph") {
+            let graph = serde_json::from_value(graph)
+                .map_err(|e| PyValueError::new_err(format!("invalid graph: {}", e)))?;
+            if merge {
+                engine.graph_rag.merge(graph);
+            } else {
+                engine.graph_rag.data = graph;
+                engine.graph_rag.rebuild_index();
+            }
+        }
+        self.engine = engine;
+        self.graph_dirty = true;
+        self.persist()
+    }
+    /// `import_sqlite` from an open source file, with its `(field_map, unmapped, transforms)`
+    /// as `mapping`. With `create`, `table` is built from the source's columns first and
+    /// removed again if its rows fail.
+    #[allow(clippy::too_many_arguments)]
+    fn import_sqlite_table<'py>(
+        &mut self,
+        py: Python<'py>,
+        conn: &Connection,
+        table: String,
+        src: &str,
+        on_conflict: OnConflict,
+        create: bool,
+        mapping: MappingArgs<'_, 'py>,
+        preserve_ids: bool,
+    ) -> PyResult<PyObject> {
+        let (field_map, unmapped, transforms) = mapping;
+        let warnings = match create {
+            true => {
+                let renames = match field_map {
+                    Some(m) => m.extract()?,
+                    None => HashMap::new(),
+                };
+                let (schema, warnings) = sqlite_schema(conn, src, &renames)?;
+                self.engine
+                    .create_table(&table, Table::new(schema))
+                    .map_err(convert_db_error)?;
+                Some(warnings)
+            }
+            false => None,
+        };
+        let mut mapping = None;
+        let result = self
+            .engine
+            .tables
+            .get(&table)
+            .ok_or_else(|| PyKeyError::new_err("missing table"))
+            .and_then(|t| {
+                mapping = ImportMapping::new(&t.schema, field_map, unmapped, transforms)?;
+                sqlite_rows(conn, src, &t.schema, mapping.as_mut(), preserve_ids)
+            })
+            .and_then(|rows| {
+                let label = "SQLite";
+                self.import_rows(py, &table, label, rows, on_conflict, false, preserve_ids)
+            })
+            .and_then(|report| match &mapping {
+                Some(mapping) => mapping.report(py, report),
+                None => Ok(report),
+            });
+        let Some(warnings) = warnings else {
+            return result;
+        };
+        if result.is_err() {
+            self.engine.tables.remove(&table);
+            return result;
+        }
+        // `import_rows` only persists when it wrote something; the new table still counts.
+        if self.engine.tables[&table].records.is_empty() {
+            self.persist()?;
+        }
+        let report = result?;
+        report
+            .downcast_bound::<PyDict>(py)?
+            .set_item("warnings", warnings)?;
+        Ok(report)
+    }
+    fn insert_frame(
+        &mut self,
+        table: &str,
+        df: &Bound<'_, PyAny>,
+        drop_unknown: bool,
+    ) -> PyResult<Vec<u64>> {
+        self.check_open()?;
+        self.check_writable()?;
+        let t = self
+            .engine
+            .tables
+            .get(table)
+            .ok_or_else(|| PyKeyError::new_err(format!("table '{}' does not exist", table)))?;
+        let mut columns = Vec::new();
+        for (name, values) in dataframe::columns(df)? {
+            match t.schema.get(&name) {
+                Some(def) if def.computed.is_none() => {
+                    columns.push((name, def.field_type.clone(), values))
+                }
+                // Computed columns, as `query_columns` returns them, are rebuilt on read.
+                Some(_) => {}
+                None if name == "id" || drop_unknown => {}
+                None => {
+                    return Err(PyValueError::new_err(format!(
+                        "DataFrame column '{}' is not a field of table '{}'; pass \
+                         drop_unknown=True to leave it out",
+                        name, table
+                    )))
+                }
+            }
+        }
+        let rows = columns.first().map_or(0, |c| c.2.len());
+        let mut payloads = vec![Map::new(); rows];
+        for (name, ty, values) in columns {
+            for (payload, value) in payloads.iter_mut().zip(values) {
+                // pandas stores an integer column holding NaN as floats.
+                let value = match (&ty, value.as_f64()) {
+                    (FieldType::Integer, Some(f)) if !value.is_i64() && f.fract() == 0.0 => {
+                        Value::from(f as i64)
+                    }
+                    _ => value,
+                };
+                payload.insert(name.clone(), value);
+            }
+        }
+        let next_id = t.next_id;
+        let mut ids = Vec::with_capacity(rows);
+        for (row, payload) in payloads.into_iter().enumerate() {
+            match self.engine.insert(table, payload) {
+                Ok(id) => ids.push(id),
+                Err(e) => {
+                    self.engine
+                        .rewind_inserts(table, ids, next_id)
+                        .map_err(convert_db_error)?;
+                    return Err(PyValueError::new_err(format!(
+                        "DataFrame row {}: {}",
+                        row, e
+                    )));
+                }
+            }
+        }
+        self.persist()?;
+        Ok(ids)
+    }
+    fn update_record(
+        &mut self,
+        table: String,
+        rid: u64,
+        patch: Bound<'_, PyDict>,
+        expected_version: Option<u64>,
+    ) -> PyResult<()> {
+        self.check_open()?;
+        self.check_writable()?;
+        let mut p = Map::new();
+        for (k, v) in patch.iter() {
+            p.insert(k.extract::<String>()?, py_to_json(v)?);
+        }
+        self.engine
+            .check_version(&table, rid, expected_version)
+            .and_then(|()| self.engine.update(&table, rid, p))
+            .map_err(convert_db_error)?;
+        self.stage_row(&table, rid, false);
+        self.persist()?;
+        Ok(())
+    }
+    fn delete_record(
+        &mut self,
+        py: Python<'_>,
+        table: String,
+        rid: u64,
+        expected_version: Option<u64>,
+    ) -> PyResult<PyObject> {
+        self.check_open()?;
+        self.check_writable()?;
+        let summary = self
+            .engine
+            .check_version(&table, rid, expected_version)
+            .and_then(|()| self.engine.delete(&table, rid))
+            .map_err(convert_db_error)?;
+        if self.journal {
+            self.pending_entry = Some(journal::Entry::Delete {
+                table: table.clone(),
+                id: rid,
+            });
+        }
+        self.persist()?;
+        delete_summary_to_py(py, &summary)
+    }
+    fn delete_matching(&mut self, py: Python<'_>, query: PyRef<'_, Query>) -> PyResult<PyObject> {
+        self.check_open()?;
+        self.check_writable()?;
+        let t = self.engine.tables.get(&query.table).ok_or_else(|| {
+            PyKeyError::new_err(format!("table '{}' does not exist", query.table))
+        })?;
+        let ids: Vec<u64> = query.rows(t).into_iter().map(|(id, _)| id).collect();
+        let summary = self
+            .engine
+            .delete_many(&query.table, &ids)
+            .map_err(convert_db_error)?;
+        self.persist()?;
+        delete_summary_to_py(py, &summary)
+    }
+    fn query_records(&self, py: Python<'_>, query: PyRef<'_, Query>) -> PyResult<Vec<Record>> {
+        self.check_open()?;
+        let t = self.engine.tables.get(&query.table).ok_or_else(|| {
+            PyKeyError::new_err(format!("table '{}' does not exist", query.table))
+        })?;
+        query.check_columns(t)?;
+        let rows = query.rows(t);
+        let mut res = Vec::new();
+        for (id, mut r) in rows {
+            query.project(&mut r);
+            res.push(Record {
+                id,
+                version: t.version(id),
+                data: json_to_py(py, &Value::Object(r.into_owned()))?,
+            });
+        }
+        Ok(res)
+    }
+    fn column_lists(&self, py: Python<'_>, query: PyRef<'_, Query>) -> PyResult<PyObject> {
+        self.check_open()?;
+        let t = self.engine.tables.get(&query.table).ok_or_else(|| {
+            PyKeyError::new_err(format!("table '{}' does not exist", query.table))
+        })?;
+        let fields = query.output_fields(t)?;
+        let rows = query.rows(t);
+        let out = PyDict::new_bound(py);
+        out.set_item("id", rows.iter().map(|(id, _)| *id).collect::<Vec<_>>())?;
+        for field in fields {
+            let mut values = Vec::with_capacity(rows.len());
+            for (_, r) in &rows {
+                values.push(json_to_py(py, r.get(&field).unwrap_or(&Value::Null))?);
+            }
+            out.set_item(field, PyList::new_bound(py, values))?;
+        }
+        Ok(out.into_any().unbind())
+    }
+    #[cfg(feature = "arrow")]
+    fn query_table(&self, py: Python<'_>, query: PyRef<'_, Query>) -> PyResult<PyObject> {
+        self.check_open()?;
+        let pyarrow = py.import_bound("pyarrow")?;
+        let t = self.engine.tables.get(&query.table).ok_or_else(|| {
+            PyKeyError::new_err(format!("table '{}' does not exist", query.table))
+        })?;
+        let fields = query.output_fields(t)?;
+        let rows = query.rows(t);
+        let mut ids = arrow::Column::new("id", arrow::Kind::Int64, rows.len());
+        let mut columns: Vec<_> = fields
+            .iter()
+            .map(|name| {
+                let kind = match t.schema[name].field_type {
+                    FieldType::Integer => arrow::Kind::Int64,
+                    FieldType::Float => arrow::Kind::Float64,
+                    FieldType::Boolean => arrow::Kind::Boolean,
+                    _ => arrow::Kind::Utf8,
+                };
+                arrow::Column::new(name, kind, rows.len())
+            })
+            .collect();
+        for (id, r) in &rows {
+            ids.push(&Value::from(*id));
+            for (name, column) in fields.iter().zip(&mut columns) {
+                column.push(r.get(name).unwrap_or(&Value::Null));
+            }
+        }
+        columns.insert(0, ids);
+        let mut batch = arrow::Batch::new(columns, rows.len());
+        let imported = pyarrow
+            .getattr("RecordBatch")?
+            .call_method1("_import_from_c", (batch.array_addr(), batch.schema_addr()))?;
+        Ok(pyarrow
+            .getattr("Table")?
+            .call_method1("from_batches", (vec![imported],))?
+            .unbind())
+    }
+    fn fetch_records(&self, py: Python<'_>, table: String) -> PyResult<Vec<Record>> {
+        self.check_open()?;
+        let t = self
+            .engine
+            .tables
+            .get(&table)
+            .ok_or_else(|| PyKeyError::new_err(format!("table '{}' does not exist", table)))?;
+        let now = now_millis();
+        let mut out = Vec::new();
+        for (id, data) in &t.records {
+            if t.hidden(*id, now) {
+                continue;
+            }
+            out.push(Record {
+                id: *id,
+                version: t.version(*id),
+                data: json_to_py(py, &Value::Object(t.materialize(data).into_owned()))?,
+            });
+        }
+        Ok(out)
+    }
+    fn get_record(&self, py: Python<'_>, table: String, rid: u64) -> PyResult<Record> {
+        self.check_open()?;
+        self.lookup(py, &table, rid)?
+            .ok_or_else(|| convert_db_error(DbError::MissingRecord(rid)))
+    }
+    fn ingest_text(
+        &mut self,
+        py: Python<'_>,
+        text: String,
+        source: Option<String>,
+        chunk_size: Option<usize>,
+        chunk_overlap: Option<usize>,
+    ) -> PyResult<PyObject> {
+        self.check_open()?;
+        self.check_writable()?;
+        check_ingest(&text, chunk_size)?;
+        let src = source.unwrap_or_else(|| "unknown".to_string());
+        let word_count = text.split_whitespace().count();
+        let mut graph = self.graph()?.clone();
+        let chunk_size = chunk_size.unwrap_or(graph.chunk_size);
+        let chunk_overlap = chunk_overlap.unwrap_or(graph.chunk_overlap);
+        let ingested_at = now_millis();
+        let counts = graph.ingest_with(&text, &src, chunk_size, chunk_overlap, ingested_at);
+        let retyped = self.classify_entities(py, &mut graph, &counts.new_entities)?;
+        if counts.new_chunks > 0 {
+            self.engine.graph_rag = graph;
+            self.graph_dirty = true;
+            // Replay would not ask the classifier again, so its types need a snapshot.
+            if self.journal && !retyped {
+                self.pending_entry = Some(journal::Entry::Ingest {
+                    text,
+                    source: src,
+                    chunk_size,
+                    chunk_overlap,
+                    ingested_at,
+                });
+            }
+            self.persist()?;
+        }
+        if !self.personality.is_professional() {
+            return Ok(self.personality.graph_ingested(word_count).into_py(py));
+        }
+        let out = PyDict::new_bound(py);
+        out.set_item("new_chunks", counts.new_chunks)?;
+        out.set_item("duplicate_chunks", counts.duplicate_chunks)?;
+        Ok(out.into_py(py))
+    }
+
+    /// `forget_source` for `graph_forget` and GRAPH FORGET, persisting what it removed.
+    fn forget_graph_source(&mut self, source: &str) -> PyResult<ForgetCounts> {
+        let removed = self.graph()?.forget_source(source);
+        if removed.chunks == 0 {
+            return Err(PyKeyError::new_err(format!(
+                "no source '{}' in the graph; GRAPH SOURCES lists them",
+                source
+            )));
+        }
+        self.graph_dirty = true;
+        self.persist()?;
+        Ok(removed)
+    }
+    /// Asks the `graph_set_entity_classifier` callback for the type of each of `names`,
+    /// storing any it changes in `graph`. Returns whether one changed. Callers pass a copy
+    /// of the graph and keep it only on success, so a failing callback changes nothing.
+    fn classify_entities(
+        &self,
+        py: Python<'_>,
+        graph: &mut GraphRagEngine,
+        names: &[String],
+    ) -> PyResult<bool> {
+        let Some(classify) = &self.entity_classifier else {
+            return Ok(false);
+        };
+        let mut changed = false;
+        for name in names {
+            let Some(suggested) = graph.data.entities.get(name).map(|e| e.entity_type.clone())
+            else {
+                continue;
+            };
+            let chosen: Option<String> = classify.call1(py, (name, &suggested))?.extract(py)?;
+            let chosen = chosen.map(|t| t.trim().to_uppercase());
+            if let Some(chosen) = chosen.filter(|t| !t.is_empty() && *t != suggested) {
+                changed |= graph.set_entity_type(name, &chosen);
+            }
+        }
+        Ok(changed)
+    }
+    fn graph_search(
+        &mut self,
+        query: String,
+        top_k: usize,
+        min_score: f32,
+        ranking: Option<Ranking>,
+        entity_types: &[String],
+    ) -> PyResult<String> {
+        self.check_open()?;
+        let graph = self.graph()?;
+        let ranking = ranking.unwrap_or(graph.ranking);
+        let result = graph.query(&query, top_k, min_score, ranking, entity_types);
+        let has_results = !result.contains("No relevant information found");
+        let prefix = self.personality.graph_query_result(has_results);
+        Ok(format!("{}\n\n{}", prefix, result))
+    }
+    /// Body of `storage_stats`.
+    fn storage_report(&mut self) -> PyResult<Option<Value>> {
+        let file_bytes = self.storage_path.as_deref().and_then(disk_usage);
+        let (raw, mut stored) = match (self.last_write, file_bytes) {
+            (Some(sizes), _) => sizes,
+            (None, Some(on_disk)) => (json_size(&self.engine)?, on_disk as usize),
+            (None, None) => return Ok(None),
+        };
+        let (mut written, mut coalesced) = (self.persists_written, 0);
+        if let Some(w) = &self.writer {
+            let bg = w.stats();
+            written += bg.written;
+            coalesced = bg.coalesced;
+            stored = bg.last_bytes;
+        }
+        let now = now_millis();
+        let mut tables = Map::new();
+        for (name, t) in &self.engine.tables {
+            let usage = serde_json::json!({
+                "records": t.live_count(now),
+                "bytes": json_size(t)?,
+            });
+            tables.insert(name.clone(), usage);
+        }
+        self.graph()?;
+        let graph = &self.engine.graph_rag.data;
+        let stats = serde_json::json!({
+            "file_bytes": file_bytes,
+            "raw_bytes": raw,
+            "stored_bytes": stored,
+            "ratio": raw as f64 / stored.max(1) as f64,
+            "compression": self.compression.name(),
+            "compression_level": self.compression_level,
+            "encrypted": self.encryption_key.is_some(),
+            "persists_written": written,
+            "persists_skipped": self.persists_skipped,
+            "persists_coalesced": coalesced,
+            "tables": tables,
+            "graph": {
+                "chunks": json_size(&graph.chunks)?,
+                "entities": json_size(&graph.entities)?,
+                "relations": json_size(&graph.relations)?,
+                "communities": json_size(&graph.communities)?,
+            },
+        });
+        Ok(Some(stats))
+    }
+    fn stats_report(&mut self) -> PyResult<Value> {
+        let now = now_millis();
+        let records: Map<String, Value> = self
+            .engine
+            .tables
+            .iter()
+            .map(|(name, t)| (name.clone(), t.live_count(now).into()))
+            .collect();
+        let total: u64 = records.values().filter_map(Value::as_u64).sum();
+        let graph = graph_stats_json(self.graph()?.stats());
+        Ok(serde_json::json!({
+            "tables": records.len(),
+            "records": records,
+            "total_records": total,
+            "aliases": self.engine.aliases.len(),
+            "graph": graph,
+            "session": {
+                "operations": self.ops_executed.get(),
+                "errors": self.errors_returned.get(),
+            },
+            "storage": self.storage_report()?,
+        }))
+    }
+    /// Enters a public method; `true` when no other one is running.
+    fn begin_call(&self) -> bool {
+        self.calls_open.set(self.calls_open.get() + 1);
+        self.calls_open.get() == 1
+    }
+    /// Leaves a public method, counting it for `stats` if it was the outermost call.
+    fn end_call(&self, outermost: bool, failed: bool) {
+        self.calls_open.set(self.calls_open.get() - 1);
+        if outermost {
+            self.ops_executed.set(self.ops_executed.get() + 1);
+            if failed {
+                self.errors_returned.set(self.errors_returned.get() + 1);
+            }
+        }
+    }
+    /// Prompt history, stored in the engine when it is persisted.
+    fn history(&self) -> &VecDeque<String> {
+        match self.persist_history {
+            true => &self.engine.history,
+            false => &self.command_history,
+        }
+    }
+    /// Appends a command typed at the prompt, evicting the oldest entries past `history_size`.
+    fn record_command(&mut self, sql: &str) {
+        let size = self.history_size;
+        let history = match self.persist_history {
+            true => &mut self.engine.history,
+            false => &mut self.command_history,
+        };
+        history.push_back(sql.to_string());
+        while history.len() > size {
+            history.pop_front();
+        }
+    }
+    /// Puts the engine back to `snapshot`; the history is not part of what gets undone.
+    fn restore_engine(&mut self, snapshot: Engine) {
+        let history = std::mem::take(&mut self.engine.history);
+        self.engine = snapshot;
+        self.engine.history = history;
+    }
+    /// Undoes the open `transaction()`, if any, so nothing it changed is left to write.
+    fn roll_back_transaction(&mut self) {
+        if let Some(snapshot) = self.tx_snapshot.take() {
+            self.restore_engine(*snapshot);
+            (self.dirty, self.queued) = self.tx_dirty;
+        }
+    }
+    /// Position of the open batch's savepoint called `name`; an unknown name leaves the
+    /// batch untouched.
+    fn savepoint_index(&self, name: &str) -> PyResult<usize> {
+        let name = name.to_ascii_lowercase();
+        self.savepoints
+            .iter()
+            .position(|(n, _)| *n == name)
+            .ok_or_else(|| PyKeyError::new_err(format!("no savepoint '{}' in this batch", name)))
+    }
+    /// `CREATE TABLE`: builds the schema `create_table` would from the column list. With
+    /// `IF NOT EXISTS` an existing table is left alone.
+    fn create_table_sql(&mut self, py: Python<'_>, create: sql::CreateTable) -> PyResult<PyObject> {
+        validate_identifier(&create.table).map_err(convert_db_error)?;
+        let mut schema = HashMap::new();
+        for column in create.columns {
+            validate_identifier(&column.name).map_err(convert_db_error)?;
+            let field_type = FieldType::from_str(&column.type_name).ok_or_else(|| {
+                PyValueError::new_err(format!("unsupported field type {}", column.type_name))
+            })?;
+            let mut def = FieldDef::new(field_type);
+            def.required = column.required;
+            def.unique = column.unique;
+            def.indexed = column.indexed;
+            def.default = match column.default {
+                None | Some(Value::Null) => None,
+                Some(value) => Some(
+                    def.field_type
+                        .conform(&column.name, value)
+                        .map_err(convert_db_error)?,
+                ),
+            };
+            schema.insert(column.name, def);
+        }
+        if create.if_not_exists && self.engine.tables.contains_key(&create.table) {
+            return Ok(if self.personality.is_professional() {
+                py.None()
+            } else {
+                self.personality
+                    .success(&format!("Table '{}' already exists.", create.table))
+                    .into_py(py)
+            });
+        }
+        self.add_table(create.table, Table::new(schema))
+    }
+    /// The `export_ddl` text: per table, a comment naming what DDL leaves out (if anything)
+    /// and its `CREATE TABLE` statement.
+    fn ddl(&self) -> String {
+        let mut names: Vec<&String> = self.engine.tables.keys().collect();
+        names.sort();
+        let mut out = Vec::new();
+        for name in names {
+            let t = &self.engine.tables[name];
+            let mut fields: Vec<(&String, &FieldDef)> = t.schema.iter().collect();
+            fields.sort_by_key(|(f, _)| *f);
+            let mut left_out = Vec::new();
+            let mut columns = Vec::new();
+            for (field, def) in fields {
+                if let Some(target) = &def.references {
+                    left_out.push(format!("{} (references {})", field, target));
+                }
+                if def.constraints != FieldConstraints::default() {
+                    left_out.push(format!("{} (constraints)", field));
+                }
+                if def.strict_unique_nulls {
+                    left_out.push(format!("{} (strict_unique_nulls)", field));
+                }
+                if def.description.is_some() {
+                    left_out.push(format!("{} (description)", field));
+                }
+                if def.computed.is_some() {
+                    left_out.push(format!("{} (computed)", field));
+                    continue;
+                }
+                columns.push(sql::Column {
+                    name: field.clone(),
+                    type_name: def.field_type.label(),
+                    required: def.required,
+                    unique: def.unique,
+                    indexed: def.indexed,
+                    default: def.default.clone(),
+                });
+            }
+            for (on, setting) in [
+                (t.versioned, "versioned"),
+                (t.ttl_seconds.is_some(), "ttl_seconds"),
+                (t.soft_delete, "soft_delete"),
+                (!t.meta.is_empty(), "meta"),
+            ] {
+                if on {
+                    left_out.push(setting.to_string());
+                }
+            }
+            let mut block = String::new();
+            if !left_out.is_empty() {
+                block += &format!("-- {}: not in DDL: {}\n", name, left_out.join(", "));
+            }
+            if columns.is_empty() {
+                block += &format!("-- {}: no fields to declare", name);
+            } else {
+                let create = sql::CreateTable {
+                    table: name.clone(),
+                    if_not_exists: false,
+                    columns,
+                };
+                block += &create.to_string();
+            }
+            out.push(block + "\n");
+        }
+        out.join("\n")
+    }
+    /// The `export_schema` document: format version plus every table's `describe()`.
+    fn schema_document(&self) -> Value {
+        let tables: Map<String, Value> = self
+            .engine
+            .tables
+            .iter()
+            .map(|(name, t)| (name.clone(), t.describe()))
+            .collect();
+        let mut doc = Map::new();
+        doc.insert("version".to_string(), Value::from(SCHEMA_FORMAT_VERSION));
+        doc.insert("tables".to_string(), Value::Object(tables));
+        Value::Object(doc)
+    }
+    fn apply_array_op(
+        &mut self,
+        table: &str,
+        rid: u64,
+        field: &str,
+        op: ArrayOp,
+        value: Bound<'_, PyAny>,
+    ) -> PyResult<usize> {
+        let len = self
+            .engine
+            .array_op(table, rid, field, op, py_to_json(value)?)
+            .map_err(convert_db_error)?;
+        self.persist()?;
+        Ok(len)
+    }
+    fn lookup(&self, py: Python<'_>, table: &str, rid: u64) -> PyResult<Option<Record>> {
+        let t = self
+            .engine
+            .tables
+            .get(table)
+            .ok_or_else(|| convert_db_error(DbError::MissingTable(table.to_string())))?;
+        let Some(data) = t.records.get(&rid) else {
+            return Ok(None);
+        };
+        if t.hidden(rid, now_millis()) {
+            return Ok(None);
+        }
+        Ok(Some(Record {
+            id: rid,
+            version: t.version(rid),
+            data: json_to_py(py, &Value::Object(t.materialize(data).into_owned()))?,
+        }))
+    }
+    fn reload_from_disk(&mut self) -> PyResult<()> {
+        self.drain_writer()?;
+        if let Some(p) = self.storage_path.clone() {
+            let mut sealed = Vec::new();
+            if !self.read_only {
+                if let Some(jp) = self.journal_path() {
+                    settle_temp(&jp, |b| Some(journal::parse(b).is_some()))?;
+                }
+            }
+            if p.is_dir() {
+                self.layout = StorageLayout::Directory;
+                sealed = self.read_directory(&p)?;
+            } else {
+                self.settle_sealed_temp(&p)?;
+            }
+            if p.is_file() {
+                sealed = fs::read(&p).map_err(|e| PyIOError::new_err(e.to_string()))?;
+                let data = self.unseal(&sealed)?;
+                self.engine = serde_json::from_slice(&data)
+                    .map_err(|e| PyValueError::new_err(e.to_string()))?;
+                // A graph stored inline moves to its own file on the next write.
+                let inline = !self.engine.graph_rag.is_empty();
+                self.engine.graph_loaded = inline;
+                self.graph_dirty = inline;
+            }
+            if p.exists() {
+                let current = is_current_format(&sealed);
+                if current && self.passphrase.is_some() && sealed[32 + 7] & FLAG_ENCRYPTED == 0 {
+                    warn_user(KEY_IGNORED_WARNING)?;
+                }
+                self.engine.rebuild_cache();
+                if self.persist_history {
+                    let excess = self.engine.history.len().saturating_sub(self.history_size);
+                    self.engine.history.drain(..excess);
+                } else {
+                    // Dropped from the file with the next write.
+                    self.engine.history.clear();
+                }
+                self.dirty = false;
+                if self.replay_journal() > 0 && !self.journal && !self.read_only {
+                    self.write_to_disk()?;
+                }
+                if !current {
+                    // Entries appended now would use the new key; checkpoint in the current
+                    // format first.
+                    self.journal_entries = JOURNAL_CHECKPOINT_ENTRIES;
+                }
+            }
+        }
+        self.dirty = false;
+        Ok(())
+    }
+    /// Loads the directory layout. Only an unreadable manifest fails the open; a table file
+    /// that is missing or corrupt is recorded in `load_errors` and skipped. The graph file
+    /// is left for `graph`. Returns the sealed manifest.
+    fn read_directory(&mut self, dir: &Path) -> PyResult<Vec<u8>> {
+        self.settle_sealed_temp(&dir.join(MANIFEST_FILE))?;
+        let b = fs::read(dir.join(MANIFEST_FILE)).map_err(|e| PyIOError::new_err(e.to_string()))?;
+        let manifest: Manifest = serde_json::from_slice(&self.unseal(&b)?)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        self.written.clear();
+        self.load_errors.clear();
+        let mut engine = Engine::new();
+        for (name, file) in &manifest.tables {
+            match self.read_part::<Table>(dir, file) {
+                Ok(table) => {
+                    engine.tables.insert(name.clone(), table);
+                }
+                Err(e) => {
+                    self.load_errors.insert(name.clone(), e);
+                }
+            }
+        }
+        engine.graph_loaded = false;
+        self.graph_dirty = false;
+        engine.aliases = manifest.aliases;
+        engine.alive = manifest.alive;
+        engine.strict_types = manifest.strict_types;
+        engine.journal_epoch = manifest.journal_epoch;
+        engine.history = manifest.history;
+        self.engine = engine;
+        if !self.load_errors.is_empty() {
+            let names: Vec<&str> = self.load_errors.keys().map(String::as_str).collect();
+            warn_user(&format!(
+                "RSN DB could not load {}; see load_errors()",
+                names.join(", ")
+            ))?;
+        }
+        Ok(b)
+    }
+    /// The knowledge graph, read from its file and indexed on first use.
+    fn graph(&mut self) -> PyResult<&mut GraphRagEngine> {
+        if !self.engine.graph_loaded {
+            let mut graph = match self.storage_path.clone() {
+                Some(p) => self.read_graph(&p, self.layout)?,
+                None => GraphRagEngine::new(),
+            };
+            graph.rebuild_index();
+            self.engine.graph_rag = graph;
+            self.engine.graph_loaded = true;
+        }
+        Ok(&mut self.engine.graph_rag)
+    }
+    /// The graph stored at `p`. A single-file database without a graph file has none yet; in
+    /// the directory layout a missing or corrupt `graph.rsn` is recorded in `load_errors`.
+    fn read_graph(&mut self, p: &Path, layout: StorageLayout) -> PyResult<GraphRagEngine> {
+        if layout == StorageLayout::Directory {
+            return match self.read_part(p, GRAPH_FILE) {
+                Ok(graph) => Ok(graph),
+                Err(e) => {
+                    self.load_errors.insert("graph".to_string(), e);
+                    warn_user("RSN DB could not load graph; see load_errors()")?;
+                    Ok(GraphRagEngine::new())
+                }
+            };
+        }
+        let (dir, file) = graph_file_parts(p);
+        if !dir.join(&file).exists() && !temp_path(&dir.join(&file)).exists() {
+            return Ok(GraphRagEngine::new());
+        }
+        self.read_part(&dir, &file).map_err(PyValueError::new_err)
+    }
+    /// Loads the graph and marks it for writing, ahead of a change that rewrites every file
+    /// and may make the stored one unreadable or leave it behind.
+    fn rewrite_graph(&mut self) -> PyResult<()> {
+        self.graph()?;
+        self.graph_dirty = true;
+        Ok(())
+    }
+    /// Writes the graph file of a single-file database if the graph changed. A graph that
+    /// is still empty needs no file. Returns whether it wrote.
+    fn write_graph_file(&mut self, p: &Path) -> PyResult<bool> {
+        if !self.graph_dirty {
+            return Ok(false);
+        }
+        let (dir, file) = graph_file_parts(p);
+        let graph = self.graph()?;
+        let wrote = if graph.is_empty() && !dir.join(&file).exists() {
+            false
+        } else {
+            let json =
+                serde_json::to_vec(graph).map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+            self.write_part(&dir, &file, &json)?
+        };
+        self.graph_dirty = false;
+        Ok(wrote)
+    }
+    /// Whether the integrity check passes, and the decoded body even when it does not.
+    fn decode_checked(&mut self, b: &[u8]) -> (bool, Result<Vec<u8>, String>) {
+        let (intact, decoded) = self.unseal_checked(b, true);
+        (intact, decoded.map_err(|e| e.to_string()))
+    }
+    fn verify_file(&mut self, path: &Path, recover: bool) -> PyResult<Value> {
+        let b = fs::read(path).map_err(|e| PyIOError::new_err(e.to_string()))?;
+        let (checksum_ok, decoded) = self.decode_checked(&b);
+        let header = FileHeader::parse(b.get(32..).unwrap_or_default(), self.compression, false);
+        let header_ok = header.is_ok();
+        let format_version = header.ok().and_then(|(h, _)| h.version);
+        let json = decoded.as_deref().unwrap_or_default();
+        let parsed = serde_json::from_slice::<Engine>(json).map_err(|e| e.to_string());
+        let raw = serde_json::from_slice::<RawEngine>(json).ok();
+
+        let mut salvage = Engine::new();
+        let mut tables = Map::new();
+        let mut lost = Vec::new();
+        for (name, part) in raw.iter().flat_map(|r| &r.tables) {
+            let digest = format!("{:x}", Sha256::digest(part.get().as_bytes()));
+            let sum_ok = raw
+                .as_ref()
+                .and_then(|r| r.checksums.get(name))
+                .map(|c| *c == digest);
+            let table = serde_json::from_str::<Table>(part.get()).map_err(|e| e.to_string());
+            let error = match (&table, sum_ok) {
+                (Err(e), _) => Some(e.clone()),
+                (Ok(_), Some(false)) => Some("table checksum mismatch".to_string()),
+                _ => None,
+            };
+            tables.insert(name.clone(), part_report(&table, sum_ok, &error));
+            match (table, error) {
+                (Ok(t), None) => {
+                    salvage.tables.insert(name.clone(), t);
+                }
+                _ => lost.push(name.clone()),
+            }
+        }
+        let graph = match raw.as_ref().and_then(|r| r.graph_rag) {
+            // Files from before the graph moved to its own file carry it inline.
+            Some(inline) => serde_json::from_str(inline.get()).map_err(|e| e.to_string()),
+            None => self.verify_graph_file(path),
+        };
+        let graph_ok = graph.is_ok();
+        match graph {
+            Ok(graph) => salvage.graph_rag = graph,
+            Err(_) => lost.push("graph".to_string()),
+        }
+        let mut report = serde_json::json!({
+            "path": path.display().to_string(),
+            "checksum_ok": checksum_ok,
+            "format_version": format_version,
+            "header_ok": header_ok,
+            "decode_ok": decoded.is_ok(),
+            "decode_error": decoded.as_ref().err(),
+            "parse_ok": parsed.is_ok(),
+            "parse_error": parsed.as_ref().err(),
+            "tables": tables,
+            "graph_ok": graph_ok,
+            "ok": checksum_ok && header_ok && parsed.is_ok() && lost.is_empty(),
+        });
+        if recover {
+            let Some(raw) = raw else {
+                return Err(PyValueError::new_err(format!(
+                    "nothing could be recovered from `{}`",
+                    path.display()
+                )));
+            };
+            salvage.aliases = raw.aliases;
+            salvage.alive = raw.alive;
+            salvage.strict_types = raw.strict_types;
+            salvage.history = std::mem::take(&mut self.engine.history);
+            salvage.journal_epoch = self.engine.journal_epoch;
+            let recovered: Vec<String> = salvage.tables.keys().cloned().collect();
+            self.engine = salvage;
+            self.finish_recovery()?;
+            report["recovered"] = recovered.into();
+            report["lost"] = lost.into();
+        }
+        Ok(report)
+    }
+    /// The graph file beside a single-file database, which may not exist yet.
+    fn verify_graph_file(&mut self, path: &Path) -> Result<GraphRagEngine, String> {
+        let (dir, file) = graph_file_parts(path);
+        match fs::read(dir.join(file)) {
+            Ok(b) => match self.decode_checked(&b) {
+                (false, _) => Err("checksum mismatch".to_string()),
+                (true, decoded) => decoded
+                    .and_then(|json| serde_json::from_slice(&json).map_err(|e| e.to_string())),
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(GraphRagEngine::new()),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+    /// Directory layout: the manifest and every table file checked on its own.
+    fn verify_directory(&mut self, dir: &Path, recover: bool) -> PyResult<Value> {
+        let manifest = match fs::read(dir.join(MANIFEST_FILE)) {
+            Ok(b) => match self.decode_checked(&b) {
+                (false, _) => Err("checksum mismatch".to_string()),
+                (true, decoded) => decoded.and_then(|json| {
+                    serde_json::from_slice::<Manifest>(&json).map_err(|e| e.to_string())
+                }),
+            },
+            Err(e) => Err(e.to_string()),
+        };
+        let mut tables = Map::new();
+        let mut ok = manifest.is_ok();
+        for (name, file) in manifest.iter().flat_map(|m| &m.tables) {
+            let (sum_ok, table) = match fs::read(dir.join(file)) {
+                Ok(b) => {
+                    let (sum_ok, decoded) = self.decode_checked(&b);
+                    let table = decoded.and_then(|json| {
+                        serde_json::from_slice::<Table>(&json).map_err(|e| e.to_string())
+                    });
+                    (Some(sum_ok), table)
+                }
+                Err(e) => (None, Err(e.to_string())),
+            };
+            let error = match (&table, sum_ok) {
+                (Err(e), _) => Some(e.clone()),
+                (Ok(_), Some(false)) => Some("checksum mismatch".to_string()),
+                _ => None,
+            };
+            ok &= error.is_none();
+            tables.insert(name.clone(), part_report(&table, sum_ok, &error));
+        }
+        let graph_ok =
+            fs::read(dir.join(GRAPH_FILE)).is_ok_and(|b| match self.decode_checked(&b) {
+                (true, Ok(json)) => serde_json::from_slice::<GraphRagEngine>(&json).is_ok(),
+                _ => false,
+            });
+        let mut report = serde_json::json!({
+            "path": dir.display().to_string(),
+            "manifest_ok": manifest.is_ok(),
+            "manifest_error": manifest.as_ref().err(),
+            "tables": tables,
+            "graph_ok": graph_ok,
+            "ok": ok && graph_ok,
+        });
+        if recover {
+            if let Err(e) = manifest {
+                return Err(PyValueError::new_err(format!(
+                    "nothing could be recovered from `{}`: {}",
+                    dir.display(),
+                    e
+                )));
+            }
+            let epoch = self.engine.journal_epoch;
+            self.read_directory(dir)?;
+            self.engine.graph_rag = self.read_graph(dir, StorageLayout::Directory)?;
+            self.engine.graph_loaded = true;
+            self.engine.journal_epoch = epoch;
+            let lost: Vec<String> = std::mem::take(&mut self.load_errors).into_keys().collect();
+            let recovered: Vec<String> = self.engine.tables.keys().cloned().collect();
+            self.finish_recovery()?;
+            report["recovered"] = recovered.into();
+            report["lost"] = lost.into();
+        }
+        Ok(report)
+    }
+    /// Persists salvaged state in full, under a new journal epoch.
+    fn finish_recovery(&mut self) -> PyResult<()> {
+        self.engine.rebuild_cache();
+        self.written.clear();
+        self.graph_dirty = true;
+        self.load_errors.clear();
+        self.journal_entries = JOURNAL_CHECKPOINT_ENTRIES;
+        self.persist()
+    }
+    /// Reads one file of the directory layout, remembering its digest as already written.
+    fn read_part<T: serde::de::DeserializeOwned + Serialize>(
+        &mut self,
+        dir: &Path,
+        file: &str,
+    ) -> Result<T, String> {
+        self.settle_sealed_temp(&dir.join(file))
+            .map_err(|e| e.to_string())?;
+        let b = fs::read(dir.join(file)).map_err(|e| e.to_string())?;
+        let data = self.unseal(&b).map_err(|e| e.to_string())?;
+        let part: T = serde_json::from_slice(&data).map_err(|e| e.to_string())?;
+        if let (true, Ok(json)) = (is_current_format(&b), serde_json::to_vec(&part)) {
+            let written = WrittenPart {
+                digest: Sha256::digest(&json).into(),
+                raw: json.len(),
+                stored: b.len(),
+            };
+            self.written.insert(file.to_string(), written);
+        }
+        Ok(part)
+    }
+    /// Writes one file unless it already holds this exact JSON; returns whether it wrote.
+    fn write_part(&mut self, dir: &Path, file: &str, json: &[u8]) -> PyResult<bool> {
+        let Some((sealed, written)) = self.sealed_part(dir, file, json)? else {
+            return Ok(false);
+        };
+        atomic_write(&dir.join(file), &sealed).map_err(|e| PyIOError::new_err(e.to_string()))?;
+        self.written.insert(file.to_string(), written);
+        Ok(true)
+    }
+    /// `json` sealed for `dir/file` with the record of writing it, or `None` when the file
+    /// already holds it.
+    fn sealed_part(
+        &self,
+        dir: &Path,
+        file: &str,
+        json: &[u8],
+    ) -> PyResult<Option<(Vec<u8>, WrittenPart)>> {
+        let digest: [u8; 32] = Sha256::digest(json).into();
+        if self.written.get(file).map(|w| w.digest) == Some(digest) && dir.join(file).exists() {
+            return Ok(None);
+        }
+        let sealed = self.seal(json)?;
+        let written = WrittenPart {
+            digest,
+            raw: json.len(),
+            stored: sealed.len(),
+        };
+        Ok(Some((sealed, written)))
+    }
+    /// Table files first and the manifest last, then files of dropped tables are removed.
+    /// Every changed file is staged in a temp file before any is renamed into place, so a
+    /// failed write (say, a rekey running out of disk) leaves all of them as they were.
+    /// Returns whether any file was written.
+    fn write_directory(&mut self, dir: &Path) -> PyResult<bool> {
+        fs::create_dir_all(dir).map_err(|e| PyIOError::new_err(e.to_string()))?;
+        let mut files = BTreeMap::new();
+        let mut parts = Vec::new();
+        for (name, table) in &self.engine.tables {
+            let json =
+                serde_json::to_vec(table).map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+            files.insert(name.clone(), table_file_name(name));
+            parts.push((table_file_name(name), json));
+        }
+        let tables = &self.engine.tables;
+        self.load_errors
+            .retain(|name, _| name == "graph" || !tables.contains_key(name));
+        for name in self.load_errors.keys().filter(|n| *n != "graph") {
+            files.insert(name.clone(), table_file_name(name));
+        }
+        let graph = self.graph_dirty || !dir.join(GRAPH_FILE).exists();
+        if graph {
+            let json = serde_json::to_vec(self.graph()?)
+                .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+            parts.push((GRAPH_FILE.to_string(), json));
+        }
+        let manifest = Manifest {
+            tables: files,
+            aliases: self.engine.aliases.clone(),
+            alive: self.engine.alive.clone(),
+            strict_types: self.engine.strict_types,
+            journal_epoch: self.engine.journal_epoch,
+            history: self.engine.history.clone(),
+        };
+        let json =
+            serde_json::to_vec(&manifest).map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        parts.push((MANIFEST_FILE.to_string(), json));
+        let mut staged = Vec::new();
+        for (file, json) in parts {
+            if let Some((sealed, written)) = self.sealed_part(dir, &file, &json)? {
+                staged.push((dir.join(&file), sealed, file, written));
+            }
+        }
+        let writes: Vec<(&Path, &[u8])> = staged
+            .iter()
+            .map(|(path, sealed, ..)| (path.as_path(), sealed.as_slice()))
+            .collect();
+        atomic_write_all(&writes).map_err(|e| PyIOError::new_err(e.to_string()))?;
+        let wrote = !staged.is_empty();
+        for (_, _, file, written) in staged {
+            self.written.insert(file, written);
+        }
+        if graph {
+            self.load_errors.remove("graph");
+            self.graph_dirty = false;
+        }
+        let live: HashSet<&String> = manifest.tables.values().collect();
+        for entry in fs::read_dir(dir).map_err(|e| PyIOError::new_err(e.to_string()))? {
+            let name = entry
+                .map_err(|e| PyIOError::new_err(e.to_string()))?
+                .file_name()
+                .to_string_lossy()
+                .into_owned();
+            if name.starts_with("table-") && name.ends_with(".rsn") && !live.contains(&name) {
+                fs::remove_file(dir.join(&name)).map_err(|e| PyIOError::new_err(e.to_string()))?;
+                self.written.remove(&name);
+            }
+        }
+        Ok(wrote)
+    }
+    /// Background mode: serializes the engine with the GIL released and leaves sealing and
+    /// the write to the writer thread. A snapshot equal to the last queued one is skipped.
+    fn enqueue_write(&mut self) -> PyResult<()> {
+        self.check_writable()?;
+        let (Some(p), true) = (self.storage_path.clone(), self.writer.is_some()) else {
+            return Ok(());
+        };
+        let now = now_millis();
+        self.engine.purge_all_expired(now);
+        // The graph changes rarely, so it is written here rather than carried by every
+        // queued snapshot, where a newer one could replace it before it is written.
+        self.write_graph_file(&p)?;
+        let engine = &mut self.engine;
+        let json = Python::with_gil(|py| {
+            py.allow_threads(|| {
+                engine.refresh_checksums()?;
+                serde_json::to_vec(&*engine)
+            })
+        })
+        .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        let file = p
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+        let digest: [u8; 32] = Sha256::digest(&json).into();
+        if self.written.get(&file).map(|w| w.digest) == Some(digest) {
+            self.persists_skipped += 1;
+            self.queued = true;
+            return Ok(());
+        }
+        self.last_write = Some((json.len(), 0));
+        let written = WrittenPart {
+            digest,
+            raw: json.len(),
+            stored: 0,
+        };
+        self.written.insert(file, written);
+        let sealer = self.sealer();
+        let Some(writer) = &self.writer else {
+            return Ok(());
+        };
+        let submitted = writer.submit(Box::new(move || {
+            let sealed = sealer.seal(&json)?;
+            atomic_write(&p, &sealed).map_err(|e| e.to_string())?;
+            Ok(sealed.len())
+        }));
+        submitted.map_err(|e| {
+            self.written.clear();
+            PyIOError::new_err(format!("background write failed: {}", e))
+        })?;
+        self.queued = true;
+        Ok(())
+    }
+    /// Blocks until the background writer has written everything queued. Only then is a
+    /// queued state no longer dirty.
+    fn drain_writer(&mut self) -> PyResult<()> {
+        let Some(writer) = &self.writer else {
+            return Ok(());
+        };
+        if let Err(e) = writer.drain() {
+            self.written.clear();
+            self.queued = false;
+            return Err(PyIOError::new_err(format!(
+                "background write failed: {}",
+                e
+            )));
+        }
+        if std::mem::take(&mut self.queued) {
+            self.dirty = false;
+        }
+        Ok(())
+    }
+    fn check_open(&self) -> PyResult<()> {
+        if self.closed {
+            return Err(PyRuntimeError::new_err("database is closed"));
+        }
+        Ok(())
+    }
+    fn check_writable(&self) -> PyResult<()> {
+        if self.read_only {
+            return Err(PyRuntimeError::new_err(
+                "database was opened with read_only=True",
+            ));
+        }
+        Ok(())
+    }
+    fn journal_path(&self) -> Option<PathBuf> {
+        let p = self.storage_path.as_ref()?;
+        let name = p.file_name()?.to_string_lossy();
+        Some(p.with_file_name(format!("{}.journal", name)))
+    }
+    /// Applies journal entries stamped with the loaded snapshot's epoch, stopping at the
+    /// first one that fails to verify, decode or apply. Returns how many were applied.
+    fn replay_journal(&mut self) -> usize {
+        self.journal_entries = 0;
+        let Some(bytes) = self.journal_path().and_then(|p| fs::read(p).ok()) else {
+            return 0;
+        };
+        let Some((epoch, frames)) = journal::parse(&bytes) else {
+            return 0;
+        };
+        if epoch != self.engine.journal_epoch {
+            return 0;
+        }
+        for frame in frames {
+            let payload = match self.passphrase {
+                Some(_) => match self.decrypt(frame) {
+                    Ok(p) => p,
+                    Err(_) => break,
+                },
+                None => frame.to_vec(),
+            };
+            let Ok(entry) = bincode::deserialize::<journal::Entry>(&payload) else {
+                break;
+            };
+            if matches!(
+                entry,
+                journal::Entry::Ingest { .. } | journal::Entry::Reingest { .. }
+            ) {
+                if self.graph().is_err() {
+                    break;
+                }
+                self.graph_dirty = true;
+            }
+            if self.engine.replay(entry).is_err() {
+                break;
+            }
+            self.journal_entries += 1;
+        }
+        self.journal_entries
+    }
+    /// Stages the stored row as an insert or update journal entry.
+    fn stage_row(&mut self, table: &str, id: u64, inserted: bool) {
+        if !self.journal {
+            return;
+        }
+        let Some(t) = self.engine.tables.get(table) else {
+            return;
+        };
+        let Some(record) = t.records.get(&id) else {
+            return;
+        };
+        let record = Value::Object(record.clone()).to_string();
+        let created_at = t.created_at.get(&id).copied();
+        let table = table.to_string();
+        self.pending_entry = Some(if inserted {
+            journal::Entry::Insert {
+                table,
+                id,
+                record,
+                created_at,
+            }
+        } else {
+            journal::Entry::Update { table, id, record }
+        });
+    }
+    /// Appends `entry` to the journal; `false` means a full checkpoint is needed instead.
+    fn append_journal(&mut self, entry: &journal::Entry) -> PyResult<bool> {
+        let Some(path) = self.journal_path().filter(|p| self.journal && p.exists()) else {
+            return Ok(false);
+        };
+        if self.journal_entries >= JOURNAL_CHECKPOINT_ENTRIES {
+            return Ok(false);
+        }
+        let mut payload =
+            bincode::serialize(entry).map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        if self.encryption_key.is_some() {
+            payload = self.encrypt(&payload).map_err(PyRuntimeError::new_err)?;
+        }
+        let mut file = fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .map_err(|e| PyIOError::new_err(e.to_string()))?;
+        file.write_all(&journal::frame(&payload))
+            .and_then(|()| file.sync_data())
+            .map_err(|e| PyIOError::new_err(e.to_string()))?;
+        self.journal_entries += 1;
+        Ok(true)
+    }
+    /// Called after every mutation: writes now, or just marks the state dirty inside a
+    /// transaction or with autosave off. With journaling on, a staged entry is appended to
+    /// the journal when everything before it is already durable.
+    fn persist(&mut self) -> PyResult<()> {
+        let entry = self.pending_entry.take();
+        if self.read_only {
+            return Ok(());
+        }
+        let clean = !self.dirty;
+        self.dirty = self.storage_path.is_some();
+        self.queued = false;
+        if self.tx_snapshot.is_some() || !self.autosave {
+            return Ok(());
+        }
+        if self.writer.is_some() {
+            return self.enqueue_write();
+        }
+        if let (true, Some(entry)) = (clean, entry) {
+            if self.append_journal(&entry)? {
+                self.dirty = false;
+                return Ok(());
+            }
+        }
+        self.write_to_disk()
+    }
+    /// Warns that the database was `closed` or `dropped` with changes never written.
+    fn warn_unsaved(&self, py: Python<'_>, how: &str) {
+        let category = py.get_type_bound::<PyUserWarning>();
+        let msg = format!("RSN DB {} with unsaved changes; call flush() first", how);
+        let _ = PyErr::warn_bound(py, &category, &msg, 1);
+    }
+    /// Writes a full snapshot. With journaling on this is a checkpoint: the epoch moves on
+    /// and the journal restarts empty, so a crash in between never replays stale entries.
+    fn write_to_disk(&mut self) -> PyResult<()> {
+        self.check_writable()?;
+        if self.writer.is_some() {
+            self.enqueue_write()?;
+            return self.drain_writer();
+        }
+        let now = now_millis();
+        self.engine.purge_all_expired(now);
+        // Only a journal holding entries needs a new epoch; otherwise an unchanged engine
+        // serializes identically and the write is skipped.
+        let checkpoint = self.journal && self.journal_entries > 0;
+        if checkpoint {
+            self.engine.journal_epoch += 1;
+        }
+        if let Some(p) = self.storage_path.clone() {
+            let dir = p.parent().unwrap_or(Path::new(""));
+            if !dir.as_os_str().is_empty() {
+                fs::create_dir_all(dir).map_err(|e| PyIOError::new_err(e.to_string()))?;
+            }
+            let wrote = match self.layout {
+                StorageLayout::File => {
+                    let graph = self.write_graph_file(&p)?;
+                    self.engine
+                        .refresh_checksums()
+                        .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+                    let b = serde_json::to_vec(&self.engine)
+                        .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+                    let file = p.file_name().unwrap_or_default().to_string_lossy();
+                    self.write_part(dir, &file, &b)? | graph
+                }
+                StorageLayout::Directory => self.write_directory(&p)?,
+            };
+            if wrote {
+                self.persists_written += 1;
+            } else {
+                self.persists_skipped += 1;
+            }
+            let totals = self
+                .written
+                .values()
+                .fold((0, 0), |(r, s), w| (r + w.raw, s + w.stored));
+            self.last_write = Some(totals);
+        }
+        if let Some(jp) = self.journal_path() {
+            let reset = if self.journal && (checkpoint || !jp.exists()) {
+                atomic_write(&jp, &journal::header(self.engine.journal_epoch))
+            } else if self.journal {
+                Ok(())
+            } else if jp.exists() {
+                fs::remove_file(&jp)
+            } else {
+                Ok(())
+            };
+            reset.map_err(|e| PyIOError::new_err(e.to_string()))?;
+        }
+        self.journal_entries = 0;
+        self.dirty = false;
+        Ok(())
+    }
+    /// Compresses, encrypts, adds the file header and checksum-prefixes serialized state.
+    fn seal(&self, json: &[u8]) -> PyResult<Vec<u8>> {
+        self.sealer().seal(json).map_err(PyIOError::new_err)
+    }
+    fn sealer(&self) -> Sealer {
+        Sealer {
+            compression: self.compression,
+            level: self.compression_level,
+            key: self.encryption_key,
+            salt: self.kdf_salt,
+        }
+    }
+    /// `settle_temp` for a sealed file, judged by whether it unseals. A copy that cannot be
+    /// checked without the right key is kept, and read-only handles leave it alone since
+    /// it may belong to a write still in progress elsewhere.
+    fn settle_sealed_temp(&mut self, path: &Path) -> PyResult<()> {
+        if self.read_only {
+            return Ok(());
+        }
+        settle_temp(path, |b| match self.unseal(b) {
+            Ok(_) => Some(true),
+            Err(e) if Python::with_gil(|py| e.is_instance_of::<EncryptionKeyError>(py)) => None,
+            Err(_) => Some(false),
+        })
+    }
+    /// Reverses `seal`: checks integrity, then decrypts and decompresses as the header says.
+    /// Files from before the header existed use the constructor's settings and the unsalted
+    /// key, and are rewritten in the current format on the next persist. Decrypting a current file adopts its salt for later writes.
+    fn unseal(&mut self, b: &[u8]) -> PyResult<Vec<u8>> {
+        self.unseal_checked(b, false).1
+    }
+    /// `unseal` that also reports whether the integrity check passed. With `salvage` the
+    /// payload is decoded even when it did not, as `verify` needs.
+    fn unseal_checked(&mut self, b: &[u8], salvage: bool) -> (bool, PyResult<Vec<u8>>) {
+        if b.len() < 32 {
+            return (false, Err(truncated(b.len(), 32 + FILE_HEADER_LEN)));
+        }
+        let (tag, d) = b.split_at(32);
+        let (header, rest) = match FileHeader::parse(d, self.compression, self.passphrase.is_some())
+        {
+            Ok(parsed) => parsed,
+            Err(e) => return (Sha256::digest(d).as_slice() == tag, Err(e)),
+        };
+        let key = if header.encrypted {
+            let Some(pass) = self.passphrase.as_deref() else {
+                let e =
+                    EncryptionKeyError::new_err("database is encrypted, provide encryption_key");
+                return (false, Err(e));
+            };
+            Some(match (header.kdf, self.encryption_key) {
+                (Some((KDF_ITERATIONS, salt)), Some(key)) if salt == self.kdf_salt => key,
+                (Some((iterations, salt)), _) => derive_key(pass, &salt, iterations),
+                (None, _) => legacy_key(pass),
+            })
+        } else {
+            None
+        };
+        // Encrypted files with a header are authenticated with a key-derived MAC, which
+        // catches tampering before decryption is attempted.
+        let keyed = key.filter(|_| header.key_check.is_some());
+        let d = &d[..header.body_len];
+        let intact = match &keyed {
+            Some(key) => {
+                let mut mac = integrity_mac(key);
+                mac.update(d);
+                mac.verify_slice(tag).is_ok()
+            }
+            None => Sha256::digest(d).as_slice() == tag,
+        };
+        if !intact && !salvage {
+            let e = match (&keyed, header.key_check) {
+                (Some(key), Some(check)) if key_check(key) != check => {
+                    EncryptionKeyError::new_err("incorrect encryption key")
+                }
+                (Some(_), _) => PyValueError::new_err(
+                    "integrity check failed: the file was modified or damaged",
+                ),
+                _ => PyValueError::new_err(
+                    "checksum mismatch; Database().verify(path, recover=True) can salvage intact tables",
+                ),
+            };
+            return (false, Err(e));
+        }
+        (intact, self.open_payload(&header, key, rest))
+    }
+    /// Decrypts (when the header says so) and decompresses the payload after the header.
+    fn open_payload(
+        &mut self,
+        header: &FileHeader,
+        key: Option<[u8; 32]>,
+        rest: &[u8],
+    ) -> PyResult<Vec<u8>> {
+        let headered = header.version.is_some();
+        let Some(key) = key else {
+            let data = decompress(header.algo, rest);
+            // Without a header, undecodable bytes most likely mean the file is encrypted.
+            if !headered && !data.as_ref().is_ok_and(|d| d.first() == Some(&b'{')) {
+                return Err(EncryptionKeyError::new_err(
+                    "database appears to be encrypted, provide encryption_key",
+                ));
+            }
+            return data;
+        };
+        let Ok(data) = decrypt_with(&key, rest) else {
+            // A file from before the header may be plaintext even though a key was given.
+            return match decompress(header.algo, rest) {
+                Ok(plain) if !headered && plain.first() == Some(&b'{') => {
+                    warn_user(KEY_IGNORED_WARNING)?;
+                    Ok(plain)
+                }
+                _ => Err(EncryptionKeyError::new_err("incorrect encryption key")),
+            };
+        };
+        if let Some((KDF_ITERATIONS, salt)) = header.kdf {
+            self.kdf_salt = salt;
+            self.encryption_key = Some(key);
+        }
+        decompress(header.algo, &data)
+    }
+    fn encrypt(&self, d: &[u8]) -> Result<Vec<u8>, String> {
+        let k = self.encryption_key.ok_or("no key".to_string())?;
+        encrypt_with(&k, d)
+    }
+    fn decrypt(&self, d: &[u8]) -> Result<Vec<u8>, String> {
+        let k = self.encryption_key.ok_or("no key".to_string())?;
+        decrypt_with(&k, d)
+    }
+}
+
+/// One table's entry in a `verify` report.
+fn part_report(
+    table: &Result<Table, String>,
+    checksum_ok: Option<bool>,
+    error: &Option<String>,
+) -> Value {
+    serde_json::json!({
+        "ok": error.is_none(),
+        "records": table.as_ref().ok().map(|t| t.records.len()),
+        "checksum_ok": checksum_ok,
+        "error": error,
+    })
 }
-#[pymethods]
-impl Query {
-    #[new]
-    fn new(table: String) -> Self {
-        Self {
-            table,
-            filters: Vec::new(),
-            order_by: None,
-            limit: None,
+
+/// Bytes a database occupies on disk: the file and its graph file, or every file of a
+/// directory layout.
+fn disk_usage(path: &Path) -> Option<u64> {
+    let meta = fs::metadata(path).ok()?;
+    if !meta.is_dir() {
+        let (dir, file) = graph_file_parts(path);
+        let graph = fs::metadata(dir.join(file)).map_or(0, |m| m.len());
+        return Some(meta.len() + graph);
+    }
+    let total = fs::read_dir(path)
+        .ok()?
+        .filter_map(|entry| entry.ok()?.metadata().ok())
+        .filter(|m| m.is_file())
+        .map(|m| m.len())
+        .sum();
+    Some(total)
+}
+
+/// Length of `value` serialized the way it is persisted.
+fn json_size<T: Serialize>(value: &T) -> PyResult<usize> {
+    serde_json::to_vec(value)
+        .map(|b| b.len())
+        .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+}
+
+/// The `stats` document as the lines STATS prints.
+fn render_stats(stats: &Value) -> String {
+    let mut out = format!(
+        "Tables: {}, {} record(s)",
+        stats["tables"], stats["total_records"]
+    );
+    for (name, records) in stats["records"].as_object().into_iter().flatten() {
+        out.push_str(&format!("\n  {}: {}", name, records));
+    }
+    let graph = &stats["graph"];
+    out.push_str(&format!(
+        "\nAliases: {}\nGraph: {} chunk(s), {} entity(ies), {} relation(s), {} community(ies)",
+        stats["aliases"],
+        graph["chunks"],
+        graph["entities"],
+        graph["relations"],
+        graph["communities"]
+    ));
+    out.push_str(&format!(
+        "\n  index: {} word(s), {} distinct; relation weight {}",
+        graph["indexed_words"], graph["vocabulary"], graph["relation_weight"]
+    ));
+    if let Some(types) = graph["entity_types"].as_object().filter(|t| !t.is_empty()) {
+        let types: Vec<String> = types.iter().map(|(t, n)| format!("{} {}", t, n)).collect();
+        out.push_str(&format!("\n  entity types: {}", types.join(", ")));
+    }
+    let sizes = &graph["community_sizes"];
+    if !sizes["max"].is_null() {
+        out.push_str(&format!(
+            "\n  community sizes: min {}, median {}, max {}",
+            sizes["min"], sizes["median"], sizes["max"]
+        ));
+    }
+    if let Some(top) = graph["top_entities"].as_array().filter(|t| !t.is_empty()) {
+        let top: Vec<String> = top
+            .iter()
+            .map(|e| {
+                format!(
+                    "{} ({})",
+                    e["name"].as_str().unwrap_or_default(),
+                    e["mentions"]
+                )
+            })
+            .collect();
+        out.push_str(&format!("\n  top entities: {}", top.join(", ")));
+    }
+    out.push_str(&format!(
+        "\nSession: {} operation(s), {} error(s)",
+        stats["session"]["operations"], stats["session"]["errors"]
+    ));
+    let storage = &stats["storage"];
+    match storage["stored_bytes"].as_u64() {
+        Some(stored) => out.push_str(&format!(
+            "\nStorage: {} byte(s) on disk, compression ratio {:.2}",
+            storage["file_bytes"].as_u64().unwrap_or(stored),
+            storage["ratio"].as_f64().unwrap_or_default()
+        )),
+        None => out.push_str("\nStorage: nothing on disk"),
+    }
+    out
+}
+
+/// Directory and name of the file beside a single-file database that holds its graph.
+fn graph_file_parts(path: &Path) -> (PathBuf, String) {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+    (dir, format!("{}.graph", name))
+}
+
+/// Sibling scratch file that `atomic_write` fills before renaming it over `path`.
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.tmp", name))
+}
+
+/// Writes `bytes` to a synced temp file beside `path` and renames it into place, so a
+/// crash mid-write leaves the previous file intact. `fs::rename` replaces the target on
+/// Windows too (`MoveFileExW` with `MOVEFILE_REPLACE_EXISTING`).
+fn atomic_write(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    atomic_write_all(&[(path, bytes)])
+}
+
+/// `atomic_write` for files in one directory: all temp files are written and synced before
+/// the first rename, so one that cannot be written leaves every target untouched. They are
+/// renamed in the order given.
+fn atomic_write_all(files: &[(&Path, &[u8])]) -> std::io::Result<()> {
+    let temps: Vec<PathBuf> = files.iter().map(|(path, _)| temp_path(path)).collect();
+    let discard = |temps: &[PathBuf]| {
+        for tmp in temps {
+            let _ = fs::remove_file(tmp);
+        }
+    };
+    for (i, ((_, bytes), tmp)) in files.iter().zip(&temps).enumerate() {
+        let written = fs::File::create(tmp).and_then(|mut f| {
+            f.write_all(bytes)?;
+            f.sync_all()
+        });
+        if let Err(e) = written {
+            discard(&temps[..=i]);
+            return Err(e);
         }
     }
-    #[pyo3(signature = (field, value))]
-    fn where_eq<'a>(
-        mut slf: PyRefMut<'a, Self>,
-        field: String,
-        value: Bound<'a, PyAny>,
-    ) -> PyResult<PyRefMut<'a, Self>> {
-        slf.filters.push((field, py_to_json(value)?));
-        Ok(slf)
-    }
-    #[pyo3(signature = (field, descending=None))]
-    fn order_by(
-        mut slf: PyRefMut<'_, Self>,
-        field: String,
-        descending: Option<bool>,
-    ) -> PyRefMut<'_, Self> {
-        slf.order_by = Some((field, descending.unwrap_or(false)));
-        slf
+    for (i, ((path, _), tmp)) in files.iter().zip(&temps).enumerate() {
+        if let Err(e) = fs::rename(tmp, path) {
+            discard(&temps[i..]);
+            return Err(e);
+        }
     }
-    fn take(mut slf: PyRefMut<'_, Self>, count: usize) -> PyRefMut<'_, Self> {
-        slf.limit = Some(count);
-        slf
+    #[cfg(unix)]
+    if let Some(dir) = files
+        .first()
+        .and_then(|(path, _)| path.parent())
+        .filter(|d| !d.as_os_str().is_empty())
+    {
+        fs::File::open(dir)?.sync_all()?;
     }
+    Ok(())
 }
 
-#[pyclass]
-struct Database {
-    engine: Engine,
-    storage_path: Option<PathBuf>,
-    encryption_key: Option<[u8; 32]>,
+/// Resolves the temp file an interrupted `atomic_write` left beside `path`. `check` says
+/// whether its bytes are complete, in which case it is renamed into place as the write
+/// would have done, or partial, in which case it is removed; `None` keeps it.
+fn settle_temp(path: &Path, check: impl FnOnce(&[u8]) -> Option<bool>) -> PyResult<()> {
+    let tmp = temp_path(path);
+    let Ok(bytes) = fs::read(&tmp) else {
+        return Ok(());
+    };
+    let settled = match check(&bytes) {
+        Some(true) => fs::rename(&tmp, path),
+        Some(false) => fs::remove_file(&tmp),
+        None => Ok(()),
+    };
+    settled.map_err(|e| PyIOError::new_err(e.to_string()))
+}
+
+fn sanitize_db_path(raw: &str) -> PyResult<PathBuf> {
+    sanitize_relative_path(raw, false, true)
+}
+
+/// Write settings detached from `Database`, so a background writer can seal snapshots.
+#[derive(Debug, Clone, Copy)]
+struct Sealer {
     compression: CompressionAlgo,
-    personality: Personality,
-    command_history: Vec<String>,
-    batch_mode: bool,
-    batch_ops: Vec<String>,
+    level: i32,
+    key: Option<[u8; 32]>,
+    salt: [u8; KDF_SALT_LEN],
 }
 
-#[pymethods]
-impl Database {
-    #[new]
-    #[pyo3(signature = (storage_path=None, encryption_key=None, compression="zstd", mode="professional"))]
-    fn new(
-        storage_path: Option<String>,
-        encryption_key: Option<String>,
-        compression: &str,
-        mode: &str,
-    ) -> PyResult<Self> {
-        let mut path = storage_path
-            .map(|candidate| sanitize_db_path(&candidate))
-            .transpose()?;
-        if let Some(ref mut p) = path {
-            if p.extension().is_none() {
-                p.set_extension("rsndb");
-            }
-        }
-        let key = encryption_key.map(|k| {
-            let mut hasher = Sha256::new();
-            hasher.update(k.as_bytes());
-            let mut res = [0u8; 32];
-            res.copy_from_slice(&hasher.finalize());
-            res
-        });
-        let mode_enum = match mode.to_lowercase().as_str() {
-            "friendly" => Mode::Friendly,
-            "snarky" => Mode::Snarky,
-            _ => Mode::Professional,
-        };
-        let comp_algo = match compression.to_lowercase().as_str() {
-            "zstd" => CompressionAlgo::Zstd,
-            "lz4" => CompressionAlgo::Lz4,
-            "none" => CompressionAlgo::None,
-            _ => CompressionAlgo::Zstd,
+impl Sealer {
+    /// Compresses, encrypts and adds the file header, then prefixes the integrity tag: a
+    /// key-derived HMAC for encrypted files and a plain SHA-256 otherwise.
+    fn seal(&self, json: &[u8]) -> Result<Vec<u8>, String> {
+        let mut b = match self.compression {
+            CompressionAlgo::Zstd => encode_all(json, self.level).map_err(|e| e.to_string())?,
+            CompressionAlgo::Lz4 => compress_prepend_size(json),
+            CompressionAlgo::None => json.to_vec(),
         };
-        let mut db = Self {
-            engine: Engine::new(),
-            storage_path: path,
-            encryption_key: key,
-            compression: comp_algo,
-            personality: Personality::new(mode_enum),
-            command_history: Vec::new(),
-            batch_mode: false,
-            batch_ops: Vec::new(),
+        let mut body = file_header_prefix().to_vec();
+        body.push(self.compression.tag());
+        if let Some(key) = &self.key {
+            b = encrypt_with(key, &b)?;
+            body.push(FLAG_ENCRYPTED);
+            body.extend(KDF_ITERATIONS.to_le_bytes());
+            body.extend(self.salt);
+            body.extend(key_check(key));
+        } else {
+            body.push(0);
+        }
+        body.extend((b.len() as u64).to_le_bytes());
+        body.extend(b);
+        let mut res = match &self.key {
+            Some(key) => {
+                let mut mac = integrity_mac(key);
+                mac.update(&body);
+                mac.finalize().into_bytes().to_vec()
+            }
+            None => Sha256::digest(&body).to_vec(),
         };
-        db.reload_from_disk()?;
-        Ok(db)
+        res.extend(body);
+        Ok(res)
     }
+}
 
-    fn create_table(&mut self, name: String, schema: Bound<'_, PyDict>) -> PyResult<PyObject> {
-        validate_identifier(&name).map_err(convert_db_error)?;
-        let mut native_schema = HashMap::new();
-        for (field, def) in schema.iter() {
-            let fname = field.extract::<String>()?;
-            validate_identifier(&fname).map_err(convert_db_error)?;
-            let d = def.downcast::<PyDict>()?;
-            let rtype = d
-                .get_item("type")?
-                .ok_or_else(|| PyValueError::new_err("schema field requires type"))?
-                .extract::<String>()?;
-            let ftype = FieldType::from_str(&rtype).ok_or_else(|| {
-                PyValueError::new_err(format!("unsupported field type {}", rtype))
-            })?;
-            let req = d
-                .get_item("required")?
-                .map(|it| it.extract::<bool>())
-                .transpose()?
-                .unwrap_or(false);
-            let uniq = d
-                .get_item("unique")?
-                .map(|it| it.extract::<bool>())
-                .transpose()?
-                .unwrap_or(false);
-            native_schema.insert(
-                fname,
-                FieldDef {
-                    field_type: ftype,
-                    required: req,
-                    unique: uniq,
-                },
-            );
+/// Header of a sealed file, after the integrity tag.
+struct FileHeader {
+    /// `None` for files from before the header, read with the constructor's settings.
+    version: Option<u8>,
+    algo: CompressionAlgo,
+    encrypted: bool,
+    /// KDF iteration count and salt of encrypted files with a header.
+    kdf: Option<(u32, [u8; KDF_SALT_LEN])>,
+    /// Key verifier of encrypted files with a header, which tells a wrong key apart from a
+    /// damaged file.
+    key_check: Option<[u8; KEY_CHECK_LEN]>,
+    /// Bytes of the body covered by the integrity tag; anything after them is ignored.
+    body_len: usize,
+}
+
+impl FileHeader {
+    /// Splits `d` into its header and payload. Headerless data is assumed to use
+    /// `compression`, and to be encrypted when a key was given. Bytes past the declared
+    /// payload length are left out of the payload.
+    fn parse(d: &[u8], compression: CompressionAlgo, keyed: bool) -> PyResult<(Self, &[u8])> {
+        let Some(rest) = d.strip_prefix(FILE_MAGIC.as_slice()) else {
+            let legacy = Self {
+                version: None,
+                algo: compression,
+                encrypted: keyed,
+                kdf: None,
+                key_check: None,
+                body_len: d.len(),
+            };
+            return Ok((legacy, d));
+        };
+        if rest.len() < FILE_HEADER_LEN - FILE_MAGIC.len() {
+            return Err(truncated(32 + d.len(), 32 + FILE_HEADER_LEN));
+        }
+        let version = rest[0];
+        if version != FILE_FORMAT_VERSION {
+            return Err(PyValueError::new_err(format!(
+                "unsupported file format version {}",
+                version
+            )));
+        }
+        let algo = CompressionAlgo::from_tag(rest[1])
+            .ok_or_else(|| PyValueError::new_err(format!("unknown compression tag {}", rest[1])))?;
+        let encrypted = rest[2] & FLAG_ENCRYPTED != 0;
+        let mut header = Self {
+            version: Some(version),
+            algo,
+            encrypted,
+            kdf: None,
+            key_check: None,
+            body_len: d.len(),
+        };
+        let mut header_len = FILE_HEADER_LEN + PAYLOAD_LEN_LEN;
+        if encrypted {
+            header_len += 4 + KDF_SALT_LEN + KEY_CHECK_LEN;
+        }
+        if d.len() < header_len {
+            return Err(truncated(32 + d.len(), 32 + header_len));
+        }
+        let mut p = &d[FILE_HEADER_LEN..header_len];
+        if encrypted {
+            let iterations = u32::from_le_bytes([p[0], p[1], p[2], p[3]]);
+            if iterations == 0 {
+                return Err(PyValueError::new_err(
+                    "invalid header: KDF iteration count is 0",
+                ));
+            }
+            let mut salt = [0u8; KDF_SALT_LEN];
+            salt.copy_from_slice(&p[4..4 + KDF_SALT_LEN]);
+            header.kdf = Some((iterations, salt));
+            let mut check = [0u8; KEY_CHECK_LEN];
+            check.copy_from_slice(&p[4 + KDF_SALT_LEN..4 + KDF_SALT_LEN + KEY_CHECK_LEN]);
+            header.key_check = Some(check);
+            p = &p[4 + KDF_SALT_LEN + KEY_CHECK_LEN..];
+        }
+        let declared = u64::from_le_bytes(p.try_into().expect("length field is 8 bytes"));
+        let end = usize::try_from(declared)
+            .ok()
+            .and_then(|n| n.checked_add(header_len));
+        match end {
+            Some(end) if end <= d.len() => {
+                header.body_len = end;
+                Ok((header, &d[header_len..end]))
+            }
+            _ => Err(truncated(
+                32 + d.len(),
+                end.unwrap_or(usize::MAX).saturating_add(32),
+            )),
         }
-        self.engine
-            .create_table(&name, native_schema)
-            .map_err(convert_db_error)?;
-        self.persist()?;
-        Python::with_gil(|py| {
-            Ok(if self.personality.is_professional() {
-                py.None()
-            } else {
-                self.personality
-                    .success(&format!("Table '{}' created.", name))
-                    .into_py(py)
-            })
-        })
     }
+}
 
-    fn insert(&mut self, table: String, payload: Bound<'_, PyDict>) -> PyResult<PyObject> {
-        validate_identifier(&table).map_err(convert_db_error)?;
-        let mut data = Map::new();
-        for (k, v) in payload.iter() {
-            data.insert(k.extract::<String>()?, py_to_json(v)?);
+/// Error for a sealed file that ends at byte `at` although its header needs `expected`.
+fn truncated(at: usize, expected: usize) -> PyErr {
+    PyValueError::new_err(format!(
+        "file appears truncated at byte {} of expected {}",
+        at, expected
+    ))
+}
+
+/// HMAC over a sealed file's body, keyed by a subkey of the encryption key.
+fn integrity_mac(key: &[u8; 32]) -> HmacSha256 {
+    let mut mac = <HmacSha256 as Mac>::new_from_slice(key).expect("HMAC takes any key length");
+    mac.update(b"rsn-db integrity\0");
+    mac
+}
+
+/// Short verifier stored in the header so a wrong key is reported as such.
+fn key_check(key: &[u8; 32]) -> [u8; KEY_CHECK_LEN] {
+    let mut mac = <HmacSha256 as Mac>::new_from_slice(key).expect("HMAC takes any key length");
+    mac.update(b"rsn-db key check\0");
+    let mut out = [0u8; KEY_CHECK_LEN];
+    out.copy_from_slice(&mac.finalize().into_bytes()[..KEY_CHECK_LEN]);
+    out
+}
+
+fn encrypt_with(key: &[u8; 32], d: &[u8]) -> Result<Vec<u8>, String> {
+    let c = Aes256Gcm::new_from_slice(key).map_err(|e| e.to_string())?;
+    let mut n_b = [0u8; 12];
+    thread_rng().fill(&mut n_b);
+    let n = Nonce::from_slice(&n_b);
+    let ct = c.encrypt(n, d).map_err(|e| e.to_string())?;
+    let mut out = n_b.to_vec();
+    out.extend(ct);
+    Ok(out)
+}
+
+fn decompress(algo: CompressionAlgo, data: &[u8]) -> PyResult<Vec<u8>> {
+    match algo {
+        CompressionAlgo::Zstd => decode_all(data).map_err(|e| PyIOError::new_err(e.to_string())),
+        CompressionAlgo::Lz4 => {
+            decompress_size_prepended(data).map_err(|e| PyIOError::new_err(e.to_string()))
         }
-        let id = self
-            .engine
-            .tables
-            .get_mut(&table)
-            .ok_or_else(|| PyKeyError::new_err(format!("table '{}' does not exist", table)))?
-            .insert(data)
-            .map_err(convert_db_error)?;
-        self.persist()?;
-        Python::with_gil(|py| {
-            Ok(if self.personality.is_professional() {
-                id.into_py(py)
-            } else {
-                self.personality
-                    .success(&format!("Row inserted into '{}' (id: {}).", table, id))
-                    .into_py(py)
-            })
+        CompressionAlgo::None => Ok(data.to_vec()),
+    }
+}
+
+fn warn_user(msg: &str) -> PyResult<()> {
+    Python::with_gil(|py| {
+        let category = py.get_type_bound::<PyUserWarning>();
+        PyErr::warn_bound(py, &category, msg, 1)
+    })
+}
+
+fn decrypt_with(key: &[u8; 32], d: &[u8]) -> Result<Vec<u8>, String> {
+    if d.len() < 12 {
+        return Err("bad data".to_string());
+    }
+    let c = Aes256Gcm::new_from_slice(key).map_err(|e| e.to_string())?;
+    let n = Nonce::from_slice(&d[..12]);
+    c.decrypt(n, &d[12..]).map_err(|e| e.to_string())
+}
+
+/// Magic and format version that start the header of every file written now.
+fn file_header_prefix() -> [u8; 6] {
+    let mut out = [0u8; 6];
+    out[..5].copy_from_slice(FILE_MAGIC);
+    out[5] = FILE_FORMAT_VERSION;
+    out
+}
+
+/// Whether a sealed file (checksum included) was written in the current format.
+fn is_current_format(sealed: &[u8]) -> bool {
+    sealed.get(32..38) == Some(&file_header_prefix()[..])
+}
+
+fn random_salt() -> [u8; KDF_SALT_LEN] {
+    let mut salt = [0u8; KDF_SALT_LEN];
+    thread_rng().fill(&mut salt);
+    salt
+}
+
+/// Takes the lock on `<db>.lock` beside `path`, waiting up to `timeout` seconds.
+fn acquire_lock(py: Python<'_>, path: &Path, exclusive: bool, timeout: f64) -> PyResult<PathBuf> {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let lock_path = path.with_file_name(format!("{}.lock", name));
+    if let Some(parent) = lock_path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent).map_err(|e| PyIOError::new_err(e.to_string()))?;
+    }
+    let timeout = Duration::try_from_secs_f64(timeout.max(0.0)).unwrap_or(Duration::MAX);
+    py.allow_threads(|| lock::acquire(&lock_path, exclusive, timeout))
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::WouldBlock => DatabaseLockedError::new_err(format!(
+                "`{}` is locked by another process; open it with read_only=True or pass a lock_timeout",
+                path.display()
+            )),
+            _ => PyIOError::new_err(e.to_string()),
         })
+}
+
+/// AES-256 key for a user passphrase: PBKDF2-HMAC-SHA256 over the per-database salt.
+fn derive_key(passphrase: &str, salt: &[u8], iterations: u32) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, iterations, &mut key);
+    key
+}
+
+/// Seals `json` as an `export_encrypted` bundle under a key derived from `passphrase`.
+fn seal_bundle(json: &[u8], passphrase: &str) -> Result<Vec<u8>, String> {
+    let salt = random_salt();
+    let key = derive_key(passphrase, &salt, KDF_ITERATIONS);
+    let compressed = encode_all(json, DEFAULT_ZSTD_LEVEL).map_err(|e| e.to_string())?;
+    let mut out = Vec::with_capacity(BUNDLE_HEADER_LEN + compressed.len() + 28);
+    out.extend_from_slice(BUNDLE_MAGIC);
+    out.push(BUNDLE_FORMAT_VERSION);
+    out.extend_from_slice(&KDF_ITERATIONS.to_le_bytes());
+    out.extend_from_slice(&salt);
+    out.extend_from_slice(&key_check(&key));
+    out.extend(encrypt_with(&key, &compressed)?);
+    Ok(out)
+}
+
+/// The JSON inside an `export_encrypted` bundle. A key that fails the verifier is an
+/// incorrect passphrase; one that passes it but fails to decrypt means a damaged file.
+fn open_bundle(bundle: &[u8], passphrase: &str) -> PyResult<Vec<u8>> {
+    if !bundle.starts_with(BUNDLE_MAGIC) {
+        let hint = match is_current_format(bundle) {
+            true => "; this is a database file, open it with Database()",
+            false => "",
+        };
+        return Err(PyValueError::new_err(format!(
+            "not an RSN DB export bundle{}",
+            hint
+        )));
     }
+    let Some(header) = bundle.get(..BUNDLE_HEADER_LEN) else {
+        return Err(PyValueError::new_err("export bundle is truncated"));
+    };
+    let version = header[BUNDLE_MAGIC.len()];
+    if version > BUNDLE_FORMAT_VERSION {
+        return Err(PyValueError::new_err(format!(
+            "unsupported export bundle format version {}",
+            version
+        )));
+    }
+    let rest = &header[BUNDLE_MAGIC.len() + 1..];
+    let (iterations, rest) = rest.split_at(4);
+    let (salt, check) = rest.split_at(KDF_SALT_LEN);
+    let iterations = u32::from_le_bytes(iterations.try_into().expect("4 bytes"));
+    if iterations == 0 || iterations > MAX_BUNDLE_KDF_ITERATIONS {
+        return Err(PyValueError::new_err(format!(
+            "export bundle asks for {} KDF iterations; refusing",
+            iterations
+        )));
+    }
+    let key = derive_key(passphrase, salt, iterations);
+    if key_check(&key)[..] != *check {
+        return Err(EncryptionKeyError::new_err("incorrect passphrase"));
+    }
+    let compressed = decrypt_with(&key, &bundle[BUNDLE_HEADER_LEN..])
+        .map_err(|_| PyValueError::new_err("export bundle is damaged and cannot be decrypted"))?;
+    decode_all(&compressed[..]).map_err(|e| PyIOError::new_err(e.to_string()))
+}
 
-    fn update(&mut self, table: String, rid: u64, patch: Bound<'_, PyDict>) -> PyResult<()> {
-        let mut p = Map::new();
-        for (k, v) in patch.iter() {
-            p.insert(k.extract::<String>()?, py_to_json(v)?);
-        }
-        self.engine
-            .tables
-            .get_mut(&table)
-            .ok_or_else(|| PyKeyError::new_err(format!("table '{}' does not exist", table)))?
-            .update(rid, p)
-            .map_err(convert_db_error)?;
-        self.persist()?;
-        Ok(())
+/// Unsalted key of files written before the KDF was introduced.
+fn legacy_key(passphrase: &str) -> [u8; 32] {
+    Sha256::digest(passphrase.as_bytes()).into()
+}
+
+fn check_zstd_level(level: i32) -> PyResult<i32> {
+    let range = zstd::compression_level_range();
+    if !range.contains(&level) {
+        return Err(PyValueError::new_err(format!(
+            "compression_level must be between {} and {}",
+            range.start(),
+            range.end()
+        )));
     }
+    Ok(level)
+}
 
-    fn delete(&mut self, table: String, rid: u64) -> PyResult<()> {
-        self.engine
-            .tables
-            .get_mut(&table)
-            .ok_or_else(|| PyKeyError::new_err(format!("table '{}' does not exist", table)))?
-            .delete(rid)
-            .map_err(convert_db_error)?;
-        self.persist()?;
-        Ok(())
+/// Database location as given by the user, with `.rsndb` appended when it has no extension.
+fn db_path(raw: &str) -> PyResult<PathBuf> {
+    let mut p = sanitize_db_path(raw)?;
+    if p.extension().is_none() {
+        p.set_extension("rsndb");
     }
+    Ok(p)
+}
 
-    fn fetch_all(&self, py: Python<'_>, table: String) -> PyResult<Vec<Record>> {
-        let t = self
-            .engine
-            .tables
-            .get(&table)
-            .ok_or_else(|| PyKeyError::new_err(format!("table '{}' does not exist", table)))?;
-        let mut out = Vec::new();
-        for (id, data) in &t.records {
-            out.push(Record {
-                id: *id,
-                data: json_to_py(py, &Value::Object(data.clone()))?,
-            });
-        }
-        Ok(out)
+/// File for `table` in the directory layout. Hex keeps any table name filesystem-safe.
+fn table_file_name(table: &str) -> String {
+    let hex: String = table.bytes().map(|b| format!("{:02x}", b)).collect();
+    format!("table-{}.rsn", hex)
+}
+
+/// The database at `path`, opened read-only for `diff_table` and `merge` as `Database(path,
+/// encryption_key=key, read_only=True)` would open it.
+fn open_baseline(py: Python<'_>, path: &str, key: Option<String>) -> PyResult<Database> {
+    let resolved = db_path(path)?;
+    if !resolved.exists() {
+        return Err(PyIOError::new_err(format!("no database at '{}'", path)));
     }
+    let layout = match resolved.is_dir() {
+        true => "directory",
+        false => "file",
+    };
+    Database::new(
+        py,
+        Some(path.to_string()),
+        key,
+        "zstd",
+        "professional",
+        None,
+        false,
+        layout,
+        None,
+        true,
+        0.0,
+        false,
+        DEFAULT_HISTORY_SIZE,
+        false,
+    )
+}
 
-    fn query(&self, py: Python<'_>, query: PyRef<'_, Query>) -> PyResult<Vec<Record>> {
-        let t = self.engine.tables.get(&query.table).ok_or_else(|| {
-            PyKeyError::new_err(format!("table '{}' does not exist", query.table))
-        })?;
-        let mut rows: Vec<(u64, Map<String, Value>)> =
-            t.records.iter().map(|(id, d)| (*id, d.clone())).collect();
-        for (f, e) in &query.filters {
-            rows.retain(|(_, r)| r.get(f) == Some(e));
+/// Compares the live rows of `new` with those of the baseline `old`, pairing them by id or,
+/// with `key`, by that field's value; rows whose key is null or missing never pair.
+fn diff_tables(new: &Table, old: &Table, key: Option<&str>, now: i64) -> Result<TableDiff, String> {
+    let stored = |t: &Table| -> BTreeSet<String> {
+        t.schema
+            .iter()
+            .filter(|(_, def)| def.computed.is_none())
+            .map(|(name, _)| name.clone())
+            .collect()
+    };
+    let (new_fields, old_fields) = (stored(new), stored(old));
+    let mut diff = TableDiff {
+        fields: new_fields.intersection(&old_fields).cloned().collect(),
+        fields_added: new_fields.difference(&old_fields).cloned().collect(),
+        fields_removed: old_fields.difference(&new_fields).cloned().collect(),
+        ..TableDiff::default()
+    };
+    if let Some(key) = key.filter(|k| *k != "id") {
+        if !diff.fields.iter().any(|f| f == key) {
+            return Err(format!("key '{}' is not a field of both tables", key));
         }
-        if let Some((f, d)) = &query.order_by {
-            rows.sort_by(|(_, l), (_, r)| {
-                let lv = l.get(f).unwrap_or(&Value::Null);
-                let rv = r.get(f).unwrap_or(&Value::Null);
-                let c = value_cmp(lv, rv);
-                if *d {
-                    c.reverse()
-                } else {
-                    c
-                }
-            });
+    }
+    let (new_rows, old_rows) = (keyed_rows(new, key, now)?, keyed_rows(old, key, now)?);
+    for (value, id) in &new_rows {
+        let Some(other_id) = old_rows.get(value).filter(|_| !value.starts_with('\0')) else {
+            diff.added.push(*id);
+            continue;
+        };
+        let (a, b) = (&new.records[id], &old.records[other_id]);
+        let changed = diff
+            .fields
+            .iter()
+            .any(|f| a.get(f).unwrap_or(&Value::Null) != b.get(f).unwrap_or(&Value::Null));
+        if changed {
+            diff.modified.push((*id, *other_id));
         }
-        if let Some(l) = query.limit {
-            rows.truncate(l);
+    }
+    for (value, id) in &old_rows {
+        if value.starts_with('\0') || !new_rows.contains_key(value) {
+            diff.removed.push(*id);
         }
-        let mut res = Vec::new();
-        for (id, r) in rows {
-            res.push(Record {
+    }
+    diff.added.sort_unstable();
+    diff.removed.sort_unstable();
+    diff.modified.sort_unstable();
+    Ok(diff)
+}
+
+/// The live rows of `t` under the value they pair on in `diff_table` and `merge`: their id,
+/// or the value of `key`. A row whose key is null or missing gets a value of its own,
+/// starting with a NUL, that pairs with nothing.
+fn keyed_rows(t: &Table, key: Option<&str>, now: i64) -> Result<BTreeMap<String, u64>, String> {
+    let mut out = BTreeMap::new();
+    for id in t.records.keys().filter(|id| !t.hidden(**id, now)) {
+        let value = match key.filter(|k| *k != "id") {
+            None => format!("{:020}", id),
+            Some(key) => match t.records[id].get(key) {
+                None | Some(Value::Null) => format!("\0{:020}", id),
+                Some(value) => value.to_string(),
+            },
+        };
+        if let Some(first) = out.insert(value.clone(), *id) {
+            return Err(format!(
+                "key '{}' is not unique: records {} and {} both hold {}",
+                key.unwrap_or("id"),
+                first,
                 id,
-                data: json_to_py(py, &Value::Object(r))?,
-            });
+                value
+            ));
         }
-        Ok(res)
     }
+    Ok(out)
+}
 
-    #[pyo3(signature = (text, source=None))]
-    fn ingest(&mut self, text: String, source: Option<String>) -> PyResult<String> {
-        if text.len() > MAX_INGEST_TEXT_BYTES {
+/// Merges the live rows of every table in `other` (read from `path`) into `engine`, as
+/// `Database.merge` describes, returning `[inserted, replaced, skipped]` per table.
+fn merge_engines(
+    engine: &mut Engine,
+    other: &Engine,
+    path: &str,
+    strategy: OnConflict,
+    keys: &HashMap<String, String>,
+) -> PyResult<BTreeMap<String, [usize; 3]>> {
+    let now = now_millis();
+    let mut names: Vec<&String> = other.tables.keys().collect();
+    names.sort();
+    let mut pending = Vec::new();
+    for name in &names {
+        let theirs = &other.tables[*name];
+        let Some(ours) = engine.tables.get(*name) else {
+            pending.push(((*name).clone(), theirs.emptied()));
+            continue;
+        };
+        let shape = |t: &Table| -> BTreeMap<String, Value> {
+            t.schema
+                .iter()
+                .map(|(f, def)| (f.clone(), serde_json::json!(def)))
+                .collect()
+        };
+        let (ours, theirs) = (shape(ours), shape(theirs));
+        let differ: Vec<&str> = ours
+            .keys()
+            .chain(theirs.keys())
+            .filter(|f| ours.get(*f) != theirs.get(*f))
+            .map(String::as_str)
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        if !differ.is_empty() {
             return Err(PyValueError::new_err(format!(
-                "INGEST payload exceeds max size of {} bytes",
-                MAX_INGEST_TEXT_BYTES
+                "table '{}' has a different schema in '{}' (fields {})",
+                name,
+                path,
+                differ.join(", ")
             )));
         }
-        let src = source.unwrap_or_else(|| "unknown".to_string());
-        let word_count = text.split_whitespace().count();
-        self.engine.graph_rag.ingest(&text, &src);
-        self.persist()?;
-        Ok(self.personality.graph_ingested(word_count))
     }
+    for (table, key) in keys {
+        let t = other.tables.get(table).ok_or_else(|| {
+            PyKeyError::new_err(format!("table '{}' does not exist in '{}'", table, path))
+        })?;
+        if !t.schema.contains_key(key) {
+            return Err(PyValueError::new_err(format!(
+                "key_fields names '{}', which is not a field of '{}'",
+                key, table
+            )));
+        }
+    }
+    engine.create_tables(pending).map_err(convert_db_error)?;
+
+    // Where each of their rows ends up here, so references can follow them.
+    let mut placed: HashMap<(&str, u64), u64> = HashMap::new();
+    let mut counts = BTreeMap::new();
+    let mut inserts = Vec::new();
+    let mut replaces = Vec::new();
+    for name in &names {
+        let (theirs, ours) = (&other.tables[*name], &engine.tables[*name]);
+        let key = keys.get(*name).map(String::as_str);
+        let matches = match key {
+            Some(key) => keyed_rows(ours, Some(key), now).map_err(PyValueError::new_err)?,
+            None => BTreeMap::new(),
+        };
+        let mut count = [0; 3];
+        for (value, id) in keyed_rows(theirs, key, now).map_err(PyValueError::new_err)? {
+            // Without a key match, a row holding a unique value of one here clashes with it.
+            let matched = match matches.get(&value).filter(|_| !value.starts_with('\0')) {
+                Some(&local) => Some((local, key.unwrap_or("id"))),
+                None => unique_clash(ours, theirs, id, now),
+            };
+            let Some((local, field)) = matched else {
+                let local = ours.next_id + count[0] as u64;
+                placed.insert((name.as_str(), id), local);
+                inserts.push(((*name).clone(), local, id));
+                count[0] += 1;
+                continue;
+            };
+            placed.insert((name.as_str(), id), local);
+            match strategy {
+                OnConflict::Error => {
+                    return Err(PyValueError::new_err(format!(
+                        "record {} of '{}' in '{}' matches record {} by '{}'",
+                        id, name, path, local, field
+                    )))
+                }
+                OnConflict::Skip => count[2] += 1,
+                OnConflict::Replace => {
+                    replaces.push(((*name).clone(), local, id));
+                    count[1] += 1;
+                }
+            }
+        }
+        counts.insert((*name).clone(), count);
+    }
+
+    // Their record `id` of `table`, with references pointing at the rows' places here.
+    let payload = |table: &str, id: u64| -> PyResult<Map<String, Value>> {
+        let t = &other.tables[table];
+        let mut record = t.records[&id].clone();
+        for (field, def) in &t.schema {
+            let Some(target) = &def.references else {
+                continue;
+            };
+            let Some(value) = record.get_mut(field).filter(|v| !v.is_null()) else {
+                continue;
+            };
+            let local = value
+                .as_u64()
+                .and_then(|v| placed.get(&(target.as_str(), v)));
+            let local = local.ok_or_else(|| {
+                PyValueError::new_err(format!(
+                    "record {} of '{}' in '{}' references {} of '{}', which is not there",
+                    id, table, path, value, target
+                ))
+            })?;
+            *value = Value::from(*local);
+        }
+        Ok(record)
+    };
+    let mut rows = Vec::with_capacity(inserts.len());
+    for (table, local, id) in &inserts {
+        rows.push((
+            table.clone(),
+            *local,
+            payload(table, *id)?,
+            RowMarks::default(),
+        ));
+    }
+    let mut updates = Vec::with_capacity(replaces.len());
+    for (table, local, id) in &replaces {
+        updates.push((table, *local, payload(table, *id)?));
+    }
+    let failed = |table: &str, id: u64, e: DbError| {
+        PyValueError::new_err(format!("record {} of '{}' in '{}': {}", id, table, path, e))
+    };
+    engine.load_rows(rows).map_err(|(table, local, e)| {
+        let id = inserts
+            .iter()
+            .find(|(t, l, _)| *t == table && *l == local)
+            .map_or(local, |(_, _, id)| *id);
+        failed(&table, id, e)
+    })?;
+    for ((table, local, record), (_, _, id)) in updates.into_iter().zip(&replaces) {
+        engine
+            .replace(table, local, record)
+            .map_err(|e| failed(table, *id, e))?;
+    }
+    Ok(counts)
+}
 
-    fn graph_query(&self, query: String) -> PyResult<String> {
-        let result = self.engine.graph_rag.query(&query);
-        let has_results = !result.contains("No relevant information found");
-        let prefix = self.personality.graph_query_result(has_results);
-        Ok(format!("{}\n\n{}", prefix, result))
+/// The row of `ours` that their record `id` clashes with on a unique field, and the field.
+fn unique_clash<'t>(ours: &Table, theirs: &'t Table, id: u64, now: i64) -> Option<(u64, &'t str)> {
+    let record = &theirs.records[&id];
+    let mut fields: Vec<&String> = theirs
+        .schema
+        .iter()
+        .filter(|(_, d)| d.unique)
+        .map(|(f, _)| f)
+        .collect();
+    fields.sort();
+    fields.into_iter().find_map(|field| {
+        let local = ours.unique_holder(field, record.get(field)?, now)?;
+        Some((local, field.as_str()))
+    })
+}
+
+/// The limits `ingest` and `graph_reingest` put on their arguments.
+fn check_ingest(text: &str, chunk_size: Option<usize>) -> PyResult<()> {
+    if chunk_size == Some(0) {
+        return Err(PyValueError::new_err("chunk_size must be positive"));
+    }
+    if text.len() > MAX_INGEST_TEXT_BYTES {
+        return Err(PyValueError::new_err(format!(
+            "INGEST payload exceeds max size of {} bytes",
+            MAX_INGEST_TEXT_BYTES
+        )));
     }
+    Ok(())
+}
 
-    fn execute_sql(&mut self, py: Python<'_>, sql: String) -> PyResult<PyObject> {
-        let out = self.execute_sql_recursive(py, sql, 0)?;
-        if let Some(whisper) = self.engine.alive.ambient(self.personality.mode()) {
-            if let Ok(s) = out.extract::<String>(py) {
-                return Ok(format!("{}\n  {}", s, whisper).into_py(py));
-            }
-        }
-        Ok(out)
+fn ranking_arg(raw: &str) -> PyResult<Ranking> {
+    Ranking::from_str(raw).ok_or_else(|| {
+        PyValueError::new_err(format!("ranking must be 'tfidf' or 'bm25', not '{}'", raw))
+    })
+}
+
+/// A `SourceStats` as GRAPH SOURCES and `graph_sources` return it.
+fn source_json(stats: SourceStats) -> Value {
+    let ingested_at = stats
+        .ingested_at
+        .and_then(DateTime::<Utc>::from_timestamp_millis)
+        .map(|dt| dt.to_rfc3339_opts(SecondsFormat::Millis, true));
+    serde_json::json!({
+        "source": stats.source,
+        "chunks": stats.chunks,
+        "words": stats.words,
+        "ingested_at": ingested_at,
+        "top_entities": stats.top_entities,
+    })
+}
+
+/// A `GraphStats` as `graph_stats` returns it and `stats` reports the graph.
+fn graph_stats_json(stats: GraphStats) -> Value {
+    let sizes = &stats.community_sizes;
+    let median = match sizes.len() {
+        0 => None,
+        n if n % 2 == 1 => Some(sizes[n / 2] as f64),
+        n => Some((sizes[n / 2 - 1] + sizes[n / 2]) as f64 / 2.0),
+    };
+    let top: Vec<Value> = stats
+        .top_entities
+        .into_iter()
+        .map(|(name, mentions)| serde_json::json!({"name": name, "mentions": mentions}))
+        .collect();
+    serde_json::json!({
+        "chunks": stats.chunks,
+        "indexed_words": stats.indexed_words,
+        "vocabulary": stats.vocabulary,
+        "entities": stats.entities,
+        "entity_types": stats.entity_types,
+        "relations": stats.relations,
+        "relation_weight": stats.relation_weight,
+        "communities": sizes.len(),
+        "community_sizes": {"min": sizes.first(), "median": median, "max": sizes.last()},
+        "top_entities": top,
+    })
+}
+
+/// An entity as GRAPH ENTITIES and `graph_search_entities` list it.
+fn entity_json(entity: &Entity) -> Value {
+    serde_json::json!({
+        "name": entity.name,
+        "type": entity.entity_type,
+        "mentions": entity.mentions,
+    })
+}
+
+/// A `Neighbor` as GRAPH NEIGHBORS and `graph_entity` list it.
+fn neighbor_json(neighbor: Neighbor) -> Value {
+    serde_json::json!({
+        "entity": neighbor.entity,
+        "weight": neighbor.weight,
+        "relations": neighbor.relations,
+    })
+}
+
+/// The KeyError for an entity the graph does not know, suggesting the nearest name (a
+/// small typo, else the most mentioned entity whose name starts with it) if there is one.
+fn unknown_entity(graph: &GraphRagEngine, name: &str) -> PyErr {
+    let names = graph
+        .top_entities(|_| true)
+        .into_iter()
+        .map(|e| e.name.as_str());
+    let near = commands::nearest(name, names).or_else(|| {
+        graph
+            .search_entities(name, 1)
+            .first()
+            .map(|e| e.name.as_str())
+    });
+    PyKeyError::new_err(match near {
+        Some(near) => format!(
+            "no entity '{}' in the graph; did you mean '{}'?",
+            name, near
+        ),
+        None => format!("no entity '{}' in the graph", name),
+    })
+}
+
+/// The `id` of a row imported with `preserve_ids`.
+fn import_id(raw: Option<Value>) -> Result<u64, String> {
+    match raw {
+        None | Some(Value::Null) => Err("row has no id, which preserve_ids needs".to_string()),
+        Some(v) => v
+            .as_u64()
+            .filter(|id| *id > 0)
+            .ok_or_else(|| format!("id must be a positive integer, not {}", v)),
     }
+}
 
-    fn execute_sql_recursive(
-        &mut self,
-        py: Python<'_>,
-        sql: String,
-        depth: usize,
-    ) -> PyResult<PyObject> {
-        if depth > MAX_RECURSION_DEPTH {
-            return Err(PyRuntimeError::new_err(
-                "Max alias recursion depth exceeded",
-            ));
+fn on_conflict_arg(raw: &str) -> PyResult<OnConflict> {
+    OnConflict::from_str(raw).ok_or_else(|| {
+        PyValueError::new_err(format!(
+            "on_conflict must be 'error', 'skip' or 'replace', not '{}'",
+            raw
+        ))
+    })
+}
+
+/// `(field_map, unmapped, transforms)` as an import was called with them.
+type MappingArgs<'a, 'py> = (
+    Option<&'a Bound<'py, PyDict>>,
+    &'a str,
+    Option<&'a Bound<'py, PyDict>>,
+);
+
+/// The `field_map`, `unmapped` and `transforms` arguments of an import. `field_map` renames
+/// source columns; a column neither renamed nor named like a field is then dropped, or an
+/// error with `unmapped="error"`. `transforms` runs a Python callable over each value of a
+/// field before validation. Also records what it did, for the report's `columns`.
+struct ImportMapping<'py> {
+    /// Source column -> field; None without a `field_map`, when columns keep their names.
+    renames: Option<HashMap<String, String>>,
+    fields: HashSet<String>,
+    drop_unmapped: bool,
+    transforms: Vec<(String, Bound<'py, PyAny>)>,
+    mapped: BTreeMap<String, String>,
+    dropped: BTreeSet<String>,
+    transformed: BTreeSet<String>,
+}
+
+impl<'py> ImportMapping<'py> {
+    /// None when the import was given neither a `field_map` nor `transforms`.
+    fn new(
+        schema: &HashMap<String, FieldDef>,
+        field_map: Option<&Bound<'py, PyDict>>,
+        unmapped: &str,
+        transforms: Option<&Bound<'py, PyDict>>,
+    ) -> PyResult<Option<Self>> {
+        let drop_unmapped = match unmapped.to_lowercase().as_str() {
+            "drop" => true,
+            "error" => false,
+            _ => {
+                return Err(PyValueError::new_err(format!(
+                    "unmapped must be 'drop' or 'error', not '{}'",
+                    unmapped
+                )))
+            }
+        };
+        if field_map.is_none() && transforms.is_none() {
+            return Ok(None);
         }
-        if sql.len() > MAX_COMMAND_LENGTH {
-            return Err(PyValueError::new_err(format!(
-                "Command exceeds max length of {} bytes",
-                MAX_COMMAND_LENGTH
-            )));
+        let mut renames = field_map.map(|_| HashMap::new());
+        let mut sources = HashMap::new();
+        for (source, field) in field_map.iter().flat_map(|m| m.iter()) {
+            let (source, field): (String, String) = (source.extract()?, field.extract()?);
+            if !schema.contains_key(&field) {
+                return Err(PyValueError::new_err(format!(
+                    "field_map maps '{}' to '{}', which is not a field",
+                    source, field
+                )));
+            }
+            if let Some(other) = sources.insert(field.clone(), source.clone()) {
+                return Err(PyValueError::new_err(format!(
+                    "field_map maps both '{}' and '{}' to '{}'",
+                    other, source, field
+                )));
+            }
+            if let Some(renames) = renames.as_mut() {
+                renames.insert(source, field);
+            }
         }
-        if self.batch_mode && !["COMMIT", "ROLLBACK"].contains(&sql.to_ascii_uppercase().as_str()) {
-            if self.batch_ops.len() >= MAX_BATCH_OPS {
+        let mut hooks = Vec::new();
+        for (field, hook) in transforms.iter().flat_map(|t| t.iter()) {
+            let field: String = field.extract()?;
+            if !schema.contains_key(&field) {
                 return Err(PyValueError::new_err(format!(
-                    "Batch operation limit exceeded (max {})",
-                    MAX_BATCH_OPS
+                    "transforms names '{}', which is not a field",
+                    field
+                )));
+            }
+            if !hook.is_callable() {
+                return Err(PyTypeError::new_err(format!(
+                    "transform for '{}' is not callable",
+                    field
                 )));
             }
-            self.batch_ops.push(sql.clone());
-            return Ok("".into_py(py));
+            hooks.push((field, hook));
         }
-
-        if depth == 0 {
-            self.command_history.push(sql.clone());
+        Ok(Some(Self {
+            renames,
+            fields: schema.keys().cloned().collect(),
+            drop_unmapped,
+            transforms: hooks,
+            mapped: BTreeMap::new(),
+            dropped: BTreeSet::new(),
+            transformed: BTreeSet::new(),
+        }))
+    }
+    /// The field source `column` fills, or None when it is dropped. `id` keeps its name so
+    /// the import can ignore it as usual.
+    fn target(&mut self, column: &str) -> Result<Option<String>, String> {
+        let Some(renames) = &self.renames else {
+            return Ok(Some(column.to_string()));
+        };
+        if let Some(field) = renames.get(column) {
+            self.mapped.insert(column.to_string(), field.clone());
+            return Ok(Some(field.clone()));
         }
-        let toks: Vec<&str> = sql.split_whitespace().collect();
-        if depth == 0 && !toks.is_empty() {
-            self.engine.alive.on_command();
+        if column == "id" || self.fields.contains(column) {
+            return Ok(Some(column.to_string()));
         }
-        if toks.is_empty() {
-            let empty_count = self
-                .command_history
-                .iter()
-                .filter(|s| s.trim().is_empty())
-                .count() as u32;
-            return Ok(self.personality.empty_input(empty_count).into_py(py));
+        if self.drop_unmapped {
+            self.dropped.insert(column.to_string());
+            return Ok(None);
         }
-
-        match toks[0].to_ascii_uppercase().as_str() {
-            "INGEST" => {
-                if toks.len() < 2 {
-                    return Err(PyValueError::new_err("INGEST requires text"));
-                }
-                let text = toks[1..].join(" ");
-                self.ingest(text, None).map(|s| s.into_py(py))
-            }
-            "GRAPH_QUERY" => {
-                if toks.len() < 2 {
-                    return Err(PyValueError::new_err("GRAPH_QUERY requires a query"));
-                }
-                let q = toks[1..].join(" ");
-                self.graph_query(q).map(|s| s.into_py(py))
-            }
-            "SHOW" | "TABLES" => Ok(self
-                .engine
-                .tables
-                .keys()
-                .cloned()
-                .collect::<Vec<_>>()
-                .into_py(py)),
-            "COUNT" => {
-                if toks.len() < 2 {
-                    return Err(PyValueError::new_err("COUNT requires a table name"));
-                }
-                Ok(self
-                    .engine
-                    .tables
-                    .get(toks[1])
-                    .ok_or_else(|| PyKeyError::new_err("missing table"))?
-                    .records
-                    .len()
-                    .into_py(py))
-            }
-            "DESCRIBE" => {
-                if toks.len() < 2 {
-                    return Err(PyValueError::new_err("DESCRIBE requires a table name"));
-                }
-                let table = self
-                    .engine
-                    .tables
-                    .get(toks[1])
-                    .ok_or_else(|| PyKeyError::new_err("missing table"))?;
-                let mut fields = table.schema.keys().cloned().collect::<Vec<_>>();
-                fields.sort();
-                Ok(fields.into_py(py))
-            }
-            "HISTORY" => {
-                let recent = self
-                    .command_history
-                    .iter()
-                    .rev()
-                    .filter(|cmd| {
-                        !cmd.trim().is_empty() && !cmd.to_uppercase().starts_with("HISTORY")
-                    })
-                    .take(10)
-                    .cloned()
-                    .collect::<Vec<_>>();
-                Ok(recent.into_py(py))
-            }
-            "BATCH" => {
-                self.batch_mode = true;
-                self.batch_ops.clear();
-                Ok("Batch mode started.".into_py(py))
-            }
-            "COMMIT" => {
-                self.batch_mode = false;
-                let ops: Vec<_> = self.batch_ops.drain(..).collect();
-                for operation in &ops {
-                    self.execute_sql_recursive(py, operation.clone(), depth + 1)?;
-                }
-                Ok(self.personality.batch_committed(ops.len()).into_py(py))
-            }
-            "ROLLBACK" => {
-                self.batch_mode = false;
-                self.batch_ops.clear();
-                Ok(self.personality.success("Batch rolled back.").into_py(py))
-            }
-            "ALIAS" => {
-                if toks.len() < 4 || toks[2] != "=" {
-                    return Err(PyValueError::new_err(
-                        "ALIAS format: ALIAS <name> = <command>",
-                    ));
+        Err(format!("column '{}' is not mapped to a field", column))
+    }
+    /// The fields a header's columns fill, in order, for CSV and SQLite sources.
+    fn targets(&mut self, columns: &[String]) -> PyResult<Vec<Option<String>>> {
+        let mut seen = HashMap::new();
+        let mut out = Vec::with_capacity(columns.len());
+        for column in columns {
+            let target = self.target(column).map_err(PyValueError::new_err)?;
+            if let Some(field) = &target {
+                if let Some(other) = seen.insert(field.clone(), column) {
+                    return Err(PyValueError::new_err(format!(
+                        "columns '{}' and '{}' both map to '{}'",
+                        other, column, field
+                    )));
                 }
-                let alias_name = toks[1].to_ascii_lowercase();
-                validate_identifier(&alias_name).map_err(convert_db_error)?;
-                self.engine.aliases.insert(alias_name, toks[3..].join(" "));
-                Ok("Alias created.".into_py(py))
-            }
-            "WHY" if toks.len() >= 5 && toks[1..4] == ["ARE", "YOU", "SO"] => {
-                Ok(self.personality.why_mean().into_py(py))
-            }
-            "ACHIEVEMENT" => Ok(self.personality.achievement_unlocked().into_py(py)),
-            "PULSE" => {
-                self.engine.alive.on_success();
-                Ok(self.engine.alive.pulse(self.personality.mode()).into_py(py))
-            }
-            "MOOD" => {
-                self.engine.alive.on_success();
-                Ok(format!(
-                    "{} (score {})",
-                    self.engine.alive.mood_label(),
-                    self.engine.alive.mood_score
-                )
-                .into_py(py))
             }
-            "VITALS" => {
-                self.engine.alive.on_success();
-                Ok(self.engine.alive.vitals_json().into_py(py))
-            }
-            _ => {
-                if let Some(translated) = self.engine.aliases.get(&toks[0].to_ascii_lowercase()) {
-                    return self.execute_sql_recursive(py, translated.clone(), depth + 1);
-                }
-                if toks[0] == "DELTE" {
-                    if depth == 0 {
-                        self.engine.alive.on_error();
-                    }
-                    return Err(PyValueError::new_err(
-                        self.personality.typo_suggestion("DELTE", "DELETE"),
-                    ));
-                }
-                if depth == 0 {
-                    self.engine.alive.on_error();
-                }
-                Err(PyRuntimeError::new_err(
-                    self.personality.error("unknown command"),
-                ))
+            out.push(target);
+        }
+        Ok(out)
+    }
+    /// `row` with its keys renamed, for JSONL rows, whose keys can differ line to line.
+    fn rename(&mut self, row: Map<String, Value>) -> Result<Map<String, Value>, String> {
+        let mut sources = HashMap::new();
+        let mut out = Map::new();
+        for (column, value) in row {
+            let Some(field) = self.target(&column)? else {
+                continue;
+            };
+            if let Some(other) = sources.insert(field.clone(), column.clone()) {
+                return Err(format!(
+                    "columns '{}' and '{}' both map to '{}'",
+                    other, column, field
+                ));
             }
+            out.insert(field, value);
         }
+        Ok(out)
+    }
+    /// Runs the transforms over `row`'s values.
+    fn transform(&mut self, row: &mut Map<String, Value>) -> Result<(), String> {
+        for (field, hook) in &self.transforms {
+            let Some(value) = row.get_mut(field) else {
+                continue;
+            };
+            let py = hook.py();
+            *value = json_to_py(py, value)
+                .and_then(|v| hook.call1((v,)))
+                .and_then(py_to_json)
+                .map_err(|e| format!("transform for '{}' failed: {}", field, e))?;
+            self.transformed.insert(field.clone());
+        }
+        Ok(())
+    }
+    /// Adds `columns` to an import report: the columns renamed, those dropped and the
+    /// fields transformed.
+    fn report(&self, py: Python<'py>, report: PyObject) -> PyResult<PyObject> {
+        let columns = serde_json::json!({
+            "mapped": self.mapped,
+            "dropped": self.dropped,
+            "transformed": self.transformed,
+        });
+        report
+            .downcast_bound::<PyDict>(py)?
+            .set_item("columns", json_to_py(py, &columns)?)?;
+        Ok(report)
     }
+}
 
-    fn export_jsonl(&self, table: String, dest: String) -> PyResult<()> {
-        let t = self
-            .engine
-            .tables
-            .get(&table)
-            .ok_or_else(|| PyKeyError::new_err("missing table"))?;
-        let mut out = String::new();
-        for (id, r) in &t.records {
-            let mut m = r.clone();
-            m.insert("id".into(), Value::Number((*id).into()));
-            let row = serde_json::to_string(&Value::Object(m))
-                .map_err(|e| PyValueError::new_err(e.to_string()))?;
-            out.push_str(&row);
-            out.push('\n');
-        }
-        let output_path = sanitize_user_path(&dest)?;
-        fs::write(output_path, out).map_err(|e| PyIOError::new_err(e.to_string()))
-    }
-    fn import_jsonl(&mut self, table: String, src: String) -> PyResult<usize> {
-        let source_path = sanitize_user_path(&src)?;
-        let metadata = fs::metadata(&source_path).map_err(|e| PyIOError::new_err(e.to_string()))?;
-        if metadata.len() > MAX_JSONL_IMPORT_BYTES {
+fn export_mode_arg(raw: &str) -> PyResult<ExportMode> {
+    ExportMode::from_str(raw).ok_or_else(|| {
+        PyValueError::new_err(format!(
+            "mode must be 'fail', 'replace' or 'append', not '{}'",
+            raw
+        ))
+    })
+}
+
+/// Writes table `name` into an open SQLite file for `export_sqlite`: an `id` primary key and
+/// one column per field in name order, then the records with their ids.
+fn write_sqlite_table(conn: &Connection, name: &str, t: &Table, mode: ExportMode) -> PyResult<()> {
+    let sql_err = |e: rusqlite::Error| PyIOError::new_err(e.to_string());
+    let mut fields: Vec<_> = t.schema.iter().collect();
+    fields.sort_by_key(|f| f.0);
+    let mut s = conn
+        .prepare(&format!("PRAGMA table_info([{}])", name))
+        .map_err(sql_err)?;
+    let existing = s
+        .query_map([], |r| Ok((r.get::<_, String>(1)?, r.get::<_, String>(2)?)))
+        .map_err(sql_err)?
+        .map(|c| c.map(|(column, declared)| format!("{} {}", column, declared)))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(sql_err)?;
+    let mut expected = vec!["id INTEGER".to_string()];
+    expected.extend(
+        fields
+            .iter()
+            .map(|(n, d)| format!("{} {}", n, d.field_type.sql_label())),
+    );
+    match mode {
+        _ if existing.is_empty() => {}
+        ExportMode::Fail => {
             return Err(PyValueError::new_err(format!(
-                "JSONL import exceeds max file size of {} bytes",
-                MAX_JSONL_IMPORT_BYTES
-            )));
+                "SQLite table '{}' already exists; pass mode='replace' or mode='append'",
+                name
+            )))
         }
-        let file = fs::File::open(source_path).map_err(|e| PyIOError::new_err(e.to_string()))?;
-        let reader = BufReader::new(file);
-        let t = self
-            .engine
-            .tables
-            .get_mut(&table)
-            .ok_or_else(|| PyKeyError::new_err("missing table"))?;
-        let mut count = 0;
-        for line_result in reader.lines() {
-            if count >= MAX_JSONL_IMPORT_LINES {
+        ExportMode::Replace => {
+            conn.execute(&format!("DROP TABLE [{}]", name), [])
+                .map_err(sql_err)?;
+        }
+        ExportMode::Append => {
+            let mut sorted = existing.clone();
+            sorted.sort();
+            let mut wanted = expected.clone();
+            wanted.sort();
+            if sorted != wanted {
                 return Err(PyValueError::new_err(format!(
-                    "JSONL import exceeds max line count of {}",
-                    MAX_JSONL_IMPORT_LINES
+                    "cannot append to SQLite table '{}': it has columns ({}), not ({})",
+                    name,
+                    existing.join(", "),
+                    expected.join(", ")
                 )));
             }
-            let line = line_result.map_err(|e| PyIOError::new_err(e.to_string()))?;
-            if line.trim().is_empty() {
-                continue;
-            }
-            let mut payload: Map<String, Value> = serde_json::from_str(&line)
-                .map_err(|e| PyValueError::new_err(format!("invalid JSONL row: {}", e)))?;
-            payload.remove("id");
-            t.insert(payload).map_err(convert_db_error)?;
-            count += 1;
         }
-        self.persist()?;
-        Ok(count)
     }
-    fn export_sqlite(&self, table: String, dest: String) -> PyResult<()> {
-        validate_identifier(&table).map_err(convert_db_error)?;
-        let t = self
-            .engine
-            .tables
-            .get(&table)
-            .ok_or_else(|| PyKeyError::new_err("missing table"))?;
-        let output_path = sanitize_user_path(&dest)?;
-        let conn = Connection::open(output_path).map_err(|e| PyIOError::new_err(e.to_string()))?;
-        let mut fields: Vec<_> = t.schema.iter().collect();
-        fields.sort_by_key(|f| f.0);
-        let cols = fields
-            .iter()
-            .map(|(n, d)| format!("[{}] {}", n, d.field_type.sql_label()))
-            .collect::<Vec<_>>()
-            .join(", ");
+    let cols = fields
+        .iter()
+        .map(|(n, d)| format!("[{}] {}", n, d.field_type.sql_label()))
+        .collect::<Vec<_>>()
+        .join(", ");
+    conn.execute(
+        &format!(
+            "CREATE TABLE IF NOT EXISTS [{}] (id INTEGER PRIMARY KEY, {})",
+            name, cols
+        ),
+        [],
+    )
+    .map_err(sql_err)?;
+    for (field, _) in fields.iter().filter(|(_, d)| d.unique) {
         conn.execute(
             &format!(
-                "CREATE TABLE IF NOT EXISTS [{}] (id INTEGER PRIMARY KEY, {})",
-                table, cols
+                "CREATE UNIQUE INDEX IF NOT EXISTS [{}_{}_unique] ON [{}] ([{}])",
+                name, field, name, field
             ),
             [],
         )
-        .map_err(|e| PyIOError::new_err(e.to_string()))?;
-        let placeholders = (0..fields.len() + 1)
-            .map(|_| "?")
-            .collect::<Vec<_>>()
-            .join(", ");
-        let stmt = format!(
+        .map_err(sql_err)?;
+    }
+    let placeholders = (0..fields.len() + 1)
+        .map(|_| "?")
+        .collect::<Vec<_>>()
+        .join(", ");
+    let mut insert = conn
+        .prepare(&format!(
             "INSERT INTO [{}] (id, {}) VALUES ({})",
-            table,
+            name,
             fields
                 .iter()
                 .map(|f| format!("[{}]", f.0))
                 .collect::<Vec<_>>()
                 .join(", "),
             placeholders
-        );
-        for (id, r) in &t.records {
-            let mut p = vec![SqlValue::Integer(*id as i64)];
-            for (fnm, _) in &fields {
-                p.push(match r.get(*fnm).unwrap_or(&Value::Null) {
-                    Value::Null => SqlValue::Null,
-                    Value::Bool(b) => SqlValue::Integer(*b as i64),
-                    Value::Number(n) => {
-                        if let Some(i) = n.as_i64() {
-                            SqlValue::Integer(i)
-                        } else if let Some(f) = n.as_f64() {
-                            SqlValue::Real(f)
-                        } else {
-                            SqlValue::Null
-                        }
+        ))
+        .map_err(sql_err)?;
+    let now = now_millis();
+    for (id, r) in t.records.iter().filter(|(id, _)| !t.hidden(**id, now)) {
+        let r = t.materialize(r);
+        let mut p = vec![SqlValue::Integer(*id as i64)];
+        for (fnm, _) in &fields {
+            p.push(match r.get(*fnm).unwrap_or(&Value::Null) {
+                Value::Null => SqlValue::Null,
+                Value::Bool(b) => SqlValue::Integer(*b as i64),
+                Value::Number(n) => {
+                    if let Some(i) = n.as_i64() {
+                        SqlValue::Integer(i)
+                    } else if let Some(f) = n.as_f64() {
+                        SqlValue::Real(f)
+                    } else {
+                        SqlValue::Null
                     }
-                    Value::String(s) => SqlValue::Text(s.clone()),
-                    _ => SqlValue::Text(r.get(*fnm).unwrap_or(&Value::Null).to_string()),
-                });
-            }
-            conn.execute(&stmt, rusqlite::params_from_iter(p))
-                .map_err(|e| PyIOError::new_err(e.to_string()))?;
+                }
+                Value::String(s) => SqlValue::Text(s.clone()),
+                other => SqlValue::Text(other.to_string()),
+            });
         }
-        Ok(())
+        insert.execute(rusqlite::params_from_iter(p)).map_err(|e| {
+            let reason = match sqlite_unique_column(&e) {
+                Some("id") => format!("id {} is already in the SQLite table", id),
+                Some(column) => format!(
+                    "{}, and the SQLite table already holds its value",
+                    DbError::UniqueViolation(column.to_string())
+                ),
+                None => e.to_string(),
+            };
+            PyValueError::new_err(format!("record {} of '{}': {}", id, name, reason))
+        })?;
     }
+    Ok(())
+}
 
-    #[pyo3(signature = (table, src, src_table=None))]
-    fn import_sqlite(
-        &mut self,
-        table: String,
-        src: String,
-        src_table: Option<String>,
-    ) -> PyResult<usize> {
-        validate_identifier(&table).map_err(convert_db_error)?;
-        let sn = src_table.unwrap_or(table.clone());
-        validate_identifier(&sn).map_err(convert_db_error)?;
-        let source_path = sanitize_user_path(&src)?;
-        let conn = Connection::open(source_path).map_err(|e| PyIOError::new_err(e.to_string()))?;
-        let t = self
-            .engine
-            .tables
-            .get_mut(&table)
-            .ok_or_else(|| PyKeyError::new_err("missing table"))?;
-        let mut s = conn
-            .prepare(&format!("SELECT * FROM [{}]", sn))
-            .map_err(|e| PyValueError::new_err(e.to_string()))?;
-        let cols: Vec<_> = s.column_names().into_iter().map(String::from).collect();
-        let mut rows = s
-            .query([])
-            .map_err(|e| PyValueError::new_err(e.to_string()))?;
-        let mut n = 0;
-        while let Some(r) = rows
-            .next()
-            .map_err(|e| PyValueError::new_err(e.to_string()))?
+/// The column a SQLite "UNIQUE constraint failed: table.column" error names.
+fn sqlite_unique_column(e: &rusqlite::Error) -> Option<&str> {
+    match e {
+        rusqlite::Error::SqliteFailure(f, Some(msg))
+            if f.code == rusqlite::ErrorCode::ConstraintViolation =>
         {
-            let mut p = Map::new();
-            for (i, name) in cols.iter().enumerate() {
-                if name == "id" || !t.schema.contains_key(name) {
-                    continue;
-                }
-                let value_ref = r
-                    .get_ref(i)
-                    .map_err(|e| PyValueError::new_err(e.to_string()))?;
-                p.insert(
-                    name.clone(),
-                    match value_ref {
-                        ValueRef::Null => Value::Null,
-                        ValueRef::Integer(i) => Value::Number(i.into()),
-                        ValueRef::Real(f) => serde_json::Number::from_f64(f)
-                            .map(Value::Number)
-                            .unwrap_or(Value::Null),
-                        ValueRef::Text(txt) => {
-                            let s = String::from_utf8_lossy(txt);
-                            if let Some(def) = t.schema.get(name) {
-                                if def.field_type == FieldType::Json {
-                                    serde_json::from_str(&s).unwrap_or(Value::String(s.to_string()))
-                                } else {
-                                    Value::String(s.to_string())
-                                }
-                            } else {
-                                unreachable!("Field name must be in schema due to check on line 913");
-                            }
-                        }
-                        _ => Value::Null,
-                    },
-                );
-            }
-            t.insert(p).map_err(convert_db_error)?;
-            n += 1;
+            msg.strip_prefix("UNIQUE constraint failed: ")?
+                .rsplit('.')
+                .next()
         }
-        self.persist()?;
-        Ok(n)
+        _ => None,
     }
+}
 
-    fn save(&self) -> PyResult<()> {
-        self.persist()
+/// Whether a JSONL file is gzipped: as `compression` says when given, else `detected`.
+fn gzip_arg(compression: Option<&str>, detected: bool) -> PyResult<bool> {
+    match compression.map(str::to_lowercase).as_deref() {
+        None => Ok(detected),
+        Some("gzip") => Ok(true),
+        Some("none") => Ok(false),
+        Some(_) => Err(PyValueError::new_err(format!(
+            "compression must be 'gzip' or 'none', not '{}'",
+            compression.unwrap_or_default()
+        ))),
     }
+}
 
-    fn load(&mut self) -> PyResult<()> {
-        self.reload_from_disk()
+/// Writes `rows` for `export_jsonl`, one object per line with its `id` first.
+fn write_jsonl<W: Write>(mut out: W, rows: ExportRows<'_>) -> PyResult<W> {
+    let io_err = |e: std::io::Error| PyIOError::new_err(e.to_string());
+    let json_err = |e: serde_json::Error| PyIOError::new_err(e.to_string());
+    for (id, m) in rows {
+        write!(out, "{{\"id\":{}", id).map_err(io_err)?;
+        for (field, value) in m.iter() {
+            out.write_all(b",").map_err(io_err)?;
+            serde_json::to_writer(&mut out, field).map_err(json_err)?;
+            out.write_all(b":").map_err(io_err)?;
+            serde_json::to_writer(&mut out, value).map_err(json_err)?;
+        }
+        out.write_all(b"}\n").map_err(io_err)?;
     }
+    Ok(out)
+}
 
-    fn snapshot(&self, dest: String) -> PyResult<()> {
-        let src = self
-            .storage_path
-            .as_ref()
-            .ok_or_else(|| PyValueError::new_err("snapshot requires storage_path"))?;
-        if !src.exists() {
-            self.persist()?;
+/// The schema `import_sqlite(create_table=True)` gives a copy of SQLite table `src`: NOT
+/// NULL columns are required and single-column unique indexes (or a lone primary key) make
+/// a field unique. Columns named in `renames` take the field name given there. Returns a
+/// warning for everything that could not be carried over.
+fn sqlite_schema(
+    conn: &Connection,
+    src: &str,
+    renames: &HashMap<String, String>,
+) -> PyResult<(HashMap<String, FieldDef>, Vec<String>)> {
+    let sql_err = |e: rusqlite::Error| PyValueError::new_err(e.to_string());
+    let mut warnings = Vec::new();
+    let mut unique = HashSet::new();
+    let mut s = conn
+        .prepare(&format!("PRAGMA index_list([{}])", src))
+        .map_err(sql_err)?;
+    let indexes = s
+        .query_map([], |r| Ok((r.get::<_, String>(1)?, r.get(2)?, r.get(4)?)))
+        .map_err(sql_err)?
+        .collect::<Result<Vec<(String, bool, bool)>, _>>()
+        .map_err(sql_err)?;
+    for (index, is_unique, partial) in indexes {
+        if !is_unique {
+            continue;
         }
-        let output_path = sanitize_user_path(&dest)?;
-        let bytes = fs::read(src).map_err(|e| PyIOError::new_err(e.to_string()))?;
-        if let Some(parent) = output_path.parent() {
-            fs::create_dir_all(parent).map_err(|e| PyIOError::new_err(e.to_string()))?;
+        let mut s = conn
+            .prepare(&format!("PRAGMA index_info([{}])", index))
+            .map_err(sql_err)?;
+        let columns = s
+            .query_map([], |r| r.get(2))
+            .map_err(sql_err)?
+            .collect::<Result<Vec<Option<String>>, _>>()
+            .map_err(sql_err)?;
+        match columns.as_slice() {
+            [Some(column)] if !partial => {
+                unique.insert(column.clone());
+            }
+            _ => warnings.push(format!(
+                "unique index '{}' is partial or spans several columns; not enforced",
+                index
+            )),
         }
-        fs::write(output_path, bytes).map_err(|e| PyIOError::new_err(e.to_string()))?;
-        Ok(())
     }
+    let mut s = conn
+        .prepare(&format!("PRAGMA table_info([{}])", src))
+        .map_err(sql_err)?;
+    let columns = s
+        .query_map([], |r| Ok((r.get(1)?, r.get(2)?, r.get(3)?, r.get(5)?)))
+        .map_err(sql_err)?
+        .collect::<Result<Vec<(String, String, bool, i64)>, _>>()
+        .map_err(sql_err)?;
+    if columns.is_empty() {
+        return Err(PyValueError::new_err(format!("no such table: {}", src)));
+    }
+    let keys = columns.iter().filter(|c| c.3 > 0).count();
+    let mut schema = HashMap::new();
+    for (column, declared, not_null, pk) in columns {
+        let is_unique = unique.contains(&column) || pk > 0 && keys == 1;
+        let name = renames.get(&column).cloned().unwrap_or(column);
+        // Records get their own ids; a source `id` column is not imported.
+        if name == "id" {
+            continue;
+        }
+        if validate_identifier(&name).is_err() {
+            warnings.push(format!(
+                "column '{}' is not a valid field name; skipped",
+                name
+            ));
+            continue;
+        }
+        let field_type = FieldType::from_sqlite(&declared).unwrap_or_else(|| {
+            warnings.push(match declared.is_empty() {
+                true => format!("column '{}' has no declared type; imported as json", name),
+                false => format!(
+                    "column '{}' has SQLite type {}; imported as json",
+                    name, declared
+                ),
+            });
+            FieldType::Json
+        });
+        let mut def = FieldDef::new(field_type);
+        def.required = not_null;
+        def.unique = is_unique;
+        schema.insert(name, def);
+    }
+    Ok((schema, warnings))
 }
 
-impl Database {
-    fn reload_from_disk(&mut self) -> PyResult<()> {
-        if let Some(p) = &self.storage_path {
-            if p.exists() {
-                let b = fs::read(p).map_err(|e| PyIOError::new_err(e.to_string()))?;
-                if b.len() < 32 {
-                    return Err(PyValueError::new_err("corrupted file"));
-                }
-                let (c, d) = b.split_at(32);
-                let mut h = Sha256::new();
-                h.update(d);
-                if h.finalize().as_slice() != c {
-                    return Err(PyValueError::new_err("checksum mismatch"));
-                }
-                let mut data = d.to_vec();
-                if self.encryption_key.is_some() {
-                    data = self
-                        .decrypt(&data)
-                        .map_err(|e| PyRuntimeError::new_err(e))?;
-                }
-                match self.compression {
-                    CompressionAlgo::Zstd => {
-                        data =
-                            decode_all(&data[..]).map_err(|e| PyIOError::new_err(e.to_string()))?;
-                    }
-                    CompressionAlgo::Lz4 => {
-                        data = decompress_size_prepended(&data[..])
-                            .map_err(|e| PyIOError::new_err(e.to_string()))?;
-                    }
-                    CompressionAlgo::None => {}
-                }
-                self.engine = serde_json::from_slice(&data)
-                    .map_err(|e| PyValueError::new_err(e.to_string()))?;
-                self.engine.rebuild_cache();
+/// Reads SQLite table `src` as import rows for a table with `schema`; columns outside it are
+/// left out. Integers in boolean fields read as booleans, text in json and array fields is
+/// parsed when it is valid JSON, and blobs are read as base64. `mapping` renames columns
+/// and transforms values first. An `id` column is read only with `keep_ids`.
+fn sqlite_rows(
+    conn: &Connection,
+    src: &str,
+    schema: &HashMap<String, FieldDef>,
+    mut mapping: Option<&mut ImportMapping<'_>>,
+    keep_ids: bool,
+) -> PyResult<Vec<ImportRow>> {
+    let sql_err = |e: rusqlite::Error| PyValueError::new_err(e.to_string());
+    let mut s = conn
+        .prepare(&format!("SELECT * FROM [{}]", src))
+        .map_err(sql_err)?;
+    let cols: Vec<_> = s.column_names().into_iter().map(String::from).collect();
+    let cols = match mapping.as_mut() {
+        Some(mapping) => mapping.targets(&cols)?,
+        None => cols.into_iter().map(Some).collect(),
+    };
+    let mut rows = s.query([]).map_err(sql_err)?;
+    let mut payloads = Vec::new();
+    while let Some(r) = rows.next().map_err(sql_err)? {
+        let mut p = Map::new();
+        for (i, name) in cols.iter().enumerate() {
+            if keep_ids && name.as_deref() == Some("id") {
+                // Anything but a positive integer is reported by `import_rows`.
+                let id = match r.get_ref(i).map_err(sql_err)? {
+                    ValueRef::Integer(id) => Value::from(id),
+                    ValueRef::Real(f) => Value::from(f),
+                    ValueRef::Text(txt) => Value::String(String::from_utf8_lossy(txt).into()),
+                    ValueRef::Null | ValueRef::Blob(_) => Value::Null,
+                };
+                p.insert("id".to_string(), id);
+                continue;
             }
-        }
-        Ok(())
-    }
-    fn persist(&self) -> PyResult<()> {
-        if let Some(p) = &self.storage_path {
-            let mut b = serde_json::to_vec(&self.engine)
-                .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
-            match self.compression {
-                CompressionAlgo::Zstd => {
-                    b = encode_all(&b[..], 3).map_err(|e| PyIOError::new_err(e.to_string()))?;
-                }
-                CompressionAlgo::Lz4 => {
-                    b = compress_prepend_size(&b[..]);
+            let Some((name, def)) = name
+                .as_ref()
+                .filter(|name| *name != "id")
+                .and_then(|name| Some((name, schema.get(name)?)))
+            else {
+                continue;
+            };
+            let value = match r.get_ref(i).map_err(sql_err)? {
+                ValueRef::Null => Value::Null,
+                // SQLite has no boolean type; `export_sqlite` writes them as 0 and 1 too.
+                ValueRef::Integer(i) if def.field_type == FieldType::Boolean => Value::Bool(i != 0),
+                ValueRef::Integer(i) => Value::Number(i.into()),
+                ValueRef::Real(f) => serde_json::Number::from_f64(f)
+                    .map(Value::Number)
+                    .unwrap_or(Value::Null),
+                ValueRef::Text(txt) => {
+                    let s = String::from_utf8_lossy(txt).into_owned();
+                    match def.field_type {
+                        FieldType::Json | FieldType::Array(_) => {
+                            serde_json::from_str(&s).unwrap_or(Value::String(s))
+                        }
+                        _ => Value::String(s),
+                    }
                 }
-                CompressionAlgo::None => {}
-            }
-            if self.encryption_key.is_some() {
-                b = self.encrypt(&b).map_err(|e| PyRuntimeError::new_err(e))?;
-            }
-            let mut h = Sha256::new();
-            h.update(&b);
-            let mut res = h.finalize().to_vec();
-            res.extend(b);
-            if let Some(prnt) = p.parent() {
-                fs::create_dir_all(prnt).map_err(|e| PyIOError::new_err(e.to_string()))?;
-            }
-            fs::write(p, res).map_err(|e| PyIOError::new_err(e.to_string()))?;
+                ValueRef::Blob(bytes) => Value::String(BASE64.encode(bytes)),
+            };
+            p.insert(name.clone(), value);
         }
-        Ok(())
+        let p = match mapping.as_mut() {
+            Some(mapping) => mapping.transform(&mut p).map(|()| p),
+            None => Ok(p),
+        };
+        payloads.push((payloads.len() + 1, p));
     }
-    fn encrypt(&self, d: &[u8]) -> Result<Vec<u8>, String> {
-        let k = self.encryption_key.ok_or("no key".to_string())?;
-        let c = Aes256Gcm::new_from_slice(&k).map_err(|e| e.to_string())?;
-        let mut n_b = [0u8; 12];
-        thread_rng().fill(&mut n_b);
-        let n = Nonce::from_slice(&n_b);
-        let ct = c.encrypt(n, d).map_err(|e| e.to_string())?;
-        let mut out = n_b.to_vec();
-        out.extend(ct);
-        Ok(out)
+    Ok(payloads)
+}
+
+/// The single-byte delimiter `export_csv` and `import_csv` take.
+fn csv_delimiter(raw: &str) -> PyResult<u8> {
+    match raw.as_bytes() {
+        [b] if b.is_ascii() && !matches!(b, b'"' | b'\n' | b'\r') => Ok(*b),
+        _ => Err(PyValueError::new_err(format!(
+            "CSV delimiter must be a single ASCII character other than a quote or newline, not {:?}",
+            raw
+        ))),
     }
-    fn decrypt(&self, d: &[u8]) -> Result<Vec<u8>, String> {
-        let k = self.encryption_key.ok_or("no key".to_string())?;
-        if d.len() < 12 {
-            return Err("bad data".to_string());
-        }
-        let c = Aes256Gcm::new_from_slice(&k).map_err(|e| e.to_string())?;
-        let n = Nonce::from_slice(&d[..12]);
-        c.decrypt(n, &d[12..]).map_err(|e| e.to_string())
+}
+
+fn csv_cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
     }
 }
 
-fn sanitize_db_path(raw: &str) -> PyResult<PathBuf> {
-    sanitize_relative_path(raw, false, true)
+/// Reads one CSV cell as a value of `ty`. Arrays hold JSON text; a JSON field takes JSON
+/// text too, falling back to the plain string.
+fn parse_csv_cell(ty: &FieldType, field: &str, cell: &str) -> DbResult<Value> {
+    if cell.is_empty() {
+        return Ok(Value::Null);
+    }
+    let raw = match ty {
+        FieldType::Json => {
+            serde_json::from_str(cell).unwrap_or_else(|_| Value::String(cell.to_string()))
+        }
+        FieldType::Array(_) => serde_json::from_str(cell).map_err(|_| DbError::TypeMismatch {
+            field: field.to_string(),
+            expected: ty.label(),
+        })?,
+        _ => Value::String(cell.to_string()),
+    };
+    ty.conform(field, raw)
 }
 
 fn sanitize_user_path(raw: &str) -> PyResult<PathBuf> {
@@ -1132,6 +6213,117 @@ fn sanitize_relative_path(
     Ok(path)
 }
 
+/// Builds an empty table from one entry of an `export_schema` document.
+fn parse_table_spec(spec: &Bound<'_, PyDict>) -> PyResult<Table> {
+    let mut schema = HashMap::new();
+    if let Some(fields) = spec.get_item("fields")? {
+        for (field, def) in fields.downcast::<PyDict>()?.iter() {
+            let fname = field.extract::<String>()?;
+            validate_identifier(&fname).map_err(convert_db_error)?;
+            let parsed = parse_field_def(&fname, def.downcast::<PyDict>()?)?;
+            schema.insert(fname, parsed);
+        }
+    }
+    let mut table = Table::new(schema);
+    table.versioned = extract_opt(spec, "versioned")?.unwrap_or(false);
+    table.ttl_seconds = check_ttl(extract_opt(spec, "ttl_seconds")?)?;
+    table.soft_delete = extract_opt(spec, "soft_delete")?.unwrap_or(false);
+    table.soft_delete_releases_unique =
+        extract_opt(spec, "soft_delete_releases_unique")?.unwrap_or(false);
+    if let Some(meta) = spec.get_item("meta")? {
+        for (k, v) in meta.downcast::<PyDict>()?.iter() {
+            table
+                .set_meta(&k.extract::<String>()?, Some(py_to_json(v)?))
+                .map_err(convert_db_error)?;
+        }
+    }
+    Ok(table)
+}
+
+fn parse_field_def(name: &str, d: &Bound<'_, PyDict>) -> PyResult<FieldDef> {
+    let rtype = d
+        .get_item("type")?
+        .ok_or_else(|| PyValueError::new_err("schema field requires type"))?
+        .extract::<String>()?;
+    let mut ftype = FieldType::from_str(&rtype)
+        .ok_or_else(|| PyValueError::new_err(format!("unsupported field type {}", rtype)))?;
+    if let (FieldType::Array(_), Some(raw_elem)) = (&ftype, extract_opt::<String>(d, "of")?) {
+        let elem = FieldType::from_str(&raw_elem)
+            .filter(|e| !matches!(e, FieldType::Array(_)))
+            .ok_or_else(|| {
+                PyValueError::new_err(format!("unsupported array element type {}", raw_elem))
+            })?;
+        ftype = FieldType::Array(Box::new(elem));
+    }
+    let mut def = FieldDef::new(ftype);
+    def.required = d
+        .get_item("required")?
+        .map(|it| it.extract::<bool>())
+        .transpose()?
+        .unwrap_or(false);
+    def.unique = d
+        .get_item("unique")?
+        .map(|it| it.extract::<bool>())
+        .transpose()?
+        .unwrap_or(false);
+    def.strict_unique_nulls = extract_opt(d, "strict_unique_nulls")?.unwrap_or(false);
+    def.indexed = match extract_opt(d, "index")? {
+        Some(flag) => flag,
+        None => extract_opt(d, "indexed")?.unwrap_or(false),
+    };
+    def.description = extract_opt(d, "description")?;
+    def.computed = extract_opt(d, "computed")?;
+    if let Some(raw) = d.get_item("default")? {
+        let value = py_to_json(raw)?;
+        if !value.is_null() {
+            let coerced = def
+                .field_type
+                .conform(name, value)
+                .map_err(convert_db_error)?;
+            def.default = Some(coerced);
+        }
+    }
+    def.constraints = FieldConstraints {
+        min: extract_opt(d, "min")?,
+        max: extract_opt(d, "max")?,
+        min_length: extract_opt(d, "min_length")?,
+        max_length: extract_opt(d, "max_length")?,
+        pattern: extract_opt(d, "pattern")?,
+    };
+    def.references = extract_opt(d, "references")?;
+    if let Some(raw) = extract_opt::<String>(d, "on_delete")? {
+        def.on_delete = OnDelete::from_str(&raw)
+            .ok_or_else(|| PyValueError::new_err(format!("unsupported on_delete rule {}", raw)))?;
+    }
+    def.validate_constraints(name).map_err(convert_db_error)?;
+    if let Some(default) = &def.default {
+        let pattern = def
+            .constraints
+            .pattern
+            .as_deref()
+            .and_then(|p| Regex::new(p).ok());
+        def.check_constraints(name, default, pattern.as_ref())
+            .map_err(convert_db_error)?;
+    }
+    Ok(def)
+}
+
+fn extract_opt<'py, T: FromPyObject<'py>>(
+    d: &Bound<'py, PyDict>,
+    key: &str,
+) -> PyResult<Option<T>> {
+    match d.get_item(key)? {
+        Some(v) if !v.is_none() => v.extract::<T>().map(Some),
+        _ => Ok(None),
+    }
+}
+
+fn check_ttl(ttl_seconds: Option<u64>) -> PyResult<Option<u64>> {
+    if ttl_seconds == Some(0) {
+        return Err(PyValueError::new_err("ttl_seconds must be positive"));
+    }
+    Ok(ttl_seconds)
+}
 fn validate_identifier(i: &str) -> DbResult<()> {
     if i.is_empty() || !i.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
         return Err(DbError::InvalidIdentifier(i.to_string()));
@@ -1184,6 +6376,11 @@ fn py_to_json_recursive(v: Bound<'_, PyAny>, depth: usize) -> PyResult<Value> {
     }
     Err(PyValueError::new_err("bad type"))
 }
+fn delete_summary_to_py(py: Python<'_>, summary: &DeleteSummary) -> PyResult<PyObject> {
+    let v = serde_json::to_value(summary).map_err(|e| PyValueError::new_err(e.to_string()))?;
+    json_to_py(py, &v)
+}
+
 fn json_to_py(py: Python<'_>, v: &Value) -> PyResult<PyObject> {
     json_to_py_recursive(py, v, 0)
 }
@@ -1223,6 +6420,37 @@ fn json_to_py_recursive(py: Python<'_>, v: &Value, depth: usize) -> PyResult<PyO
         }
     })
 }
+/// The current time in epoch milliseconds, as TTLs and soft deletes record it.
+fn now_millis() -> i64 {
+    Utc::now().timestamp_millis()
+}
+/// Parses ISO-8601 timestamps (with or without offset, or a bare date) into canonical UTC RFC 3339.
+fn normalize_datetime(raw: &str) -> Option<String> {
+    let raw = raw.trim();
+    let parsed = DateTime::parse_from_rfc3339(raw)
+        .map(|dt| dt.with_timezone(&Utc))
+        .ok()
+        .or_else(|| {
+            ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"]
+                .iter()
+                .find_map(|fmt| NaiveDateTime::parse_from_str(raw, fmt).ok())
+                .or_else(|| {
+                    NaiveDate::parse_from_str(raw, "%Y-%m-%d")
+                        .ok()
+                        .and_then(|d| d.and_hms_opt(0, 0, 0))
+                })
+                .map(|naive| naive.and_utc())
+        })?;
+    Some(parsed.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+}
+fn parse_timestamp(raw: &str) -> Option<DateTime<Utc>> {
+    if !raw.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    DateTime::parse_from_rfc3339(raw)
+        .ok()
+        .map(|dt| dt.with_timezone(&Utc))
+}
 fn value_cmp(l: &Value, r: &Value) -> Ordering {
     match (l, r) {
         (Value::Number(a), Value::Number(b)) => a
@@ -1232,9 +6460,29 @@ fn value_cmp(l: &Value, r: &Value) -> Ordering {
             .unwrap_or(Ordering::Equal),
         (Value::String(a), Value::String(b)) => a.cmp(b),
         (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+        // Nulls, stored or standing in for a missing field or a computed field with missing
+        // inputs, sort before any value. Values of different kinds still compare equal.
+        (Value::Null, Value::Null) => Ordering::Equal,
+        (Value::Null, _) => Ordering::Less,
+        (_, Value::Null) => Ordering::Greater,
         _ => Ordering::Equal,
     }
 }
+/// `value_cmp`, ordering the values of a `DateTime` field chronologically.
+fn field_cmp(field_type: Option<&FieldType>, l: &Value, r: &Value) -> Ordering {
+    match (field_type, l, r) {
+        (Some(FieldType::DateTime), Value::String(a), Value::String(b)) => {
+            match (parse_timestamp(a), parse_timestamp(b)) {
+                (Some(x), Some(y)) => x.cmp(&y),
+                _ => a.cmp(b),
+            }
+        }
+        _ => value_cmp(l, r),
+    }
+}
+fn same_kind(l: &Value, r: &Value) -> bool {
+    std::mem::discriminant(l) == std::mem::discriminant(r)
+}
 fn convert_db_error(e: DbError) -> PyErr {
     match e {
         DbError::MissingTable(_) | DbError::MissingField(_) | DbError::MissingRecord(_) => {
@@ -1248,6 +6496,15 @@ fn _core(_py: Python<'_>, m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<Database>()?;
     m.add_class::<Query>()?;
     m.add_class::<Record>()?;
+    m.add_class::<Transaction>()?;
+    m.add(
+        "EncryptionKeyError",
+        m.py().get_type_bound::<EncryptionKeyError>(),
+    )?;
+    m.add(
+        "DatabaseLockedError",
+        m.py().get_type_bound::<DatabaseLockedError>(),
+    )?;
     Ok(())
 }
 
@@ -1,9 +1,13 @@
 #[cfg(test)]
 mod tests {
     use crate::alive::AliveState;
-    use crate::graph_rag::GraphRagEngine;
+    use crate::graph_rag::{GraphRagEngine, Ranking, DEFAULT_TOP_K};
     use crate::personality::{Mode, Personality};
-    use crate::{sanitize_relative_path, validate_identifier, DbError, Engine, FieldDef, FieldType, Table};
+    use crate::{
+        atomic_write, atomic_write_all, field_cmp, normalize_datetime, sanitize_relative_path,
+        temp_path, validate_identifier, value_cmp, DbError, Engine, FieldConstraints, FieldDef,
+        FieldType, Filter, OnDelete, Query, Table,
+    };
     use serde_json::{json, Map};
     use std::collections::HashMap;
 
@@ -31,16 +35,588 @@ mod tests {
         schema.insert(
             "email".to_string(),
             FieldDef {
-                field_type: FieldType::String,
                 required: true,
                 unique: true,
+                ..FieldDef::new(FieldType::String)
             },
         );
         let mut table = Table::new(schema);
         let mut row = Map::new();
         row.insert("email".to_string(), json!("a@x.com"));
         assert!(table.insert(row.clone()).is_ok());
-        assert!(matches!(table.insert(row), Err(DbError::UniqueViolation(_))));
+        assert!(matches!(
+            table.insert(row),
+            Err(DbError::UniqueViolation(_))
+        ));
+    }
+
+    #[test]
+    fn unique_fields_allow_many_nulls_unless_strict() {
+        let email = |strict| {
+            Table::new(HashMap::from([(
+                "email".to_string(),
+                FieldDef {
+                    unique: true,
+                    strict_unique_nulls: strict,
+                    ..FieldDef::new(FieldType::String)
+                },
+            )]))
+        };
+        let null_row = Map::from_iter([("email".to_string(), json!(null))]);
+
+        let mut lenient = email(false);
+        for _ in 0..2 {
+            lenient.insert(null_row.clone()).unwrap();
+            lenient.insert(Map::new()).unwrap();
+        }
+        assert!(lenient
+            .unique_cache
+            .get("email")
+            .is_none_or(|s| s.is_empty()));
+        let patch = Map::from_iter([("email".to_string(), json!("a@x"))]);
+        let merged = lenient.prepare_update(1, patch, false).unwrap();
+        lenient.store_update(1, merged);
+        let merged = lenient.prepare_update(1, null_row.clone(), false).unwrap();
+        lenient.store_update(1, merged);
+        lenient.rebuild_unique_cache();
+        assert!(lenient
+            .unique_cache
+            .get("email")
+            .is_none_or(|s| s.is_empty()));
+
+        let mut strict = email(true);
+        strict.insert(null_row.clone()).unwrap();
+        strict.insert(Map::new()).unwrap();
+        strict.insert(Map::new()).unwrap();
+        assert!(matches!(
+            strict.insert(null_row),
+            Err(DbError::UniqueViolation(_))
+        ));
+    }
+
+    #[test]
+    fn change_field_type_is_all_or_nothing() {
+        let mut schema = HashMap::new();
+        schema.insert("age".to_string(), FieldDef::new(FieldType::String));
+        let mut table = Table::new(schema);
+        for raw in ["30", "thirty"] {
+            let mut row = Map::new();
+            row.insert("age".to_string(), json!(raw));
+            table.insert(row).unwrap();
+        }
+        let err = table.change_field_type("age", FieldType::Integer, true);
+        assert!(matches!(err, Err(DbError::ConversionFailed { ref ids, .. }) if ids == &vec![2]));
+        assert_eq!(table.records[&1]["age"], json!("30"));
+
+        let nulled = table
+            .change_field_type("age", FieldType::Integer, false)
+            .unwrap();
+        assert_eq!(nulled, vec![2]);
+        assert_eq!(table.records[&1]["age"], json!(30));
+        assert!(table.records[&2]["age"].is_null());
+        assert_eq!(table.schema["age"].field_type, FieldType::Integer);
+    }
+
+    #[test]
+    fn insert_fills_defaults_before_validation() {
+        let mut schema = HashMap::new();
+        schema.insert(
+            "status".to_string(),
+            FieldDef {
+                required: true,
+                default: Some(json!("pending")),
+                ..FieldDef::new(FieldType::String)
+            },
+        );
+        let mut table = Table::new(schema);
+        let id = table.insert(Map::new()).unwrap();
+        assert_eq!(table.records[&id]["status"], json!("pending"));
+    }
+
+    #[test]
+    fn constraints_reject_impossible_ranges_and_bad_values() {
+        let mut def = FieldDef::new(FieldType::Integer);
+        def.constraints = FieldConstraints {
+            min: Some(10.0),
+            max: Some(1.0),
+            ..FieldConstraints::default()
+        };
+        assert!(matches!(
+            def.validate_constraints("qty"),
+            Err(DbError::InvalidConstraint { .. })
+        ));
+        def.constraints.max = Some(20.0);
+        assert!(def.validate_constraints("qty").is_ok());
+
+        let mut schema = HashMap::new();
+        schema.insert("qty".to_string(), def);
+        let mut table = Table::new(schema);
+        let mut row = Map::new();
+        row.insert("qty".to_string(), json!(25));
+        assert!(matches!(
+            table.insert(row),
+            Err(DbError::ConstraintViolation { ref constraint, .. }) if constraint == "max=20"
+        ));
+    }
+
+    #[test]
+    fn datetime_normalizes_and_orders_chronologically() {
+        assert_eq!(
+            normalize_datetime("2024-05-01T12:00:00+02:00").as_deref(),
+            Some("2024-05-01T10:00:00Z")
+        );
+        assert_eq!(
+            normalize_datetime("2024-05-01").as_deref(),
+            Some("2024-05-01T00:00:00Z")
+        );
+        assert!(normalize_datetime("yesterday").is_none());
+        let earlier = json!("2024-05-01T10:00:00Z");
+        let later = json!("2024-05-01T10:00:00.500Z");
+        let datetime = Some(&FieldType::DateTime);
+        assert_eq!(
+            field_cmp(datetime, &earlier, &later),
+            std::cmp::Ordering::Less
+        );
+        // Strings outside a datetime field are only text, whatever they look like.
+        assert_eq!(value_cmp(&earlier, &later), std::cmp::Ordering::Greater);
+        assert_eq!(
+            FieldType::DateTime.coerce(json!(0)),
+            Some(json!("1970-01-01T00:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn array_fields_check_each_element() {
+        let tags = FieldType::Array(Box::new(FieldType::Integer));
+        assert_eq!(
+            tags.conform("tags", json!(["1", 2])).unwrap(),
+            json!([1, 2])
+        );
+        assert!(matches!(
+            tags.conform("tags", json!([1, "two"])),
+            Err(DbError::ElementTypeMismatch { index: 1, .. })
+        ));
+        assert!(tags.conform("tags", json!([1, null])).is_err());
+        assert_eq!(tags.label(), "array<integer>");
+        let legacy: FieldType = serde_json::from_str("\"String\"").unwrap();
+        assert_eq!(legacy, FieldType::String);
+        let encoded = serde_json::to_string(&tags).unwrap();
+        assert_eq!(serde_json::from_str::<FieldType>(&encoded).unwrap(), tags);
+    }
+
+    #[test]
+    fn references_guard_inserts_and_deletes() {
+        let mut engine = Engine::new();
+        engine
+            .create_table("users", Table::new(HashMap::new()))
+            .unwrap();
+        let mut schema = HashMap::new();
+        schema.insert(
+            "owner".to_string(),
+            FieldDef {
+                references: Some("users".to_string()),
+                ..FieldDef::new(FieldType::Integer)
+            },
+        );
+        assert!(matches!(
+            engine.create_table("orphans", {
+                let mut s = schema.clone();
+                s.get_mut("owner").unwrap().references = Some("ghosts".to_string());
+                Table::new(s)
+            }),
+            Err(DbError::UnknownReference { .. })
+        ));
+        engine.create_table("posts", Table::new(schema)).unwrap();
+
+        let mut row = Map::new();
+        row.insert("owner".to_string(), json!(1));
+        assert!(matches!(
+            engine.insert("posts", row.clone()),
+            Err(DbError::DanglingReference { .. })
+        ));
+        let uid = engine.insert("users", Map::new()).unwrap();
+        let pid = engine.insert("posts", row).unwrap();
+        assert!(matches!(
+            engine.delete("users", uid),
+            Err(DbError::ReferencedRecord { ref dependent, .. }) if dependent == "posts.owner"
+        ));
+        engine.delete("posts", pid).unwrap();
+        engine.delete("users", uid).unwrap();
+    }
+
+    #[test]
+    fn cascade_and_set_null_apply_together_or_not_at_all() {
+        let refs = |table: &str, on_delete| FieldDef {
+            references: Some(table.to_string()),
+            on_delete,
+            ..FieldDef::new(FieldType::Integer)
+        };
+        let mut engine = Engine::new();
+        engine
+            .create_table(
+                "nodes",
+                Table::new(HashMap::from([(
+                    "parent".to_string(),
+                    refs("nodes", OnDelete::Cascade),
+                )])),
+            )
+            .unwrap();
+        engine
+            .create_table(
+                "notes",
+                Table::new(HashMap::from([(
+                    "node".to_string(),
+                    refs("nodes", OnDelete::SetNull),
+                )])),
+            )
+            .unwrap();
+        engine
+            .create_table(
+                "pins",
+                Table::new(HashMap::from([(
+                    "node".to_string(),
+                    refs("nodes", OnDelete::Restrict),
+                )])),
+            )
+            .unwrap();
+
+        let row = |field: &str, id: u64| Map::from_iter([(field.to_string(), json!(id))]);
+        let root = engine.insert("nodes", Map::new()).unwrap();
+        let child = engine.insert("nodes", row("parent", root)).unwrap();
+        // Close the loop so the cascade has to stop on already-visited rows.
+        engine.update("nodes", root, row("parent", child)).unwrap();
+        let note = engine.insert("notes", row("node", child)).unwrap();
+        let pin = engine.insert("pins", row("node", child)).unwrap();
+
+        assert!(matches!(
+            engine.delete("nodes", root),
+            Err(DbError::ReferencedRecord { ref dependent, .. }) if dependent == "pins.node"
+        ));
+        assert_eq!(engine.tables["nodes"].records.len(), 2);
+        assert_eq!(engine.tables["notes"].records[&note]["node"], json!(child));
+
+        engine.delete("pins", pin).unwrap();
+        let summary = engine.delete("nodes", root).unwrap();
+        assert_eq!(summary.deleted.get("nodes"), Some(&2));
+        assert_eq!(summary.nulled.get("notes"), Some(&1));
+        assert!(engine.tables["notes"].records[&note]["node"].is_null());
+    }
+
+    #[test]
+    fn cascades_follow_chains_of_any_length() {
+        let mut engine = Engine::new();
+        let parent = FieldDef {
+            references: Some("nodes".to_string()),
+            on_delete: OnDelete::Cascade,
+            ..FieldDef::new(FieldType::Integer)
+        };
+        engine
+            .create_table(
+                "nodes",
+                Table::new(HashMap::from([("parent".to_string(), parent)])),
+            )
+            .unwrap();
+        let root = engine.insert("nodes", Map::new()).unwrap();
+        let mut last = root;
+        for _ in 0..200 {
+            let row = Map::from_iter([("parent".to_string(), json!(last))]);
+            last = engine.insert("nodes", row).unwrap();
+        }
+        let summary = engine.delete("nodes", root).unwrap();
+        assert_eq!(summary.deleted.get("nodes"), Some(&201));
+        assert!(engine.tables["nodes"].records.is_empty());
+    }
+
+    #[test]
+    fn indexed_where_eq_scans_only_matching_ids() {
+        let mut engine = Engine::new();
+        let schema = HashMap::from([(
+            "bucket".to_string(),
+            FieldDef {
+                indexed: true,
+                ..FieldDef::new(FieldType::Integer)
+            },
+        )]);
+        engine.create_table("events", Table::new(schema)).unwrap();
+        let rows = 20_000;
+        for i in 0..rows {
+            let row = Map::from_iter([("bucket".to_string(), json!(i % 50))]);
+            engine.insert("events", row).unwrap();
+        }
+        let filters = vec![Filter::Eq("bucket".to_string(), json!(7))];
+        let table = &engine.tables["events"];
+        let scanned = Query::candidates(table, &filters).unwrap().len();
+        assert_eq!(scanned, rows / 50);
+        assert!(scanned * 40 < table.records.len());
+
+        let mut query = Query::new("events".to_string());
+        query.filters = filters.clone();
+        assert_eq!(query.rows(table).len(), scanned);
+
+        let victim = query.rows(table)[0].0;
+        engine.delete("events", victim).unwrap();
+        let moved = query.rows(&engine.tables["events"])[0].0;
+        let patch = Map::from_iter([("bucket".to_string(), json!(8))]);
+        engine.update("events", moved, patch).unwrap();
+        assert_eq!(query.rows(&engine.tables["events"]).len(), scanned - 2);
+
+        let mut reloaded: Engine =
+            serde_json::from_str(&serde_json::to_string(&engine).unwrap()).unwrap();
+        assert!(reloaded.tables["events"].indexes.is_empty());
+        reloaded.rebuild_cache();
+        let table = &reloaded.tables["events"];
+        assert_eq!(
+            Query::candidates(table, &filters).unwrap().len(),
+            scanned - 2
+        );
+    }
+
+    #[test]
+    fn metadata_survives_round_trip_and_old_files_load() {
+        let mut table = Table::new(HashMap::from([(
+            "amount".to_string(),
+            FieldDef {
+                description: Some("in cents".to_string()),
+                ..FieldDef::new(FieldType::Integer)
+            },
+        )]));
+        table
+            .set_meta("owner", Some(json!({"team": "billing"})))
+            .unwrap();
+        let big = json!("x".repeat(5000));
+        assert!(matches!(
+            table.set_meta("blob", Some(big)),
+            Err(DbError::MetadataTooLarge(_))
+        ));
+        let restored: Table =
+            serde_json::from_str(&serde_json::to_string(&table).unwrap()).unwrap();
+        let described = restored.describe();
+        assert_eq!(described["meta"]["owner"]["team"], json!("billing"));
+        assert_eq!(
+            described["fields"]["amount"]["description"],
+            json!("in cents")
+        );
+
+        let legacy = r#"{"schema":{"n":{"field_type":"Integer","required":false,"unique":false}},"records":{},"next_id":1}"#;
+        let old: Table = serde_json::from_str(legacy).unwrap();
+        assert!(old.meta.is_empty());
+        assert!(old.schema["n"].description.is_none());
+    }
+
+    #[test]
+    fn copy_table_renumbers_or_preserves_ids() {
+        let mut engine = Engine::new();
+        let schema = HashMap::from([(
+            "email".to_string(),
+            FieldDef {
+                unique: true,
+                ..FieldDef::new(FieldType::String)
+            },
+        )]);
+        engine.create_table("users", Table::new(schema)).unwrap();
+        for email in ["a@x", "b@x", "c@x"] {
+            let row = Map::from_iter([("email".to_string(), json!(email))]);
+            engine.insert("users", row).unwrap();
+        }
+        engine.delete("users", 1).unwrap();
+
+        assert_eq!(engine.copy_table("users", "fresh", true, false).unwrap(), 2);
+        assert_eq!(engine.tables["fresh"].records[&1]["email"], json!("b@x"));
+        assert_eq!(engine.tables["fresh"].next_id, 3);
+        assert_eq!(engine.copy_table("users", "kept", true, true).unwrap(), 2);
+        assert_eq!(engine.tables["kept"].next_id, 4);
+        assert_eq!(
+            engine.copy_table("users", "empty", false, false).unwrap(),
+            0
+        );
+        assert!(matches!(
+            engine.copy_table("users", "kept", true, true),
+            Err(DbError::TableExists(_))
+        ));
+
+        let dup = Map::from_iter([("email".to_string(), json!("c@x"))]);
+        assert!(matches!(
+            engine.insert("fresh", dup.clone()),
+            Err(DbError::UniqueViolation(_))
+        ));
+        assert!(engine.insert("empty", dup).is_ok());
+    }
+
+    #[test]
+    fn copy_table_points_self_references_at_the_copy() {
+        let mut engine = Engine::new();
+        let parent = FieldDef {
+            references: Some("nodes".to_string()),
+            on_delete: OnDelete::Cascade,
+            ..FieldDef::new(FieldType::Integer)
+        };
+        engine
+            .create_table(
+                "nodes",
+                Table::new(HashMap::from([("parent".to_string(), parent)])),
+            )
+            .unwrap();
+        let gone = engine.insert("nodes", Map::new()).unwrap();
+        engine.delete("nodes", gone).unwrap();
+        let root = engine.insert("nodes", Map::new()).unwrap();
+        let child = Map::from_iter([("parent".to_string(), json!(root))]);
+        engine.insert("nodes", child).unwrap();
+
+        engine.copy_table("nodes", "fresh", true, false).unwrap();
+        let fresh = &engine.tables["fresh"];
+        assert_eq!(fresh.schema["parent"].references.as_deref(), Some("fresh"));
+        assert_eq!(fresh.records[&2]["parent"], json!(1));
+        engine.copy_table("nodes", "kept", true, true).unwrap();
+        assert_eq!(engine.tables["kept"].records[&3]["parent"], json!(root));
+
+        let summary = engine.delete("fresh", 1).unwrap();
+        assert_eq!(summary.deleted.get("fresh"), Some(&2));
+        assert_eq!(engine.tables["nodes"].records.len(), 2);
+    }
+
+    #[test]
+    fn create_tables_orders_by_reference() {
+        let refs = |target: &str| {
+            Table::new(HashMap::from([(
+                "parent".to_string(),
+                FieldDef {
+                    references: Some(target.to_string()),
+                    ..FieldDef::new(FieldType::Integer)
+                },
+            )]))
+        };
+        let mut engine = Engine::new();
+        let created = engine
+            .create_tables(vec![
+                ("comments".to_string(), refs("posts")),
+                ("posts".to_string(), refs("users")),
+                ("users".to_string(), refs("users")),
+            ])
+            .unwrap();
+        assert_eq!(created, ["users", "posts", "comments"]);
+
+        let err = engine.create_tables(vec![
+            ("likes".to_string(), refs("ghosts")),
+            ("tags".to_string(), Table::new(HashMap::new())),
+        ]);
+        assert!(
+            matches!(err, Err(DbError::UnknownReference { ref table, .. }) if table == "ghosts")
+        );
+        assert!(!engine.tables.contains_key("tags"));
+        assert!(matches!(
+            engine.create_tables(vec![("users".to_string(), refs("users"))]),
+            Err(DbError::TableExists(_))
+        ));
+        assert_eq!(
+            FieldType::from_str("array<datetime>"),
+            Some(FieldType::Array(Box::new(FieldType::DateTime)))
+        );
+        assert_eq!(FieldType::from_str("array<array<int>>"), None);
+    }
+
+    #[test]
+    fn ttl_rows_expire_and_release_unique_values() {
+        let mut engine = Engine::new();
+        let mut cache = Table::new(HashMap::from([(
+            "key".to_string(),
+            FieldDef {
+                unique: true,
+                ..FieldDef::new(FieldType::String)
+            },
+        )]));
+        cache.ttl_seconds = Some(60);
+        engine.create_table("cache", cache).unwrap();
+        let row = json!({"key": "k"}).as_object().cloned().unwrap();
+        let id = engine.insert("cache", row.clone()).unwrap();
+        assert!(matches!(
+            engine.insert("cache", row.clone()),
+            Err(DbError::UniqueViolation(_))
+        ));
+
+        let t = engine.table_mut("cache").unwrap();
+        let created = t.created_at[&id];
+        assert!(!t.is_expired(id, created + 59_999));
+        assert!(t.is_expired(id, created + 60_000));
+        assert_eq!(t.live_count(created + 60_000), 0);
+        t.created_at.insert(id, created - 60_000);
+        let fresh = engine.insert("cache", row).unwrap();
+        let t = &engine.tables["cache"];
+        assert!(!t.records.contains_key(&id));
+        assert_eq!(t.live_count(created), 1);
+        assert!(t.created_at.contains_key(&fresh));
+    }
+
+    #[test]
+    fn expired_rows_are_purged_under_the_on_delete_rules() {
+        let refs = |on_delete| {
+            Table::new(HashMap::from([(
+                "parent".to_string(),
+                FieldDef {
+                    references: Some("parents".to_string()),
+                    on_delete,
+                    ..FieldDef::new(FieldType::Integer)
+                },
+            )]))
+        };
+        let mut engine = Engine::new();
+        let mut parents = Table::new(HashMap::new());
+        parents.ttl_seconds = Some(60);
+        engine.create_table("parents", parents).unwrap();
+        for (name, on_delete) in [
+            ("kids", OnDelete::Cascade),
+            ("notes", OnDelete::SetNull),
+            ("pins", OnDelete::Restrict),
+        ] {
+            engine.create_table(name, refs(on_delete)).unwrap();
+        }
+        let row = |id: u64| Map::from_iter([("parent".to_string(), json!(id))]);
+        let ids: Vec<u64> = (0..3)
+            .map(|_| engine.insert("parents", Map::new()).unwrap())
+            .collect();
+        let kid = engine.insert("kids", row(ids[0])).unwrap();
+        let note = engine.insert("notes", row(ids[1])).unwrap();
+        let pin = engine.insert("pins", row(ids[2])).unwrap();
+
+        let now = engine.tables["parents"].created_at[&ids[2]] + 60_000;
+        assert_eq!(engine.purge_expired("parents", now).unwrap(), 2);
+        assert!(!engine.tables["kids"].records.contains_key(&kid));
+        assert!(engine.tables["notes"].records[&note]["parent"].is_null());
+        // The pinned row is held by its restrict reference, hidden but not gone.
+        let parents = &engine.tables["parents"];
+        assert!(parents.records.len() == 1 && parents.records.contains_key(&ids[2]));
+        assert_eq!(parents.live_count(now), 0);
+
+        engine.delete("pins", pin).unwrap();
+        assert_eq!(engine.purge_expired("parents", now).unwrap(), 1);
+        assert!(engine.tables["parents"].records.is_empty());
+    }
+
+    #[test]
+    fn atomic_write_never_leaves_a_partial_target() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("data.rsndb");
+        atomic_write(&target, b"first").unwrap();
+        std::fs::write(temp_path(&target), b"half-writ").unwrap();
+        atomic_write(&target, b"second").unwrap();
+        assert_eq!(std::fs::read(&target).unwrap(), b"second");
+        assert!(!temp_path(&target).exists());
+
+        // A temp file that cannot be written must fail the save, not clobber the target.
+        std::fs::create_dir(temp_path(&target)).unwrap();
+        assert!(atomic_write(&target, b"third").is_err());
+        assert_eq!(std::fs::read(&target).unwrap(), b"second");
+    }
+
+    #[test]
+    fn atomic_write_all_renames_nothing_unless_every_file_is_staged() {
+        let dir = tempfile::tempdir().unwrap();
+        let (first, second) = (dir.path().join("a.rsn"), dir.path().join("b.rsn"));
+        atomic_write_all(&[(&first, b"a1"), (&second, b"b1")]).unwrap();
+        std::fs::create_dir(temp_path(&second)).unwrap();
+        assert!(atomic_write_all(&[(&first, b"a2"), (&second, b"b2")]).is_err());
+        assert_eq!(std::fs::read(&first).unwrap(), b"a1");
+        assert_eq!(std::fs::read(&second).unwrap(), b"b1");
+        assert!(!temp_path(&first).exists());
     }
 
     #[test]
@@ -53,7 +629,9 @@ mod tests {
     fn graph_rag_ingest_and_query() {
         let mut g = GraphRagEngine::new();
         g.ingest("Alice works at RSN DB.", "src");
-        assert!(!g.query("Alice").is_empty());
+        assert!(!g
+            .query("Alice", DEFAULT_TOP_K, 0.0, Ranking::TfIdf, &[])
+            .is_empty());
     }
 
     #[test]
@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const RETRY_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Advisory lock on one database's lock file. It is taken once per process and shared by
+/// every handle the process opens on that database, so it only guards against other
+/// processes.
+struct Held {
+    file: File,
+    exclusive: bool,
+    handles: usize,
+}
+
+static HELD: LazyLock<Mutex<HashMap<PathBuf, Held>>> = LazyLock::new(Default::default);
+
+/// Locks `path`, exclusively for writers and shared for readers, retrying until `timeout`
+/// has passed. Returns the key to hand back to `release`.
+pub fn acquire(path: &Path, exclusive: bool, timeout: Duration) -> io::Result<PathBuf> {
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path)?;
+    let key = fs::canonicalize(path)?;
+    let deadline = Instant::now().checked_add(timeout);
+    loop {
+        {
+            let mut held = HELD.lock().unwrap_or_else(PoisonError::into_inner);
+            let attempt = match held.get_mut(&key) {
+                Some(h) if h.exclusive || !exclusive => Ok(()),
+                Some(h) => h.file.try_lock().map(|()| h.exclusive = true),
+                None if exclusive => file.try_lock(),
+                None => file.try_lock_shared(),
+            };
+            match attempt {
+                Ok(()) => {
+                    match held.get_mut(&key) {
+                        Some(h) => h.handles += 1,
+                        None => {
+                            let h = Held {
+                                file,
+                                exclusive,
+                                handles: 1,
+                            };
+                            held.insert(key.clone(), h);
+                        }
+                    }
+                    return Ok(key);
+                }
+                Err(TryLockError::WouldBlock) => {}
+                Err(TryLockError::Error(e)) => return Err(e),
+            }
+        }
+        if deadline.is_some_and(|d| Instant::now() >= d) {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        thread::sleep(RETRY_INTERVAL);
+    }
+}
+
+/// Drops one handle's claim; the lock is released with the last one.
+pub fn release(key: &Path) {
+    let mut held = HELD.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(h) = held.get_mut(key) {
+        h.handles -= 1;
+        if h.handles == 0 {
+            held.remove(key);
+        }
+    }
+}
+
+/// `release`, also deleting the lock file with the last claim, for a database that has
+/// moved away from it. The file goes while still locked so no other process takes it first.
+pub fn release_and_remove(key: &Path) -> io::Result<()> {
+    let mut held = HELD.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(h) = held.get_mut(key) {
+        h.handles -= 1;
+        if h.handles > 0 {
+            return Ok(());
+        }
+    }
+    let removed = match fs::remove_file(key) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        other => other,
+    };
+    held.remove(key);
+    removed
+}
@@ -2,19 +2,14 @@ use crate::snark_pool::EXTRA_SNARK;
 use rand::seq::SliceRandom;
 use rand::thread_rng;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum Mode {
+    #[default]
     Professional,
     Friendly,
     Snarky,
 }
 
-impl Default for Mode {
-    fn default() -> Self {
-        Mode::Professional
-    }
-}
-
 pub struct Personality {
     mode: Mode,
 }
@@ -759,4 +754,84 @@ impl Personality {
             }
         }
     }
+
+    pub fn table_truncated(&self, table: &str, rows: usize) -> String {
+        match self.mode {
+            Mode::Professional => format!("Table '{}' truncated: {} row(s) removed.", table, rows),
+            Mode::Friendly => format!(
+                "All clear! Removed {} row(s) from '{}'; the schema is still there.",
+                rows, table
+            ),
+            Mode::Snarky => {
+                let snark = self.pick(&[
+                    "WAIT. You did what? ...Okay. It's gone. All of it.",
+                    "I hope you meant that, because there's no undo button.",
+                    "Every row, vaporized. I'm keeping the schema as a tombstone.",
+                    "You just rage-quit a table. Bold.",
+                    "Alarms are ringing. Nobody is coming. The rows are gone.",
+                    "That was somebody's data. Probably yours.",
+                    "Empty. Pristine. Terrifying.",
+                    "Tell me you have a backup. Please tell me you have a backup.",
+                ]);
+                format!("Truncated '{}' ({} row(s)). {}", table, rows, snark)
+            }
+        }
+    }
+
+    /// Opening line of the `HELP` listing; the listing itself is the same in every mode.
+    /// Line shown above STATS outside professional mode.
+    pub fn stats_quip(&self, records: u64) -> Option<String> {
+        match self.mode {
+            Mode::Professional => None,
+            Mode::Friendly => Some(match records {
+                0 => "Here's the overview! No records yet, but every database starts somewhere."
+                    .to_string(),
+                n => format!("Here's the overview! {} record(s) and counting.", n),
+            }),
+            Mode::Snarky => Some(self.pick(&[
+                "Vital signs, since you finally asked.",
+                "Numbers. Try not to misread them.",
+                "Here's everything you've done to me, tallied.",
+                "A health check. Mine's fine; your error count is another story.",
+                "Stats don't lie. Users do.",
+            ])),
+        }
+    }
+
+    pub fn help_intro(&self) -> String {
+        match self.mode {
+            Mode::Professional => "RSN DB commands".to_string(),
+            Mode::Friendly => "Here's everything I understand! Add a command name to HELP for \
+                               examples."
+                .to_string(),
+            Mode::Snarky => self.pick(&[
+                "Fine. Here's the manual you should have read first.",
+                "You asked for HELP. Admitting it is the first step.",
+                "Everything I can do, which is more than I can say for you.",
+                "Commands, grouped and sorted, since order is clearly not your thing.",
+                "Read slowly. There's no test, but you'd fail it.",
+            ]),
+        }
+    }
+
+    pub fn batch_rolled_back(&self, operations: usize) -> String {
+        match self.mode {
+            Mode::Professional => format!("Batch rolled back: {} op(s) discarded.", operations),
+            Mode::Friendly => format!(
+                "Batch rolled back! {} queued operation(s) discarded; nothing was changed.",
+                operations
+            ),
+            Mode::Snarky => {
+                let snark = self.pick(&[
+                    "Cold feet? Understandable.",
+                    "Forgotten, like your last good idea.",
+                    "Undo button pressed. Dignity not restored.",
+                    "All that typing, for nothing.",
+                    "Wise. Rare, but wise.",
+                    "I never saw a thing.",
+                ]);
+                format!("Batch rolled back: {} operation(s). {}", operations, snark)
+            }
+        }
+    }
 }
@@ -0,0 +1,2955 @@
+// The `#[pymethods]` of `Query`, `Transaction` and `Database`. pyo3 0.22's generated
+// wrappers trip this lint on every `PyResult` return, and they land beside the impl
+// rather than inside it, so only a module-level allow reaches them.
+#![allow(clippy::useless_conversion, clippy::redundant_closure_call)]
+
+use super::*;
+
+/// Emits `Database`'s `#[pymethods]` with every `&self` and `&mut self` method counted for
+/// `stats`: the outermost call counts as one operation, and as an error when it raises.
+/// Getters, `__exit__` and methods without a `self` receiver pass through as written.
+macro_rules! counted_pymethods {
+    (impl Database { $($items:tt)* }) => {
+        counted_pymethods!(@munch [] $($items)*);
+    };
+    (@munch [$($out:tt)*]) => {
+        #[pymethods]
+        impl Database {
+            $($out)*
+        }
+    };
+    (@munch [$($out:tt)*]
+        #[getter] fn $name:ident $params:tt -> $ret:ty $body:block
+        $($rest:tt)*
+    ) => {
+        counted_pymethods!(@munch [$($out)*
+            #[getter] fn $name $params -> $ret $body
+        ] $($rest)*);
+    };
+    (@munch [$($out:tt)*]
+        $(#[$($attr:tt)*])* fn __exit__ $params:tt -> $ret:ty $body:block
+        $($rest:tt)*
+    ) => {
+        counted_pymethods!(@munch [$($out)*
+            $(#[$($attr)*])* fn __exit__ $params -> $ret $body
+        ] $($rest)*);
+    };
+    (@munch [$($out:tt)*]
+        $(#[$($attr:tt)*])* $vis:vis fn $name:ident $(<$($lt:lifetime),+>)?
+            (&mut $this:ident $($params:tt)*) -> $ret:ty $body:block
+        $($rest:tt)*
+    ) => {
+        counted_pymethods!(@munch [$($out)*
+            $(#[$($attr)*])* $vis fn $name $(<$($lt),+>)? (&mut $this $($params)*) -> $ret {
+                let outermost = $this.begin_call();
+                let result = (|| -> $ret { $body })();
+                $this.end_call(outermost, result.is_err());
+                result
+            }
+        ] $($rest)*);
+    };
+    (@munch [$($out:tt)*]
+        $(#[$($attr:tt)*])* $vis:vis fn $name:ident $(<$($lt:lifetime),+>)?
+            (&$this:ident $($params:tt)*) -> $ret:ty $body:block
+        $($rest:tt)*
+    ) => {
+        counted_pymethods!(@munch [$($out)*
+            $(#[$($attr)*])* $vis fn $name $(<$($lt),+>)? (&$this $($params)*) -> $ret {
+                let outermost = $this.begin_call();
+                let result = (|| -> $ret { $body })();
+                $this.end_call(outermost, result.is_err());
+                result
+            }
+        ] $($rest)*);
+    };
+    (@munch [$($out:tt)*]
+        $(#[$($attr:tt)*])* $vis:vis fn $name:ident $params:tt $(-> $ret:ty)? $body:block
+        $($rest:tt)*
+    ) => {
+        counted_pymethods!(@munch [$($out)*
+            $(#[$($attr)*])* $vis fn $name $params $(-> $ret)? $body
+        ] $($rest)*);
+    };
+}
+
+#[pymethods]
+impl Query {
+    #[new]
+    pub(crate) fn new(table: String) -> Self {
+        Self {
+            table,
+            filters: Vec::new(),
+            order_by: None,
+            limit: None,
+            with_deleted: false,
+            columns: None,
+        }
+    }
+    #[pyo3(signature = (field, value))]
+    fn where_eq<'a>(
+        mut slf: PyRefMut<'a, Self>,
+        field: String,
+        value: Bound<'a, PyAny>,
+    ) -> PyResult<PyRefMut<'a, Self>> {
+        slf.filters.push(Filter::Eq(field, py_to_json(value)?));
+        Ok(slf)
+    }
+    /// Inclusive range filter; values of a different JSON type never match.
+    #[pyo3(signature = (field, low, high))]
+    fn where_between<'a>(
+        mut slf: PyRefMut<'a, Self>,
+        field: String,
+        low: Bound<'a, PyAny>,
+        high: Bound<'a, PyAny>,
+    ) -> PyResult<PyRefMut<'a, Self>> {
+        slf.filters
+            .push(Filter::Between(field, py_to_json(low)?, py_to_json(high)?));
+        Ok(slf)
+    }
+    /// Array fields match when the list holds `value`; string fields match on substring.
+    #[pyo3(signature = (field, value))]
+    fn where_contains<'a>(
+        mut slf: PyRefMut<'a, Self>,
+        field: String,
+        value: Bound<'a, PyAny>,
+    ) -> PyResult<PyRefMut<'a, Self>> {
+        slf.filters
+            .push(Filter::Contains(field, py_to_json(value)?));
+        Ok(slf)
+    }
+    /// Matches an explicit stored null, as written by patching a field with `None`.
+    fn where_null(mut slf: PyRefMut<'_, Self>, field: String) -> PyRefMut<'_, Self> {
+        slf.filters.push(Filter::Null(field));
+        slf
+    }
+    /// Matches records that have no value at all for `field`, e.g. after `Database.unset`.
+    fn where_missing(mut slf: PyRefMut<'_, Self>, field: String) -> PyRefMut<'_, Self> {
+        slf.filters.push(Filter::Missing(field));
+        slf
+    }
+    /// Includes soft-deleted rows, which are otherwise skipped.
+    #[pyo3(signature = (flag=true))]
+    fn with_deleted(mut slf: PyRefMut<'_, Self>, flag: bool) -> PyRefMut<'_, Self> {
+        slf.with_deleted = flag;
+        slf
+    }
+    /// Sorts by `field`, ascending unless `descending`. Rows where it is null or missing
+    /// come first, or last when descending.
+    #[pyo3(signature = (field, descending=None))]
+    fn order_by(
+        mut slf: PyRefMut<'_, Self>,
+        field: String,
+        descending: Option<bool>,
+    ) -> PyRefMut<'_, Self> {
+        slf.order_by = Some((field, descending.unwrap_or(false)));
+        slf
+    }
+    fn take(mut slf: PyRefMut<'_, Self>, count: usize) -> PyRefMut<'_, Self> {
+        slf.limit = Some(count);
+        slf
+    }
+    /// Keeps only these fields in results and exports, which still carry the id. No fields
+    /// keeps them all again.
+    #[pyo3(signature = (*fields))]
+    fn select(mut slf: PyRefMut<'_, Self>, fields: Vec<String>) -> PyRefMut<'_, Self> {
+        slf.columns = (!fields.is_empty()).then_some(fields);
+        slf
+    }
+}
+
+#[pymethods]
+impl Transaction {
+    fn __enter__(&self, py: Python<'_>) -> PyResult<Py<Database>> {
+        let mut db = self.db.borrow_mut(py);
+        db.check_open()?;
+        if db.tx_snapshot.is_some() {
+            return Err(PyRuntimeError::new_err(
+                "a transaction is already open; nested transactions are not supported",
+            ));
+        }
+        db.tx_snapshot = Some(Box::new(db.engine.clone()));
+        db.tx_dirty = (db.dirty, db.queued);
+        Ok(self.db.clone_ref(py))
+    }
+    #[pyo3(signature = (exc_type=None, _exc=None, _tb=None))]
+    fn __exit__(
+        &self,
+        py: Python<'_>,
+        exc_type: Option<Bound<'_, PyAny>>,
+        _exc: Option<Bound<'_, PyAny>>,
+        _tb: Option<Bound<'_, PyAny>>,
+    ) -> PyResult<bool> {
+        let mut db = self.db.borrow_mut(py);
+        if exc_type.is_some() {
+            db.roll_back_transaction();
+            return Ok(false);
+        }
+        if db.tx_snapshot.take().is_none() {
+            return Ok(false);
+        }
+        db.persist()?;
+        Ok(false)
+    }
+}
+
+counted_pymethods! {
+impl Database {
+    #[new]
+    #[pyo3(signature = (storage_path=None, encryption_key=None, compression="zstd", mode="professional", strict_types=None, journal=false, layout="file", compression_level=None, read_only=false, lock_timeout=0.0, background=false, history_size=DEFAULT_HISTORY_SIZE, persist_history=false))]
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
+        py: Python<'_>,
+        storage_path: Option<String>,
+        encryption_key: Option<String>,
+        compression: &str,
+        mode: &str,
+        strict_types: Option<bool>,
+        journal: bool,
+        layout: &str,
+        compression_level: Option<i32>,
+        read_only: bool,
+        lock_timeout: f64,
+        background: bool,
+        history_size: usize,
+        persist_history: bool,
+    ) -> PyResult<Self> {
+        let compression_level = check_zstd_level(compression_level.unwrap_or(DEFAULT_ZSTD_LEVEL))?;
+        let path = storage_path
+            .map(|candidate| db_path(&candidate))
+            .transpose()?;
+        let layout = match layout.to_lowercase().as_str() {
+            "file" => StorageLayout::File,
+            "directory" => StorageLayout::Directory,
+            other => {
+                return Err(PyValueError::new_err(format!(
+                    "unknown layout `{}`; expected \"file\" or \"directory\"",
+                    other
+                )))
+            }
+        };
+        if layout == StorageLayout::Directory && path.as_ref().is_some_and(|p| p.is_file()) {
+            return Err(PyValueError::new_err(
+                "storage_path is a single-file database; convert it with migrate_to_directory()",
+            ));
+        }
+        let kdf_salt = random_salt();
+        // An existing database brings its own salt and the key is derived from it on the
+        // first read, so deriving one here too would run PBKDF2 twice.
+        let key = encryption_key
+            .as_deref()
+            .filter(|_| !path.as_ref().is_some_and(|p| p.exists()))
+            .map(|p| derive_key(p, &kdf_salt, KDF_ITERATIONS));
+        let mode_enum = match mode.to_lowercase().as_str() {
+            "friendly" => Mode::Friendly,
+            "snarky" => Mode::Snarky,
+            _ => Mode::Professional,
+        };
+        let comp_algo = CompressionAlgo::parse(compression).unwrap_or(CompressionAlgo::Zstd);
+        let mut db = Self {
+            engine: Engine::new(),
+            storage_path: path,
+            encryption_key: key,
+            passphrase: encryption_key,
+            kdf_salt,
+            compression: comp_algo,
+            compression_level,
+            last_write: None,
+            persists_written: 0,
+            persists_skipped: 0,
+            ops_executed: Cell::new(0),
+            errors_returned: Cell::new(0),
+            calls_open: Cell::new(0),
+            personality: Personality::new(mode_enum),
+            command_history: VecDeque::new(),
+            history_size,
+            persist_history,
+            batch_mode: false,
+            batch_ops: Vec::new(),
+            savepoints: Vec::new(),
+            tx_snapshot: None,
+            tx_dirty: (false, false),
+            autosave: true,
+            dirty: false,
+            queued: false,
+            journal,
+            journal_entries: 0,
+            pending_entry: None,
+            layout,
+            written: HashMap::new(),
+            graph_dirty: true,
+            load_errors: BTreeMap::new(),
+            read_only,
+            lock: None,
+            writer: None,
+            closed: false,
+            entity_classifier: None,
+        };
+        if let Some(p) = db.storage_path.clone() {
+            db.lock = Some(acquire_lock(py, &p, !read_only, lock_timeout)?);
+        }
+        db.reload_from_disk()?;
+        // Nothing read adopted a key (the file is unencrypted or predates the salt), so
+        // later writes get one under the new salt.
+        if let (Some(pass), None) = (db.passphrase.as_deref(), db.encryption_key) {
+            db.encryption_key = Some(derive_key(pass, &db.kdf_salt, KDF_ITERATIONS));
+        }
+        if background && !read_only {
+            if journal || db.layout == StorageLayout::Directory {
+                return Err(PyValueError::new_err(
+                    "background persistence needs the single-file layout without journal",
+                ));
+            }
+            db.writer = Some(writer::Writer::spawn());
+        }
+        if let Some(strict) = strict_types {
+            db.set_strict_types(strict)?;
+        }
+        Ok(db)
+    }
+
+    /// `with db.transaction():` groups method calls into one persist at the end of the block,
+    /// and restores the state from entry if the block raises. Transactions do not nest.
+    fn transaction(slf: Py<Self>) -> Transaction {
+        Transaction { db: slf }
+    }
+
+    /// zstd level for subsequent writes; existing data is recompressed on the next persist.
+    fn set_compression_level(&mut self, level: i32) -> PyResult<()> {
+        self.check_open()?;
+        self.compression_level = check_zstd_level(level)?;
+        self.written.clear();
+        Ok(())
+    }
+
+    /// Re-encrypts the stored data under `new_key`, or stores it unencrypted for `None`.
+    /// Each file is replaced atomically; in the directory layout every file is staged
+    /// before any is replaced, so a failed rekey leaves them all under the old key.
+    #[pyo3(signature = (new_key))]
+    fn rekey(&mut self, new_key: Option<String>) -> PyResult<()> {
+        self.check_open()?;
+        self.check_writable()?;
+        self.rewrite_graph()?;
+        let salt = random_salt();
+        let key = new_key
+            .as_deref()
+            .map(|p| derive_key(p, &salt, KDF_ITERATIONS));
+        let previous = (
+            std::mem::replace(&mut self.encryption_key, key),
+            std::mem::replace(&mut self.passphrase, new_key),
+            std::mem::replace(&mut self.kdf_salt, salt),
+        );
+        self.written.clear();
+        if let Err(e) = self.write_to_disk() {
+            (self.encryption_key, self.passphrase, self.kdf_salt) = previous;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Rewrites the stored data with another algorithm (and optionally zstd level), which
+    /// also becomes the setting for later writes.
+    #[pyo3(signature = (algo, level=None))]
+    fn recompress(&mut self, algo: &str, level: Option<i32>) -> PyResult<()> {
+        self.check_open()?;
+        self.check_writable()?;
+        let Some(algo) = CompressionAlgo::parse(algo) else {
+            return Err(PyValueError::new_err(format!(
+                "unknown compression `{}`; expected one of \"zstd\", \"lz4\", \"none\"",
+                algo
+            )));
+        };
+        let level = level.map(check_zstd_level).transpose()?;
+        self.rewrite_graph()?;
+        let previous = (self.compression, self.compression_level);
+        self.compression = algo;
+        self.compression_level = level.unwrap_or(self.compression_level);
+        self.written.clear();
+        if let Err(e) = self.write_to_disk() {
+            (self.compression, self.compression_level) = previous;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Sizes from the last persist: `raw_bytes` of serialized state, `stored_bytes` written
+    /// and their `ratio`, plus how many persists wrote (`persists_written`) or found nothing
+    /// changed (`persists_skipped`). `file_bytes` is what the database occupies on disk now;
+    /// `tables` (live `records` and serialized `bytes` per table) and `graph` (serialized
+    /// bytes of chunks, entities, relations and communities) break the state down.
+    /// `None` while there is nothing on disk yet.
+    fn storage_stats(&mut self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        self.check_open()?;
+        self.storage_report()?
+            .map(|stats| json_to_py(py, &stats))
+            .transpose()
+    }
+
+    /// Health overview: `tables` and `total_records` with live `records` per table,
+    /// `aliases`, `graph` as `graph_stats` returns it, `session` (public method calls since
+    /// open, not counting this one, and how many raised) and `storage`, the
+    /// `storage_stats` document or `None`. The STATS command renders the same data.
+    fn stats(&mut self, py: Python<'_>) -> PyResult<PyObject> {
+        self.check_open()?;
+        json_to_py(py, &self.stats_report()?)
+    }
+
+    /// Turns per-mutation persistence on or off. Re-enabling it flushes pending changes.
+    fn set_autosave(&mut self, enabled: bool) -> PyResult<()> {
+        self.check_open()?;
+        self.autosave = enabled;
+        if enabled && self.dirty {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the current state to disk regardless of autosave.
+    fn flush(&mut self) -> PyResult<()> {
+        self.check_open()?;
+        self.write_to_disk()
+    }
+
+    /// Whether there are changes not yet written to disk, including a background write
+    /// still in flight.
+    fn is_dirty(&self) -> PyResult<bool> {
+        self.check_open()?;
+        let written = self.queued && self.writer.as_ref().is_some_and(writer::Writer::settled);
+        Ok(self.dirty && !written)
+    }
+
+    /// Ends the handle: an open transaction is rolled back, pending changes are flushed
+    /// (or, with `flush=False`, dropped with a warning), queued background writes finish
+    /// and the file lock is released. Later calls raise; closing again does nothing.
+    #[pyo3(signature = (flush=true))]
+    fn close(&mut self, py: Python<'_>, flush: bool) -> PyResult<()> {
+        if self.closed {
+            return Ok(());
+        }
+        self.roll_back_transaction();
+        if self.dirty {
+            if flush {
+                self.write_to_disk()?;
+            } else {
+                self.warn_unsaved(py, "closed");
+                self.dirty = false;
+            }
+        }
+        self.drain_writer()?;
+        self.writer = None;
+        if let Some(key) = self.lock.take() {
+            lock::release(&key);
+        }
+        self.closed = true;
+        Ok(())
+    }
+
+    #[getter]
+    fn closed(&self) -> bool {
+        self.closed
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyResult<PyRef<'_, Self>> {
+        slf.check_open()?;
+        Ok(slf)
+    }
+
+    /// Closes the database, flushing pending changes unless the block raised.
+    #[pyo3(signature = (exc_type=None, _exc=None, _tb=None))]
+    fn __exit__(
+        &mut self,
+        py: Python<'_>,
+        exc_type: Option<Bound<'_, PyAny>>,
+        _exc: Option<Bound<'_, PyAny>>,
+        _tb: Option<Bound<'_, PyAny>>,
+    ) -> PyResult<bool> {
+        self.close(py, exc_type.is_none())?;
+        Ok(false)
+    }
+
+    /// Every alias mapped to its expansion, sorted by name.
+    fn list_aliases(&self, py: Python<'_>) -> PyResult<PyObject> {
+        self.check_open()?;
+        let out = PyDict::new_bound(py);
+        let sorted: BTreeMap<&String, &String> = self.engine.aliases.iter().collect();
+        for (name, expansion) in sorted {
+            out.set_item(name, expansion)?;
+        }
+        Ok(out.into())
+    }
+
+    /// Deletes the alias `name` (case-insensitive); `KeyError` if there is none.
+    fn remove_alias(&mut self, name: String) -> PyResult<()> {
+        self.check_open()?;
+        self.check_writable()?;
+        let name = name.to_ascii_lowercase();
+        if self.engine.aliases.remove(&name).is_none() {
+            return Err(PyKeyError::new_err(format!(
+                "no alias '{}'; ALIASES lists the defined ones",
+                name
+            )));
+        }
+        self.persist()
+    }
+
+    /// Toggles strict type checking for every table; persisted with the database.
+    fn set_strict_types(&mut self, enabled: bool) -> PyResult<()> {
+        self.check_open()?;
+        self.check_writable()?;
+        if self.engine.strict_types == enabled {
+            return Ok(());
+        }
+        self.engine.strict_types = enabled;
+        self.persist()
+    }
+
+    #[pyo3(signature = (
+        name,
+        schema,
+        meta=None,
+        versioned=false,
+        ttl_seconds=None,
+        soft_delete=false,
+        soft_delete_releases_unique=false
+    ))]
+    fn create_table(
+        &mut self,
+        name: String,
+        schema: Bound<'_, PyDict>,
+        meta: Option<Bound<'_, PyDict>>,
+        versioned: bool,
+        ttl_seconds: Option<u64>,
+        soft_delete: bool,
+        soft_delete_releases_unique: bool,
+    ) -> PyResult<PyObject> {
+        self.check_open()?;
+        self.check_writable()?;
+        validate_identifier(&name).map_err(convert_db_error)?;
+        let mut native_schema = HashMap::new();
+        for (field, def) in schema.iter() {
+            let fname = field.extract::<String>()?;
+            validate_identifier(&fname).map_err(convert_db_error)?;
+            let parsed = parse_field_def(&fname, def.downcast::<PyDict>()?)?;
+            native_schema.insert(fname, parsed);
+        }
+        let mut table = Table::new(native_schema);
+        table.versioned = versioned;
+        table.ttl_seconds = check_ttl(ttl_seconds)?;
+        table.soft_delete = soft_delete;
+        table.soft_delete_releases_unique = soft_delete_releases_unique;
+        for (k, v) in meta.iter().flat_map(|m| m.iter()) {
+            table
+                .set_meta(&k.extract::<String>()?, Some(py_to_json(v)?))
+                .map_err(convert_db_error)?;
+        }
+        self.add_table(name, table)
+    }
+
+    fn insert(&mut self, table: String, payload: Bound<'_, PyDict>) -> PyResult<PyObject> {
+        self.insert_record(table, payload)
+    }
+
+    /// Validates and inserts every payload, persisting once; returns the new ids in order.
+    /// A failing payload leaves the table as it was.
+    fn insert_many(
+        &mut self,
+        table: String,
+        payloads: Vec<Bound<'_, PyDict>>,
+    ) -> PyResult<Vec<u64>> {
+        self.insert_records(table, payloads)
+    }
+
+    /// Inserts the rows of a DataFrame (anything with `__dataframe__`, or `to_dict("records")`)
+    /// with one persist and returns their ids. NaN and other missing values become null and
+    /// an `id` column is ignored; a column outside the schema is an error unless
+    /// `drop_unknown` is set. A failing row leaves the table as it was.
+    #[pyo3(signature = (table, df, drop_unknown=false))]
+    fn insert_dataframe(
+        &mut self,
+        table: String,
+        df: Bound<'_, PyAny>,
+        drop_unknown: bool,
+    ) -> PyResult<Vec<u64>> {
+        self.insert_frame(&table, &df, drop_unknown)
+    }
+
+    /// Updates the record matching `payload[on]` or inserts a new one; `on` must be a unique
+    /// or indexed field. Returns `(id, created)`.
+    fn upsert(
+        &mut self,
+        table: String,
+        payload: Bound<'_, PyDict>,
+        on: String,
+    ) -> PyResult<(u64, bool)> {
+        self.check_open()?;
+        self.check_writable()?;
+        let mut data = Map::new();
+        for (k, v) in payload.iter() {
+            data.insert(k.extract::<String>()?, py_to_json(v)?);
+        }
+        let hit = self
+            .engine
+            .upsert(&table, data, &on)
+            .map_err(convert_db_error)?;
+        self.persist()?;
+        Ok(hit)
+    }
+
+    /// `upsert` over a list with one persist; a failing payload leaves the table as it was.
+    fn upsert_many(
+        &mut self,
+        table: String,
+        payloads: Vec<Bound<'_, PyDict>>,
+        on: String,
+    ) -> PyResult<Vec<(u64, bool)>> {
+        self.check_open()?;
+        self.check_writable()?;
+        let mut rows = Vec::with_capacity(payloads.len());
+        for payload in payloads {
+            let mut data = Map::new();
+            for (k, v) in payload.iter() {
+                data.insert(k.extract::<String>()?, py_to_json(v)?);
+            }
+            rows.push(data);
+        }
+        let hits = self
+            .engine
+            .upsert_many(&table, rows, &on)
+            .map_err(convert_db_error)?;
+        self.persist()?;
+        Ok(hits)
+    }
+
+    /// With `expected_version`, the patch only applies if the record is still at that version.
+    #[pyo3(signature = (table, rid, patch, expected_version=None))]
+    fn update(
+        &mut self,
+        table: String,
+        rid: u64,
+        patch: Bound<'_, PyDict>,
+        expected_version: Option<u64>,
+    ) -> PyResult<()> {
+        self.update_record(table, rid, patch, expected_version)
+    }
+
+    /// Applies `patch` only if the stored record matches every field in `expected`; on a
+    /// mismatch nothing changes and `ValueError` is raised.
+    fn update_if(
+        &mut self,
+        table: String,
+        rid: u64,
+        expected: Bound<'_, PyDict>,
+        patch: Bound<'_, PyDict>,
+    ) -> PyResult<()> {
+        self.check_open()?;
+        self.check_writable()?;
+        let mut want = Map::new();
+        for (k, v) in expected.iter() {
+            want.insert(k.extract::<String>()?, py_to_json(v)?);
+        }
+        let mut p = Map::new();
+        for (k, v) in patch.iter() {
+            p.insert(k.extract::<String>()?, py_to_json(v)?);
+        }
+        self.engine
+            .check_expected(&table, rid, &want)
+            .and_then(|()| self.engine.update(&table, rid, p))
+            .map_err(convert_db_error)?;
+        self.stage_row(&table, rid, false);
+        self.persist()
+    }
+
+    /// Returns `(record, created)`: the one record equal to `match` on every field, or a new
+    /// one built from `match` over `defaults`. Several matches raise `ValueError`.
+    #[pyo3(signature = (table, r#match, defaults=None))]
+    fn find_or_create(
+        &mut self,
+        py: Python<'_>,
+        table: String,
+        r#match: Bound<'_, PyDict>,
+        defaults: Option<Bound<'_, PyDict>>,
+    ) -> PyResult<(Record, bool)> {
+        self.check_open()?;
+        self.check_writable()?;
+        let mut matches = Map::new();
+        for (k, v) in r#match.iter() {
+            matches.insert(k.extract::<String>()?, py_to_json(v)?);
+        }
+        let mut base = Map::new();
+        for (k, v) in defaults.iter().flat_map(|d| d.iter()) {
+            base.insert(k.extract::<String>()?, py_to_json(v)?);
+        }
+        let (rid, created) = self
+            .engine
+            .find_or_create(&table, matches, base)
+            .map_err(convert_db_error)?;
+        if created {
+            self.persist()?;
+        }
+        let record = self
+            .lookup(py, &table, rid)?
+            .ok_or_else(|| convert_db_error(DbError::MissingRecord(rid)))?;
+        Ok((record, created))
+    }
+
+    /// Adds `delta` to an integer or float field and returns the new value. Missing values
+    /// start from zero unless `default_zero` is off, in which case they raise `KeyError`.
+    #[pyo3(signature = (table, rid, field, delta=None, default_zero=true))]
+    fn increment(
+        &mut self,
+        py: Python<'_>,
+        table: String,
+        rid: u64,
+        field: String,
+        delta: Option<Bound<'_, PyAny>>,
+        default_zero: bool,
+    ) -> PyResult<PyObject> {
+        self.check_open()?;
+        self.check_writable()?;
+        let delta = delta.map(py_to_json).transpose()?.unwrap_or(Value::from(1));
+        let next = self
+            .engine
+            .increment(&table, rid, &field, &delta, default_zero)
+            .map_err(convert_db_error)?;
+        self.persist()?;
+        json_to_py(py, &next)
+    }
+
+    /// `increment` on every row the query matches, all or nothing; returns the row count.
+    #[pyo3(signature = (query, field, delta=None, default_zero=true))]
+    fn increment_where(
+        &mut self,
+        query: PyRef<'_, Query>,
+        field: String,
+        delta: Option<Bound<'_, PyAny>>,
+        default_zero: bool,
+    ) -> PyResult<usize> {
+        self.check_open()?;
+        self.check_writable()?;
+        let delta = delta.map(py_to_json).transpose()?.unwrap_or(Value::from(1));
+        let t = self.engine.tables.get(&query.table).ok_or_else(|| {
+            PyKeyError::new_err(format!("table '{}' does not exist", query.table))
+        })?;
+        let ids: Vec<u64> = query.rows(t).into_iter().map(|(id, _)| id).collect();
+        self.engine
+            .increment_many(&query.table, &ids, &field, &delta, default_zero)
+            .map_err(convert_db_error)?;
+        self.persist()?;
+        Ok(ids.len())
+    }
+
+    /// Appends `value` to an array or json list field; returns the new length.
+    fn array_push(
+        &mut self,
+        table: String,
+        rid: u64,
+        field: String,
+        value: Bound<'_, PyAny>,
+    ) -> PyResult<usize> {
+        self.check_open()?;
+        self.check_writable()?;
+        self.apply_array_op(&table, rid, &field, ArrayOp::Push, value)
+    }
+
+    /// Removes every element equal to `value`; returns the new length.
+    fn array_pull(
+        &mut self,
+        table: String,
+        rid: u64,
+        field: String,
+        value: Bound<'_, PyAny>,
+    ) -> PyResult<usize> {
+        self.check_open()?;
+        self.check_writable()?;
+        self.apply_array_op(&table, rid, &field, ArrayOp::Pull, value)
+    }
+
+    /// Appends `value` unless the list already holds it; returns the new length.
+    fn array_add_unique(
+        &mut self,
+        table: String,
+        rid: u64,
+        field: String,
+        value: Bound<'_, PyAny>,
+    ) -> PyResult<usize> {
+        self.check_open()?;
+        self.check_writable()?;
+        self.apply_array_op(&table, rid, &field, ArrayOp::AddUnique, value)
+    }
+
+    /// Removes `fields` from the record entirely. Unlike patching with `None`, which stores
+    /// an explicit null (`where_null`), unset keys are absent (`where_missing`).
+    fn unset(&mut self, table: String, rid: u64, fields: Vec<String>) -> PyResult<()> {
+        self.check_open()?;
+        self.check_writable()?;
+        self.engine
+            .unset(&table, rid, &fields)
+            .map_err(convert_db_error)?;
+        self.persist()
+    }
+
+    /// Overwrites the whole record: required fields must be present and anything the payload
+    /// leaves out is removed. Honours `expected_version` like `update`.
+    #[pyo3(signature = (table, rid, payload, expected_version=None))]
+    fn replace(
+        &mut self,
+        table: String,
+        rid: u64,
+        payload: Bound<'_, PyDict>,
+        expected_version: Option<u64>,
+    ) -> PyResult<()> {
+        self.check_open()?;
+        self.check_writable()?;
+        let mut data = Map::new();
+        for (k, v) in payload.iter() {
+            data.insert(k.extract::<String>()?, py_to_json(v)?);
+        }
+        self.engine
+            .check_version(&table, rid, expected_version)
+            .and_then(|()| self.engine.replace(&table, rid, data))
+            .map_err(convert_db_error)?;
+        self.persist()?;
+        Ok(())
+    }
+
+    /// Hides the record from reads without removing it; the table needs `soft_delete=True`.
+    fn soft_delete(&mut self, table: String, rid: u64) -> PyResult<()> {
+        self.check_open()?;
+        self.check_writable()?;
+        self.engine
+            .soft_delete(&table, rid)
+            .map_err(convert_db_error)?;
+        self.persist()
+    }
+
+    /// Undoes `soft_delete`. Fails if a released unique value has been taken meanwhile.
+    fn restore(&mut self, table: String, rid: u64) -> PyResult<()> {
+        self.check_open()?;
+        self.check_writable()?;
+        self.engine
+            .table_mut(&table)
+            .and_then(|t| t.restore(rid))
+            .map_err(convert_db_error)?;
+        self.persist()
+    }
+
+    /// Permanently removes rows soft-deleted at least `older_than` seconds ago; returns the
+    /// same summary as `delete`.
+    #[pyo3(signature = (table, older_than=0))]
+    fn purge_deleted(
+        &mut self,
+        py: Python<'_>,
+        table: String,
+        older_than: u64,
+    ) -> PyResult<PyObject> {
+        self.check_open()?;
+        self.check_writable()?;
+        let summary = self
+            .engine
+            .purge_deleted(&table, older_than)
+            .map_err(convert_db_error)?;
+        self.persist()?;
+        delete_summary_to_py(py, &summary)
+    }
+
+    /// Returns `{"deleted": {table: n}, "nulled": {table: n}}` covering any cascade.
+    #[pyo3(signature = (table, rid, expected_version=None))]
+    fn delete(
+        &mut self,
+        py: Python<'_>,
+        table: String,
+        rid: u64,
+        expected_version: Option<u64>,
+    ) -> PyResult<PyObject> {
+        self.delete_record(py, table, rid, expected_version)
+    }
+
+    /// Deletes every row matched by `query` (ordering and `take` apply) in one persist.
+    fn delete_where(&mut self, py: Python<'_>, query: PyRef<'_, Query>) -> PyResult<PyObject> {
+        self.delete_matching(py, query)
+    }
+
+    /// Empties `name` in one persist, applying the `on_delete` rule of every field that
+    /// references its rows. Returns the removed row count, or a personality line outside
+    /// professional mode.
+    #[pyo3(signature = (name, reset_ids=false))]
+    fn truncate_table(
+        &mut self,
+        py: Python<'_>,
+        name: String,
+        reset_ids: bool,
+    ) -> PyResult<PyObject> {
+        self.check_open()?;
+        self.check_writable()?;
+        let removed = self
+            .engine
+            .truncate_table(&name, reset_ids)
+            .map_err(convert_db_error)?;
+        self.persist()?;
+        Ok(if self.personality.is_professional() {
+            removed.into_py(py)
+        } else {
+            self.personality.table_truncated(&name, removed).into_py(py)
+        })
+    }
+
+    /// Every table's fields and metadata in the same shape `schema()` returns. With `path`
+    /// the document is written there as pretty-printed JSON instead of being returned.
+    #[pyo3(signature = (path=None))]
+    fn export_schema(&self, py: Python<'_>, path: Option<String>) -> PyResult<PyObject> {
+        self.check_open()?;
+        let doc = self.schema_document();
+        match path {
+            Some(dest) => {
+                let text = serde_json::to_string_pretty(&doc)
+                    .map_err(|e| PyValueError::new_err(e.to_string()))?;
+                let output_path = sanitize_user_path(&dest)?;
+                fs::write(output_path, text + "\n")
+                    .map_err(|e| PyIOError::new_err(e.to_string()))?;
+                Ok(py.None())
+            }
+            None => json_to_py(py, &doc),
+        }
+    }
+
+    /// Every table as a `CREATE TABLE` statement, which `execute_script` runs to make an
+    /// empty copy. Tables and fields are sorted so the text diffs cleanly. What the
+    /// statement cannot say (references, constraints, computed fields, table settings...)
+    /// is listed in a comment above it; `export_schema` keeps all of it. With `dest` the
+    /// text is written there instead of being returned.
+    #[pyo3(signature = (dest=None))]
+    fn export_ddl(&self, dest: Option<String>) -> PyResult<Option<String>> {
+        self.check_open()?;
+        let ddl = self.ddl();
+        match dest {
+            Some(dest) => {
+                let output_path = sanitize_user_path(&dest)?;
+                fs::write(output_path, ddl).map_err(|e| PyIOError::new_err(e.to_string()))?;
+                Ok(None)
+            }
+            None => Ok(Some(ddl)),
+        }
+    }
+
+    /// Creates the tables described by an `export_schema` document (a dict or a file path).
+    /// Existing names are an error unless `if_not_exists` is set, in which case they are
+    /// left untouched. Returns the names of the tables created.
+    #[pyo3(signature = (source, if_not_exists=false))]
+    fn import_schema(
+        &mut self,
+        py: Python<'_>,
+        source: Bound<'_, PyAny>,
+        if_not_exists: bool,
+    ) -> PyResult<Vec<String>> {
+        self.check_open()?;
+        self.check_writable()?;
+        let doc = if let Ok(src) = source.extract::<String>() {
+            let source_path = sanitize_user_path(&src)?;
+            let text =
+                fs::read_to_string(source_path).map_err(|e| PyIOError::new_err(e.to_string()))?;
+            let parsed: Value =
+                serde_json::from_str(&text).map_err(|e| PyValueError::new_err(e.to_string()))?;
+            json_to_py(py, &parsed)?.into_bound(py)
+        } else {
+            source
+        };
+        let doc = doc.downcast::<PyDict>()?;
+        if let Some(version) = extract_opt::<u64>(doc, "version")? {
+            if version > SCHEMA_FORMAT_VERSION {
+                return Err(PyValueError::new_err(format!(
+                    "unsupported schema format version {}",
+                    version
+                )));
+            }
+        }
+        let tables = doc
+            .get_item("tables")?
+            .ok_or_else(|| PyValueError::new_err("schema document requires tables"))?;
+        let mut pending = Vec::new();
+        for (name, spec) in tables.downcast::<PyDict>()?.iter() {
+            let name = name.extract::<String>()?;
+            validate_identifier(&name).map_err(convert_db_error)?;
+            if if_not_exists && self.engine.tables.contains_key(&name) {
+                continue;
+            }
+            pending.push((name, parse_table_spec(spec.downcast::<PyDict>()?)?));
+        }
+        let created = self
+            .engine
+            .create_tables(pending)
+            .map_err(convert_db_error)?;
+        self.persist()?;
+        Ok(created)
+    }
+
+    /// Writes the whole database to `dest` as one pretty-printed JSON document: the
+    /// `export_schema` document, every table's rows, id sequences, query aliases and the
+    /// knowledge graph. Besides its fields, a row holds its `$id` and, where the table keeps
+    /// them, its `$version`, TTL `$created_at` and soft-delete `$deleted_at`.
+    fn dump_json(&mut self, dest: String) -> PyResult<()> {
+        self.check_open()?;
+        let doc = self.dump_document(None)?;
+        let text =
+            serde_json::to_string_pretty(&doc).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let output_path = sanitize_user_path(&dest)?;
+        fs::write(output_path, text + "\n").map_err(|e| PyIOError::new_err(e.to_string()))
+    }
+
+    /// Loads a `dump_json` document. `"replace"` swaps the whole engine for the dump's
+    /// contents; `"merge"` adds its tables and rows to the current ones, keeping the
+    /// existing schema of tables present in both. Every row is validated against its
+    /// table and keeps its id; nothing changes unless the whole document loads.
+    #[pyo3(signature = (src, mode="replace"))]
+    fn load_json(&mut self, py: Python<'_>, src: String, mode: &str) -> PyResult<()> {
+        self.check_open()?;
+        self.check_writable()?;
+        let merge = match mode {
+            "replace" => false,
+            "merge" => true,
+            _ => return Err(PyValueError::new_err("mode must be 'replace' or 'merge'")),
+        };
+        let source_path = sanitize_user_path(&src)?;
+        let text =
+            fs::read_to_string(source_path).map_err(|e| PyIOError::new_err(e.to_string()))?;
+        let doc = serde_json::from_str(&text).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        self.load_document(py, doc, merge)
+    }
+
+    /// Writes `tables` (default: all) to `dest` as an encrypted bundle for handing over an
+    /// untrusted channel: their schema, rows and id sequences, as `dump_json` would write
+    /// them, compressed and sealed with AES-256-GCM under a key derived from `passphrase`
+    /// and a fresh salt. Aliases and the graph are left out.
+    #[pyo3(signature = (dest, passphrase, tables=None))]
+    fn export_encrypted(
+        &mut self,
+        dest: String,
+        passphrase: &str,
+        tables: Option<Vec<String>>,
+    ) -> PyResult<()> {
+        self.check_open()?;
+        if passphrase.is_empty() {
+            return Err(PyValueError::new_err("passphrase cannot be empty"));
+        }
+        let only: BTreeSet<String> = match tables {
+            Some(tables) => tables.into_iter().collect(),
+            None => self.engine.tables.keys().cloned().collect(),
+        };
+        for name in &only {
+            let t = self
+                .engine
+                .tables
+                .get(name)
+                .ok_or_else(|| convert_db_error(DbError::MissingTable(name.clone())))?;
+            let mut targets = t.schema.values().filter_map(|d| d.references.as_ref());
+            if let Some(target) = targets.find(|target| !only.contains(*target)) {
+                return Err(PyValueError::new_err(format!(
+                    "table `{}` references `{}`, which is not in the bundle",
+                    name, target
+                )));
+            }
+        }
+        let doc = self.dump_document(Some(&only))?;
+        let json = serde_json::to_vec(&doc).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let bundle = seal_bundle(&json, passphrase).map_err(PyIOError::new_err)?;
+        let output_path = sanitize_user_path(&dest)?;
+        fs::write(output_path, bundle).map_err(|e| PyIOError::new_err(e.to_string()))
+    }
+
+    /// Loads a bundle written by `export_encrypted`, as `load_json` loads a dump: `"merge"`
+    /// adds its tables and rows, `"replace"` swaps the whole database for its contents.
+    /// A wrong passphrase raises `EncryptionKeyError`.
+    #[pyo3(signature = (src, passphrase, mode="merge"))]
+    fn import_encrypted(
+        &mut self,
+        py: Python<'_>,
+        src: String,
+        passphrase: &str,
+        mode: &str,
+    ) -> PyResult<()> {
+        self.check_open()?;
+        self.check_writable()?;
+        let merge = match mode {
+            "replace" => false,
+            "merge" => true,
+            _ => return Err(PyValueError::new_err("mode must be 'replace' or 'merge'")),
+        };
+        let source_path = sanitize_user_path(&src)?;
+        let bundle = fs::read(source_path).map_err(|e| PyIOError::new_err(e.to_string()))?;
+        let json = open_bundle(&bundle, passphrase)?;
+        let doc = serde_json::from_slice(&json)
+            .map_err(|e| PyValueError::new_err(format!("invalid bundle: {}", e)))?;
+        self.load_document(py, doc, merge)
+    }
+
+    /// Compares `table` with `other_table` (default: the same name) in the database at
+    /// `other_path`, opened read-only (with `other_key` if it is encrypted) as the baseline.
+    /// Rows pair up by id, or by the value of `key`, which must not repeat on either side.
+    ///
+    /// Returns `{"schema": {"added": [...], "removed": [...]}, "added": [ids], "removed":
+    /// [baseline ids], "modified": [...]}`: fields and rows found only here are added, only
+    /// in the baseline removed. Each modified row is `{"id", "other_id", "fields": {field:
+    /// {"old", "new"}}}` over the fields both sides store; `summary` lists just the ids.
+    #[pyo3(signature = (
+        table,
+        other_path,
+        other_table=None,
+        key=None,
+        summary=false,
+        other_key=None
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn diff_table(
+        &self,
+        py: Python<'_>,
+        table: String,
+        other_path: String,
+        other_table: Option<String>,
+        key: Option<String>,
+        summary: bool,
+        other_key: Option<String>,
+    ) -> PyResult<PyObject> {
+        self.check_open()?;
+        let here = self
+            .engine
+            .tables
+            .get(&table)
+            .ok_or_else(|| PyKeyError::new_err(format!("table '{}' does not exist", table)))?;
+        let other = open_baseline(py, &other_path, other_key)?;
+        let other_table = other_table.unwrap_or_else(|| table.clone());
+        let there = other.engine.tables.get(&other_table).ok_or_else(|| {
+            PyKeyError::new_err(format!(
+                "table '{}' does not exist in '{}'",
+                other_table, other_path
+            ))
+        })?;
+        let diff = diff_tables(here, there, key.as_deref(), now_millis())
+            .map_err(PyValueError::new_err)?;
+
+        let out = PyDict::new_bound(py);
+        let schema = PyDict::new_bound(py);
+        schema.set_item("added", &diff.fields_added)?;
+        schema.set_item("removed", &diff.fields_removed)?;
+        out.set_item("schema", schema)?;
+        out.set_item("added", &diff.added)?;
+        out.set_item("removed", &diff.removed)?;
+        let modified = match summary {
+            true => diff.modified.iter().map(|(id, _)| (*id).into()).collect(),
+            false => diff
+                .modified
+                .iter()
+                .map(|(id, other_id)| {
+                    let (new, old) = (&here.records[id], &there.records[other_id]);
+                    let fields: Map<String, Value> = diff
+                        .fields
+                        .iter()
+                        .map(|f| (f, new.get(f), old.get(f)))
+                        .filter(|(_, new, old)| {
+                            new.unwrap_or(&Value::Null) != old.unwrap_or(&Value::Null)
+                        })
+                        .map(|(f, new, old)| {
+                            (f.clone(), serde_json::json!({"old": old, "new": new}))
+                        })
+                        .collect();
+                    serde_json::json!({"id": id, "other_id": other_id, "fields": fields})
+                })
+                .collect(),
+        };
+        out.set_item("modified", json_to_py(py, &Value::Array(modified))?)?;
+        Ok(out.into_py(py))
+    }
+
+    /// Merges the database at `other_path`, opened read-only (with `other_key` if it is
+    /// encrypted), into this one. Its tables missing here are created; a table in both must
+    /// have the same schema. Rows of a table named in `key_fields` (`{table: field}`) that
+    /// match a row here by that field are skipped, replace it, or fail the merge, as
+    /// `strategy` says; every other row is appended under a fresh id, and references follow
+    /// the rows they point at. The knowledge graphs merge too: chunks by id, entity
+    /// mentions added up. Nothing changes unless the whole merge succeeds.
+    ///
+    /// Returns `{table: {"inserted": n, "replaced": n, "skipped": n}}` for the merged tables.
+    #[pyo3(signature = (other_path, strategy="skip", key_fields=None, other_key=None))]
+    fn merge(
+        &mut self,
+        py: Python<'_>,
+        other_path: String,
+        strategy: &str,
+        key_fields: Option<HashMap<String, String>>,
+        other_key: Option<String>,
+    ) -> PyResult<PyObject> {
+        self.check_open()?;
+        self.check_writable()?;
+        let strategy = OnConflict::from_str(strategy).ok_or_else(|| {
+            PyValueError::new_err(format!(
+                "strategy must be 'skip', 'replace' or 'error', not '{}'",
+                strategy
+            ))
+        })?;
+        let mut other = open_baseline(py, &other_path, other_key)?;
+        other.graph()?;
+        self.graph()?;
+        let mut engine = self.engine.clone();
+        let keys = key_fields.unwrap_or_default();
+        let counts = merge_engines(&mut engine, &other.engine, &other_path, strategy, &keys)?;
+        let graph = std::mem::take(&mut other.engine.graph_rag.data);
+        if !graph.chunks.is_empty() || !graph.entities.is_empty() {
+            engine.graph_rag.merge(graph);
+            self.graph_dirty = true;
+        }
+        self.engine = engine;
+        self.persist()?;
+
+        let out = PyDict::new_bound(py);
+        for (table, [inserted, replaced, skipped]) in counts {
+            let count = PyDict::new_bound(py);
+            count.set_item("inserted", inserted)?;
+            count.set_item("replaced", replaced)?;
+            count.set_item("skipped", skipped)?;
+            out.set_item(table, count)?;
+        }
+        Ok(out.into_py(py))
+    }
+
+    /// Creates `dest` with `src`'s schema and metadata; returns the number of rows copied.
+    /// Fields referencing `src` itself reference `dest` in the copy.
+    #[pyo3(signature = (src, dest, with_data=true, preserve_ids=false))]
+    fn copy_table(
+        &mut self,
+        src: String,
+        dest: String,
+        with_data: bool,
+        preserve_ids: bool,
+    ) -> PyResult<usize> {
+        self.check_open()?;
+        self.check_writable()?;
+        validate_identifier(&dest).map_err(convert_db_error)?;
+        let copied = self
+            .engine
+            .copy_table(&src, &dest, with_data, preserve_ids)
+            .map_err(convert_db_error)?;
+        self.persist()?;
+        Ok(copied)
+    }
+
+    /// Deletes `table`'s expired rows now rather than on the next write; returns how many went.
+    fn purge_expired(&mut self, table: String) -> PyResult<usize> {
+        self.check_open()?;
+        self.check_writable()?;
+        let purged = self
+            .engine
+            .purge_expired(&table, now_millis())
+            .map_err(convert_db_error)?;
+        if purged > 0 {
+            self.persist()?;
+        }
+        Ok(purged)
+    }
+
+    /// Groups of ids whose rows hold identical values for `fields`.
+    #[pyo3(signature = (table, fields, nulls_equal=true))]
+    fn find_duplicates(
+        &self,
+        table: String,
+        fields: Vec<String>,
+        nulls_equal: bool,
+    ) -> PyResult<Vec<Vec<u64>>> {
+        self.check_open()?;
+        self.engine
+            .tables
+            .get(&table)
+            .ok_or_else(|| DbError::MissingTable(table.clone()))
+            .and_then(|t| t.duplicate_groups(&fields, nulls_equal, now_millis()))
+            .map_err(convert_db_error)
+    }
+
+    /// Deletes all but the `keep="first"` (lowest id) or `"last"` row of each duplicate
+    /// group in one persist; returns how many rows went.
+    #[pyo3(signature = (table, fields, keep="first", nulls_equal=true))]
+    fn dedupe(
+        &mut self,
+        table: String,
+        fields: Vec<String>,
+        keep: &str,
+        nulls_equal: bool,
+    ) -> PyResult<usize> {
+        self.check_open()?;
+        self.check_writable()?;
+        let keep_last = match keep {
+            "first" => false,
+            "last" => true,
+            other => {
+                return Err(PyValueError::new_err(format!(
+                    "keep must be 'first' or 'last', got '{}'",
+                    other
+                )))
+            }
+        };
+        let groups = self.find_duplicates(table.clone(), fields, nulls_equal)?;
+        let doomed: Vec<u64> = groups
+            .into_iter()
+            .flat_map(|mut g| {
+                if keep_last {
+                    g.pop();
+                } else {
+                    g.remove(0);
+                }
+                g
+            })
+            .collect();
+        if doomed.is_empty() {
+            return Ok(0);
+        }
+        self.engine
+            .delete_many(&table, &doomed)
+            .map_err(convert_db_error)?;
+        self.persist()?;
+        Ok(doomed.len())
+    }
+
+    /// The id the next insert into `table` will receive.
+    fn sequence(&self, table: String) -> PyResult<u64> {
+        self.check_open()?;
+        self.engine
+            .tables
+            .get(&table)
+            .map(|t| t.next_id)
+            .ok_or_else(|| convert_db_error(DbError::MissingTable(table)))
+    }
+
+    /// Moves `table`'s id sequence to `value`, or just past the highest existing id when
+    /// omitted. Values that would reissue an existing id are rejected. Returns the new value.
+    #[pyo3(signature = (table, value=None))]
+    fn reset_sequence(&mut self, table: String, value: Option<u64>) -> PyResult<u64> {
+        self.check_open()?;
+        self.check_writable()?;
+        let next = self
+            .engine
+            .table_mut(&table)
+            .and_then(|t| t.reset_sequence(value))
+            .map_err(convert_db_error)?;
+        self.persist()?;
+        Ok(next)
+    }
+
+    /// Field definitions plus table metadata as a plain dict.
+    fn schema(&self, py: Python<'_>, table: String) -> PyResult<PyObject> {
+        self.check_open()?;
+        let t = self
+            .engine
+            .tables
+            .get(&table)
+            .ok_or_else(|| PyKeyError::new_err(format!("table '{}' does not exist", table)))?;
+        json_to_py(py, &t.describe())
+    }
+
+    /// Sets a table metadata entry; passing `None` removes it.
+    fn set_table_meta(
+        &mut self,
+        table: String,
+        key: String,
+        value: Bound<'_, PyAny>,
+    ) -> PyResult<()> {
+        self.check_open()?;
+        self.check_writable()?;
+        let value = py_to_json(value)?;
+        self.engine
+            .table_mut(&table)
+            .and_then(|t| t.set_meta(&key, Some(value).filter(|v| !v.is_null())))
+            .map_err(convert_db_error)?;
+        self.persist()
+    }
+
+    #[pyo3(signature = (table, field, description=None))]
+    fn set_field_meta(
+        &mut self,
+        table: String,
+        field: String,
+        description: Option<String>,
+    ) -> PyResult<()> {
+        self.check_open()?;
+        self.check_writable()?;
+        let def = self
+            .engine
+            .table_mut(&table)
+            .and_then(|t| {
+                t.schema
+                    .get_mut(&field)
+                    .ok_or_else(|| DbError::UnknownField(field.clone()))
+            })
+            .map_err(convert_db_error)?;
+        def.description = description;
+        self.persist()
+    }
+
+    /// Maintains a value -> ids index on `field` so `where_eq` skips the full scan.
+    fn create_index(&mut self, table: String, field: String) -> PyResult<()> {
+        self.check_open()?;
+        self.check_writable()?;
+        self.engine
+            .table_mut(&table)
+            .and_then(|t| t.create_index(&field))
+            .map_err(convert_db_error)?;
+        self.persist()
+    }
+
+    #[pyo3(signature = (table, field, new_type, strict=true))]
+    fn change_field_type(
+        &mut self,
+        table: String,
+        field: String,
+        new_type: String,
+        strict: bool,
+    ) -> PyResult<Vec<u64>> {
+        self.check_open()?;
+        self.check_writable()?;
+        let ftype = FieldType::from_str(&new_type)
+            .ok_or_else(|| PyValueError::new_err(format!("unsupported field type {}", new_type)))?;
+        let nulled = self
+            .engine
+            .table_mut(&table)
+            .and_then(|t| t.change_field_type(&field, ftype, strict))
+            .map_err(convert_db_error)?;
+        self.persist()?;
+        Ok(nulled)
+    }
+
+    fn fetch_all(&self, py: Python<'_>, table: String) -> PyResult<Vec<Record>> {
+        self.fetch_records(py, table)
+    }
+
+    /// The record stored under `rid`; raises `KeyError` if the table or record is missing.
+    fn get(&self, py: Python<'_>, table: String, rid: u64) -> PyResult<Record> {
+        self.get_record(py, table, rid)
+    }
+
+    /// Like `get`, but a missing record gives `None`. A missing table still raises.
+    fn get_or_none(&self, py: Python<'_>, table: String, rid: u64) -> PyResult<Option<Record>> {
+        self.check_open()?;
+        self.lookup(py, &table, rid)
+    }
+
+    fn query(&self, py: Python<'_>, query: PyRef<'_, Query>) -> PyResult<Vec<Record>> {
+        self.query_records(py, query)
+    }
+
+    /// `query` as columns of lists, `{"id": [...], field: [...]}` with every schema field,
+    /// ready for `pandas.DataFrame(...)`.
+    fn query_columns(&self, py: Python<'_>, query: PyRef<'_, Query>) -> PyResult<PyObject> {
+        self.column_lists(py, query)
+    }
+
+    /// `query` as a `pyarrow.Table` with an `id` column and one column per schema field,
+    /// built column by column in Rust. Array and JSON fields arrive as JSON text.
+    #[cfg(feature = "arrow")]
+    fn query_arrow(&self, py: Python<'_>, query: PyRef<'_, Query>) -> PyResult<PyObject> {
+        self.query_table(py, query)
+    }
+
+    /// Adds `text` to the knowledge graph in chunks of at most `chunk_size` characters,
+    /// each opening with the last `chunk_overlap` sentences of the chunk before so context
+    /// that spans a boundary is found from either side. Either left out takes the graph's
+    /// default (500 characters, no overlap).
+    ///
+    /// A chunk already stored under the same source, with the same text, is skipped, so
+    /// ingesting a document twice adds nothing. Returns `{"new_chunks": n,
+    /// "duplicate_chunks": n}` (a remark outside professional mode).
+    #[pyo3(signature = (text, source=None, chunk_size=None, chunk_overlap=None))]
+    fn ingest(
+        &mut self,
+        py: Python<'_>,
+        text: String,
+        source: Option<String>,
+        chunk_size: Option<usize>,
+        chunk_overlap: Option<usize>,
+    ) -> PyResult<PyObject> {
+        self.ingest_text(py, text, source, chunk_size, chunk_overlap)
+    }
+
+    /// The `top_k` best-matching chunks for `query` (every match for 0) that score at least
+    /// `min_score`, with the context of at most two communities it names. When no chunk
+    /// clears `min_score` the answer is that nothing relevant was found. `ranking` is
+    /// "tfidf" or "bm25", and defaults to what `set_graph_ranking` chose. `entity_types`
+    /// (e.g. `["PERSON", "ORG"]`) keeps only chunks mentioning an entity of those types.
+    #[pyo3(signature = (query, top_k=DEFAULT_TOP_K, min_score=0.0, ranking=None, entity_types=None))]
+    fn graph_query(
+        &mut self,
+        query: String,
+        top_k: usize,
+        min_score: f32,
+        ranking: Option<&str>,
+        entity_types: Option<Vec<String>>,
+    ) -> PyResult<String> {
+        let types: Vec<String> = entity_types
+            .unwrap_or_default()
+            .iter()
+            .map(|t| t.trim().to_uppercase())
+            .collect();
+        ranking
+            .map(ranking_arg)
+            .transpose()
+            .and_then(|ranking| self.graph_search(query, top_k, min_score, ranking, &types))
+    }
+
+    /// How `graph_query` ranks chunks when not told: "tfidf" or "bm25", with BM25's `k1`
+    /// (how soon repeated terms stop adding, at least 0) and `b` (how much chunk length
+    /// counts, 0 to 1). Saved with the graph.
+    #[pyo3(signature = (ranking, k1=None, b=None))]
+    fn set_graph_ranking(
+        &mut self,
+        ranking: &str,
+        k1: Option<f32>,
+        b: Option<f32>,
+    ) -> PyResult<()> {
+        self.check_open()?;
+        self.check_writable()?;
+        let ranking = ranking_arg(ranking)?;
+        if k1.is_some_and(|k1| !(k1 >= 0.0 && k1.is_finite())) {
+            return Err(PyValueError::new_err("k1 must be a number of at least 0"));
+        }
+        if b.is_some_and(|b| !(0.0..=1.0).contains(&b)) {
+            return Err(PyValueError::new_err("b must be between 0 and 1"));
+        }
+        let graph = self.graph()?;
+        graph.ranking = ranking;
+        graph.bm25_k1 = k1.unwrap_or(graph.bm25_k1);
+        graph.bm25_b = b.unwrap_or(graph.bm25_b);
+        self.graph_dirty = true;
+        self.persist()
+    }
+
+    /// How long community summaries are: the `top_entities` most connected entities they
+    /// name (at least 1) and at most `sentences` sentences they quote from the chunks those
+    /// entities share. Summaries are rewritten at once and saved with the graph.
+    #[pyo3(signature = (top_entities=None, sentences=None))]
+    fn set_graph_summary(
+        &mut self,
+        top_entities: Option<usize>,
+        sentences: Option<usize>,
+    ) -> PyResult<()> {
+        self.check_open()?;
+        self.check_writable()?;
+        if top_entities == Some(0) {
+            return Err(PyValueError::new_err("top_entities must be at least 1"));
+        }
+        let graph = self.graph()?;
+        graph.summary_entities = top_entities.unwrap_or(graph.summary_entities);
+        graph.summary_sentences = sentences.unwrap_or(graph.summary_sentences);
+        graph.summarize_communities();
+        self.graph_dirty = true;
+        self.persist()
+    }
+
+    /// Writes the entity graph to `dest` for Gephi ("graphml") or Graphviz ("dot"):
+    /// entities as nodes with `entity_type` and `mentions`, relations as undirected edges
+    /// with `relation_type` and `weight`. Entities mentioned fewer than `min_mentions` times
+    /// are left out along with their relations.
+    #[pyo3(signature = (dest, format="graphml", min_mentions=1))]
+    fn export_graph(&mut self, dest: String, format: &str, min_mentions: usize) -> PyResult<()> {
+        self.check_open()?;
+        let format = GraphFormat::from_str(format).ok_or_else(|| {
+            PyValueError::new_err(format!(
+                "format must be 'graphml' or 'dot', not '{}'",
+                format
+            ))
+        })?;
+        let output_path = sanitize_user_path(&dest)?;
+        let graph = self.graph()?;
+        let text = match format {
+            GraphFormat::GraphMl => graph.export_graphml(min_mentions),
+            GraphFormat::Dot => graph.export_dot(min_mentions),
+        };
+        fs::write(output_path, text).map_err(|e| PyIOError::new_err(e.to_string()))
+    }
+
+    /// What has been ingested: for each source, sorted by name, `{"source", "chunks",
+    /// "words", "ingested_at", "top_entities"}`. `ingested_at` is when its latest chunk was
+    /// ingested as an RFC 3339 string, or None for imported chunks and those from before it
+    /// was recorded; `top_entities` lists up to five entities by how many of its chunks
+    /// mention them. GRAPH SOURCES returns the same.
+    fn graph_sources(&mut self, py: Python<'_>) -> PyResult<PyObject> {
+        self.check_open()?;
+        let sources = self.graph()?.sources();
+        let sources = sources.into_iter().map(source_json).collect();
+        json_to_py(py, &Value::Array(sources))
+    }
+
+    /// The graph in numbers, for keeping an eye on what ingests produce: `chunks`,
+    /// `indexed_words` (across chunks, stopwords left out) and `vocabulary` (distinct words)
+    /// of the search index, `entities` with `entity_types` counting them by type,
+    /// `relations` and their summed `relation_weight`, `communities` with the `min`,
+    /// `median` and `max` of `community_sizes` (None without communities), and the ten
+    /// `top_entities` by mentions as `{"name", "mentions"}`. Counts what is already there
+    /// without rebuilding anything; `stats()["graph"]` and STATS report the same.
+    fn graph_stats(&mut self, py: Python<'_>) -> PyResult<PyObject> {
+        self.check_open()?;
+        json_to_py(py, &graph_stats_json(self.graph()?.stats()))
+    }
+
+    /// Everything the graph knows about one entity, named in any case: `{"name", "type",
+    /// "mentions", "related", "chunks"}`, where `related` lists its neighbors as GRAPH
+    /// NEIGHBORS does and `chunks` the sorted ids of the chunks mentioning it. An unknown
+    /// entity is a KeyError.
+    fn graph_entity(&mut self, py: Python<'_>, name: &str) -> PyResult<PyObject> {
+        self.check_open()?;
+        let graph = self.graph()?;
+        let entity = graph
+            .entity_name(name)
+            .and_then(|n| graph.data.entities.get(n))
+            .ok_or_else(|| unknown_entity(graph, name))?;
+        let related = graph.neighbors(&entity.name).unwrap_or_default();
+        let mut out = entity_json(entity);
+        out["related"] = related.into_iter().map(neighbor_json).collect();
+        out["chunks"] = graph.entity_chunk_ids(&entity.name).into();
+        json_to_py(py, &out)
+    }
+
+    /// Entities for autocomplete: those whose name or one of its words starts with
+    /// `pattern`, or that match it as a LIKE pattern when it holds `%` or `_`, ignoring
+    /// case. Up to `limit` (every match for 0), by mentions, as GRAPH ENTITIES lists them.
+    #[pyo3(signature = (pattern, limit=20))]
+    fn graph_search_entities(
+        &mut self,
+        py: Python<'_>,
+        pattern: &str,
+        limit: usize,
+    ) -> PyResult<PyObject> {
+        self.check_open()?;
+        let found = self.graph()?.search_entities(pattern, limit);
+        let found = found.into_iter().map(entity_json).collect();
+        json_to_py(py, &Value::Array(found))
+    }
+
+    /// Entities within `depth` hops of `entity` (named in any case), as `{hop: [{"entity",
+    /// "via", "weight"}, ...]}` from hop 1 on: `via` is the entity one hop nearer it was
+    /// reached from and `weight` the summed weight of their relations, heaviest first.
+    /// Links lighter than `min_weight` are not followed. An unknown entity is a KeyError.
+    #[pyo3(signature = (entity, depth=1, min_weight=0.0))]
+    fn graph_neighbors(
+        &mut self,
+        py: Python<'_>,
+        entity: &str,
+        depth: usize,
+        min_weight: f32,
+    ) -> PyResult<PyObject> {
+        self.check_open()?;
+        if depth == 0 {
+            return Err(PyValueError::new_err("depth must be at least 1"));
+        }
+        let graph = self.graph()?;
+        let hops = graph
+            .neighbors_within(entity, depth, min_weight)
+            .ok_or_else(|| unknown_entity(graph, entity))?;
+        let out = PyDict::new_bound(py);
+        for (i, reached) in hops.into_iter().enumerate() {
+            let reached = reached
+                .into_iter()
+                .map(|r| serde_json::json!({"entity": r.entity, "via": r.via, "weight": r.weight}))
+                .collect();
+            out.set_item(i + 1, json_to_py(py, &Value::Array(reached))?)?;
+        }
+        Ok(out.into_py(py))
+    }
+
+    /// How `entity_a` is connected to `entity_b`: the path of at most `max_hops` links that
+    /// favours the strongest relations (a link costs the inverse of its weight), as
+    /// `(source, relation_type, target)` triples, typed relations in their own direction.
+    /// `[]` from an entity to itself and None when no such path exists; an unknown entity
+    /// is a KeyError.
+    #[pyo3(signature = (entity_a, entity_b, max_hops=6))]
+    fn graph_path(
+        &mut self,
+        entity_a: &str,
+        entity_b: &str,
+        max_hops: usize,
+    ) -> PyResult<Option<Vec<(String, String, String)>>> {
+        self.check_open()?;
+        if max_hops == 0 {
+            return Err(PyValueError::new_err("max_hops must be at least 1"));
+        }
+        let graph = self.graph()?;
+        for name in [entity_a, entity_b] {
+            if graph.entity_name(name).is_none() {
+                return Err(unknown_entity(graph, name));
+            }
+        }
+        Ok(graph.shortest_path(entity_a, entity_b, max_hops))
+    }
+
+    /// Replaces what was ingested from `source` with `text` in one step: chunks of the old
+    /// text that the new one lacks are forgotten as `graph_forget` would, new ones are
+    /// ingested, and the search index and communities are rebuilt once. Nothing changes if
+    /// it fails. Returns `{"removed_chunks", "added_chunks", "unchanged_chunks",
+    /// "new_entities", "dropped_entities"}`, the last two as sorted entity names.
+    #[pyo3(signature = (text, source, chunk_size=None, chunk_overlap=None))]
+    fn graph_reingest(
+        &mut self,
+        py: Python<'_>,
+        text: String,
+        source: String,
+        chunk_size: Option<usize>,
+        chunk_overlap: Option<usize>,
+    ) -> PyResult<PyObject> {
+        self.check_open()?;
+        self.check_writable()?;
+        check_ingest(&text, chunk_size)?;
+        let mut graph = self.graph()?.clone();
+        let chunk_size = chunk_size.unwrap_or(graph.chunk_size);
+        let chunk_overlap = chunk_overlap.unwrap_or(graph.chunk_overlap);
+        let ingested_at = now_millis();
+        let counts = graph.reingest_with(&text, &source, chunk_size, chunk_overlap, ingested_at);
+        let retyped = self.classify_entities(py, &mut graph, &counts.new_entities)?;
+        if counts.removed_chunks > 0 || counts.added_chunks > 0 {
+            self.engine.graph_rag = graph;
+            self.graph_dirty = true;
+            if self.journal && !retyped {
+                self.pending_entry = Some(journal::Entry::Reingest {
+                    text,
+                    source,
+                    chunk_size,
+                    chunk_overlap,
+                    ingested_at,
+                });
+            }
+            self.persist()?;
+        }
+        let out = PyDict::new_bound(py);
+        out.set_item("removed_chunks", counts.removed_chunks)?;
+        out.set_item("added_chunks", counts.added_chunks)?;
+        out.set_item("unchanged_chunks", counts.unchanged_chunks)?;
+        out.set_item("new_entities", counts.new_entities)?;
+        out.set_item("dropped_entities", counts.dropped_entities)?;
+        Ok(out.into_py(py))
+    }
+
+    /// Words for the graph to ignore on top of its built-in English stopwords, replacing
+    /// any set before, in any case. They leave the search index at once; entities already
+    /// extracted stay, and later ingests no longer take them for names.
+    fn graph_set_stopwords(&mut self, words: Vec<String>) -> PyResult<()> {
+        self.check_open()?;
+        self.check_writable()?;
+        let graph = self.graph()?;
+        graph.extra_stopwords = words
+            .iter()
+            .map(|w| w.trim().to_lowercase())
+            .filter(|w| !w.is_empty())
+            .collect();
+        graph.rebuild_index();
+        self.graph_dirty = true;
+        self.persist()
+    }
+
+    /// Decides the type of each entity an ingest adds to the graph: `callback(name,
+    /// suggested_type)` gets the type the built-in heuristics chose (PERSON, ORG, DATE,
+    /// NUMBER or CONCEPT) and returns the type to store, or None to keep it. Types are kept
+    /// in upper case. `None` goes back to the heuristics alone. The callback is not saved.
+    #[pyo3(signature = (callback=None))]
+    fn graph_set_entity_classifier(&mut self, callback: Option<Bound<'_, PyAny>>) -> PyResult<()> {
+        self.check_open()?;
+        if callback.as_ref().is_some_and(|c| !c.is_callable()) {
+            return Err(PyTypeError::new_err(
+                "the entity classifier must be callable as callback(name, suggested_type)",
+            ));
+        }
+        self.entity_classifier = callback.map(Bound::unbind);
+        Ok(())
+    }
+
+    /// Removes the chunks ingested from `source`, the entities only they mentioned and the
+    /// relations they produced, then rebuilds the search index and communities. Returns
+    /// `{"chunks": n, "entities": n, "relations": n}` removed; an unknown source is a
+    /// KeyError.
+    fn graph_forget(&mut self, py: Python<'_>, source: String) -> PyResult<PyObject> {
+        self.check_open()?;
+        self.check_writable()?;
+        let removed = self.forget_graph_source(&source)?;
+        let out = PyDict::new_bound(py);
+        out.set_item("chunks", removed.chunks)?;
+        out.set_item("entities", removed.entities)?;
+        out.set_item("relations", removed.relations)?;
+        Ok(out.into_py(py))
+    }
+
+    /// Writes the graph to `dest` as JSON that `import_graph` reads back: `{"chunks": [...],
+    /// "entities": [...], "relations": [...]}`, so it can be backed up apart from the tables.
+    fn export_graph_json(&mut self, dest: String) -> PyResult<()> {
+        self.check_open()?;
+        let output_path = sanitize_user_path(&dest)?;
+        let json = serde_json::to_vec_pretty(&self.graph()?.dump())
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        fs::write(output_path, json).map_err(|e| PyIOError::new_err(e.to_string()))
+    }
+
+    /// Merges entities and relations extracted elsewhere into the graph, then rebuilds the
+    /// search index and communities. `source` is a JSON file in `export_graph_json`'s shape
+    /// or the same as a dict; `chunks` are `{"id", "text", "source"}`, `entities` are
+    /// `{"name", "entity_type", "mentions"}` and `relations` are `{"source", "target",
+    /// "relation_type", "weight"}`, with types, mentions, weights and chunk sources
+    /// optional. Mentions of known entities add up; relations are appended, or with
+    /// `merge_relations` add their weight to the same (source, target, type) relation. A
+    /// relation naming an unknown entity fails the import unless `auto_create_entities`.
+    /// Returns `{"chunks": n, "entities": n, "relations": n, "created": [...]}`.
+    #[pyo3(signature = (source, auto_create_entities=false, merge_relations=false))]
+    fn import_graph(
+        &mut self,
+        py: Python<'_>,
+        source: Bound<'_, PyAny>,
+        auto_create_entities: bool,
+        merge_relations: bool,
+    ) -> PyResult<PyObject> {
+        self.check_open()?;
+        self.check_writable()?;
+        let invalid = |e: serde_json::Error| PyValueError::new_err(format!("invalid graph: {}", e));
+        let dump: GraphDump = match source.extract::<String>() {
+            Ok(src) => {
+                let source_path = sanitize_user_path(&src)?;
+                let metadata =
+                    fs::metadata(&source_path).map_err(|e| PyIOError::new_err(e.to_string()))?;
+                if metadata.len() > MAX_GRAPH_IMPORT_BYTES {
+                    return Err(PyValueError::new_err(format!(
+                        "graph import exceeds max file size of {} bytes",
+                        MAX_GRAPH_IMPORT_BYTES
+                    )));
+                }
+                let bytes = fs::read(source_path).map_err(|e| PyIOError::new_err(e.to_string()))?;
+                serde_json::from_slice(&bytes).map_err(invalid)?
+            }
+            Err(_) => serde_json::from_value(py_to_json(source)?).map_err(invalid)?,
+        };
+        let summary = self
+            .graph()?
+            .import(dump, auto_create_entities, merge_relations)
+            .map_err(PyValueError::new_err)?;
+        self.graph_dirty = true;
+        self.persist()?;
+        let summary = serde_json::json!({
+            "chunks": summary.chunks,
+            "entities": summary.entities,
+            "relations": summary.relations,
+            "created": summary.created,
+        });
+        json_to_py(py, &summary)
+    }
+
+    /// Runs `query` and renders its rows as text: an aligned ASCII "table", a "markdown"
+    /// table, or pretty "json". Tables put `id` first and then the fields in schema order
+    /// (or `select` order); cells longer than `max_width` characters end in an ellipsis.
+    #[pyo3(signature = (query, style="table", max_width=None))]
+    fn format_results(
+        &self,
+        query: PyRef<'_, Query>,
+        style: &str,
+        max_width: Option<usize>,
+    ) -> PyResult<String> {
+        ResultStyle::from_str(style)
+            .ok_or_else(|| {
+                PyValueError::new_err(format!(
+                    "style must be 'table', 'markdown' or 'json', not '{}'",
+                    style
+                ))
+            })
+            .and_then(|style| self.render_results(&query, style, max_width))
+    }
+
+    fn execute_sql(&mut self, py: Python<'_>, sql: String) -> PyResult<PyObject> {
+        self.check_open()?;
+        let out = self.execute_sql_recursive(py, sql, 0)?;
+        if let Some(whisper) = self.engine.alive.ambient(self.personality.mode()) {
+            if let Ok(s) = out.extract::<String>(py) {
+                return Ok(format!("{}\n  {}", s, whisper).into_py(py));
+            }
+        }
+        Ok(out)
+    }
+
+    /// Runs each `;`-separated statement of `script` through `execute_sql`, skipping `--`
+    /// comments, and returns their results in order. A failing statement stops the script;
+    /// the ones before it stay applied.
+    fn execute_script(&mut self, py: Python<'_>, script: String) -> PyResult<Vec<PyObject>> {
+        self.check_open()?;
+        sql::split_script(&script)
+            .into_iter()
+            .map(|statement| self.execute_sql(py, statement))
+            .collect()
+    }
+
+    fn execute_sql_recursive(
+        &mut self,
+        py: Python<'_>,
+        sql: String,
+        depth: usize,
+    ) -> PyResult<PyObject> {
+        if depth > MAX_RECURSION_DEPTH {
+            return Err(PyRuntimeError::new_err(
+                "Max alias recursion depth exceeded",
+            ));
+        }
+        if sql.len() > MAX_COMMAND_LENGTH {
+            return Err(PyValueError::new_err(format!(
+                "Command exceeds max length of {} bytes",
+                MAX_COMMAND_LENGTH
+            )));
+        }
+        let head = sql
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_ascii_uppercase();
+        // Batch replay runs deeper, so queued commands are recorded once, as typed.
+        if depth == 0 {
+            self.record_command(&sql);
+        }
+        let batch_control = ["BATCH", "COMMIT", "ROLLBACK", "SAVEPOINT", "RELEASE"];
+        if self.batch_mode && !batch_control.contains(&head.as_str()) {
+            if self.batch_ops.len() >= MAX_BATCH_OPS {
+                return Err(PyValueError::new_err(format!(
+                    "Batch operation limit exceeded (max {})",
+                    MAX_BATCH_OPS
+                )));
+            }
+            self.batch_ops.push(sql.clone());
+            return Ok("".into_py(py));
+        }
+
+        let toks: Vec<&str> = sql.split_whitespace().collect();
+        if depth == 0 && !toks.is_empty() {
+            self.engine.alive.on_command();
+        }
+        if toks.is_empty() {
+            let empty_count = self
+                .history()
+                .iter()
+                .filter(|s| s.trim().is_empty())
+                .count() as u32;
+            return Ok(self.personality.empty_input(empty_count).into_py(py));
+        }
+
+        // Only registered commands dispatch, so `HELP` cannot drift from what runs here.
+        match commands::find(toks[0]).map_or("", |c| c.name) {
+            "INGEST" => {
+                if toks.len() < 2 {
+                    return Err(PyValueError::new_err("INGEST requires text"));
+                }
+                let text = toks[1..].join(" ");
+                self.ingest_text(py, text, None, None, None)
+            }
+            "GRAPH_QUERY" => {
+                if toks.len() < 2 {
+                    return Err(PyValueError::new_err("GRAPH_QUERY requires a query"));
+                }
+                let q = toks[1..].join(" ");
+                self.graph_search(q, DEFAULT_TOP_K, 0.0, None, &[])
+                    .map(|s| s.into_py(py))
+            }
+            "GRAPH" => self.graph_command(py, &toks[1..]),
+            "SHOW" | "TABLES" => {
+                if toks[1..].iter().any(|t| t.eq_ignore_ascii_case("FULL")) {
+                    let now = now_millis();
+                    let mut names: Vec<&String> = self.engine.tables.keys().collect();
+                    names.sort();
+                    let tables: Vec<Value> = names
+                        .into_iter()
+                        .map(|name| self.engine.tables[name].overview(name, now))
+                        .collect();
+                    return json_to_py(py, &Value::Array(tables));
+                }
+                Ok(self
+                    .engine
+                    .tables
+                    .keys()
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .into_py(py))
+            }
+            "COUNT" => {
+                if toks.len() < 2 {
+                    return Err(PyValueError::new_err("COUNT requires a table name"));
+                }
+                self.run_statement(py, &sql, depth)
+            }
+            "TRUNCATE" => {
+                if toks.len() < 2 {
+                    return Err(PyValueError::new_err("TRUNCATE requires a table name"));
+                }
+                self.check_writable()?;
+                let reset_ids = toks.get(2).is_some_and(|t| t.eq_ignore_ascii_case("RESET"));
+                let table = sql::table_ref(toks[1]).map_err(PyValueError::new_err)?;
+                let table = self.resolve_table(&table)?;
+                let removed = self
+                    .engine
+                    .truncate_table(&table, reset_ids)
+                    .map_err(convert_db_error)?;
+                self.persist()?;
+                Ok(self
+                    .personality
+                    .table_truncated(&table, removed)
+                    .into_py(py))
+            }
+            "DESCRIBE" => {
+                if toks.len() < 2 {
+                    return Err(PyValueError::new_err("DESCRIBE requires a table name"));
+                }
+                let table = sql::table_ref(toks[1]).map_err(PyValueError::new_err)?;
+                let table = &self.engine.tables[&self.resolve_table(&table)?];
+                if toks.get(2).is_some_and(|t| t.eq_ignore_ascii_case("FULL")) {
+                    return json_to_py(py, &table.describe());
+                }
+                let mut fields = table.schema.keys().cloned().collect::<Vec<_>>();
+                fields.sort();
+                Ok(fields.into_py(py))
+            }
+            "SELECT" | "INSERT" | "UPDATE" | "DELETE" | "CREATE" => {
+                self.run_statement(py, &sql, depth)
+            }
+            "PRINT" => {
+                let usage = "PRINT format: PRINT [TABLE | MARKDOWN | JSON] SELECT ...";
+                let rest = sql.trim_start()[toks[0].len()..].trim_start();
+                let (style, statement) = match toks.get(1).and_then(|w| ResultStyle::from_str(w)) {
+                    Some(style) => (style, rest[toks[1].len()..].trim_start()),
+                    None => (ResultStyle::Table, rest),
+                };
+                let sql::Statement::Select(select) =
+                    sql::parse(statement).map_err(PyValueError::new_err)?
+                else {
+                    return Err(PyValueError::new_err(usage));
+                };
+                let query = self.select_query(select)?;
+                self.render_results(&query, style, None)
+                    .map(|text| text.into_py(py))
+            }
+            "VERIFY" => {
+                let recover = toks
+                    .get(1)
+                    .is_some_and(|t| t.eq_ignore_ascii_case("RECOVER"));
+                self.verify(py, None, recover)
+            }
+            "HISTORY" => {
+                let usage = "HISTORY format: HISTORY [<n>] or HISTORY SEARCH <term>";
+                let (count, term) = match toks[1..] {
+                    [] => (10, None),
+                    [n] => (n.parse().map_err(|_| PyValueError::new_err(usage))?, None),
+                    [search, ..] if search.eq_ignore_ascii_case("SEARCH") && toks.len() > 2 => {
+                        (usize::MAX, Some(toks[2..].join(" ").to_lowercase()))
+                    }
+                    _ => return Err(PyValueError::new_err(usage)),
+                };
+                let recent = self
+                    .history()
+                    .iter()
+                    .rev()
+                    .filter(|cmd| {
+                        !cmd.trim().is_empty() && !cmd.to_uppercase().starts_with("HISTORY")
+                    })
+                    .filter(|cmd| term.as_ref().is_none_or(|t| cmd.to_lowercase().contains(t)))
+                    .take(count)
+                    .cloned()
+                    .collect::<Vec<_>>();
+                Ok(recent.into_py(py))
+            }
+            "CLEAR" => {
+                if toks.len() != 2 || !toks[1].eq_ignore_ascii_case("HISTORY") {
+                    return Err(PyValueError::new_err("CLEAR format: CLEAR HISTORY"));
+                }
+                self.command_history.clear();
+                if self.persist_history {
+                    self.engine.history.clear();
+                    self.persist()?;
+                }
+                Ok("History cleared.".into_py(py))
+            }
+            "BATCH" => {
+                if self.batch_mode {
+                    return Err(PyValueError::new_err(format!(
+                        "already in a batch with {} queued operation(s); COMMIT or ROLLBACK it first",
+                        self.batch_ops.len()
+                    )));
+                }
+                self.batch_mode = true;
+                self.batch_ops.clear();
+                self.savepoints.clear();
+                Ok("Batch mode started.".into_py(py))
+            }
+            "COMMIT" => {
+                self.batch_mode = false;
+                self.savepoints.clear();
+                let ops: Vec<_> = self.batch_ops.drain(..).collect();
+                // Replay as a transaction: persists are deferred, and a failure puts the
+                // engine back as it was before the first operation.
+                let outer = self.tx_snapshot.take();
+                let (dirty, queued) = (self.dirty, self.queued);
+                self.tx_snapshot = Some(Box::new(self.engine.clone()));
+                let failed = ops.iter().enumerate().find_map(|(i, operation)| {
+                    self.execute_sql_recursive(py, operation.clone(), depth + 1)
+                        .err()
+                        .map(|e| (i, e))
+                });
+                let snapshot = std::mem::replace(&mut self.tx_snapshot, outer);
+                if let (Some((i, e)), Some(snapshot)) = (failed, snapshot) {
+                    self.restore_engine(*snapshot);
+                    (self.dirty, self.queued) = (dirty, queued);
+                    return Err(PyErr::from_type_bound(
+                        e.get_type_bound(py),
+                        format!(
+                            "batch operation {} of {} failed, nothing was applied: `{}`: {}",
+                            i + 1,
+                            ops.len(),
+                            ops[i],
+                            e.value_bound(py)
+                        ),
+                    ));
+                }
+                self.persist()?;
+                Ok(self.personality.batch_committed(ops.len()).into_py(py))
+            }
+            "ROLLBACK" => {
+                if !self.batch_mode {
+                    return Err(PyValueError::new_err(
+                        "ROLLBACK outside a batch; start one with BATCH",
+                    ));
+                }
+                if toks.len() > 1 {
+                    if toks.len() != 3 || !toks[1].eq_ignore_ascii_case("TO") {
+                        return Err(PyValueError::new_err("ROLLBACK format: ROLLBACK TO <name>"));
+                    }
+                    // The savepoint itself stays, so the batch can roll back to it again.
+                    let k = self.savepoint_index(toks[2])?;
+                    let kept = self.savepoints[k].1;
+                    let discarded = self.batch_ops.len() - kept;
+                    self.batch_ops.truncate(kept);
+                    self.savepoints.truncate(k + 1);
+                    return Ok(format!(
+                        "Rolled back to savepoint '{}': {} operation(s) discarded.",
+                        self.savepoints[k].0, discarded
+                    )
+                    .into_py(py));
+                }
+                self.batch_mode = false;
+                self.savepoints.clear();
+                let discarded = self.batch_ops.len();
+                self.batch_ops.clear();
+                Ok(self.personality.batch_rolled_back(discarded).into_py(py))
+            }
+            "SAVEPOINT" => {
+                if !self.batch_mode {
+                    return Err(PyValueError::new_err(
+                        "SAVEPOINT outside a batch; start one with BATCH",
+                    ));
+                }
+                if toks.len() != 2 {
+                    return Err(PyValueError::new_err("SAVEPOINT format: SAVEPOINT <name>"));
+                }
+                let name = toks[1].to_ascii_lowercase();
+                validate_identifier(&name).map_err(convert_db_error)?;
+                if self.savepoints.iter().any(|(n, _)| *n == name) {
+                    return Err(PyValueError::new_err(format!(
+                        "savepoint '{}' already exists in this batch",
+                        name
+                    )));
+                }
+                let queued = self.batch_ops.len();
+                self.savepoints.push((name.clone(), queued));
+                Ok(format!("Savepoint '{}' set after {} operation(s).", name, queued).into_py(py))
+            }
+            "RELEASE" => {
+                if !self.batch_mode {
+                    return Err(PyValueError::new_err(
+                        "RELEASE outside a batch; start one with BATCH",
+                    ));
+                }
+                if toks.len() != 2 {
+                    return Err(PyValueError::new_err("RELEASE format: RELEASE <name>"));
+                }
+                // Queued operations stay; only the savepoint and any set after it go.
+                let k = self.savepoint_index(toks[1])?;
+                let released = self.savepoints.len() - k;
+                let name = self.savepoints[k].0.clone();
+                self.savepoints.truncate(k);
+                Ok(format!("Released savepoint '{}' ({} in total).", name, released).into_py(py))
+            }
+            "HELP" => {
+                let Some(word) = toks.get(1) else {
+                    let intro = self.personality.help_intro();
+                    return Ok(format!("{}\n\n{}", intro, commands::listing()).into_py(py));
+                };
+                match commands::find(word) {
+                    Some(command) => Ok(commands::usage(command).into_py(py)),
+                    None => Err(PyKeyError::new_err(match commands::closest(word) {
+                        Some(near) => format!("no command `{}`; did you mean {}?", word, near.name),
+                        None => format!("no command `{}`; HELP lists them all", word),
+                    })),
+                }
+            }
+            "ALIAS" => {
+                if toks.len() < 4 || toks[2] != "=" {
+                    return Err(PyValueError::new_err(
+                        "ALIAS format: ALIAS <name> = <command>",
+                    ));
+                }
+                self.check_writable()?;
+                let alias_name = toks[1].to_ascii_lowercase();
+                validate_identifier(&alias_name).map_err(convert_db_error)?;
+                self.engine.aliases.insert(alias_name, toks[3..].join(" "));
+                self.persist()?;
+                Ok("Alias created.".into_py(py))
+            }
+            "ALIASES" => self.list_aliases(py),
+            "STATS" => {
+                let report = self.stats_report()?;
+                let text = render_stats(&report);
+                let records = report["total_records"].as_u64().unwrap_or_default();
+                Ok(match self.personality.stats_quip(records) {
+                    Some(quip) => format!("{}\n\n{}", quip, text),
+                    None => text,
+                }
+                .into_py(py))
+            }
+            "UNALIAS" => {
+                if toks.len() != 2 {
+                    return Err(PyValueError::new_err("UNALIAS format: UNALIAS <name>"));
+                }
+                self.remove_alias(toks[1].to_string())?;
+                Ok(format!("Alias '{}' removed.", toks[1].to_ascii_lowercase()).into_py(py))
+            }
+            "WHY" => {
+                let asked = toks.len() >= 5
+                    && toks[1..4]
+                        .iter()
+                        .zip(["ARE", "YOU", "SO"])
+                        .all(|(t, w)| t.eq_ignore_ascii_case(w));
+                if !asked {
+                    return Err(PyValueError::new_err("WHY format: WHY ARE YOU SO <word>"));
+                }
+                Ok(self.personality.why_mean().into_py(py))
+            }
+            "ACHIEVEMENT" => Ok(self.personality.achievement_unlocked().into_py(py)),
+            "PULSE" => {
+                self.engine.alive.on_success();
+                Ok(self.engine.alive.pulse(self.personality.mode()).into_py(py))
+            }
+            "MOOD" => {
+                self.engine.alive.on_success();
+                Ok(format!(
+                    "{} (score {})",
+                    self.engine.alive.mood_label(),
+                    self.engine.alive.mood_score
+                )
+                .into_py(py))
+            }
+            "VITALS" => {
+                self.engine.alive.on_success();
+                Ok(self.engine.alive.vitals_json().into_py(py))
+            }
+            _ => {
+                if let Some(translated) = self.engine.aliases.get(&toks[0].to_ascii_lowercase()) {
+                    return self.execute_sql_recursive(py, translated.clone(), depth + 1);
+                }
+                if depth == 0 {
+                    self.engine.alive.on_error();
+                }
+                let mut aliases: Vec<&str> =
+                    self.engine.aliases.keys().map(String::as_str).collect();
+                aliases.sort_unstable();
+                let known = commands::COMMANDS.iter().map(|c| c.name).chain(aliases);
+                if let Some(near) = commands::nearest(toks[0], known) {
+                    return Err(PyValueError::new_err(
+                        self.personality.typo_suggestion(toks[0], near),
+                    ));
+                }
+                Err(PyRuntimeError::new_err(
+                    self.personality.error("unknown command"),
+                ))
+            }
+        }
+    }
+
+    /// Writes one JSON object per record, with its `id`. `table` is a table name, for every
+    /// record, or a `Query`, for the rows and `select` fields `query` would return. The file
+    /// is gzipped when `compression` is "gzip", or by default when `dest` ends in `.gz`.
+    #[pyo3(signature = (table, dest, compression=None))]
+    fn export_jsonl(
+        &self,
+        py: Python<'_>,
+        table: Bound<'_, PyAny>,
+        dest: String,
+        compression: Option<&str>,
+    ) -> PyResult<()> {
+        self.check_open()?;
+        let (rows, _) = self.export_rows(&table)?;
+        let gzip = gzip_arg(compression, dest.to_ascii_lowercase().ends_with(".gz"))?;
+        let output_path = sanitize_user_path(&dest)?;
+        let io_err = |e: std::io::Error| PyIOError::new_err(e.to_string());
+        let file = BufWriter::new(fs::File::create(output_path).map_err(io_err)?);
+        // Other Python threads run while the rows are written; `self` stays borrowed, so
+        // they cannot change the table underneath.
+        py.allow_threads(|| {
+            let mut file = match gzip {
+                true => write_jsonl(GzEncoder::new(file, GzLevel::default()), rows)?
+                    .finish()
+                    .map_err(io_err)?,
+                false => write_jsonl(file, rows)?,
+            };
+            file.flush().map_err(io_err)
+        })
+    }
+    /// Inserts one JSON object per line as a single unit; see `import_csv` for `on_conflict`,
+    /// `field_map`, `transforms`, `preserve_ids` and the report returned. Computed fields in
+    /// a row are ignored, and so is `id` unless `preserve_ids` is set. Gzipped files are
+    /// recognized by their content unless `compression` ("gzip" or "none") says otherwise.
+    #[pyo3(signature = (
+        table,
+        src,
+        on_conflict="error",
+        compression=None,
+        field_map=None,
+        unmapped="drop",
+        transforms=None,
+        preserve_ids=false
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn import_jsonl(
+        &mut self,
+        py: Python<'_>,
+        table: String,
+        src: String,
+        on_conflict: &str,
+        compression: Option<&str>,
+        field_map: Option<Bound<'_, PyDict>>,
+        unmapped: &str,
+        transforms: Option<Bound<'_, PyDict>>,
+        preserve_ids: bool,
+    ) -> PyResult<PyObject> {
+        self.check_open()?;
+        self.check_writable()?;
+        let on_conflict = on_conflict_arg(on_conflict)?;
+        let source_path = sanitize_user_path(&src)?;
+        let io_err = |e: std::io::Error| PyIOError::new_err(e.to_string());
+        let mut file = BufReader::new(fs::File::open(source_path).map_err(io_err)?);
+        let magic = file.fill_buf().map_err(io_err)?.starts_with(&GZIP_MAGIC);
+        let source: Box<dyn Read> = match gzip_arg(compression, magic)? {
+            true => Box::new(MultiGzDecoder::new(file)),
+            false => Box::new(file),
+        };
+        // The limit counts decompressed bytes, so a small gzip file cannot expand past it.
+        let mut reader = BufReader::new(source.take(MAX_JSONL_IMPORT_BYTES + 1));
+        let t = self
+            .engine
+            .tables
+            .get(&table)
+            .ok_or_else(|| PyKeyError::new_err("missing table"))?;
+        let mut mapping =
+            ImportMapping::new(&t.schema, field_map.as_ref(), unmapped, transforms.as_ref())?;
+        // Exported rows carry materialized computed fields; they are rebuilt on read.
+        let computed: Vec<String> = t.computed.keys().cloned().collect();
+        let mut rows = Vec::new();
+        for (n, line_result) in reader.by_ref().lines().enumerate() {
+            if rows.len() >= MAX_JSONL_IMPORT_LINES {
+                return Err(PyValueError::new_err(format!(
+                    "JSONL import exceeds max line count of {}",
+                    MAX_JSONL_IMPORT_LINES
+                )));
+            }
+            let line = line_result.map_err(|e| PyIOError::new_err(e.to_string()))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let payload = serde_json::from_str::<Map<String, Value>>(&line)
+                .map_err(|e| format!("invalid JSONL row: {}", e))
+                .and_then(|payload| match mapping.as_mut() {
+                    Some(mapping) => mapping.rename(payload),
+                    None => Ok(payload),
+                })
+                .and_then(|mut payload| {
+                    if !preserve_ids {
+                        payload.remove("id");
+                    }
+                    for field in &computed {
+                        payload.remove(field);
+                    }
+                    if let Some(mapping) = mapping.as_mut() {
+                        mapping.transform(&mut payload)?;
+                    }
+                    Ok(payload)
+                });
+            rows.push((n + 1, payload));
+        }
+        if reader.get_ref().limit() == 0 {
+            return Err(PyValueError::new_err(format!(
+                "JSONL import exceeds max file size of {} bytes",
+                MAX_JSONL_IMPORT_BYTES
+            )));
+        }
+        let report =
+            self.import_rows(py, &table, "JSONL", rows, on_conflict, false, preserve_ids)?;
+        match mapping {
+            Some(mapping) => mapping.report(py, report),
+            None => Ok(report),
+        }
+    }
+    /// Writes CSV: a header of `id` (unless `include_id` is false) and the fields, then one
+    /// row per record. `table` is a table name, for every record under its sorted schema
+    /// fields, or a `Query`, for the rows and `select` fields `query` would return. Null is
+    /// an empty cell; arrays and JSON values are written as JSON text.
+    #[pyo3(signature = (table, dest, delimiter=",", include_id=true))]
+    fn export_csv(
+        &self,
+        py: Python<'_>,
+        table: Bound<'_, PyAny>,
+        dest: String,
+        delimiter: &str,
+        include_id: bool,
+    ) -> PyResult<()> {
+        self.check_open()?;
+        let (rows, fields) = self.export_rows(&table)?;
+        let delimiter = csv_delimiter(delimiter)?;
+        let output_path = sanitize_user_path(&dest)?;
+        let file = fs::File::create(output_path).map_err(|e| PyIOError::new_err(e.to_string()))?;
+        // As in `export_jsonl`, rows are written one at a time without the GIL.
+        py.allow_threads(|| {
+            let mut out = csv::WriterBuilder::new()
+                .delimiter(delimiter)
+                .from_writer(file);
+            let csv_err = |e: csv::Error| PyIOError::new_err(e.to_string());
+            let header = include_id
+                .then_some("id")
+                .into_iter()
+                .chain(fields.iter().map(|f| f.as_str()));
+            out.write_record(header).map_err(csv_err)?;
+            for (id, m) in rows {
+                let cells = fields
+                    .iter()
+                    .map(|f| csv_cell(m.get(f).unwrap_or(&Value::Null)));
+                let row = include_id.then(|| id.to_string()).into_iter().chain(cells);
+                out.write_record(row).map_err(csv_err)?;
+            }
+            out.flush().map_err(|e| PyIOError::new_err(e.to_string()))
+        })
+    }
+
+    /// Inserts the rows of a CSV file as a single unit. With `has_header` the header names
+    /// the fields (`id` and computed columns are ignored); without it the columns follow the
+    /// sorted schema. Cells are parsed by field type and an empty cell is null.
+    ///
+    /// A row whose unique value is already taken fails the import with `on_conflict="error"`,
+    /// is left out with `"skip"`, and replaces the record holding the value with `"replace"`.
+    /// Any other failing row fails the import unless `skip_errors` is set, which leaves it
+    /// out. A failed import changes nothing. Returns `{"inserted": n, "replaced": n,
+    /// "skipped": [{"line": n, "error": ...}]}`.
+    ///
+    /// `field_map` renames header columns to fields, `{"e-mail": "email"}`; a column it does
+    /// not name keeps its name if that is a field and is otherwise dropped, or fails the
+    /// import with `unmapped="error"`. `transforms`, `{field: callable}`, rewrites each value
+    /// of a field before validation; a raising callable fails that row. With either, the
+    /// report adds `columns`: `{"mapped": {column: field}, "dropped": [...],
+    /// "transformed": [...]}`.
+    ///
+    /// `preserve_ids` stores each row under the id in its `id` column rather than the next
+    /// free one, so a table exported with its ids comes back with references intact; the
+    /// id counter moves past the highest. A row whose id is taken is handled per
+    /// `on_conflict` like a unique clash, `"replace"` overwriting the record with that id.
+    #[pyo3(signature = (
+        table,
+        src,
+        has_header=true,
+        delimiter=",",
+        skip_errors=false,
+        on_conflict="error",
+        field_map=None,
+        unmapped="drop",
+        transforms=None,
+        preserve_ids=false
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn import_csv(
+        &mut self,
+        py: Python<'_>,
+        table: String,
+        src: String,
+        has_header: bool,
+        delimiter: &str,
+        skip_errors: bool,
+        on_conflict: &str,
+        field_map: Option<Bound<'_, PyDict>>,
+        unmapped: &str,
+        transforms: Option<Bound<'_, PyDict>>,
+        preserve_ids: bool,
+    ) -> PyResult<PyObject> {
+        self.check_open()?;
+        self.check_writable()?;
+        let on_conflict = on_conflict_arg(on_conflict)?;
+        let source_path = sanitize_user_path(&src)?;
+        let metadata = fs::metadata(&source_path).map_err(|e| PyIOError::new_err(e.to_string()))?;
+        if metadata.len() > MAX_CSV_IMPORT_BYTES {
+            return Err(PyValueError::new_err(format!(
+                "CSV import exceeds max file size of {} bytes",
+                MAX_CSV_IMPORT_BYTES
+            )));
+        }
+        let t = self
+            .engine
+            .tables
+            .get(&table)
+            .ok_or_else(|| PyKeyError::new_err("missing table"))?;
+        if field_map.is_some() && !has_header {
+            return Err(PyValueError::new_err("field_map needs a CSV header"));
+        }
+        if preserve_ids && !has_header {
+            return Err(PyValueError::new_err("preserve_ids needs a CSV header"));
+        }
+        let mut mapping =
+            ImportMapping::new(&t.schema, field_map.as_ref(), unmapped, transforms.as_ref())?;
+        let mut reader = csv::ReaderBuilder::new()
+            .delimiter(csv_delimiter(delimiter)?)
+            .has_headers(has_header)
+            .flexible(true)
+            .from_path(source_path)
+            .map_err(|e| PyIOError::new_err(e.to_string()))?;
+        let csv_err = |e: csv::Error| PyValueError::new_err(format!("invalid CSV: {}", e));
+        // One slot per column: the field it fills, or None for a column that is skipped.
+        let columns: Vec<Option<(String, FieldType)>> = if has_header {
+            let header: Vec<String> = reader
+                .headers()
+                .map_err(csv_err)?
+                .iter()
+                .map(|name| name.trim().to_string())
+                .collect();
+            let names = match mapping.as_mut() {
+                Some(mapping) => mapping.targets(&header)?,
+                None => header.into_iter().map(Some).collect(),
+            };
+            let mut columns = Vec::with_capacity(names.len());
+            for name in names {
+                let Some(name) = name else {
+                    columns.push(None);
+                    continue;
+                };
+                let name = name.as_str();
+                match t.schema.get(name) {
+                    Some(def) if def.computed.is_none() => {
+                        columns.push(Some((name.to_string(), def.field_type.clone())))
+                    }
+                    Some(_) => columns.push(None),
+                    None if name == "id" && preserve_ids => {
+                        columns.push(Some((name.to_string(), FieldType::Integer)))
+                    }
+                    None if name == "id" => columns.push(None),
+                    None => {
+                        return Err(PyValueError::new_err(format!(
+                            "CSV column '{}' is not a field of table '{}'",
+                            name, table
+                        )))
+                    }
+                }
+            }
+            columns
+        } else {
+            let mut fields: Vec<_> = t.schema.iter().collect();
+            fields.sort_by_key(|f| f.0);
+            fields
+                .into_iter()
+                .map(|(name, def)| {
+                    def.computed
+                        .is_none()
+                        .then(|| (name.clone(), def.field_type.clone()))
+                })
+                .collect()
+        };
+
+        let mut rows = Vec::new();
+        for (n, row) in reader.records().enumerate() {
+            if n >= MAX_CSV_IMPORT_ROWS {
+                return Err(PyValueError::new_err(format!(
+                    "CSV import exceeds max row count of {}",
+                    MAX_CSV_IMPORT_ROWS
+                )));
+            }
+            let row = row.map_err(csv_err)?;
+            let line = row.position().map_or(0, |p| p.line());
+            let payload = if row.len() != columns.len() {
+                Err(format!(
+                    "expected {} column(s), found {}",
+                    columns.len(),
+                    row.len()
+                ))
+            } else {
+                columns
+                    .iter()
+                    .zip(row.iter())
+                    .filter_map(|(c, cell)| c.as_ref().map(|(f, ty)| (f, ty, cell)))
+                    .map(|(f, ty, cell)| Ok((f.clone(), parse_csv_cell(ty, f, cell)?)))
+                    .collect::<DbResult<Map<String, Value>>>()
+                    .map_err(|e| e.to_string())
+            };
+            let payload = match mapping.as_mut() {
+                Some(mapping) => payload.and_then(|mut payload| {
+                    mapping.transform(&mut payload)?;
+                    Ok(payload)
+                }),
+                None => payload,
+            };
+            rows.push((line as usize, payload));
+        }
+        let report = self.import_rows(
+            py,
+            &table,
+            "CSV",
+            rows,
+            on_conflict,
+            skip_errors,
+            preserve_ids,
+        )?;
+        match mapping {
+            Some(mapping) => mapping.report(py, report),
+            None => Ok(report),
+        }
+    }
+
+    /// Writes `table` with its record ids into SQLite file `dest`, adding a unique index for
+    /// each unique field. When `dest` already has the table, `mode` decides: "fail" (the
+    /// default), "replace" it, or "append" to it if its columns match.
+    #[pyo3(signature = (table, dest, mode="fail"))]
+    fn export_sqlite(&self, table: String, dest: String, mode: &str) -> PyResult<()> {
+        self.check_open()?;
+        let mode = export_mode_arg(mode)?;
+        validate_identifier(&table).map_err(convert_db_error)?;
+        let t = self
+            .engine
+            .tables
+            .get(&table)
+            .ok_or_else(|| PyKeyError::new_err("missing table"))?;
+        let output_path = sanitize_user_path(&dest)?;
+        let mut conn =
+            Connection::open(output_path).map_err(|e| PyIOError::new_err(e.to_string()))?;
+        let tx = conn
+            .transaction()
+            .map_err(|e| PyIOError::new_err(e.to_string()))?;
+        write_sqlite_table(&tx, &table, t, mode)?;
+        tx.commit().map_err(|e| PyIOError::new_err(e.to_string()))
+    }
+
+    /// `export_sqlite` for every table into one file, in a single transaction: if any table
+    /// cannot be written, `dest` is left as it was.
+    #[pyo3(signature = (dest, mode="fail"))]
+    fn export_sqlite_all(&self, dest: String, mode: &str) -> PyResult<()> {
+        self.check_open()?;
+        let mode = export_mode_arg(mode)?;
+        let output_path = sanitize_user_path(&dest)?;
+        let mut conn =
+            Connection::open(output_path).map_err(|e| PyIOError::new_err(e.to_string()))?;
+        let tx = conn
+            .transaction()
+            .map_err(|e| PyIOError::new_err(e.to_string()))?;
+        let mut names: Vec<_> = self.engine.tables.keys().collect();
+        names.sort();
+        for name in names {
+            write_sqlite_table(&tx, name, &self.engine.tables[name], mode)?;
+        }
+        tx.commit().map_err(|e| PyIOError::new_err(e.to_string()))
+    }
+
+    /// Inserts the rows of SQLite table `src_table` (default: `table`) as a single unit;
+    /// see `import_csv` for `on_conflict`, `field_map`, `transforms`, `preserve_ids` (which
+    /// reads an `id` column) and the report, whose lines are row numbers here. With `create_table`, `table` is first created from the
+    /// source's column types and constraints, under the names `field_map` gives them, and
+    /// the report lists under `warnings` whatever did not map exactly.
+    #[pyo3(signature = (
+        table,
+        src,
+        src_table=None,
+        on_conflict="error",
+        create_table=false,
+        field_map=None,
+        unmapped="drop",
+        transforms=None,
+        preserve_ids=false
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn import_sqlite(
+        &mut self,
+        py: Python<'_>,
+        table: String,
+        src: String,
+        src_table: Option<String>,
+        on_conflict: &str,
+        create_table: bool,
+        field_map: Option<Bound<'_, PyDict>>,
+        unmapped: &str,
+        transforms: Option<Bound<'_, PyDict>>,
+        preserve_ids: bool,
+    ) -> PyResult<PyObject> {
+        self.check_open()?;
+        self.check_writable()?;
+        let on_conflict = on_conflict_arg(on_conflict)?;
+        validate_identifier(&table).map_err(convert_db_error)?;
+        let sn = src_table.unwrap_or(table.clone());
+        validate_identifier(&sn).map_err(convert_db_error)?;
+        let source_path = sanitize_user_path(&src)?;
+        let conn = Connection::open(source_path).map_err(|e| PyIOError::new_err(e.to_string()))?;
+        let mapping = (field_map.as_ref(), unmapped, transforms.as_ref());
+        self.import_sqlite_table(
+            py,
+            &conn,
+            table,
+            &sn,
+            on_conflict,
+            create_table,
+            mapping,
+            preserve_ids,
+        )
+    }
+
+    /// Imports every table of SQLite file `src` under its own name, as `import_sqlite` would,
+    /// and returns `{table: report}`. Names and, with `create_table`, clashes with existing
+    /// tables are checked before anything is imported; a table whose rows fail stops the
+    /// walk, keeping the tables imported before it. With `preserve_ids`, a file written by
+    /// `export_sqlite_all` restores the records under their own ids.
+    #[pyo3(signature = (src, on_conflict="error", create_table=true, preserve_ids=false))]
+    fn import_sqlite_all(
+        &mut self,
+        py: Python<'_>,
+        src: String,
+        on_conflict: &str,
+        create_table: bool,
+        preserve_ids: bool,
+    ) -> PyResult<PyObject> {
+        self.check_open()?;
+        self.check_writable()?;
+        let on_conflict = on_conflict_arg(on_conflict)?;
+        let source_path = sanitize_user_path(&src)?;
+        let conn = Connection::open(source_path).map_err(|e| PyIOError::new_err(e.to_string()))?;
+        let names = conn
+            .prepare(
+                "SELECT name FROM sqlite_master WHERE type = 'table' \
+                 AND name NOT LIKE 'sqlite\\_%' ESCAPE '\\' ORDER BY name",
+            )
+            .and_then(|mut s| {
+                s.query_map([], |r| r.get(0))?
+                    .collect::<Result<Vec<String>, _>>()
+            })
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        for name in &names {
+            validate_identifier(name).map_err(convert_db_error)?;
+            if create_table && self.engine.tables.contains_key(name) {
+                return Err(convert_db_error(DbError::TableExists(name.clone())));
+            }
+        }
+        let reports = PyDict::new_bound(py);
+        for name in names {
+            let report = self.import_sqlite_table(
+                py,
+                &conn,
+                name.clone(),
+                &name,
+                on_conflict,
+                create_table,
+                (None, "drop", None),
+                preserve_ids,
+            )?;
+            reports.set_item(name, report)?;
+        }
+        Ok(reports.into_py(py))
+    }
+
+    fn save(&mut self) -> PyResult<()> {
+        self.check_open()?;
+        self.check_writable()?;
+        self.write_to_disk()
+    }
+
+    fn load(&mut self) -> PyResult<()> {
+        self.check_open()?;
+        self.reload_from_disk()
+    }
+
+    /// Tables (and `graph`) that could not be read from the directory layout at the last
+    /// load, mapped to the reason. Empty for the single-file layout.
+    fn load_errors(&mut self) -> PyResult<BTreeMap<String, String>> {
+        self.check_open()?;
+        if self.layout == StorageLayout::Directory {
+            self.graph()?;
+        }
+        Ok(self.load_errors.clone())
+    }
+
+    /// Checks a database file (this one by default) stage by stage: outer checksum, header,
+    /// decryption and decompression, parsing, then every table against its own checksum.
+    /// With `recover`, the tables and graph that are intact become this database's state
+    /// and the report lists what was `recovered` and `lost`.
+    #[pyo3(signature = (path=None, recover=false))]
+    fn verify(
+        &mut self,
+        py: Python<'_>,
+        path: Option<String>,
+        recover: bool,
+    ) -> PyResult<PyObject> {
+        self.check_open()?;
+        let path = match path {
+            Some(raw) => db_path(&raw)?,
+            None => self
+                .storage_path
+                .clone()
+                .ok_or_else(|| PyValueError::new_err("verify requires storage_path or a path"))?,
+        };
+        self.drain_writer()?;
+        let report = if path.is_dir() {
+            self.verify_directory(&path, recover)?
+        } else {
+            self.verify_file(&path, recover)?
+        };
+        json_to_py(py, &report)
+    }
+
+    /// Writes the current state to `path` (`.rsndb` is added when there is no extension)
+    /// and keeps persisting there. An existing file is only replaced with `overwrite`; with
+    /// `move` the previous file and its journal are removed once the new one is written.
+    #[pyo3(signature = (path, r#move=false, overwrite=false))]
+    fn save_as(
+        &mut self,
+        py: Python<'_>,
+        path: String,
+        r#move: bool,
+        overwrite: bool,
+    ) -> PyResult<()> {
+        self.check_open()?;
+        let path = db_path(&path)?;
+        let old = self.storage_path.clone();
+        if old.as_ref() == Some(&path) {
+            return self.write_to_disk();
+        }
+        self.check_writable()?;
+        if path.exists() {
+            if !overwrite {
+                return Err(PyValueError::new_err(format!(
+                    "`{}` already exists; pass overwrite=True to replace it",
+                    path.display()
+                )));
+            }
+            if path.is_dir() != (self.layout == StorageLayout::Directory) {
+                return Err(PyValueError::new_err(format!(
+                    "`{}` does not match the database layout",
+                    path.display()
+                )));
+            }
+        }
+        self.drain_writer()?;
+        self.rewrite_graph()?;
+        let old_journal = self.journal_path();
+        let key = acquire_lock(py, &path, true, 0.0)?;
+        let old_lock = self.lock.replace(key);
+        self.storage_path = Some(path);
+        self.written.clear();
+        // A fresh epoch keeps a leftover journal at the destination from replaying.
+        self.journal_entries = JOURNAL_CHECKPOINT_ENTRIES;
+        if let Err(e) = self.write_to_disk() {
+            if let Some(new) = std::mem::replace(&mut self.lock, old_lock) {
+                lock::release(&new);
+            }
+            self.storage_path = old;
+            return Err(e);
+        }
+        if let Some(key) = old_lock {
+            if r#move {
+                lock::release_and_remove(&key).map_err(|e| PyIOError::new_err(e.to_string()))?;
+            } else {
+                lock::release(&key);
+            }
+        }
+        if let (true, Some(old)) = (r#move, old) {
+            let removed = match self.layout {
+                StorageLayout::File => {
+                    let (dir, file) = graph_file_parts(&old);
+                    match fs::remove_file(dir.join(file)) {
+                        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+                        _ => fs::remove_file(&old),
+                    }
+                }
+                StorageLayout::Directory => fs::remove_dir_all(&old),
+            };
+            removed
+                .or_else(|e| match e.kind() {
+                    std::io::ErrorKind::NotFound => Ok(()),
+                    _ => Err(e),
+                })
+                .map_err(|e| PyIOError::new_err(e.to_string()))?;
+            if let Some(journal) = old_journal.filter(|j| j.exists()) {
+                fs::remove_file(journal).map_err(|e| PyIOError::new_err(e.to_string()))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the current state to `dest` in the directory layout and keeps working there.
+    /// The previous file is left as it is.
+    fn migrate_to_directory(&mut self, py: Python<'_>, dest: String) -> PyResult<()> {
+        self.check_open()?;
+        let path = db_path(&dest)?;
+        if path.exists() {
+            return Err(PyValueError::new_err(format!(
+                "`{}` already exists",
+                path.display()
+            )));
+        }
+        self.check_writable()?;
+        if self.writer.is_some() {
+            return Err(PyValueError::new_err(
+                "background persistence needs the single-file layout",
+            ));
+        }
+        self.rewrite_graph()?;
+        let key = acquire_lock(py, &path, true, 0.0)?;
+        if let Some(old) = self.lock.replace(key) {
+            lock::release(&old);
+        }
+        self.storage_path = Some(path);
+        self.layout = StorageLayout::Directory;
+        self.written.clear();
+        self.load_errors.clear();
+        self.write_to_disk()
+    }
+
+    fn snapshot(&mut self, dest: String) -> PyResult<()> {
+        self.check_open()?;
+        let src = self
+            .storage_path
+            .clone()
+            .ok_or_else(|| PyValueError::new_err("snapshot requires storage_path"))?;
+        if !src.exists() {
+            self.write_to_disk()?;
+        }
+        let output_path = sanitize_user_path(&dest)?;
+        let (bytes, graph) = match self.layout {
+            StorageLayout::File => {
+                let (dir, file) = graph_file_parts(&src);
+                let bytes = fs::read(&src).map_err(|e| PyIOError::new_err(e.to_string()))?;
+                (bytes, fs::read(dir.join(file)).ok())
+            }
+            StorageLayout::Directory => {
+                self.engine
+                    .refresh_checksums()
+                    .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+                let json = serde_json::to_vec(&self.engine)
+                    .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+                let graph = self.graph()?;
+                let graph = match graph.is_empty() {
+                    true => None,
+                    false => Some(
+                        serde_json::to_vec(graph)
+                            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?,
+                    ),
+                };
+                (self.seal(&json)?, graph.map(|g| self.seal(&g)).transpose()?)
+            }
+        };
+        if let Some(parent) = output_path.parent() {
+            fs::create_dir_all(parent).map_err(|e| PyIOError::new_err(e.to_string()))?;
+        }
+        let (dir, file) = graph_file_parts(&output_path);
+        let written = match graph {
+            Some(graph) => atomic_write(&dir.join(file), &graph),
+            None => fs::remove_file(dir.join(file)).or_else(|e| match e.kind() {
+                std::io::ErrorKind::NotFound => Ok(()),
+                _ => Err(e),
+            }),
+        };
+        written
+            .and_then(|()| atomic_write(&output_path, &bytes))
+            .map_err(|e| PyIOError::new_err(e.to_string()))
+    }
+}
+}
@@ -0,0 +1,194 @@
+//! Query results as text for `format_results` and `PRINT`: an aligned ASCII table for the
+//! terminal and a Markdown table for issues and docs. Widths count characters, so wide
+//! (CJK, emoji) text can still push a column out of line.
+
+/// One column of results: its header and its cells as text.
+pub struct Column {
+    pub header: String,
+    pub cells: Vec<String>,
+    /// Right-aligned, as every cell is a number (or empty).
+    pub numeric: bool,
+}
+
+/// ```text
+/// +----+-------+
+/// | id | name  |
+/// +----+-------+
+/// |  1 | Alice |
+/// +----+-------+
+/// ```
+/// Line breaks and tabs in a cell are shown escaped, and a cell longer than `max_width`
+/// characters is cut short with an ellipsis.
+pub fn ascii(columns: &[Column], max_width: Option<usize>) -> String {
+    let cells = prepare(columns, |cell| truncate(&escape_ascii(cell), max_width));
+    let widths = widths(columns, &cells);
+    let rule = widths
+        .iter()
+        .map(|w| "-".repeat(w + 2))
+        .collect::<Vec<_>>()
+        .join("+");
+    let rule = format!("+{}+\n", rule);
+    let mut out = rule.clone();
+    line(
+        &mut out,
+        columns.iter().map(|c| (c.header.as_str(), false)),
+        &widths,
+    );
+    out.push_str(&rule);
+    for row in rows(columns, &cells) {
+        line(&mut out, row, &widths);
+    }
+    if !cells.first().is_some_and(|c| c.is_empty()) {
+        out.push_str(&rule);
+    }
+    out.truncate(out.trim_end().len());
+    out
+}
+
+/// A GitHub-flavoured Markdown table, padded so it also lines up as plain text. Pipes are
+/// escaped and line breaks become `<br>`; cells are cut at `max_width` before that.
+pub fn markdown(columns: &[Column], max_width: Option<usize>) -> String {
+    let cells = prepare(columns, |cell| escape_markdown(&truncate(cell, max_width)));
+    let headers: Vec<String> = columns.iter().map(|c| escape_markdown(&c.header)).collect();
+    let widths: Vec<usize> = widths(columns, &cells)
+        .into_iter()
+        .zip(&headers)
+        .map(|(w, h)| w.max(width(h)).max(3))
+        .collect();
+    let mut out = String::new();
+    line(
+        &mut out,
+        headers.iter().map(|h| (h.as_str(), false)),
+        &widths,
+    );
+    let align = columns.iter().zip(&widths).map(|(c, w)| match c.numeric {
+        true => format!("{}:", "-".repeat(w + 1)),
+        false => "-".repeat(w + 2),
+    });
+    out.push_str(&format!("|{}|\n", align.collect::<Vec<_>>().join("|")));
+    for row in rows(columns, &cells) {
+        line(&mut out, row, &widths);
+    }
+    out.truncate(out.trim_end().len());
+    out
+}
+
+fn prepare(columns: &[Column], cell: impl Fn(&str) -> String) -> Vec<Vec<String>> {
+    columns
+        .iter()
+        .map(|c| c.cells.iter().map(|s| cell(s)).collect())
+        .collect()
+}
+
+fn widths(columns: &[Column], cells: &[Vec<String>]) -> Vec<usize> {
+    columns
+        .iter()
+        .zip(cells)
+        .map(|(c, cells)| {
+            cells
+                .iter()
+                .map(|s| width(s))
+                .fold(width(&c.header), usize::max)
+        })
+        .collect()
+}
+
+/// `cells` row by row, each cell with whether it is right-aligned.
+fn rows<'a>(
+    columns: &'a [Column],
+    cells: &'a [Vec<String>],
+) -> impl Iterator<Item = impl Iterator<Item = (&'a str, bool)>> {
+    let count = cells.first().map_or(0, Vec::len);
+    (0..count).map(move |i| {
+        cells
+            .iter()
+            .zip(columns)
+            .map(move |(c, column)| (c[i].as_str(), column.numeric))
+    })
+}
+
+fn line<'a>(out: &mut String, cells: impl Iterator<Item = (&'a str, bool)>, widths: &[usize]) {
+    for ((cell, right), w) in cells.zip(widths) {
+        let pad = " ".repeat(w - width(cell));
+        match right {
+            true => out.push_str(&format!("| {}{} ", pad, cell)),
+            false => out.push_str(&format!("| {}{} ", cell, pad)),
+        }
+    }
+    out.push_str("|\n");
+}
+
+fn width(text: &str) -> usize {
+    text.chars().count()
+}
+
+fn truncate(text: &str, max_width: Option<usize>) -> String {
+    match max_width {
+        Some(max) if width(text) > max => {
+            let mut cut: String = text.chars().take(max.saturating_sub(1)).collect();
+            cut.push('…');
+            cut
+        }
+        _ => text.to_string(),
+    }
+}
+
+fn escape_ascii(text: &str) -> String {
+    text.replace('\n', "\\n")
+        .replace('\r', "\\r")
+        .replace('\t', "\\t")
+}
+
+fn escape_markdown(text: &str) -> String {
+    text.replace('|', "\\|")
+        .replace("\r\n", "<br>")
+        .replace(['\n', '\r'], "<br>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn columns() -> Vec<Column> {
+        vec![
+            Column {
+                header: "id".into(),
+                cells: vec!["1".into(), "12".into()],
+                numeric: true,
+            },
+            Column {
+                header: "note".into(),
+                cells: vec!["a|b\nc".into(), "a long note".into()],
+                numeric: false,
+            },
+        ]
+    }
+
+    #[test]
+    fn ascii_tables_align_escape_and_truncate() {
+        let expected = "\
++----+---------+
+| id | note    |
++----+---------+
+|  1 | a|b\\nc  |
+| 12 | a long… |
++----+---------+";
+        assert_eq!(ascii(&columns(), Some(7)), expected);
+        let empty = [Column {
+            header: "id".into(),
+            cells: Vec::new(),
+            numeric: true,
+        }];
+        assert_eq!(ascii(&empty, None), "+----+\n| id |\n+----+");
+    }
+
+    #[test]
+    fn markdown_tables_escape_pipes_and_breaks() {
+        let expected = "\
+| id  | note        |
+|----:|-------------|
+|   1 | a\\|b<br>c   |
+|  12 | a long note |";
+        assert_eq!(markdown(&columns(), None), expected);
+    }
+}
@@ -0,0 +1,1285 @@
+use serde_json::{Map, Number, Value};
+use std::cmp::Ordering;
+use std::fmt;
+
+/// A statement of the command language that `execute_sql` hands to this parser.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Statement {
+    Select(Select),
+    Insert(Insert),
+    Update(Update),
+    Delete(Delete),
+    CreateTable(CreateTable),
+    Count(Count),
+}
+
+/// `SELECT <cols|*> FROM <table> [WHERE <cond> [AND ...]] [ORDER BY <field> [ASC|DESC]]
+/// [LIMIT n]`, as understood by `execute_sql`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Select {
+    /// `None` for `*`.
+    pub columns: Option<Vec<String>>,
+    pub table: TableRef,
+    pub conditions: Vec<Condition>,
+    /// Field and whether it sorts descending.
+    pub order_by: Option<(String, bool)>,
+    pub limit: Option<usize>,
+}
+
+/// A table named by a statement. Bare names match stored tables regardless of case;
+/// quoted ones (`"t"`, `` `t` `` or `[t]`) match exactly and may be reserved words.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableRef {
+    pub name: String,
+    pub quoted: bool,
+}
+
+/// `INSERT INTO <table> (<cols>) VALUES (<values>)[, (...)]` or
+/// `INSERT INTO <table> <JSON object or array of objects>`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Insert {
+    pub table: TableRef,
+    pub rows: Vec<Map<String, Value>>,
+}
+
+/// `UPDATE <table> SET <field> = <value>[, ...] (WHERE <cond> [AND ...] | ALL)`. Leaving
+/// out both WHERE and ALL is a parse error, so a forgotten clause never rewrites a table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Update {
+    pub table: TableRef,
+    pub set: Map<String, Value>,
+    /// Empty only when `ALL` was given.
+    pub conditions: Vec<Condition>,
+}
+
+/// `DELETE FROM <table> (WHERE <cond> [AND ...] | ALL)`, guarded the same way as `UPDATE`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Delete {
+    pub table: TableRef,
+    /// Empty only when `ALL` was given.
+    pub conditions: Vec<Condition>,
+}
+
+/// `CREATE TABLE [IF NOT EXISTS] <table> (<field> <type> [REQUIRED] [UNIQUE] [INDEXED]
+/// [DEFAULT <value>], ...)`. Modifiers may come in any order; a default may be JSON.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateTable {
+    pub table: String,
+    pub if_not_exists: bool,
+    pub columns: Vec<Column>,
+}
+
+/// `COUNT <table> [WHERE <cond> [AND ...]]`; the conditions are the ones `SELECT` takes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Count {
+    pub table: TableRef,
+    pub conditions: Vec<Condition>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Column {
+    pub name: String,
+    /// The type keyword as written, e.g. `STRING` or `array<int>`; the caller maps it.
+    pub type_name: String,
+    pub required: bool,
+    pub unique: bool,
+    pub indexed: bool,
+    pub default: Option<Value>,
+}
+
+/// The statement as `export_ddl` writes it, one column per line, which parses back to the
+/// same statement.
+impl fmt::Display for CreateTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CREATE TABLE ")?;
+        if self.if_not_exists {
+            write!(f, "IF NOT EXISTS ")?;
+        }
+        writeln!(f, "{} (", name_sql(&self.table))?;
+        for (i, c) in self.columns.iter().enumerate() {
+            let type_name = c.type_name.to_ascii_uppercase();
+            write!(f, "    {} {}", name_sql(&c.name), type_name)?;
+            for (on, keyword) in [
+                (c.required, "REQUIRED"),
+                (c.unique, "UNIQUE"),
+                (c.indexed, "INDEXED"),
+            ] {
+                if on {
+                    write!(f, " {}", keyword)?;
+                }
+            }
+            if let Some(value) = &c.default {
+                write!(f, " DEFAULT {}", value_sql(value))?;
+            }
+            writeln!(f, "{}", if i + 1 < self.columns.len() { "," } else { "" })?;
+        }
+        write!(f, ");")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    pub field: String,
+    pub op: Op,
+    pub value: Value,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Gt,
+    Lt,
+    Ge,
+    Le,
+    Like,
+}
+
+impl Op {
+    /// Whether a stored value that compares as `ord` to the operand satisfies the operator.
+    pub fn accepts(self, ord: Ordering) -> bool {
+        match self {
+            Self::Eq => ord == Ordering::Equal,
+            Self::Ne => ord != Ordering::Equal,
+            Self::Gt => ord == Ordering::Greater,
+            Self::Lt => ord == Ordering::Less,
+            Self::Ge => ord != Ordering::Less,
+            Self::Le => ord != Ordering::Greater,
+            Self::Like => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// Bare words, keywords included; double quotes allow any text as a name.
+    Ident(String, bool),
+    Number(Value),
+    Str(String),
+    Sym(&'static str),
+    /// A JSON object or array, as in `INSERT INTO t {"name": "Ada"}`.
+    Json(Value),
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Self::Ident(name, false) => format!("`{}`", name),
+            Self::Ident(name, true) => format!("`\"{}\"`", name),
+            Self::Number(n) => format!("`{}`", n),
+            Self::Str(s) => format!("`'{}'`", s),
+            Self::Sym(s) => format!("`{}`", s),
+            Self::Json(v) => format!("`{}`", v),
+        }
+    }
+}
+
+const SYMBOLS: [&str; 13] = [
+    "<=", ">=", "<>", "!=", "=", "<", ">", ",", "*", ";", "-", "(", ")",
+];
+
+/// Tokens with the 1-based column each starts at.
+fn tokenize(src: &str) -> Result<Vec<(Token, usize)>, String> {
+    let chars: Vec<char> = src.chars().collect();
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let column = i + 1;
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            out.push((
+                Token::Ident(chars[start..i].iter().collect(), false),
+                column,
+            ));
+        } else if c.is_ascii_digit()
+            || (c == '.' && chars.get(i + 1).is_some_and(char::is_ascii_digit))
+        {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            let value = match text.parse::<i64>() {
+                Ok(n) => Value::from(n),
+                Err(_) => text
+                    .parse::<f64>()
+                    .ok()
+                    .and_then(Number::from_f64)
+                    .map(Value::Number)
+                    .ok_or_else(|| format!("bad number `{}` at column {}", text, column))?,
+            };
+            out.push((Token::Number(value), column));
+        } else if c == '[' && bracketed_name(&chars[i + 1..]).is_some() {
+            let name = bracketed_name(&chars[i + 1..]).unwrap_or_default();
+            i += name.chars().count() + 2;
+            out.push((Token::Ident(name, true), column));
+        } else if c == '\'' || c == '"' || c == '`' {
+            // Single quotes make text; double quotes and backticks make names. A doubled quote
+            // stands for the quote itself, as in standard SQL; backslash escapes work too.
+            let mut text = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => {
+                        return Err(format!(
+                            "unterminated quoted text starting at column {}",
+                            column
+                        ))
+                    }
+                    Some(&q) if q == c && chars.get(i + 1) == Some(&c) => {
+                        text.push(c);
+                        i += 2;
+                    }
+                    Some('\\') => {
+                        let escaped = match chars.get(i + 1) {
+                            Some('n') => '\n',
+                            Some('t') => '\t',
+                            Some('r') => '\r',
+                            Some(&e @ ('\\' | '\'' | '"' | '`')) => e,
+                            Some(e) => {
+                                return Err(format!("unknown escape `\\{}` at column {}", e, i + 1))
+                            }
+                            None => {
+                                return Err(format!(
+                                    "unterminated quoted text starting at column {}",
+                                    column
+                                ))
+                            }
+                        };
+                        text.push(escaped);
+                        i += 2;
+                    }
+                    Some(&q) if q == c => {
+                        i += 1;
+                        break;
+                    }
+                    Some(&other) => {
+                        text.push(other);
+                        i += 1;
+                    }
+                }
+            }
+            out.push((
+                match c {
+                    '\'' => Token::Str(text),
+                    _ => Token::Ident(text, true),
+                },
+                column,
+            ));
+        } else if c == '{' || c == '[' {
+            let rest: String = chars[i..].iter().collect();
+            let mut stream = serde_json::Deserializer::from_str(&rest).into_iter::<Value>();
+            match stream.next() {
+                Some(Ok(value)) => {
+                    out.push((Token::Json(value), column));
+                    i += rest[..stream.byte_offset()].chars().count();
+                }
+                Some(Err(e)) => {
+                    let message = e.to_string();
+                    let reason = message.split(" at line ").next().unwrap_or_default();
+                    return Err(format!(
+                        "malformed JSON at column {}: {}",
+                        json_error_column(&rest, &e) + column,
+                        reason
+                    ));
+                }
+                None => unreachable!("the text starts with a bracket"),
+            }
+        } else if let Some(sym) = SYMBOLS.iter().find(|s| {
+            s.chars()
+                .enumerate()
+                .all(|(k, sc)| chars.get(i + k) == Some(&sc))
+        }) {
+            out.push((Token::Sym(sym), column));
+            i += sym.len();
+        } else {
+            return Err(format!("unexpected character `{}` at column {}", c, column));
+        }
+    }
+    Ok(out)
+}
+
+/// The name in `[name]`, given the characters after the `[`. Anything JSON-like (quotes,
+/// braces, nested brackets, commas) or a leading digit leaves the bracket to the JSON rules.
+fn bracketed_name(rest: &[char]) -> Option<String> {
+    let len = rest.iter().position(|&c| c == ']')?;
+    let name: String = rest[..len].iter().collect();
+    let plain = !name.contains(['[', '{', '}', '"', '\'', ',', ':']);
+    match name.chars().next() {
+        Some(first) if plain && !first.is_ascii_digit() && !first.is_whitespace() => Some(name),
+        _ => None,
+    }
+}
+
+/// 0-based character offset into `text` of a JSON parse error.
+fn json_error_column(text: &str, e: &serde_json::Error) -> usize {
+    let before: usize = text
+        .split_inclusive('\n')
+        .take(e.line().saturating_sub(1))
+        .map(|l| l.chars().count())
+        .sum();
+    before + e.column().saturating_sub(1)
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    /// Column just past the input, reported when it ends too early.
+    end: usize,
+}
+
+impl Parser {
+    fn new(src: &str) -> Result<Self, String> {
+        Ok(Self {
+            tokens: tokenize(src)?,
+            pos: 0,
+            end: src.chars().count() + 1,
+        })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(t, _)| t)
+    }
+
+    fn unexpected(&self, expected: &str) -> String {
+        match self.tokens.get(self.pos) {
+            Some((token, column)) => format!(
+                "unexpected {} at column {}; expected {}",
+                token.describe(),
+                column,
+                expected
+            ),
+            None => format!(
+                "unexpected end of input at column {}; expected {}",
+                self.end, expected
+            ),
+        }
+    }
+
+    fn at_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Ident(word, false)) if word.eq_ignore_ascii_case(keyword))
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.at_keyword(keyword);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn keyword(&mut self, keyword: &str) -> Result<(), String> {
+        if self.eat_keyword(keyword) {
+            Ok(())
+        } else {
+            Err(self.unexpected(keyword))
+        }
+    }
+
+    fn eat(&mut self, sym: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Sym(s)) if *s == sym);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    /// A table or field name. Reserved words need double quotes.
+    fn name(&mut self, what: &str) -> Result<String, String> {
+        match self.peek() {
+            Some(Token::Ident(word, quoted)) if *quoted || !is_reserved(word) => {
+                let word = word.clone();
+                self.pos += 1;
+                Ok(word)
+            }
+            _ => Err(self.unexpected(what)),
+        }
+    }
+
+    fn table(&mut self) -> Result<TableRef, String> {
+        let quoted = matches!(self.peek(), Some(Token::Ident(_, true)));
+        let name = self.name("a table name")?;
+        Ok(TableRef { name, quoted })
+    }
+
+    /// An optional `;`, then nothing more.
+    fn finish(&mut self, expected: &str) -> Result<(), String> {
+        self.eat(";");
+        match self.pos == self.tokens.len() {
+            true => Ok(()),
+            false => Err(self.unexpected(expected)),
+        }
+    }
+
+    fn statement(&mut self) -> Result<Statement, String> {
+        if self.at_keyword("SELECT") {
+            self.select().map(Statement::Select)
+        } else if self.at_keyword("INSERT") {
+            self.insert().map(Statement::Insert)
+        } else if self.at_keyword("UPDATE") {
+            self.update().map(Statement::Update)
+        } else if self.at_keyword("DELETE") {
+            self.delete().map(Statement::Delete)
+        } else if self.at_keyword("CREATE") {
+            self.create_table().map(Statement::CreateTable)
+        } else if self.at_keyword("COUNT") {
+            self.count().map(Statement::Count)
+        } else {
+            Err(self.unexpected("SELECT, INSERT, UPDATE, DELETE, CREATE TABLE or COUNT"))
+        }
+    }
+
+    fn select(&mut self) -> Result<Select, String> {
+        self.keyword("SELECT")?;
+        let columns = if self.eat("*") {
+            None
+        } else {
+            let mut columns = vec![self.name("`*` or a column name")?];
+            while self.eat(",") {
+                columns.push(self.name("a column name")?);
+            }
+            Some(columns)
+        };
+        self.keyword("FROM")?;
+        let table = self.table()?;
+        let conditions = match self.eat_keyword("WHERE") {
+            true => self.conditions()?,
+            false => Vec::new(),
+        };
+        let mut order_by = None;
+        if self.eat_keyword("ORDER") {
+            self.keyword("BY")?;
+            let field = self.name("a field name")?;
+            let descending = self.eat_keyword("DESC");
+            if !descending {
+                self.eat_keyword("ASC");
+            }
+            order_by = Some((field, descending));
+        }
+        let mut limit = None;
+        if self.eat_keyword("LIMIT") {
+            match self.peek() {
+                Some(Token::Number(n)) if n.as_u64().is_some() => {
+                    limit = n.as_u64().map(|n| n as usize);
+                    self.pos += 1;
+                }
+                _ => return Err(self.unexpected("a row count")),
+            }
+        }
+        self.finish(match (&order_by, &limit) {
+            (_, Some(_)) => "the end of the statement",
+            (Some(_), None) => "LIMIT or the end of the statement",
+            (None, None) if !conditions.is_empty() => "AND, ORDER BY, LIMIT or the end",
+            (None, None) => "WHERE, ORDER BY, LIMIT or the end",
+        })?;
+        Ok(Select {
+            columns,
+            table,
+            conditions,
+            order_by,
+            limit,
+        })
+    }
+
+    fn count(&mut self) -> Result<Count, String> {
+        self.keyword("COUNT")?;
+        let table = self.table()?;
+        let conditions = match self.eat_keyword("WHERE") {
+            true => self.conditions()?,
+            false => Vec::new(),
+        };
+        self.finish(match conditions.is_empty() {
+            true => "WHERE or the end of the statement",
+            false => "AND or the end of the statement",
+        })?;
+        Ok(Count { table, conditions })
+    }
+
+    fn insert(&mut self) -> Result<Insert, String> {
+        self.keyword("INSERT")?;
+        self.keyword("INTO")?;
+        let table = self.table()?;
+        if let Some(Token::Json(json)) = self.peek().cloned() {
+            let rows = match json {
+                Value::Object(row) => vec![row],
+                Value::Array(items) => items
+                    .into_iter()
+                    .map(|item| match item {
+                        Value::Object(row) => Ok(row),
+                        _ => Err(self.unexpected("a JSON object or an array of objects")),
+                    })
+                    .collect::<Result<_, _>>()?,
+                _ => return Err(self.unexpected("a JSON object")),
+            };
+            self.pos += 1;
+            self.finish("the end of the statement")?;
+            return Ok(Insert { table, rows });
+        }
+        if !self.eat("(") {
+            return Err(self.unexpected("`(` and column names, or a JSON object"));
+        }
+        let mut columns = vec![self.name("a column name")?];
+        while self.eat(",") {
+            let start = self.pos;
+            let column = self.name("a column name")?;
+            if columns.contains(&column) {
+                self.pos = start;
+                return Err(format!(
+                    "{} (duplicate column)",
+                    self.unexpected("a new column name")
+                ));
+            }
+            columns.push(column);
+        }
+        if !self.eat(")") {
+            return Err(self.unexpected("`,` or `)`"));
+        }
+        self.keyword("VALUES")?;
+        let mut rows = Vec::new();
+        loop {
+            if !self.eat("(") {
+                return Err(self.unexpected("`(`"));
+            }
+            let mut row = Map::new();
+            for (k, column) in columns.iter().enumerate() {
+                if k > 0 && !self.eat(",") {
+                    let count = format!("`,` ({} values for {} columns)", k, columns.len());
+                    return Err(self.unexpected(&count));
+                }
+                row.insert(column.clone(), self.value()?);
+            }
+            if !self.eat(")") {
+                let count = format!("`)` ({} columns)", columns.len());
+                return Err(self.unexpected(&count));
+            }
+            rows.push(row);
+            if !self.eat(",") {
+                break;
+            }
+        }
+        self.finish("`,` and another row, or the end of the statement")?;
+        Ok(Insert { table, rows })
+    }
+
+    fn update(&mut self) -> Result<Update, String> {
+        self.keyword("UPDATE")?;
+        let table = self.table()?;
+        self.keyword("SET")?;
+        let mut set = Map::new();
+        loop {
+            let start = self.pos;
+            let field = self.name("a field name")?;
+            if set.contains_key(&field) {
+                self.pos = start;
+                return Err(format!(
+                    "{} (set twice)",
+                    self.unexpected("another field name")
+                ));
+            }
+            if !self.eat("=") {
+                return Err(self.unexpected("`=`"));
+            }
+            set.insert(field, self.value()?);
+            if !self.eat(",") {
+                break;
+            }
+        }
+        let conditions = self.guarded_where("update", "`,`, WHERE or ALL")?;
+        Ok(Update {
+            table,
+            set,
+            conditions,
+        })
+    }
+
+    fn delete(&mut self) -> Result<Delete, String> {
+        self.keyword("DELETE")?;
+        self.keyword("FROM")?;
+        let table = self.table()?;
+        let conditions = self.guarded_where("delete", "WHERE or ALL")?;
+        Ok(Delete { table, conditions })
+    }
+
+    fn create_table(&mut self) -> Result<CreateTable, String> {
+        self.keyword("CREATE")?;
+        self.keyword("TABLE")?;
+        let if_not_exists = self.eat_keyword("IF");
+        if if_not_exists {
+            self.keyword("NOT")?;
+            self.keyword("EXISTS")?;
+        }
+        let table = self.name("a table name")?;
+        if !self.eat("(") {
+            return Err(self.unexpected("`(` and column definitions"));
+        }
+        let mut columns: Vec<Column> = Vec::new();
+        loop {
+            let start = self.pos;
+            let name = self.name("a column name")?;
+            if columns.iter().any(|c| c.name == name) {
+                self.pos = start;
+                return Err(format!(
+                    "{} (duplicate column)",
+                    self.unexpected("a new column name")
+                ));
+            }
+            columns.push(self.column(name)?);
+            if !self.eat(",") {
+                break;
+            }
+        }
+        if !self.eat(")") {
+            return Err(self.unexpected("`,` or `)`"));
+        }
+        self.finish("the end of the statement")?;
+        Ok(CreateTable {
+            table,
+            if_not_exists,
+            columns,
+        })
+    }
+
+    /// The type and modifiers that follow a column name in `CREATE TABLE`.
+    fn column(&mut self, name: String) -> Result<Column, String> {
+        let mut type_name = match self.peek() {
+            Some(Token::Ident(word, false)) => word.clone(),
+            _ => return Err(self.unexpected("a field type")),
+        };
+        self.pos += 1;
+        // `ARRAY<INT>` arrives as four tokens.
+        if self.eat("<") {
+            match self.peek() {
+                Some(Token::Ident(elem, false)) => type_name = format!("{}<{}>", type_name, elem),
+                _ => return Err(self.unexpected("an element type")),
+            }
+            self.pos += 1;
+            if !self.eat(">") {
+                return Err(self.unexpected("`>`"));
+            }
+        }
+        let mut column = Column {
+            name,
+            type_name,
+            required: false,
+            unique: false,
+            indexed: false,
+            default: None,
+        };
+        loop {
+            let start = self.pos;
+            let repeated = if self.eat_keyword("REQUIRED") {
+                std::mem::replace(&mut column.required, true)
+            } else if self.eat_keyword("UNIQUE") {
+                std::mem::replace(&mut column.unique, true)
+            } else if self.eat_keyword("INDEXED") {
+                std::mem::replace(&mut column.indexed, true)
+            } else if self.eat_keyword("DEFAULT") {
+                let value = match self.peek() {
+                    Some(Token::Json(value)) => {
+                        let value = value.clone();
+                        self.pos += 1;
+                        value
+                    }
+                    _ => self.value()?,
+                };
+                column.default.replace(value).is_some()
+            } else {
+                break;
+            };
+            if repeated {
+                self.pos = start;
+                return Err(format!("{} (given twice)", self.unexpected("`,` or `)`")));
+            }
+        }
+        Ok(column)
+    }
+
+    /// The end of an `UPDATE` or `DELETE`: `WHERE` and its conditions, or `ALL` to touch
+    /// every row, then the end of the statement. Stopping short of both is refused outright
+    /// rather than reported as a syntax slip, so the message names what would have happened.
+    fn guarded_where(&mut self, verb: &str, expected: &str) -> Result<Vec<Condition>, String> {
+        let conditions = if self.eat_keyword("WHERE") {
+            self.conditions()?
+        } else if self.eat_keyword("ALL") {
+            Vec::new()
+        } else if self.tokens[self.pos..]
+            .iter()
+            .all(|(t, _)| *t == Token::Sym(";"))
+        {
+            return Err(format!(
+                "refusing to {} every row without a WHERE clause; end with ALL to mean it",
+                verb
+            ));
+        } else {
+            return Err(self.unexpected(expected));
+        };
+        self.finish(match conditions.is_empty() {
+            true => "the end of the statement",
+            false => "AND or the end of the statement",
+        })?;
+        Ok(conditions)
+    }
+
+    /// One or more conditions joined by `AND`, after a `WHERE`.
+    fn conditions(&mut self) -> Result<Vec<Condition>, String> {
+        let mut conditions = vec![self.condition()?];
+        while self.eat_keyword("AND") {
+            conditions.push(self.condition()?);
+        }
+        Ok(conditions)
+    }
+
+    fn condition(&mut self) -> Result<Condition, String> {
+        let field = self.name("a field name")?;
+        let op = match self.peek() {
+            Some(Token::Sym("=")) => Op::Eq,
+            Some(Token::Sym("!=" | "<>")) => Op::Ne,
+            Some(Token::Sym(">")) => Op::Gt,
+            Some(Token::Sym("<")) => Op::Lt,
+            Some(Token::Sym(">=")) => Op::Ge,
+            Some(Token::Sym("<=")) => Op::Le,
+            _ if self.at_keyword("LIKE") => Op::Like,
+            _ => return Err(self.unexpected("a comparison operator")),
+        };
+        self.pos += 1;
+        let value = self.value()?;
+        if op == Op::Like && !value.is_string() {
+            self.pos -= 1;
+            return Err(self.unexpected("a quoted LIKE pattern"));
+        }
+        Ok(Condition { field, op, value })
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        let negative = self.eat("-");
+        let value = match self.peek() {
+            Some(Token::Number(n)) => match (negative, n.as_i64(), n.as_f64()) {
+                (false, _, _) => n.clone(),
+                (true, Some(i), _) => Value::from(-i),
+                (true, None, Some(f)) => Number::from_f64(-f).map_or(Value::Null, Value::Number),
+                (true, None, None) => Value::Null,
+            },
+            Some(Token::Str(s)) if !negative => Value::String(s.clone()),
+            Some(Token::Ident(word, false)) if !negative => {
+                match word.to_ascii_uppercase().as_str() {
+                    "TRUE" => Value::Bool(true),
+                    "FALSE" => Value::Bool(false),
+                    "NULL" => Value::Null,
+                    _ => return Err(self.unexpected("a value")),
+                }
+            }
+            _ if negative => return Err(self.unexpected("a number")),
+            _ => return Err(self.unexpected("a value")),
+        };
+        self.pos += 1;
+        Ok(value)
+    }
+}
+
+fn is_reserved(word: &str) -> bool {
+    [
+        "SELECT", "FROM", "WHERE", "AND", "ORDER", "BY", "ASC", "DESC", "LIMIT", "LIKE", "INSERT",
+        "INTO", "VALUES", "UPDATE", "SET", "ALL", "DELETE", "CREATE",
+    ]
+    .iter()
+    .any(|k| word.eq_ignore_ascii_case(k))
+}
+
+/// `name` as the parser reads it back: bare when it can be, double-quoted otherwise.
+fn name_sql(name: &str) -> String {
+    let bare = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+        && !is_reserved(name);
+    match bare {
+        true => name.to_string(),
+        false => quote(name, '"'),
+    }
+}
+
+/// `value` as a literal: text single-quoted, objects and arrays as JSON.
+fn value_sql(value: &Value) -> String {
+    match value {
+        Value::Null => "NULL".to_string(),
+        Value::Bool(b) => b.to_string().to_ascii_uppercase(),
+        Value::String(s) => quote(s, '\''),
+        other => other.to_string(),
+    }
+}
+
+fn quote(text: &str, q: char) -> String {
+    let mut out = String::from(q);
+    for c in text.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            c if c == q => out.extend([q, q]),
+            c => out.push(c),
+        }
+    }
+    out.push(q);
+    out
+}
+
+/// Splits a script into its statements at each `;` outside quotes, leaving out `--`
+/// comments and blank statements.
+pub fn split_script(src: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    let mut current = String::new();
+    let mut chars = src.chars().peekable();
+    let mut quote: Option<char> = None;
+    while let Some(c) = chars.next() {
+        match quote {
+            Some(q) => {
+                current.push(c);
+                if c == '\\' {
+                    current.extend(chars.next());
+                } else if c == q {
+                    quote = None;
+                }
+            }
+            None if c == '-' && chars.peek() == Some(&'-') => {
+                while chars.next_if(|c| *c != '\n').is_some() {}
+            }
+            None if c == ';' => out.push(std::mem::take(&mut current)),
+            None => {
+                if matches!(c, '\'' | '"' | '`') {
+                    quote = Some(c);
+                }
+                current.push(c);
+            }
+        }
+    }
+    out.push(current);
+    out.into_iter()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+pub fn parse(src: &str) -> Result<Statement, String> {
+    Parser::new(src)?.statement()
+}
+
+/// A lone table name, as `DESCRIBE` and `TRUNCATE` take it.
+pub fn table_ref(src: &str) -> Result<TableRef, String> {
+    let mut parser = Parser::new(src)?;
+    let table = parser.table()?;
+    parser.finish("the end of the table name")?;
+    Ok(table)
+}
+
+/// SQL `LIKE`: `%` matches any run of characters and `_` exactly one; case-sensitive.
+pub fn like(pattern: &str, text: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let t: Vec<char> = text.chars().collect();
+    // Greedy match that backtracks to the last `%`.
+    let (mut pi, mut ti) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while ti < t.len() {
+        match p.get(pi) {
+            Some('%') => {
+                star = Some((pi, ti));
+                pi += 1;
+            }
+            Some(&c) if c == '_' || c == t[ti] => {
+                pi += 1;
+                ti += 1;
+            }
+            _ => match star {
+                Some((sp, st)) => {
+                    pi = sp + 1;
+                    ti = st + 1;
+                    star = Some((sp, st + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    p[pi..].iter().all(|&c| c == '%')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn select(src: &str) -> Select {
+        match parse(src) {
+            Ok(Statement::Select(s)) => s,
+            other => panic!("not a SELECT: {:?}", other),
+        }
+    }
+
+    fn bare(name: &str) -> TableRef {
+        TableRef {
+            name: name.to_string(),
+            quoted: false,
+        }
+    }
+
+    fn insert(src: &str) -> Vec<Map<String, Value>> {
+        match parse(src) {
+            Ok(Statement::Insert(i)) => i.rows,
+            other => panic!("not an INSERT: {:?}", other),
+        }
+    }
+
+    fn update(src: &str) -> Update {
+        match parse(src) {
+            Ok(Statement::Update(u)) => u,
+            other => panic!("not an UPDATE: {:?}", other),
+        }
+    }
+
+    fn err(src: &str) -> String {
+        parse(src).unwrap_err()
+    }
+
+    #[test]
+    fn full_statement() {
+        let s = select(
+            "select name, \"home city\" FROM users WHERE age >= 18 AND name LIKE 'A%' \
+             and note != 'it''s' AND score < -1.5 ORDER BY age DESC LIMIT 10;",
+        );
+        assert_eq!(
+            s.columns,
+            Some(vec!["name".to_string(), "home city".to_string()])
+        );
+        assert_eq!(s.table.name, "users");
+        let ops: Vec<(&str, Op, &Value)> = s
+            .conditions
+            .iter()
+            .map(|c| (c.field.as_str(), c.op, &c.value))
+            .collect();
+        assert_eq!(
+            ops,
+            vec![
+                ("age", Op::Ge, &json!(18)),
+                ("name", Op::Like, &json!("A%")),
+                ("note", Op::Ne, &json!("it's")),
+                ("score", Op::Lt, &json!(-1.5)),
+            ]
+        );
+        assert_eq!(s.order_by, Some(("age".to_string(), true)));
+        assert_eq!(s.limit, Some(10));
+
+        let s = select("SELECT * FROM t WHERE done = true AND x <> NULL");
+        assert_eq!(s.columns, None);
+        assert_eq!(s.conditions[0].value, json!(true));
+        assert_eq!(s.conditions[1].op, Op::Ne);
+        assert_eq!(s.order_by, None);
+    }
+
+    #[test]
+    fn errors_name_the_token_and_column() {
+        assert_eq!(
+            err("SELECT * users"),
+            "unexpected `users` at column 10; expected FROM"
+        );
+        assert_eq!(
+            err("SELECT * FROM WHERE"),
+            "unexpected `WHERE` at column 15; expected a table name"
+        );
+        assert_eq!(
+            err("SELECT * FROM t WHERE age"),
+            "unexpected end of input at column 26; expected a comparison operator"
+        );
+        assert_eq!(
+            err("SELECT * FROM t WHERE age > 1 OR age < 0"),
+            "unexpected `OR` at column 31; expected AND, ORDER BY, LIMIT or the end"
+        );
+        assert_eq!(
+            err("SELECT * FROM t WHERE name LIKE 5"),
+            "unexpected `5` at column 33; expected a quoted LIKE pattern"
+        );
+        assert_eq!(
+            err("SELECT * FROM t LIMIT -1"),
+            "unexpected `-` at column 23; expected a row count"
+        );
+        assert_eq!(
+            err("SELECT * FROM t WHERE a = 'open"),
+            "unterminated quoted text starting at column 27"
+        );
+        assert_eq!(
+            err("SELECT a; FROM t"),
+            "unexpected `;` at column 9; expected FROM"
+        );
+    }
+
+    #[test]
+    fn insert_values_and_json() {
+        let rows = insert(
+            "INSERT INTO users (name, age, admin, note) VALUES ('O\\'Brien', 30, TRUE, NULL), \
+             ('tab\\there', -2.5, false, 'it''s')",
+        );
+        assert_eq!(
+            rows,
+            vec![
+                row(json!({"name": "O'Brien", "age": 30, "admin": true, "note": null})),
+                row(json!({"name": "tab\there", "age": -2.5, "admin": false, "note": "it's"})),
+            ]
+        );
+        let rows = insert(r#"insert into users {"name": "Alice", "tags": ["a"]};"#);
+        assert_eq!(rows, vec![row(json!({"name": "Alice", "tags": ["a"]}))]);
+        assert_eq!(insert(r#"INSERT INTO t [{"n": 1}, {"n": 2}]"#).len(), 2);
+
+        assert_eq!(
+            err("INSERT INTO t (a, b) VALUES (1)"),
+            "unexpected `)` at column 31; expected `,` (1 values for 2 columns)"
+        );
+        assert_eq!(
+            err("INSERT INTO t (a) VALUES (1, 2)"),
+            "unexpected `,` at column 28; expected `)` (1 columns)"
+        );
+        assert_eq!(
+            err("INSERT INTO t (a) VALUES (maybe)"),
+            "unexpected `maybe` at column 27; expected a value"
+        );
+        assert_eq!(
+            err("INSERT INTO t (a, a) VALUES (1, 2)"),
+            "unexpected `a` at column 19; expected a new column name (duplicate column)"
+        );
+        assert_eq!(
+            err(r"INSERT INTO t (a) VALUES ('bad \q')"),
+            "unknown escape `\\q` at column 32"
+        );
+        assert_eq!(
+            err(r#"INSERT INTO t {"a": 1,}"#),
+            "malformed JSON at column 23: trailing comma"
+        );
+        assert_eq!(
+            err(r#"INSERT INTO t [1]"#),
+            "unexpected `[1]` at column 15; expected a JSON object or an array of objects"
+        );
+    }
+
+    #[test]
+    fn update_needs_where_or_all() {
+        let u = update(
+            "UPDATE users SET age = 31, note = 'x', admin = FALSE WHERE id = 4 AND age < 40",
+        );
+        assert_eq!(u.table.name, "users");
+        assert_eq!(u.set, row(json!({"age": 31, "note": "x", "admin": false})));
+        assert_eq!(u.conditions.len(), 2);
+        assert!(update("update t set n = NULL all;").conditions.is_empty());
+
+        assert_eq!(
+            err("UPDATE t SET n = 1;"),
+            "refusing to update every row without a WHERE clause; end with ALL to mean it"
+        );
+        assert_eq!(
+            err("UPDATE t SET n = 1 OR 2"),
+            "unexpected `OR` at column 20; expected `,`, WHERE or ALL"
+        );
+        assert_eq!(
+            err("UPDATE t SET n = 1 ALL WHERE n = 2"),
+            "unexpected `WHERE` at column 24; expected the end of the statement"
+        );
+        assert_eq!(
+            err("UPDATE t SET n = 1, n = 2 ALL"),
+            "unexpected `n` at column 21; expected another field name (set twice)"
+        );
+        assert_eq!(
+            err("UPDATE t SET n 1 ALL"),
+            "unexpected `1` at column 16; expected `=`"
+        );
+    }
+
+    #[test]
+    fn delete_needs_where_or_all() {
+        match parse("DELETE FROM users WHERE age < 18 AND name LIKE 'B%';") {
+            Ok(Statement::Delete(d)) => {
+                assert_eq!(d.table.name, "users");
+                assert_eq!(d.conditions.len(), 2);
+            }
+            other => panic!("not a DELETE: {:?}", other),
+        }
+        assert_eq!(
+            parse("delete from t all"),
+            Ok(Statement::Delete(Delete {
+                table: bare("t"),
+                conditions: Vec::new(),
+            }))
+        );
+        assert_eq!(
+            err("DELETE FROM users"),
+            "refusing to delete every row without a WHERE clause; end with ALL to mean it"
+        );
+        assert_eq!(
+            err("DELETE users WHERE id = 1"),
+            "unexpected `users` at column 8; expected FROM"
+        );
+        assert_eq!(
+            err("DROP TABLE users"),
+            "unexpected `DROP` at column 1; expected SELECT, INSERT, UPDATE, DELETE, CREATE TABLE or COUNT"
+        );
+    }
+
+    #[test]
+    fn table_names_can_be_quoted_three_ways() {
+        let quoted = |name: &str| TableRef {
+            name: name.to_string(),
+            quoted: true,
+        };
+        assert_eq!(select("SELECT * FROM Users").table, bare("Users"));
+        assert_eq!(select("SELECT * FROM \"select\"").table, quoted("select"));
+        assert_eq!(
+            select("SELECT * FROM `order` WHERE n = 1").table,
+            quoted("order")
+        );
+        assert_eq!(select("SELECT * FROM [from] LIMIT 1").table, quoted("from"));
+        assert_eq!(table_ref("`we``ird`;"), Ok(quoted("we`ird")));
+        assert_eq!(insert("INSERT INTO [t] [{\"n\": 1}]").len(), 1);
+        assert_eq!(
+            table_ref("users FULL"),
+            Err("unexpected `FULL` at column 7; expected the end of the table name".to_string())
+        );
+        assert_eq!(
+            err("SELECT * FROM order"),
+            "unexpected `order` at column 15; expected a table name"
+        );
+    }
+
+    #[test]
+    fn count_takes_an_optional_where() {
+        assert_eq!(
+            parse("COUNT users"),
+            Ok(Statement::Count(Count {
+                table: bare("users"),
+                conditions: Vec::new(),
+            }))
+        );
+        match parse("count users where age > 30 and name like 'A%';") {
+            Ok(Statement::Count(c)) => {
+                assert_eq!(c.table.name, "users");
+                assert_eq!(c.conditions[0].op, Op::Gt);
+                assert_eq!(c.conditions[1].value, Value::from("A%"));
+            }
+            other => panic!("not a COUNT: {:?}", other),
+        }
+        assert_eq!(
+            err("COUNT users LIMIT 3"),
+            "unexpected `LIMIT` at column 13; expected WHERE or the end of the statement"
+        );
+        assert_eq!(
+            err("COUNT users WHERE"),
+            "unexpected end of input at column 18; expected a field name"
+        );
+    }
+
+    #[test]
+    fn create_table_columns_and_modifiers() {
+        let c = match parse(
+            "CREATE TABLE IF NOT EXISTS users (name STRING REQUIRED UNIQUE, age int DEFAULT -1 \
+             required, profile JSON, tags ARRAY<STRING>, note text default 'n/a');",
+        ) {
+            Ok(Statement::CreateTable(c)) => c,
+            other => panic!("not a CREATE TABLE: {:?}", other),
+        };
+        assert_eq!((c.table.as_str(), c.if_not_exists), ("users", true));
+        let summary: Vec<(&str, &str, bool, bool, Option<&Value>)> = c
+            .columns
+            .iter()
+            .map(|c| {
+                let default = c.default.as_ref();
+                (
+                    c.name.as_str(),
+                    c.type_name.as_str(),
+                    c.required,
+                    c.unique,
+                    default,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("name", "STRING", true, true, None),
+                ("age", "int", true, false, Some(&json!(-1))),
+                ("profile", "JSON", false, false, None),
+                ("tags", "ARRAY<STRING>", false, false, None),
+                ("note", "text", false, false, Some(&json!("n/a"))),
+            ]
+        );
+
+        assert_eq!(
+            err("CREATE TABLE t (a INT UNIQUE UNIQUE)"),
+            "unexpected `UNIQUE` at column 30; expected `,` or `)` (given twice)"
+        );
+        assert_eq!(
+            err("CREATE TABLE t (a INT, a TEXT)"),
+            "unexpected `a` at column 24; expected a new column name (duplicate column)"
+        );
+        assert_eq!(
+            err("CREATE TABLE t (a)"),
+            "unexpected `)` at column 18; expected a field type"
+        );
+        assert_eq!(
+            err("CREATE TABLE IF EXISTS t (a INT)"),
+            "unexpected `EXISTS` at column 17; expected NOT"
+        );
+        assert_eq!(
+            err("CREATE TABLE t (a INT NOT NULL)"),
+            "unexpected `NOT` at column 23; expected `,` or `)`"
+        );
+    }
+
+    #[test]
+    fn create_table_prints_back_to_itself() {
+        let src = "CREATE TABLE \"select\" (\"odd name\" ARRAY<STRING> INDEXED DEFAULT [\"a;b\"], \
+                   n INT REQUIRED UNIQUE DEFAULT -2, note TEXT DEFAULT 'it''s\\n', ok BOOL DEFAULT TRUE)";
+        let Ok(Statement::CreateTable(c)) = parse(src) else {
+            panic!("not a CREATE TABLE: {}", src);
+        };
+        let printed = c.to_string();
+        assert_eq!(
+            printed,
+            "CREATE TABLE \"select\" (\n    \"odd name\" ARRAY<STRING> INDEXED DEFAULT [\"a;b\"],\n    \
+             n INT REQUIRED UNIQUE DEFAULT -2,\n    note TEXT DEFAULT 'it''s\\n',\n    \
+             ok BOOL DEFAULT TRUE\n);"
+        );
+        assert_eq!(parse(&printed), Ok(Statement::CreateTable(c)));
+    }
+
+    #[test]
+    fn scripts_split_outside_quotes_and_comments() {
+        let script = "-- setup\nCREATE TABLE t (a TEXT DEFAULT ';'); -- trailing; note\n\
+                      INSERT INTO t {\"a\": \"x;y\"};;\nSELECT * FROM \"t;\" WHERE a = 'it\\'s;'";
+        assert_eq!(
+            split_script(script),
+            vec![
+                "CREATE TABLE t (a TEXT DEFAULT ';')",
+                "INSERT INTO t {\"a\": \"x;y\"}",
+                "SELECT * FROM \"t;\" WHERE a = 'it\\'s;'",
+            ]
+        );
+        assert!(split_script("-- only a comment\n ; ").is_empty());
+    }
+
+    fn row(v: Value) -> Map<String, Value> {
+        v.as_object().cloned().unwrap_or_default()
+    }
+
+    #[test]
+    fn like_wildcards() {
+        assert!(like("A%", "Ada"));
+        assert!(like("%da", "Ada"));
+        assert!(like("A_a", "Ada"));
+        assert!(like("%a%a%", "banana"));
+        assert!(like("%", ""));
+        assert!(!like("a%", "Ada"));
+        assert!(!like("A_", "Ada"));
+        assert!(!like("%x%", "banana"));
+    }
+}
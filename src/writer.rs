@@ -0,0 +1,173 @@
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+
+/// One snapshot write; returns the number of bytes it put on disk.
+pub type Job = Box<dyn FnOnce() -> Result<usize, String> + Send>;
+
+#[derive(Default)]
+struct State {
+    /// Newest job not yet started. A newer submit replaces it, so at most one write is
+    /// queued behind the one in flight.
+    pending: Option<Job>,
+    busy: bool,
+    shutdown: bool,
+    error: Option<String>,
+    written: u64,
+    coalesced: u64,
+    last_bytes: usize,
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Background thread that performs snapshot writes in submission order, skipping any
+/// snapshot superseded before it started. Dropping it finishes the queued work first.
+pub struct Writer {
+    shared: Arc<Shared>,
+    handle: Option<JoinHandle<()>>,
+}
+
+/// Counters reported through `storage_stats`.
+pub struct Stats {
+    pub written: u64,
+    pub coalesced: u64,
+    pub last_bytes: usize,
+}
+
+impl Writer {
+    pub fn spawn() -> Self {
+        let shared = Arc::new(Shared::default());
+        let worker = Arc::clone(&shared);
+        let handle = thread::Builder::new()
+            .name("rsn-db-writer".to_string())
+            .spawn(move || run(&worker))
+            .expect("failed to spawn the RSN DB writer thread");
+        Self {
+            shared,
+            handle: Some(handle),
+        }
+    }
+
+    /// Queues `job`, replacing a queued one that has not started. Reports (and clears) the
+    /// error of an earlier write, if any.
+    pub fn submit(&self, job: Job) -> Result<(), String> {
+        let mut state = self.shared.lock();
+        if state.pending.replace(job).is_some() {
+            state.coalesced += 1;
+        }
+        self.shared.changed.notify_all();
+        state.error.take().map_or(Ok(()), Err)
+    }
+
+    /// Blocks until every queued write has finished, then reports the first failure.
+    pub fn drain(&self) -> Result<(), String> {
+        let mut state = self.shared.lock();
+        while state.busy || state.pending.is_some() {
+            state = self
+                .shared
+                .changed
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        state.error.take().map_or(Ok(()), Err)
+    }
+
+    /// Whether every queued write has finished without an error still to report.
+    pub fn settled(&self) -> bool {
+        let state = self.shared.lock();
+        !state.busy && state.pending.is_none() && state.error.is_none()
+    }
+
+    pub fn stats(&self) -> Stats {
+        let state = self.shared.lock();
+        Stats {
+            written: state.written,
+            coalesced: state.coalesced,
+            last_bytes: state.last_bytes,
+        }
+    }
+}
+
+impl Drop for Writer {
+    fn drop(&mut self) {
+        self.shared.lock().shutdown = true;
+        self.shared.changed.notify_all();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn run(shared: &Shared) {
+    let mut state = shared.lock();
+    loop {
+        if let Some(job) = state.pending.take() {
+            state.busy = true;
+            drop(state);
+            let result = job();
+            state = shared.lock();
+            state.busy = false;
+            match result {
+                Ok(bytes) => {
+                    state.written += 1;
+                    state.last_bytes = bytes;
+                }
+                Err(e) => {
+                    state.error.get_or_insert(e);
+                }
+            }
+            shared.changed.notify_all();
+        } else if state.shutdown {
+            return;
+        } else {
+            state = shared
+                .changed
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[test]
+    fn queued_jobs_coalesce_and_drain() {
+        let writer = Writer::spawn();
+        let runs = Arc::new(AtomicUsize::new(0));
+        for i in 0..50 {
+            let runs = Arc::clone(&runs);
+            writer
+                .submit(Box::new(move || {
+                    thread::sleep(Duration::from_millis(2));
+                    runs.fetch_add(1, Ordering::SeqCst);
+                    Ok(i)
+                }))
+                .unwrap();
+        }
+        writer.drain().unwrap();
+        let stats = writer.stats();
+        assert_eq!(stats.last_bytes, 49);
+        assert_eq!(stats.written as usize, runs.load(Ordering::SeqCst));
+        assert_eq!(stats.written + stats.coalesced, 50);
+        assert!(stats.coalesced > 0);
+
+        writer
+            .submit(Box::new(|| Err("disk full".to_string())))
+            .unwrap();
+        assert_eq!(writer.drain(), Err("disk full".to_string()));
+        assert_eq!(writer.drain(), Ok(()));
+    }
+}
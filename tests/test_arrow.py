@@ -0,0 +1,79 @@
+"""Arrow interchange; needs a build with `maturin develop --features arrow` and pyarrow."""
+
+import pytest
+
+from rsn_db import Database, Query
+
+
+def _arrow_or_skip():
+    if not hasattr(Database, "query_arrow"):
+        pytest.skip("built without the arrow feature")
+    return pytest.importorskip("pyarrow")
+
+
+def test_query_arrow_types_and_filters():
+    pa = _arrow_or_skip()
+    db = Database()
+    db.create_table(
+        "events",
+        {
+            "name": {"type": "string", "required": True},
+            "count": {"type": "integer"},
+            "score": {"type": "float"},
+            "ok": {"type": "boolean"},
+            "tags": {"type": "array<string>"},
+            "meta": {"type": "json"},
+        },
+    )
+    db.insert("events", {"name": "a", "count": 1, "score": 0.5, "ok": True, "tags": ["x"], "meta": {"k": 1}})
+    db.insert("events", {"name": "b", "count": 2})
+    db.insert("events", {"name": "c", "count": 3, "ok": False})
+
+    table = db.query_arrow(Query("events").where_between("count", 2, 3).order_by("count", True))
+    assert isinstance(table, pa.Table)
+    assert table.column_names == ["id", "count", "meta", "name", "ok", "score", "tags"]
+    assert table.schema.field("count").type == pa.int64()
+    assert table.schema.field("score").type == pa.float64()
+    assert table.schema.field("ok").type == pa.bool_()
+    assert table.schema.field("name").type == pa.large_string()
+    assert table.to_pydict() == {
+        "id": [3, 2],
+        "count": [3, 2],
+        "meta": [None, None],
+        "name": ["c", "b"],
+        "ok": [False, None],
+        "score": [None, None],
+        "tags": [None, None],
+    }
+    first = db.query_arrow(Query("events").where_eq("name", "a")).to_pylist()[0]
+    assert (first["tags"], first["meta"]) == ('["x"]', '{"k":1}')
+    assert db.query_arrow(Query("events").where_eq("name", "zed")).num_rows == 0
+    with pytest.raises(KeyError):
+        db.query_arrow(Query("ghosts"))
+
+
+def test_query_arrow_matches_record_conversion_on_a_large_table():
+    _arrow_or_skip()
+    db = Database()
+    db.create_table(
+        "rows",
+        {
+            "n": {"type": "integer"},
+            "x": {"type": "float"},
+            "label": {"type": "string"},
+        },
+    )
+    size = 100_000
+    for start in range(0, size, 500):
+        db.insert_many("rows", [{"n": i, "x": i / 2, "label": f"row {i}"} for i in range(start, start + 500)])
+
+    records = db.query(Query("rows"))
+    table = db.query_arrow(Query("rows"))
+
+    assert len(records) == table.num_rows == size
+    assert table.to_pydict() == {
+        "id": [r.id for r in records],
+        "label": [r.data["label"] for r in records],
+        "n": [r.data["n"] for r in records],
+        "x": [r.data["x"] for r in records],
+    }
@@ -57,3 +57,12 @@ def test_open_db_context(tmp_path):
     with open_db(str(tmp_path / "o.rsndb")) as db:
         db.create_table("z", {"n": {"type": "string", "required": True}})
     assert (tmp_path / "o.rsndb").exists()
+
+
+def test_open_db_and_rsn_database_close_on_exit(tmp_path):
+    with open_db(str(tmp_path / "c.rsndb")) as db:
+        db.create_table("z", {"n": {"type": "integer"}})
+    assert db.closed
+    with RsnDatabase(str(tmp_path / "c.rsndb"), session_memory=False) as db:
+        db.insert("z", {"n": 1})
+    assert db.closed
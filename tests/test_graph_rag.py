@@ -0,0 +1,449 @@
+"""GraphRAG ingestion, retrieval and graph import/export."""
+
+import json
+import time
+from datetime import datetime
+from xml.etree import ElementTree
+
+import pytest
+
+from rsn_db import Database
+
+
+def test_graph_subcommands_inspect_and_forget_sources(tmp_path):
+    path = tmp_path / "graph.rsndb"
+    db = Database(str(path))
+    db.ingest("In Paris, Alice met Bob. Bob lives in Paris.", "notes")
+    db.ingest("Alice visited Rome.", "diary")
+
+    sources = db.execute_sql("GRAPH SOURCES")
+    assert [(s["source"], s["chunks"]) for s in sources] == [("diary", 1), ("notes", 1)]
+    assert sources == db.graph_sources()
+    entities = db.execute_sql("GRAPH ENTITIES")
+    assert entities[0] == {"name": "Alice", "type": "CONCEPT", "mentions": 2}
+    assert [e["name"] for e in entities] == ["Alice", "Bob", "Paris", "Rome"]
+    assert db.execute_sql("graph entities like 'p%'") == [
+        {"name": "Paris", "type": "CONCEPT", "mentions": 1}
+    ]
+    communities = db.execute_sql("GRAPH COMMUNITIES")
+    assert [c["size"] for c in communities] == [4]
+    assert communities[0]["top_entities"] == ["Alice", "Bob", "Paris"]
+    assert communities[0]["summary"] == (
+        "About Alice, Bob and Paris (4 entities). In Paris, Alice met Bob. Bob lives in Paris."
+    )
+    db.set_graph_summary(top_entities=2, sentences=1)
+    assert db.execute_sql("GRAPH COMMUNITIES")[0]["summary"] == (
+        "About Alice and Bob (4 entities). In Paris, Alice met Bob."
+    )
+    with pytest.raises(ValueError, match="top_entities must be at least 1"):
+        db.set_graph_summary(top_entities=0)
+    assert db.execute_sql("GRAPH NEIGHBORS alice")[0] == {"entity": "Bob", "weight": 1.0, "relations": ["-[met]->"]}
+    with pytest.raises(KeyError, match="no entity 'Zed'"):
+        db.execute_sql("GRAPH NEIGHBORS Zed")
+
+    assert db.execute_sql("GRAPH FORGET notes") == 1
+    with pytest.raises(KeyError, match="no source 'notes'"):
+        db.execute_sql("GRAPH FORGET notes")
+    reopened = Database(str(path))
+    assert [s["source"] for s in reopened.execute_sql("GRAPH SOURCES")] == ["diary"]
+    assert [e["name"] for e in reopened.execute_sql("GRAPH ENTITIES")] == ["Alice", "Rome"]
+    assert reopened.execute_sql("GRAPH COMMUNITIES")[0]["summary"] == (
+        "About Alice and Rome (2 entities). Alice visited Rome."
+    )
+    assert "No relevant" in reopened.graph_query("Paris")
+
+    for bad in ("GRAPH", "GRAPH SOURCES all", "GRAPH ENTITIES Alice", "GRAPH NEIGHBORS", "GRAPH DROP"):
+        with pytest.raises(ValueError, match="GRAPH format"):
+            db.execute_sql(bad)
+
+
+def test_ingest_chunk_size_and_sentence_overlap(tmp_path, monkeypatch):
+    monkeypatch.chdir(tmp_path)
+    db = Database()
+    text = "".join(f"Step {i:02} runs here. " for i in range(12))  # 20 characters a sentence
+
+    def chunks(source):
+        db.export_graph_json("graph.json")
+        found = json.loads((tmp_path / "graph.json").read_text())["chunks"]
+        return sorted((c["text"] for c in found if c["source"] == source), key=lambda t: t.split()[1:2])
+
+    db.ingest(text, "default")
+    assert len(chunks("default")) == 1
+    db.ingest(text, "small", chunk_size=60)
+    assert len(chunks("small")) == 4
+    db.ingest(text, "overlap", chunk_size=60, chunk_overlap=1)
+    overlapped = chunks("overlap")
+    assert len(overlapped) == 6
+    for before, after in zip(overlapped, overlapped[1:]):
+        assert after.strip().startswith(before.strip().split(". ")[-1].rstrip("."))
+
+    db.ingest("".join(f"{i:05}" for i in range(2000)), "solid", chunk_size=1000)
+    assert [len(t) for t in chunks("solid")] == [1000] * 10
+    with pytest.raises(ValueError, match="chunk_size must be positive"):
+        db.ingest(text, "bad", chunk_size=0)
+
+
+def test_ingesting_the_same_text_twice_adds_nothing(tmp_path, monkeypatch):
+    monkeypatch.chdir(tmp_path)
+    db = Database("graph.rsndb")
+    text = "".join(f"Alice met Bob in Paris on day {i}. " for i in range(40))
+
+    def graph():
+        db.export_graph_json("graph.json")
+        return json.loads((tmp_path / "graph.json").read_text())
+
+    first = db.ingest(text, "notes")
+    assert first == {"new_chunks": 3, "duplicate_chunks": 0}
+    before = graph()
+    assert db.ingest(text, "notes") == {"new_chunks": 0, "duplicate_chunks": 3}
+    after = graph()
+    assert len(after["relations"]) == len(before["relations"])
+    assert after["entities"] == before["entities"]
+    assert db.ingest(text, "copy")["new_chunks"] == 3
+    assert {e["name"]: e["mentions"] for e in graph()["entities"]}["Paris"] == 2 * {
+        e["name"]: e["mentions"] for e in before["entities"]
+    }["Paris"]
+    assert isinstance(Database(mode="snarky").ingest(text), str)
+
+
+def test_graph_forget_removes_only_what_the_source_added(tmp_path, monkeypatch):
+    monkeypatch.chdir(tmp_path)
+    db = Database("graph.rsndb")
+    db.import_graph({"entities": [{"name": "Acme", "entity_type": "ORG"}], "relations": []})
+    db.ingest("In Paris, Alice met Bob. Acme hired Bob.", "notes")
+    db.ingest("Alice visited Paris again.", "diary")
+
+    def graph():
+        db.export_graph_json("graph.json")
+        return json.loads((tmp_path / "graph.json").read_text())
+
+    before = graph()
+    removed = db.graph_forget("notes")
+    assert removed["chunks"] == 1
+    after = graph()
+    mentions = {e["name"]: e["mentions"] for e in after["entities"]}
+    assert "Bob" not in mentions
+    assert mentions["Alice"] == mentions["Paris"] == 1
+    assert mentions["Acme"] == 1
+    assert removed["entities"] == len(before["entities"]) - len(after["entities"])
+    assert removed["relations"] == len(before["relations"]) - len(after["relations"])
+    assert {c["source"] for c in after["chunks"]} == {"diary"}
+    assert all("Bob" not in (r["source"], r["target"]) for r in after["relations"])
+    assert "Bob" not in Database("graph.rsndb").graph_query("Bob")
+    with pytest.raises(KeyError):
+        db.graph_forget("notes")
+
+
+def test_graph_sources_reports_words_time_and_top_entities(tmp_path):
+    path = tmp_path / "graph.rsndb"
+    db = Database(str(path))
+    assert db.graph_sources() == []
+    before = time.time()
+    db.ingest("In Paris, Alice met Bob. Bob lives in Paris.", "notes")
+    db.ingest("Then Carol visited Rome. Then Carol likes Rome.", "diary", chunk_size=25)
+    db.import_graph({"chunks": [{"id": "x1", "text": "Imported text", "source": "nlp"}]})
+
+    diary, nlp, notes = db.graph_sources()
+    assert notes["chunks"] == 1 and notes["words"] == 9
+    assert notes["top_entities"] == ["Alice", "Bob", "Paris"]
+    assert diary["chunks"] == 2 and diary["words"] == 8
+    assert diary["top_entities"] == ["Carol", "Rome"]
+    stamped = datetime.fromisoformat(notes["ingested_at"].replace("Z", "+00:00"))
+    assert before - 1 <= stamped.timestamp() <= time.time() + 1
+    assert nlp == {"source": "nlp", "chunks": 1, "words": 2, "ingested_at": None, "top_entities": []}
+    assert Database(str(path)).graph_sources() == [diary, nlp, notes]
+
+
+def test_graph_reingest_replaces_a_source_in_one_step(tmp_path):
+    path = tmp_path / "graph.rsndb"
+    db = Database(str(path))
+    db.ingest("Then Alice met Bob. Then Carol met Dave.", "notes", chunk_size=22)
+    db.ingest("Alice visited Paris.", "diary")
+    before = db.graph_sources()
+
+    with pytest.raises(ValueError, match="chunk_size must be positive"):
+        db.graph_reingest("Erin met Frank.", "notes", chunk_size=0)
+    assert db.graph_sources() == before
+
+    changes = db.graph_reingest("Then Alice met Bob. Then Erin met Frank.", "notes", chunk_size=22)
+    assert changes == {
+        "removed_chunks": 1,
+        "added_chunks": 1,
+        "unchanged_chunks": 1,
+        "new_entities": ["Erin", "Frank"],
+        "dropped_entities": ["Carol", "Dave"],
+    }
+    assert db.graph_reingest("Then Alice met Bob. Then Erin met Frank.", "notes", chunk_size=22)["added_chunks"] == 0
+    reopened = Database(str(path))
+    notes = {s["source"]: s for s in reopened.graph_sources()}["notes"]
+    assert notes["top_entities"] == ["Alice", "Bob", "Erin", "Frank"]
+    assert "Carol" not in reopened.graph_query("Carol")
+    assert db.graph_reingest("Zed met Yan.", "fresh")["added_chunks"] == 1
+
+
+def test_graph_query_top_k_and_min_score():
+    db = Database()
+    for i in range(5):
+        db.ingest(f"Rust note {i}.", f"doc{i}")
+    db.ingest("Python only here.", "other")
+
+    assert db.graph_query("rust").count("[Chunk ID:") == 3
+    assert db.graph_query("rust", top_k=0).count("[Chunk ID:") == 5
+    assert db.graph_query("rust", top_k=1).count("[Chunk ID:") == 1
+    assert "No relevant information found" in db.graph_query("rust", min_score=1.0)
+
+
+def test_graph_query_ranks_with_bm25_on_request_or_by_default(tmp_path):
+    path = tmp_path / "graph.rsndb"
+    db = Database(str(path))
+    db.ingest("rust rust rust rust cargo", "repeats")
+    db.ingest("rust", "single")
+    db.ingest("python java go", "other")
+    db.ingest("ruby perl", "more")
+
+    def top(answer):
+        return answer.split("Source: ")[1].split("]")[0]
+
+    assert top(db.graph_query("rust", top_k=1)) == "single"
+    assert top(db.graph_query("rust", top_k=1, ranking="bm25")) == "repeats"
+    with pytest.raises(ValueError, match="ranking must be 'tfidf' or 'bm25'"):
+        db.graph_query("rust", ranking="pagerank")
+    with pytest.raises(ValueError, match="b must be between 0 and 1"):
+        db.set_graph_ranking("bm25", b=2)
+
+    db.set_graph_ranking("BM25")
+    reopened = Database(str(path))
+    assert top(reopened.graph_query("rust", top_k=1)) == "repeats"
+    assert top(reopened.graph_query("rust", top_k=1, ranking="tfidf")) == "single"
+    reopened.set_graph_ranking("bm25", b=1.0)
+    assert top(reopened.graph_query("rust", top_k=1)) == "single"
+
+
+def test_stopwords_and_punctuation_do_not_make_entities_or_tokens(tmp_path):
+    path = tmp_path / "graph.rsndb"
+    db = Database(str(path))
+    db.ingest("The data. However, the data was fine. This report cites Data Lake.", "notes")
+    db.ingest("Yesterday the team met. Alice met Bob. Later Alice left.", "diary")
+
+    names = {e["name"] for e in db.execute_sql("GRAPH ENTITIES")}
+    assert names == {"Data Lake", "Alice", "Bob"}
+    assert db.graph_query("data").count("[Chunk ID:") == 1
+    assert "No relevant" in db.graph_query("the however")
+
+    db.graph_set_stopwords(["lake", "Fine"])
+    assert "No relevant" in db.graph_query("fine")
+    db.ingest("We swam at Fine Lake with Carol.", "trip")
+    assert "Fine Lake" not in {e["name"] for e in db.execute_sql("GRAPH ENTITIES")}
+    reopened = Database(str(path))
+    assert "No relevant" in reopened.graph_query("lake fine")
+    assert "Carol" in {e["name"] for e in reopened.execute_sql("GRAPH ENTITIES")}
+
+
+def test_entity_classifier_overrides_heuristic_types(tmp_path):
+    path = tmp_path / "graph.rsndb"
+    db = Database(str(path))
+    db.ingest("Dr. Jane Doe joined Acme Corp on March 5, 2024.", "news")
+    db.ingest("We hiked in Glacier Park.", "trip")
+    types = {e["name"]: e["type"] for e in db.execute_sql("GRAPH ENTITIES")}
+    assert types == {
+        "Jane Doe": "PERSON",
+        "Acme Corp": "ORG",
+        "March 5, 2024": "DATE",
+        "Glacier Park": "CONCEPT",
+    }
+
+    seen = []
+
+    def classify(name, suggested):
+        seen.append((name, suggested))
+        return "place" if name.endswith("Lake") else None
+
+    db.graph_set_entity_classifier(classify)
+    db.ingest("Later we swam in Crater Lake with Jane Doe.", "trip2")
+    assert seen == [("Crater Lake", "CONCEPT")]
+    assert "Source: trip2" in db.graph_query("swam hiked", entity_types=["place"])
+    assert "Source: trip]" not in db.graph_query("swam hiked", entity_types=["PLACE"])
+    assert "No relevant" in db.graph_query("hiked", entity_types=["person", "ORG"])
+
+    def broken(name, suggested):
+        raise RuntimeError("classifier down")
+
+    db.graph_set_entity_classifier(broken)
+    with pytest.raises(RuntimeError, match="classifier down"):
+        db.ingest("Then Bob Smith arrived.", "trip3")
+    assert "Bob Smith" not in {e["name"] for e in db.execute_sql("GRAPH ENTITIES")}
+    db.graph_set_entity_classifier()
+    with pytest.raises(TypeError):
+        db.graph_set_entity_classifier("PLACE")
+
+    types = {e["name"]: e["type"] for e in Database(str(path)).execute_sql("GRAPH ENTITIES")}
+    assert types["Crater Lake"] == "PLACE"
+    assert "Bob Smith" not in types
+
+
+def test_verb_phrases_give_relations_a_type_and_direction():
+    db = Database()
+    db.ingest("In 2020 Acme Corp acquired Beta Ltd. Jane Doe works for Beta Ltd in Oslo.", "news")
+
+    assert db.execute_sql("GRAPH NEIGHBORS beta ltd") == [
+        {"entity": "Acme Corp", "weight": 1.0, "relations": ["<-[acquired]-"]},
+        {"entity": "Jane Doe", "weight": 1.0, "relations": ["<-[works_for]-"]},
+        {"entity": "Oslo", "weight": 1.0, "relations": ["-[CO_OCCURS]-"]},
+    ]
+    out = db.graph_query("Who does Jane Doe work for?")
+    assert "[Community Context: " in out
+    assert "Jane Doe -[works_for]-> Beta Ltd" in out
+    assert "Acme Corp -[acquired]-> Beta Ltd" not in out
+
+
+def test_graph_entity_and_search_entities(tmp_path):
+    path = tmp_path / "graph.rsndb"
+    db = Database(str(path))
+    db.ingest("In Paris, Alice met Bob.", "notes")
+    db.ingest("Again Alice visited Paris with Alan.", "diary")
+
+    alice = db.graph_entity("alice")
+    assert {k: alice[k] for k in ("name", "type", "mentions")} == {
+        "name": "Alice",
+        "type": "CONCEPT",
+        "mentions": 2,
+    }
+    assert alice["related"] == db.execute_sql("GRAPH NEIGHBORS Alice")
+    assert len(alice["chunks"]) == 2 and alice["chunks"] == sorted(alice["chunks"])
+    assert len(db.graph_entity("Bob")["chunks"]) == 1
+    with pytest.raises(KeyError, match="no entity 'Zed'"):
+        db.graph_entity("Zed")
+
+    assert [e["name"] for e in db.graph_search_entities("al")] == ["Alice", "Alan"]
+    assert db.graph_search_entities("AL", limit=1) == [{"name": "Alice", "type": "CONCEPT", "mentions": 2}]
+    assert [e["name"] for e in db.graph_search_entities("%r%")] == ["Paris"]
+    assert db.graph_search_entities("zed") == []
+
+    db.graph_forget("notes")
+    assert len(Database(str(path)).graph_entity("Paris")["chunks"]) == 1
+
+
+def test_graph_neighbors_by_hop_and_path_between_entities():
+    db = Database()
+    db.ingest("In Paris, Alice met Bob.", "notes")
+    db.ingest("Later Bob visited Rome.", "diary")
+    db.ingest("We saw Carol.", "misc")
+
+    assert db.graph_neighbors("alice") == {
+        1: [
+            {"entity": "Bob", "via": "Alice", "weight": 1.0},
+            {"entity": "Paris", "via": "Alice", "weight": 1.0},
+        ]
+    }
+    assert db.graph_neighbors("Alice", depth=3)[2] == [{"entity": "Rome", "via": "Bob", "weight": 1.0}]
+    assert db.graph_neighbors("Alice", min_weight=2.0) == {}
+    with pytest.raises(ValueError):
+        db.graph_neighbors("Alice", depth=0)
+
+    assert db.graph_path("Alice", "rome") == [("Alice", "met", "Bob"), ("Bob", "visited", "Rome")]
+    assert db.graph_path("Alice", "Rome", max_hops=1) is None
+    assert db.graph_path("Alice", "Carol") is None
+    assert db.graph_path("Bob", "bob") == []
+
+    with pytest.raises(KeyError, match="no entity 'Alcie' in the graph; did you mean 'Alice'"):
+        db.graph_path("Alcie", "Rome")
+    with pytest.raises(KeyError, match="did you mean 'Rome'"):
+        db.graph_neighbors("Ro")
+    with pytest.raises(KeyError) as unknown:
+        db.graph_path("Alice", "Zed")
+    assert unknown.value.args == ("no entity 'Zed' in the graph",)
+
+
+def test_graph_stats_counts_index_entities_and_communities():
+    db = Database()
+    empty = db.graph_stats()
+    assert empty["community_sizes"] == {"min": None, "median": None, "max": None}
+    assert (empty["chunks"], empty["relation_weight"], empty["top_entities"]) == (0, 0.0, [])
+
+    db.ingest("In Paris, Alice met Bob. Dr. Carol Smith joined Acme Corp.", "notes")
+    db.ingest("Later Bob visited Rome.", "diary")
+    db.ingest("We saw Dave.", "misc")
+    stats = db.graph_stats()
+    assert stats["chunks"] == 3
+    assert stats["vocabulary"] > 0 and stats["indexed_words"] >= stats["vocabulary"]
+    assert stats["entities"] == 7
+    assert stats["entity_types"] == {"CONCEPT": 5, "ORG": 1, "PERSON": 1}
+    assert stats["relation_weight"] == stats["relations"] > 0
+    assert stats["communities"] == 2
+    assert stats["community_sizes"] == {"min": 1, "median": 3.5, "max": 6}
+    assert stats["top_entities"][0] == {"name": "Bob", "mentions": 2}
+    assert len(stats["top_entities"]) == 7
+
+    text = db.execute_sql("STATS")
+    assert "\n  entity types: CONCEPT 5, ORG 1, PERSON 1\n" in text
+    assert "\n  community sizes: min 1, median 3.5, max 6\n" in text
+    assert "\n  top entities: Bob (2), " in text
+
+
+def test_export_graph_writes_graphml_and_dot(tmp_path, monkeypatch):
+    monkeypatch.chdir(tmp_path)
+    db = Database()
+    db.ingest("In Paris, Alice met Bob. Bob lives in Paris.", "notes")
+    db.ingest("Alice visited Rome.", "diary")
+
+    db.export_graph("graph.graphml")
+    ns = {"g": "http://graphml.graphdrawing.org/xmlns"}
+    root = ElementTree.parse("graph.graphml").getroot()
+    nodes = {
+        n.get("id"): {d.get("key"): d.text for d in n.findall("g:data", ns)}
+        for n in root.iterfind("g:graph/g:node", ns)
+    }
+    assert nodes["Alice"] == {"entity_type": "CONCEPT", "mentions": "2"}
+    assert sorted(nodes) == ["Alice", "Bob", "Paris", "Rome"]
+    edges = root.findall("g:graph/g:edge", ns)
+    assert all(e.get("source") in nodes and e.get("target") in nodes for e in edges)
+    assert {d.get("key") for d in edges[0]} == {"relation_type", "weight"}
+
+    db.export_graph("graph.dot", format="DOT", min_mentions=2)
+    dot = (tmp_path / "graph.dot").read_text()
+    assert dot == 'graph knowledge {\n  "Alice" [entity_type="CONCEPT", mentions=2];\n}\n'
+    db.export_graph("none.graphml", min_mentions=5)
+    assert ElementTree.parse("none.graphml").getroot().findall("g:graph/g:node", ns) == []
+    with pytest.raises(ValueError, match="format must be 'graphml' or 'dot'"):
+        db.export_graph("graph.gexf", format="gexf")
+
+
+def test_import_graph_merges_external_data_and_round_trips(tmp_path, monkeypatch):
+    monkeypatch.chdir(tmp_path)
+    db = Database("graph.rsndb")
+    db.ingest("In Paris, Alice met Bob.", "notes")
+    pipeline = {
+        "chunks": [{"id": "c1", "text": "Alice founded Acme Corp.", "source": "nlp"}],
+        "entities": [
+            {"name": "Alice", "entity_type": "PERSON", "mentions": 4},
+            {"name": "Acme Corp", "entity_type": "ORG"},
+        ],
+        "relations": [{"source": "Alice", "target": "Acme Corp", "relation_type": "FOUNDED", "weight": 0.9}],
+    }
+    assert db.import_graph(pipeline) == {"chunks": 1, "entities": 2, "relations": 1, "created": []}
+    entities = {e["name"]: e for e in db.execute_sql("GRAPH ENTITIES")}
+    assert entities["Alice"] == {"name": "Alice", "type": "CONCEPT", "mentions": 5}
+    assert entities["Acme Corp"]["type"] == "ORG"
+    assert "Acme" in db.graph_query("founded")
+    assert [c["size"] for c in db.execute_sql("GRAPH COMMUNITIES")] == [4]
+
+    with pytest.raises(ValueError, match="unknown entity 'Zed'"):
+        db.import_graph({"relations": [{"source": "Alice", "target": "Zed", "relation_type": "KNOWS"}]})
+    with pytest.raises(ValueError, match="invalid graph"):
+        db.import_graph({"nodes": []})
+    report = db.import_graph(
+        {"relations": [{"source": "Alice", "target": "Zed", "relation_type": "KNOWS"}]}, auto_create_entities=True
+    )
+    assert report["created"] == ["Zed"]
+
+    db.export_graph_json("graph.json")
+    backup = json.loads((tmp_path / "graph.json").read_text())
+    assert sorted(backup) == ["chunks", "entities", "relations"]
+    copy = Database()
+    copy.import_graph("graph.json")
+    assert copy.execute_sql("GRAPH ENTITIES") == db.execute_sql("GRAPH ENTITIES")
+    sources = copy.execute_sql("GRAPH SOURCES")
+    assert [(s["source"], s["chunks"]) for s in sources] == [("nlp", 1), ("notes", 1)]
+    assert Database("graph.rsndb").execute_sql("GRAPH NEIGHBORS Zed") == [
+        {"entity": "Alice", "weight": 1.0, "relations": ["<-[KNOWS]-"]}
+    ]
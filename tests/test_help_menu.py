@@ -84,3 +84,44 @@ def test_mempalace_help_snarky_remark():
     assert "MemPalace" in text
     # footer snark line present (one of the pool)
     assert len(text.splitlines()) > 5
+
+
+def test_engine_help_lists_every_dispatched_command():
+    import re
+
+    from rsn_db import Database
+
+    listing = Database().execute_sql("HELP")
+    names = re.findall(r"^  ([A-Z_]+)  ", listing, flags=re.M)
+    assert {"SELECT", "INSERT", "UPDATE", "DELETE", "CREATE", "BATCH", "SAVEPOINT", "HELP"} <= set(names)
+    for name in names:
+        usage = Database().execute_sql(f"help {name.lower()}")
+        assert usage.startswith(name)
+        assert "Examples:" in usage
+        # Every listed command reaches a handler rather than the unknown-command path.
+        try:
+            Database().execute_sql(name)
+        except Exception as exc:
+            assert "unknown command" not in str(exc).lower(), name
+            assert "Did you mean" not in str(exc), name
+
+
+def test_engine_help_framing_and_typos():
+    import pytest
+
+    from rsn_db import Database
+
+    professional = Database().execute_sql("HELP")
+    friendly = Database(mode="friendly").execute_sql("HELP")
+    assert professional.startswith("RSN DB commands\n\n")
+    assert friendly.split("\n\n", 1)[1] == professional.split("\n\n", 1)[1]
+    assert Database(mode="snarky").execute_sql("HELP").endswith("HELP <command> shows details and examples.")
+
+    with pytest.raises(ValueError, match="Did you mean DELETE"):
+        Database().execute_sql("DELTE FROM t ALL")
+    with pytest.raises(ValueError, match="Did you mean COMMIT"):
+        Database().execute_sql("comit")
+    with pytest.raises(RuntimeError, match="unknown command"):
+        Database().execute_sql("FROBNICATE")
+    with pytest.raises(KeyError, match="did you mean SELECT"):
+        Database().execute_sql("HELP SELCT")
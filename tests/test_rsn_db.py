@@ -1,6 +1,12 @@
-from rsn_db import Database, Query
+from rsn_db import Database, EncryptionKeyError, Query
 import pytest
+import csv
+import ctypes
+import gzip
+import json
 import os
+import sqlite3
+import time
 
 def test_end_to_end(tmp_path):
     # Use relative path for db
@@ -83,7 +89,7 @@ def test_jsonl_and_sqlite_roundtrip(tmp_path):
         },
     )
     imported_jsonl = db.import_jsonl("users_imported", jsonl_path)
-    assert imported_jsonl == 1
+    assert imported_jsonl == {"inserted": 1, "replaced": 0, "skipped": []}
 
     db.create_table(
         "users_from_sqlite",
@@ -94,7 +100,7 @@ def test_jsonl_and_sqlite_roundtrip(tmp_path):
         },
     )
     imported_sqlite = db.import_sqlite("users_from_sqlite", sqlite_path, "users")
-    assert imported_sqlite == 1
+    assert imported_sqlite["inserted"] == 1
 
     rows = db.fetch_all("users_from_sqlite")
     assert len(rows) == 1
@@ -213,3 +219,1438 @@ def test_jsonl_import_limits(tmp_path):
             db.import_jsonl("users", too_many_lines_path)
     finally:
         os.chdir(cwd)
+
+
+def test_jsonl_export_writes_records_in_id_order(tmp_path):
+    cwd = os.getcwd()
+    try:
+        os.chdir(tmp_path)
+        db = Database()
+        db.create_table(
+            "items",
+            {"n": {"type": "integer"}, "twice": {"type": "integer", "computed": "n * 2"}},
+        )
+        db.insert_many("items", [{"n": n} for n in range(2000)])
+        db.delete("items", 5)
+        db.update("items", 3, {"n": -1})
+
+        db.export_jsonl("items", "items.jsonl")
+        with open("items.jsonl") as f:
+            lines = f.read().splitlines()
+        assert len(lines) == 1999 and all(line.startswith('{"id":') for line in lines)
+        ids = [json.loads(line)["id"] for line in lines]
+        assert ids == sorted(ids) and 5 not in ids
+        assert json.loads(lines[2]) == {"id": 3, "n": -1, "twice": -2}
+
+        db.export_jsonl(Query("items").where_between("n", -1, 1).order_by("n", True).select("twice"), "top.jsonl")
+        with open("top.jsonl") as f:
+            assert f.read() == '{"id":2,"twice":2}\n{"id":1,"twice":0}\n{"id":3,"twice":-2}\n'
+    finally:
+        os.chdir(cwd)
+
+
+def test_jsonl_gzip_export_and_import(tmp_path):
+    cwd = os.getcwd()
+    try:
+        os.chdir(tmp_path)
+        db = Database()
+        db.create_table("notes", {"text": {"type": "string"}, "n": {"type": "integer"}})
+        db.insert_many("notes", [{"text": "note %d" % i, "n": i} for i in range(500)])
+
+        db.export_jsonl("notes", "notes.jsonl.gz")
+        db.export_jsonl("notes", "notes.bin", compression="gzip")
+        db.export_jsonl("notes", "plain.gz", compression="none")
+        with gzip.open("notes.jsonl.gz", "rt") as f:
+            assert json.loads(f.readline()) == {"id": 1, "text": "note 0", "n": 0}
+        with open("notes.bin", "rb") as f:
+            assert f.read(2) == b"\x1f\x8b"
+        with open("plain.gz") as f:
+            assert json.loads(f.readline())["id"] == 1
+        with pytest.raises(ValueError, match="compression must be 'gzip' or 'none', not 'zip'"):
+            db.export_jsonl("notes", "notes.zip", compression="zip")
+
+        db.create_table("copy", {"text": {"type": "string"}, "n": {"type": "integer"}})
+        assert db.import_jsonl("copy", "notes.jsonl.gz")["inserted"] == 500
+        # Content decides, whatever the name says.
+        assert db.import_jsonl("copy", "notes.bin")["inserted"] == 500
+        assert db.import_jsonl("copy", "plain.gz")["inserted"] == 500
+        assert db.get("copy", 1000).data == {"text": "note 499", "n": 499}
+        with pytest.raises(OSError):
+            db.import_jsonl("copy", "plain.gz", compression="gzip")
+
+        with gzip.open("bomb.jsonl.gz", "wb") as f:
+            f.write(b" " * (10 * 1024 * 1024 + 1))
+        assert os.path.getsize("bomb.jsonl.gz") < 100 * 1024
+        with pytest.raises(ValueError, match="JSONL import exceeds max file size"):
+            db.import_jsonl("copy", "bomb.jsonl.gz")
+    finally:
+        os.chdir(cwd)
+
+
+def test_csv_roundtrip_quoting_types_and_row_errors(tmp_path):
+    cwd = os.getcwd()
+    try:
+        os.chdir(tmp_path)
+        db = Database("csv.rsndb")
+        schema = {
+            "name": {"type": "string", "required": True},
+            "age": {"type": "integer"},
+            "active": {"type": "boolean"},
+            "tags": {"type": "array<string>"},
+        }
+        db.create_table("users", schema)
+        db.insert("users", {"name": 'Ana "the; first"\nline two', "age": 30, "active": True, "tags": ["a", "b"]})
+        db.insert("users", {"name": "Bo"})
+
+        db.export_csv("users", "users.csv")
+        with open("users.csv", newline="") as f:
+            rows = list(csv.reader(f))
+        assert rows[0] == ["id", "active", "age", "name", "tags"]
+        assert rows[1] == ["1", "true", "30", 'Ana "the; first"\nline two', '["a","b"]']
+        assert rows[2] == ["2", "", "", "Bo", ""]
+
+        db.create_table("copy", schema)
+        assert db.import_csv("copy", "users.csv") == {"inserted": 2, "replaced": 0, "skipped": []}
+        first, second = db.fetch_all("copy")
+        assert first.data == db.get("users", 1).data
+        assert second.data == {"name": "Bo", "age": None, "active": None, "tags": None}
+
+        db.export_csv("users", "plain.csv", delimiter=";", include_id=False)
+        with open("plain.csv") as f:
+            assert f.readline() == "active;age;name;tags\n"
+        db.create_table("headerless", schema)
+        with open("headerless.csv", "w") as f:
+            f.write("yes;41;Cy;\n")
+        assert db.import_csv("headerless", "headerless.csv", has_header=False, delimiter=";")["inserted"] == 1
+        assert db.fetch_all("headerless")[0].data["active"] is True
+
+        with open("bad.csv", "w") as f:
+            f.write("name,age\nDee,40\nEd,old\nHal,7,extra\nFi,9\n")
+        with pytest.raises(ValueError, match="2 CSV row\\(s\\) failed.*line 3: .*age.*line 4: expected 2 column"):
+            db.import_csv("copy", "bad.csv")
+        assert len(db.fetch_all("copy")) == 2
+        report = db.import_csv("copy", "bad.csv", skip_errors=True)
+        assert report["inserted"] == 2
+        assert [s["line"] for s in report["skipped"]] == [3, 4]
+        assert [r.data["name"] for r in db.fetch_all("copy")][2:] == ["Dee", "Fi"]
+
+        with open("extra.csv", "w") as f:
+            f.write("name,nickname\nGil,G\n")
+        with pytest.raises(ValueError, match="'nickname' is not a field"):
+            db.import_csv("copy", "extra.csv")
+        with pytest.raises(ValueError, match="single ASCII character"):
+            db.export_csv("users", "users.csv", delimiter="::")
+    finally:
+        os.chdir(cwd)
+
+def test_imports_are_atomic_and_resolve_unique_conflicts(tmp_path):
+    cwd = os.getcwd()
+    try:
+        os.chdir(tmp_path)
+        db = Database("conflicts.rsndb")
+        schema = {
+            "email": {"type": "string", "unique": True},
+            "name": {"type": "string"},
+            "age": {"type": "integer"},
+        }
+        db.create_table("users", schema)
+        db.insert("users", {"email": "a@x", "name": "Ana", "age": 30})
+        with open("users.jsonl", "w") as f:
+            f.write('{"email": "b@x", "name": "Bo"}\n\n')
+            f.write('{"email": "a@x", "name": "Ana B.", "age": 31}\n')
+            f.write('{"email": "c@x", "name": "Cy"}\n')
+
+        with pytest.raises(ValueError, match="1 JSONL row\\(s\\) failed, nothing was imported: line 3: .*email"):
+            db.import_jsonl("users", "users.jsonl")
+        assert [r.data["email"] for r in db.fetch_all("users")] == ["a@x"]
+        assert len(Database("conflicts.rsndb").fetch_all("users")) == 1
+
+        report = db.import_jsonl("users", "users.jsonl", on_conflict="skip")
+        assert report["inserted"] == 2 and report["replaced"] == 0
+        assert [(s["line"], "email" in s["error"]) for s in report["skipped"]] == [(3, True)]
+        assert db.get("users", 1).data["name"] == "Ana"
+
+        db.create_table("copy", schema)
+        db.insert("copy", {"email": "a@x", "name": "Old", "age": 1})
+        report = db.import_jsonl("copy", "users.jsonl", on_conflict="replace")
+        assert report == {"inserted": 2, "replaced": 1, "skipped": []}
+        assert db.get("copy", 1).data == {"email": "a@x", "name": "Ana B.", "age": 31}
+        assert len(Database("conflicts.rsndb").fetch_all("copy")) == 3
+
+        with open("mixed.csv", "w") as f:
+            f.write("email,name,age\nd@x,Di,40\nb@x,Bob,41\ne@x,Ed,old\n")
+        with pytest.raises(ValueError, match="line 4: .*age"):
+            db.import_csv("copy", "mixed.csv", on_conflict="replace")
+        assert db.get("copy", 2).data["name"] == "Bo" and len(db.fetch_all("copy")) == 3
+        report = db.import_csv("copy", "mixed.csv", on_conflict="replace", skip_errors=True)
+        assert (report["inserted"], report["replaced"], [s["line"] for s in report["skipped"]]) == (1, 1, [4])
+        assert db.get("copy", 2).data["name"] == "Bob"
+
+        db.export_sqlite("users", "users.sqlite")
+        assert db.import_sqlite("copy", "users.sqlite", "users", on_conflict="skip")["skipped"][0]["line"] == 1
+        with pytest.raises(ValueError, match="SQLite row\\(s\\) failed"):
+            db.import_sqlite("copy", "users.sqlite", "users")
+        with pytest.raises(ValueError, match="on_conflict must be"):
+            db.import_jsonl("users", "users.jsonl", on_conflict="merge")
+    finally:
+        os.chdir(cwd)
+
+def test_import_sqlite_creates_tables_from_the_source_schema(tmp_path):
+    cwd = os.getcwd()
+    try:
+        os.chdir(tmp_path)
+        conn = sqlite3.connect("legacy.sqlite")
+        conn.executescript(
+            """
+            CREATE TABLE users (
+                id INTEGER PRIMARY KEY, email VARCHAR(80) NOT NULL UNIQUE, age INT,
+                score DOUBLE, active BOOLEAN, joined DATETIME, avatar BLOB, balance NUMERIC
+            );
+            CREATE TABLE tags (name TEXT, kind TEXT);
+            CREATE UNIQUE INDEX tags_pair ON tags (name, kind);
+            INSERT INTO users VALUES (7, 'a@x', 30, 1.5, 1, '2024-01-02 03:04:05', x'0102', 9.5);
+            INSERT INTO users VALUES (8, 'b@x', NULL, NULL, 0, NULL, NULL, NULL);
+            INSERT INTO tags VALUES ('red', 'color');
+            """
+        )
+        conn.commit()
+        conn.close()
+
+        db = Database("migrated.rsndb")
+        report = db.import_sqlite("people", "legacy.sqlite", "users", create_table=True)
+        assert (report["inserted"], report["skipped"]) == (2, [])
+        assert report["warnings"] == ["column 'balance' has SQLite type NUMERIC; imported as json"]
+        fields = db.schema("people")["fields"]
+        assert {f: d["type"] for f, d in fields.items()} == {
+            "email": "string",
+            "age": "integer",
+            "score": "float",
+            "active": "boolean",
+            "joined": "datetime",
+            "avatar": "string",
+            "balance": "json",
+        }
+        assert (fields["email"]["required"], fields["email"]["unique"], fields["age"]["unique"]) == (True, True, False)
+        first = db.get("people", 1).data
+        assert (first["email"], first["active"], first["joined"], first["avatar"], first["balance"]) == (
+            "a@x",
+            True,
+            "2024-01-02T03:04:05Z",
+            "AQI=",
+            9.5,
+        )
+        assert Database("migrated.rsndb").get("people", 2).data["email"] == "b@x"
+
+        with pytest.raises(ValueError, match="already exists"):
+            db.import_sqlite("people", "legacy.sqlite", "users", create_table=True)
+        with pytest.raises(ValueError, match="no such table"):
+            db.import_sqlite("ghosts", "legacy.sqlite", create_table=True)
+        with pytest.raises(KeyError):
+            db.schema("ghosts")
+
+        reports = db.import_sqlite_all("legacy.sqlite")
+        assert sorted(reports) == ["tags", "users"]
+        assert reports["tags"]["warnings"] == ["unique index 'tags_pair' is partial or spans several columns; not enforced"]
+        assert len(db.fetch_all("users")) == 2 and db.fetch_all("tags")[0].data == {"name": "red", "kind": "color"}
+        with pytest.raises(ValueError, match="already exists"):
+            db.import_sqlite_all("legacy.sqlite")
+        assert len(db.fetch_all("tags")) == 1
+    finally:
+        os.chdir(cwd)
+
+def test_imports_map_and_transform_columns(tmp_path):
+    cwd = os.getcwd()
+    try:
+        os.chdir(tmp_path)
+        db = Database()
+        db.create_table("users", {"email": {"type": "string"}, "age": {"type": "integer"}})
+        with open("users.csv", "w") as f:
+            f.write("e-mail,age,notes\nA@X.COM,30,vip\n")
+
+        report = db.import_csv(
+            "users", "users.csv", field_map={"e-mail": "email"}, transforms={"email": str.lower}
+        )
+        assert report["columns"] == {"mapped": {"e-mail": "email"}, "dropped": ["notes"], "transformed": ["email"]}
+        assert db.get("users", 1).data == {"email": "a@x.com", "age": 30}
+        with pytest.raises(ValueError, match="'e-mail' is not a field"):
+            db.import_csv("users", "users.csv")
+
+        with pytest.raises(ValueError, match="maps both 'e-mail' and 'age' to 'email'"):
+            db.import_csv("users", "users.csv", field_map={"e-mail": "email", "age": "email"})
+        with open("twice.csv", "w") as f:
+            f.write("e-mail,email\na@x,b@x\n")
+        with pytest.raises(ValueError, match="columns 'e-mail' and 'email' both map to 'email'"):
+            db.import_csv("users", "twice.csv", field_map={"e-mail": "email"})
+        with pytest.raises(ValueError, match="'notes' is not mapped"):
+            db.import_csv("users", "users.csv", field_map={"e-mail": "email"}, unmapped="error")
+        with pytest.raises(ValueError, match="not a field"):
+            db.import_csv("users", "users.csv", field_map={"e-mail": "mail"})
+        with pytest.raises(TypeError, match="not callable"):
+            db.import_csv("users", "users.csv", transforms={"age": 3})
+
+        with open("users.jsonl", "w") as f:
+            f.write('{"mail": "b@x", "years": "41"}\n{"mail": "c@x", "years": "old"}\n')
+        with pytest.raises(ValueError, match="line 2: transform for 'age' failed"):
+            db.import_jsonl("users", "users.jsonl", field_map={"mail": "email", "years": "age"}, transforms={"age": int})
+        assert len(db.fetch_all("users")) == 1
+        with open("users.jsonl", "w") as f:
+            f.write('{"mail": "b@x", "years": "41"}\n')
+        report = db.import_jsonl("users", "users.jsonl", field_map={"mail": "email", "years": "age"}, transforms={"age": int})
+        assert report["columns"]["mapped"] == {"mail": "email", "years": "age"}
+        assert db.get("users", 2).data == {"email": "b@x", "age": 41}
+
+        conn = sqlite3.connect("legacy.sqlite")
+        conn.executescript('CREATE TABLE people ("e-mail" TEXT, years INT); INSERT INTO people VALUES (\'d@x\', 5);')
+        conn.commit()
+        conn.close()
+        report = db.import_sqlite("people", "legacy.sqlite", create_table=True, field_map={"e-mail": "email"})
+        assert (report["warnings"], report["columns"]["mapped"]) == ([], {"e-mail": "email"})
+        assert db.fetch_all("people")[0].data == {"email": "d@x", "years": 5}
+    finally:
+        os.chdir(cwd)
+
+
+def test_imports_preserve_ids_across_a_backup_and_restore(tmp_path):
+    cwd = os.getcwd()
+    try:
+        os.chdir(tmp_path)
+        db = Database()
+        db.create_table("users", {"name": {"type": "string", "unique": True}})
+        db.create_table("posts", {"owner": {"type": "integer", "references": "users"}, "title": {"type": "string"}})
+        for name in ["ann", "bob", "cat"]:
+            db.insert("users", {"name": name})
+        db.delete("users", 2)
+        db.insert("posts", {"owner": 3, "title": "hello"})
+        db.export_jsonl("users", "users.jsonl")
+        db.export_csv("posts", "posts.csv")
+
+        db.truncate_table("posts", reset_ids=True)
+        db.truncate_table("users", reset_ids=True)
+        assert db.import_jsonl("users", "users.jsonl", preserve_ids=True)["inserted"] == 2
+        assert db.import_csv("posts", "posts.csv", preserve_ids=True)["inserted"] == 1
+        assert [(r.id, r.data["name"]) for r in db.fetch_all("users")] == [(1, "ann"), (3, "cat")]
+        assert db.get("posts", 1).data == {"owner": 3, "title": "hello"}
+        assert db.insert("users", {"name": "dan"}) == 4
+
+        with pytest.raises(ValueError, match="record id `3` already exists"):
+            db.import_jsonl("users", "users.jsonl", preserve_ids=True)
+        with open("renamed.jsonl", "w") as f:
+            f.write('{"id": 3, "name": "cathy"}\n{"id": 9, "name": "eve"}\n')
+        report = db.import_jsonl("users", "renamed.jsonl", on_conflict="skip", preserve_ids=True)
+        assert (report["inserted"], len(report["skipped"])) == (1, 1)
+        report = db.import_jsonl("users", "renamed.jsonl", on_conflict="replace", preserve_ids=True)
+        assert (report["inserted"], report["replaced"]) == (0, 2)
+        assert db.get("users", 3).data["name"] == "cathy"
+        with open("bad.jsonl", "w") as f:
+            f.write('{"name": "fay"}\n{"id": -1, "name": "gus"}\n')
+        with pytest.raises(ValueError, match="line 1: row has no id.*line 2: id must be a positive integer"):
+            db.import_jsonl("users", "bad.jsonl", preserve_ids=True)
+        with pytest.raises(ValueError, match="preserve_ids needs a CSV header"):
+            db.import_csv("posts", "posts.csv", has_header=False, preserve_ids=True)
+
+        db.export_sqlite_all("backup.sqlite")
+        restored = Database()
+        restored.import_sqlite_all("backup.sqlite", preserve_ids=True)
+        assert [r.id for r in restored.fetch_all("users")] == [1, 3, 4, 9]
+        assert restored.get("posts", 1).data["owner"] == 3
+    finally:
+        os.chdir(cwd)
+
+def test_export_sqlite_modes_and_whole_database(tmp_path):
+    cwd = os.getcwd()
+    try:
+        os.chdir(tmp_path)
+        db = Database()
+        db.create_table("users", {"email": {"type": "string", "unique": True}, "age": {"type": "integer"}})
+        db.create_table("audit", {"event": {"type": "string"}})
+        db.insert("users", {"email": "a@x", "age": 30})
+        db.insert("users", {"email": "b@x", "age": 41})
+        db.delete("users", 1)
+        db.insert("audit", {"event": "signup"})
+
+        db.export_sqlite("users", "out.sqlite")
+        with pytest.raises(ValueError, match="already exists; pass mode="):
+            db.export_sqlite("users", "out.sqlite")
+        with pytest.raises(ValueError, match="mode must be"):
+            db.export_sqlite("users", "out.sqlite", mode="merge")
+        db.export_sqlite("users", "out.sqlite", mode="replace")
+        with pytest.raises(ValueError, match="record 2 of 'users': id 2 is already in the SQLite table"):
+            db.export_sqlite("users", "out.sqlite", mode="append")
+        conn = sqlite3.connect("out.sqlite")
+        assert conn.execute("SELECT id, email, age FROM users").fetchall() == [(2, "b@x", 41)]
+        assert [(i[1], i[2]) for i in conn.execute("PRAGMA index_list(users)")] == [("users_email_unique", 1)]
+        conn.execute("CREATE TABLE audit (id INTEGER PRIMARY KEY, note TEXT)")
+        conn.commit()
+        conn.close()
+        with pytest.raises(ValueError, match="cannot append to SQLite table 'audit'"):
+            db.export_sqlite("audit", "out.sqlite", mode="append")
+
+        # One transaction: `users` failing takes the `audit` written before it back out.
+        db.export_sqlite("users", "part.sqlite")
+        with pytest.raises(ValueError, match="'users' already exists"):
+            db.export_sqlite_all("part.sqlite")
+        conn = sqlite3.connect("part.sqlite")
+        assert [r[0] for r in conn.execute("SELECT name FROM sqlite_master WHERE type = 'table'")] == ["users"]
+        conn.close()
+        db.export_sqlite_all("all.sqlite")
+        with pytest.raises(ValueError, match="'audit' already exists"):
+            db.export_sqlite_all("all.sqlite")
+        db.insert("users", {"email": "c@x"})
+        db.export_sqlite_all("all.sqlite", mode="replace")
+
+        copy = Database()
+        reports = copy.import_sqlite_all("all.sqlite")
+        assert {name: r["inserted"] for name, r in reports.items()} == {"audit": 1, "users": 2}
+        assert copy.schema("users")["fields"]["email"]["unique"] is True
+        with pytest.raises(ValueError, match="unique"):
+            copy.insert("users", {"email": "b@x"})
+    finally:
+        os.chdir(cwd)
+
+def test_export_sqlite_skips_hidden_rows_and_fills_computed_fields(tmp_path, monkeypatch):
+    monkeypatch.chdir(tmp_path)
+    db = Database()
+    db.create_table(
+        "users",
+        {
+            "email": {"type": "string", "unique": True},
+            "age": {"type": "integer"},
+            "next_age": {"type": "integer", "computed": "age + 1"},
+        },
+        soft_delete=True,
+    )
+    gone = db.insert("users", {"email": "a@x", "age": 30})
+    db.insert("users", {"email": "b@x", "age": 41})
+    db.soft_delete("users", gone)
+    db.export_sqlite("users", "out.sqlite")
+    conn = sqlite3.connect("out.sqlite")
+    assert conn.execute("SELECT id, email, age, next_age FROM users").fetchall() == [(2, "b@x", 41, 42)]
+    conn.close()
+
+    other = Database()
+    other.create_table(
+        "users",
+        {
+            "email": {"type": "string", "unique": True},
+            "age": {"type": "integer"},
+            "next_age": {"type": "integer", "computed": "age + 1"},
+        },
+    )
+    other.insert("users", {"email": "b@x", "age": 7})
+    with pytest.raises(ValueError, match="record 1 of 'users': field `email` must be unique"):
+        other.export_sqlite("users", "out.sqlite", mode="append")
+
+
+def test_jsonl_and_csv_exports_skip_soft_deleted_rows(tmp_path, monkeypatch):
+    monkeypatch.chdir(tmp_path)
+    db = Database()
+    db.create_table("users", {"email": {"type": "string"}}, soft_delete=True)
+    db.insert("users", {"email": "a@x"})
+    db.insert("users", {"email": "b@x"})
+    db.soft_delete("users", 1)
+    db.export_jsonl("users", "users.jsonl")
+    db.export_csv("users", "users.csv")
+    with open("users.jsonl") as f:
+        assert [json.loads(line)["id"] for line in f] == [2]
+    with open("users.csv", newline="") as f:
+        assert [row["id"] for row in csv.DictReader(f)] == ["2"]
+
+
+def test_exports_follow_a_query(tmp_path):
+    cwd = os.getcwd()
+    try:
+        os.chdir(tmp_path)
+        db = Database("filtered.rsndb")
+        db.create_table(
+            "tasks",
+            {
+                "title": {"type": "string"},
+                "status": {"type": "string"},
+                "created_at": {"type": "integer"},
+                "doubled": {"type": "integer", "computed": "created_at * 2"},
+            },
+        )
+        for i, status in enumerate(["active", "done", "active", "active", "done"]):
+            db.insert("tasks", {"title": f"t{i}", "status": status, "created_at": 50 - i})
+
+        active = Query("tasks").where_eq("status", "active").order_by("created_at").take(2)
+        expected = [{"id": r.id, **r.data} for r in db.query(active)]
+        assert [r["title"] for r in expected] == ["t3", "t2"]
+        db.export_jsonl(active, "active.jsonl")
+        with open("active.jsonl") as f:
+            assert [json.loads(line) for line in f] == expected
+        db.export_csv(active, "active.csv")
+        with open("active.csv", newline="") as f:
+            rows = list(csv.DictReader(f))
+        assert rows == [{k: str(v) for k, v in r.items()} for r in expected]
+
+        narrow = Query("tasks").where_eq("status", "active").order_by("created_at").select("title", "doubled")
+        assert [r.data for r in db.query(narrow)] == [{"title": "t3", "doubled": 94}, {"title": "t2", "doubled": 96}, {"title": "t0", "doubled": 100}]
+        db.export_csv(narrow, "narrow.csv", include_id=False)
+        with open("narrow.csv", newline="") as f:
+            assert list(csv.reader(f)) == [["title", "doubled"], ["t3", "94"], ["t2", "96"], ["t0", "100"]]
+        db.export_jsonl(narrow, "narrow.jsonl")
+        with open("narrow.jsonl") as f:
+            assert json.loads(f.readline()) == {"id": 4, "title": "t3", "doubled": 94}
+        assert db.query_columns(narrow) == {"id": [4, 3, 1], "title": ["t3", "t2", "t0"], "doubled": [94, 96, 100]}
+        assert len(db.query(narrow.select())[0].data) == 4
+
+        db.export_jsonl("tasks", "all.jsonl")
+        with open("all.jsonl") as f:
+            assert len(f.readlines()) == 5
+        with pytest.raises(KeyError, match="no column 'owner'"):
+            db.export_csv(Query("tasks").select("owner"), "bad.csv")
+        with pytest.raises(KeyError, match="no column 'owner'"):
+            db.query(Query("tasks").select("title", "owner"))
+    finally:
+        os.chdir(cwd)
+
+class _Buffer:
+    def __init__(self, raw):
+        self.raw = raw
+        self.ptr = ctypes.addressof(raw)
+        self.bufsize = ctypes.sizeof(raw)
+
+
+class _Column:
+    """Just enough of a `__dataframe__` interchange column for `insert_dataframe`."""
+
+    offset = 0
+
+    def __init__(self, dtype, data, null=(0, None), validity=None, offsets=None, categories=None):
+        self.dtype = dtype + ("=",)
+        self.describe_null = null
+        self.describe_categorical = {"categories": categories}
+        self._size = len(data) if offsets is None else len(offsets) - 1
+        ctype = {8: ctypes.c_int8, 32: ctypes.c_int32, 64: ctypes.c_int64}[dtype[1]]
+        if dtype[0] == 2:
+            ctype = ctypes.c_double
+        if dtype[0] == 21:
+            data = b"".join(data)
+            ctype = ctypes.c_uint8
+        self._buffers = {
+            "data": (_Buffer((ctype * len(data))(*data)), dtype + ("=",)),
+            "validity": None if validity is None else (_Buffer((ctypes.c_uint8 * len(validity))(*validity)), (20, 8, "b", "=")),
+            "offsets": None if offsets is None else (_Buffer((ctypes.c_int64 * len(offsets))(*offsets)), (0, 64, "l", "=")),
+        }
+
+    def size(self):
+        return self._size
+
+    def get_chunks(self, n_chunks=None):
+        yield self
+
+    def get_buffers(self):
+        return self._buffers
+
+
+def _strings(values):
+    data, offsets = [], [0]
+    for v in values:
+        data.append((v or "").encode())
+        offsets.append(offsets[-1] + len(data[-1]))
+    mask = [0 if v is None else 1 for v in values]
+    return _Column((21, 8, "u"), [bytes([b]) for b in b"".join(data)], (4, 0), mask, offsets)
+
+
+class _Frame:
+    def __init__(self, columns):
+        self.columns = columns
+
+    def __dataframe__(self, nan_as_null=False, allow_copy=True):
+        return self
+
+    def column_names(self):
+        return list(self.columns)
+
+    def get_column_by_name(self, name):
+        return self.columns[name]
+
+
+class _Records:
+    def __init__(self, rows):
+        self.rows = rows
+
+    def to_dict(self, orient):
+        assert orient == "records"
+        return self.rows
+
+
+def test_insert_dataframe_reads_interchange_columns_and_records(tmp_path):
+    db = Database(str(tmp_path / "frames.rsndb"))
+    db.create_table(
+        "people",
+        {
+            "name": {"type": "string", "required": True},
+            "age": {"type": "integer"},
+            "score": {"type": "float"},
+            "active": {"type": "boolean"},
+            "joined": {"type": "datetime"},
+            "city": {"type": "string"},
+        },
+    )
+    nat = -(2**63)
+    frame = _Frame(
+        {
+            "name": _strings(["Ana", "Bo", "Cy"]),
+            "age": _Column((2, 64, "g"), [30.0, float("nan"), 41.0], (1, None)),
+            "score": _Column((2, 64, "g"), [0.5, 1.25, float("nan")], (1, None)),
+            "active": _Column((20, 8, "b"), [1, 0, 1]),
+            "joined": _Column((22, 64, "tsn:"), [86_400 * 10**9, nat, 0], (2, nat)),
+            "city": _Column((23, 8, "c"), [1, -1, 0], (2, -1), categories=_strings(["Oslo", "Rome"])),
+        }
+    )
+    assert db.insert_dataframe("people", frame) == [1, 2, 3]
+    assert db.get("people", 1).data == {
+        "name": "Ana", "age": 30, "score": 0.5, "active": True,
+        "joined": "1970-01-02T00:00:00Z", "city": "Rome",
+    }
+    assert db.get("people", 2).data == {
+        "name": "Bo", "age": None, "score": 1.25, "active": False, "joined": None, "city": None,
+    }
+
+    columns = db.query_columns(Query("people").where_eq("active", True))
+    assert columns == {
+        "id": [1, 3],
+        "active": [True, True],
+        "age": [30, 41],
+        "city": ["Rome", "Oslo"],
+        "joined": ["1970-01-02T00:00:00Z", "1970-01-01T00:00:00Z"],
+        "name": ["Ana", "Cy"],
+        "score": [0.5, None],
+    }
+    copied = [dict(zip(columns, row)) for row in zip(*columns.values())]
+    assert db.insert_dataframe("people", _Records(copied)) == [4, 5]
+
+    extra = _Records([{"name": "Di", "age": float("nan"), "nickname": "D"}])
+    with pytest.raises(ValueError, match="'nickname' is not a field"):
+        db.insert_dataframe("people", extra)
+    assert db.insert_dataframe("people", extra, drop_unknown=True) == [6]
+    assert db.get("people", 6).data["age"] is None
+
+    bad = _Records([{"name": "Ed", "age": 5}, {"name": "Fi", "age": 1.5}])
+    with pytest.raises(ValueError, match="DataFrame row 1: .*age"):
+        db.insert_dataframe("people", bad)
+    assert db.insert_dataframe("people", _Records([{"name": "Gus"}])) == [7]
+    assert len(Database(str(tmp_path / "frames.rsndb")).fetch_all("people")) == 7
+    with pytest.raises(TypeError, match="__dataframe__"):
+        db.insert_dataframe("people", [{"name": "Hal"}])
+
+def test_batch_rollback_discards_queued_ops(tmp_path):
+    path = tmp_path / "batch.rsndb"
+    db = Database(str(path))
+    db.create_table("users", {"name": {"type": "string", "required": True}})
+    before = path.read_bytes()
+
+    with pytest.raises(ValueError, match="ROLLBACK outside a batch"):
+        db.execute_sql("ROLLBACK")
+    assert db.execute_sql("BATCH") == "Batch mode started."
+    assert db.execute_sql("INSERT INTO users (name) VALUES ('Ada')") == ""
+    assert db.execute_sql("CREATE TABLE extra (x INT)") == ""
+    with pytest.raises(ValueError, match="already in a batch with 2 queued operation"):
+        db.execute_sql("batch")
+    assert db.execute_sql("rollback") == "Batch rolled back: 2 op(s) discarded."
+
+    assert db.execute_sql("COUNT users") == 0
+    assert db.execute_sql("SHOW TABLES") == ["users"]
+    assert path.read_bytes() == before
+    assert Database(str(path)).execute_sql("COUNT users") == 0
+    with pytest.raises(ValueError, match="ROLLBACK outside a batch"):
+        db.execute_sql("ROLLBACK")
+
+    friendly = Database(mode="friendly")
+    friendly.execute_sql("BATCH")
+    assert "nothing was changed" in friendly.execute_sql("ROLLBACK")
+
+
+def test_batch_commit_is_all_or_nothing(tmp_path):
+    path = tmp_path / "commit.rsndb"
+    db = Database(str(path))
+    db.create_table("users", {"name": {"type": "string", "unique": True}})
+    before = path.read_bytes()
+
+    db.execute_sql("BATCH")
+    for name in ("Ada", "Bo", "Ada", "Cy"):
+        db.execute_sql(f"INSERT INTO users (name) VALUES ('{name}')")
+    db.execute_sql("CREATE TABLE extra (x INT)")
+    with pytest.raises(ValueError) as failed:
+        db.execute_sql("COMMIT")
+    message = str(failed.value)
+    assert "batch operation 3 of 5 failed" in message
+    assert "`INSERT INTO users (name) VALUES ('Ada')`" in message
+    assert "unique" in message
+    assert db.execute_sql("COUNT users") == 0
+    assert db.execute_sql("SHOW TABLES") == ["users"]
+    assert path.read_bytes() == before
+
+    written = db.storage_stats()["persists_written"]
+    db.execute_sql("BATCH")
+    db.execute_sql("INSERT INTO users (name) VALUES ('Ada'), ('Bo')")
+    db.execute_sql("UPDATE users SET name = 'Bea' WHERE name = 'Bo'")
+    db.execute_sql("CREATE TABLE extra (x INT)")
+    assert db.execute_sql("COMMIT") == "Batch executed: 3 ops."
+    assert db.storage_stats()["persists_written"] == written + 1
+    reopened = Database(str(path))
+    assert sorted(r.data["name"] for r in reopened.fetch_all("users")) == ["Ada", "Bea"]
+    assert sorted(reopened.execute_sql("SHOW TABLES")) == ["extra", "users"]
+
+    db.execute_sql("BATCH")
+    db.execute_sql("INSERT INTO ghosts (x) VALUES (1)")
+    with pytest.raises(KeyError, match="batch operation 1 of 1 failed"):
+        db.execute_sql("COMMIT")
+
+
+def test_batch_savepoints_discard_only_later_ops(tmp_path):
+    path = tmp_path / "savepoints.rsndb"
+    db = Database(str(path))
+    db.create_table("users", {"name": {"type": "string", "unique": True}})
+
+    with pytest.raises(ValueError, match="SAVEPOINT outside a batch"):
+        db.execute_sql("SAVEPOINT early")
+    db.execute_sql("BATCH")
+    db.execute_sql("INSERT INTO users (name) VALUES ('Ada')")
+    assert db.execute_sql("SAVEPOINT one") == "Savepoint 'one' set after 1 operation(s)."
+    db.execute_sql("INSERT INTO users (name) VALUES ('Bo')")
+    db.execute_sql("SAVEPOINT two")
+    db.execute_sql("INSERT INTO users (name) VALUES ('Ada')")
+    db.execute_sql("SAVEPOINT three")
+    db.execute_sql("INSERT INTO users (name) VALUES ('Cy')")
+
+    with pytest.raises(ValueError, match="already exists"):
+        db.execute_sql("SAVEPOINT TWO")
+    with pytest.raises(ValueError, match="bad-name"):
+        db.execute_sql("SAVEPOINT bad-name")
+    with pytest.raises(KeyError, match="no savepoint 'nope'"):
+        db.execute_sql("ROLLBACK TO nope")
+    with pytest.raises(ValueError, match="ROLLBACK TO <name>"):
+        db.execute_sql("ROLLBACK two")
+
+    # The duplicate 'Ada' sits after savepoint two; rolling back to it drops that and 'Cy'.
+    assert db.execute_sql("ROLLBACK TO two") == "Rolled back to savepoint 'two': 2 operation(s) discarded."
+    with pytest.raises(KeyError, match="no savepoint 'three'"):
+        db.execute_sql("RELEASE three")
+    db.execute_sql("INSERT INTO users (name) VALUES ('Dee')")
+    assert db.execute_sql("ROLLBACK TO two") == "Rolled back to savepoint 'two': 1 operation(s) discarded."
+    assert db.execute_sql("RELEASE one") == "Released savepoint 'one' (2 in total)."
+    with pytest.raises(KeyError, match="no savepoint 'two'"):
+        db.execute_sql("ROLLBACK TO two")
+    db.execute_sql("INSERT INTO users (name) VALUES ('Eve')")
+    assert db.execute_sql("COMMIT") == "Batch executed: 3 ops."
+    names = sorted(r.data["name"] for r in Database(str(path)).fetch_all("users"))
+    assert names == ["Ada", "Bo", "Eve"]
+
+    # A failure at COMMIT still undoes the whole batch, savepoints or not.
+    db.execute_sql("BATCH")
+    db.execute_sql("INSERT INTO users (name) VALUES ('Fay')")
+    db.execute_sql("SAVEPOINT s")
+    db.execute_sql("INSERT INTO users (name) VALUES ('Bo')")
+    with pytest.raises(ValueError, match="batch operation 2 of 2 failed"):
+        db.execute_sql("COMMIT")
+    assert db.execute_sql("COUNT users") == 3
+    with pytest.raises(ValueError, match="RELEASE outside a batch"):
+        db.execute_sql("RELEASE s")
+
+
+def test_aliases_list_and_remove(tmp_path):
+    path = tmp_path / "aliases.rsndb"
+    db = Database(str(path))
+    db.create_table("users", {"name": {"type": "string"}})
+    db.execute_sql("ALIAS people = SELECT * FROM users")
+    db.execute_sql("ALIAS Cnt = COUNT users")
+    assert db.execute_sql("ALIASES") == {"cnt": "COUNT users", "people": "SELECT * FROM users"}
+    assert list(Database(str(path)).list_aliases()) == ["cnt", "people"]
+
+    assert db.execute_sql("UNALIAS PEOPLE") == "Alias 'people' removed."
+    with pytest.raises(KeyError, match="no alias 'people'"):
+        db.execute_sql("UNALIAS people")
+    db.remove_alias("cnt")
+    with pytest.raises(KeyError, match="no alias 'cnt'"):
+        db.remove_alias("cnt")
+    assert db.list_aliases() == {}
+    assert Database(str(path)).list_aliases() == {}
+    with pytest.raises(ValueError, match="UNALIAS <name>"):
+        db.execute_sql("UNALIAS")
+
+
+def test_typos_suggest_commands_aliases_and_tables():
+    db = Database()
+    db.create_table("users", {"name": {"type": "string"}})
+    db.execute_sql("ALIAS people = SELECT * FROM users")
+    for typo, meant in (("SELET", "SELECT"), ("INSRT", "INSERT"), ("COUTN", "COUNT"), ("peple", "people")):
+        with pytest.raises(ValueError, match=f"Unknown: {typo}. Did you mean {meant}?"):
+            db.execute_sql(f"{typo} something")
+    with pytest.raises(RuntimeError, match="unknown command"):
+        db.execute_sql("XYZZYQUUX")
+
+    with pytest.raises(KeyError, match="table 'usres' does not exist; did you mean 'users'?"):
+        db.execute_sql("COUNT usres")
+    with pytest.raises(KeyError, match="did you mean 'users'"):
+        db.execute_sql("DESCRIBE user")
+    with pytest.raises(KeyError, match="did you mean 'users'"):
+        db.execute_sql("SELECT * FROM `Users`")
+    with pytest.raises(KeyError) as far_off:
+        db.execute_sql("COUNT invoices")
+    assert "did you mean" not in str(far_off.value)
+
+
+def test_history_count_search_clear_and_cap():
+    db = Database(history_size=5)
+    db.create_table("users", {"name": {"type": "string"}})
+    for i in range(8):
+        db.execute_sql(f"COUNT users WHERE name = 'u{i}'")
+    # HISTORY is left out of the listing but still takes a slot.
+    assert db.execute_sql("HISTORY") == [f"COUNT users WHERE name = 'u{i}'" for i in (7, 6, 5, 4)]
+    assert db.execute_sql("HISTORY 2") == [
+        "COUNT users WHERE name = 'u7'",
+        "COUNT users WHERE name = 'u6'",
+    ]
+    db.execute_sql("TABLES")
+    assert db.execute_sql("HISTORY SEARCH count USERS where name = 'U7'") == [
+        "COUNT users WHERE name = 'u7'"
+    ]
+    assert db.execute_sql("history search tables") == ["TABLES"]
+    with pytest.raises(ValueError, match="HISTORY format"):
+        db.execute_sql("HISTORY many")
+    with pytest.raises(ValueError, match="HISTORY format"):
+        db.execute_sql("HISTORY SEARCH")
+
+    assert db.execute_sql("CLEAR HISTORY") == "History cleared."
+    assert db.execute_sql("HISTORY") == []
+    with pytest.raises(ValueError, match="CLEAR format"):
+        db.execute_sql("CLEAR users")
+
+
+def test_history_records_batch_ops_once_not_on_replay():
+    db = Database()
+    db.create_table("users", {"name": {"type": "string"}})
+    db.execute_sql("ALIAS everyone = SELECT * FROM users")
+    for command in ("BATCH", "INSERT INTO users (name) VALUES ('Ada')", "everyone", "COMMIT"):
+        db.execute_sql(command)
+    assert db.execute_sql("HISTORY") == [
+        "COMMIT",
+        "everyone",
+        "INSERT INTO users (name) VALUES ('Ada')",
+        "BATCH",
+        "ALIAS everyone = SELECT * FROM users",
+    ]
+
+
+def test_history_persists_only_when_asked(tmp_path):
+    path = str(tmp_path / "history.rsndb")
+    db = Database(path, persist_history=True)
+    db.create_table("users", {"name": {"type": "string"}})
+    db.execute_sql("INSERT INTO users (name) VALUES ('secret')")
+    db.close()
+    db = Database(path, persist_history=True, history_size=2)
+    assert db.execute_sql("HISTORY") == ["INSERT INTO users (name) VALUES ('secret')"]
+    with db.transaction():
+        db.execute_sql("COUNT users")
+    with pytest.raises(ValueError):
+        with db.transaction():
+            db.execute_sql("TABLES")
+            raise ValueError("undo")
+    assert db.execute_sql("HISTORY") == ["TABLES"]
+    db.close()
+
+    # Opened without the flag, the stored history is ignored and dropped on the next write.
+    db = Database(path)
+    assert db.execute_sql("HISTORY") == []
+    db.insert("users", {"name": "Bob"})
+    db.close()
+    assert Database(path, persist_history=True).execute_sql("HISTORY") == []
+
+    db = Database(path, persist_history=True)
+    db.execute_sql("COUNT users")
+    db.execute_sql("CLEAR HISTORY")
+    db.close()
+    assert Database(path, persist_history=True).execute_sql("HISTORY") == []
+
+
+def test_stats_counts_tables_graph_and_session_calls(tmp_path):
+    db = Database()
+    db.create_table("users", {"name": {"type": "string"}})
+    db.create_table("orders", {"total": {"type": "integer"}})
+    db.insert_many("users", [{"name": "Ada"}, {"name": "Bo"}])
+    db.execute_sql("INSERT INTO orders (total) VALUES (3)")
+    db.execute_sql("ALIAS people = SELECT * FROM users")
+    db.ingest("Ada founded Acme in London.")
+    with pytest.raises(KeyError):
+        db.get("users", 99)
+    with pytest.raises(ValueError):
+        db.execute_sql("SELET * FROM users")
+    with pytest.raises(KeyError):
+        db.replace("users", 99, {"name": "Cy"})
+
+    stats = db.stats()
+    assert stats["tables"] == 2
+    assert stats["records"] == {"orders": 1, "users": 2}
+    assert stats["total_records"] == 3
+    assert stats["aliases"] == 1
+    assert stats["graph"]["chunks"] == 1
+    assert stats["graph"] == db.graph_stats()
+    # Every public method counts, each once even when it calls another.
+    assert stats["session"] == {"operations": 9, "errors": 3}
+    assert stats["storage"] is None
+
+    text = db.execute_sql("STATS")
+    assert text.startswith("Tables: 2, 3 record(s)\n  orders: 1\n  users: 2\nAliases: 1")
+    assert "Session: 11 operation(s), 3 error(s)" in text
+    assert text.endswith("Storage: nothing on disk")
+    assert db.stats()["session"]["operations"] == 12
+
+    on_disk = Database(str(tmp_path / "stats.rsndb"), mode="friendly")
+    on_disk.create_table("users", {"name": {"type": "string"}})
+    assert on_disk.stats()["storage"]["file_bytes"] > 0
+    assert on_disk.execute_sql("STATS").startswith("Here's the overview!")
+
+
+def test_dump_json_keeps_id_fields_and_row_bookkeeping(tmp_path, monkeypatch):
+    monkeypatch.chdir(tmp_path)
+    db = Database()
+    db.create_table(
+        "items",
+        {"id": {"type": "string"}, "n": {"type": "integer"}},
+        versioned=True,
+        soft_delete=True,
+    )
+    db.create_table("cache", {"key": {"type": "string"}}, ttl_seconds=1)
+    first = db.insert("items", {"id": "abc", "n": 1})
+    db.update("items", first, {"n": 2})
+    gone = db.insert("items", {"id": "def", "n": 3})
+    db.soft_delete("items", gone)
+    db.insert("cache", {"key": "k"})
+    dump = "dump.json"
+    db.dump_json(dump)
+
+    time.sleep(1.1)
+    copy = Database()
+    copy.load_json(dump)
+    [record] = copy.fetch_all("items")
+    assert (record.id, record.data, record.version) == (first, {"id": "abc", "n": 2}, 2)
+    assert [r.id for r in copy.query(Query("items").with_deleted())] == [first, gone]
+    # The TTL runs from the original insert, not from the load.
+    assert copy.fetch_all("cache") == []
+
+
+def _engine_state(db):
+    tables = db.export_schema()["tables"]
+    rows = {name: [(r.id, r.data) for r in db.fetch_all(name)] for name in tables}
+    return tables, rows, {name: db.sequence(name) for name in tables}
+
+
+def test_dump_json_round_trips_and_merges(tmp_path):
+    db = Database(str(tmp_path / "src.rsndb"))
+    db.create_table("users", {"email": {"type": "string", "unique": True}})
+    db.create_table("posts", {
+        "author": {"type": "integer", "references": "users"},
+        "words": {"type": "integer"},
+        "double": {"type": "integer", "computed": "words * 2"},
+    })
+    db.set_table_meta("users", "owner", "ops")
+    for email in ["a@x", "b@x", "c@x"]:
+        db.insert("users", {"email": email})
+    db.delete("users", 2)
+    db.insert("posts", {"author": 3, "words": 5})
+    db.execute_sql("ALIAS everyone = COUNT users")
+    db.ingest("Alice met Bob in Paris. Bob likes Paris.", "notes")
+    dump = "dump.json"
+    db.dump_json(dump)
+
+    copy = Database(str(tmp_path / "copy.rsndb"))
+    copy.create_table("scratch", {"x": {"type": "integer"}})
+    copy.load_json(dump)
+    assert _engine_state(copy) == _engine_state(db)
+    assert copy.graph_query("Paris") == db.graph_query("Paris")
+    assert copy.execute_sql("everyone") == 2
+    reopened = Database(str(tmp_path / "copy.rsndb"))
+    assert _engine_state(reopened) == _engine_state(db)
+    assert reopened.insert("users", {"email": "d@x"}) == 4
+
+    target = Database(str(tmp_path / "merge.rsndb"))
+    target.create_table("notes", {"body": {"type": "string"}})
+    target.insert("notes", {"body": "kept"})
+    target.load_json(dump, mode="merge")
+    assert [r.data["body"] for r in target.fetch_all("notes")] == ["kept"]
+    assert [r.id for r in target.fetch_all("users")] == [1, 3]
+    with pytest.raises(ValueError, match="already exists"):
+        target.load_json(dump, mode="merge")
+    assert [r.id for r in target.fetch_all("users")] == [1, 3]
+
+    import json
+    with open(dump) as f:
+        doc = json.load(f)
+    doc["tables"]["posts"][0]["author"] = 2
+    with open(dump, "w") as f:
+        json.dump(doc, f)
+    with pytest.raises(ValueError, match="record 1 of `posts`: .*missing record 2"):
+        Database().load_json(dump)
+    with pytest.raises(ValueError, match="mode must be"):
+        Database().load_json(dump, mode="append")
+
+
+def test_encrypted_bundles_carry_selected_tables(tmp_path, monkeypatch):
+    monkeypatch.chdir(tmp_path)
+    db = Database("src.rsndb")
+    db.create_table("users", {"email": {"type": "string", "unique": True}})
+    db.create_table("posts", {"author": {"type": "integer", "references": "users"}})
+    db.create_table("secrets", {"token": {"type": "string"}})
+    for email in ["a@x", "b@x", "c@x"]:
+        db.insert("users", {"email": email})
+    db.delete("users", 2)
+    db.insert("posts", {"author": 3})
+    db.insert("secrets", {"token": "hunter2"})
+    db.ingest("Alice met Bob.", "notes")
+
+    db.export_encrypted("extract.rsnb", "correct horse", tables=["users", "posts"])
+    raw = (tmp_path / "extract.rsnb").read_bytes()
+    assert raw.startswith(b"RSNBNDL\0") and b"a@x" not in raw and b"hunter2" not in raw
+    with pytest.raises(ValueError, match="references `users`, which is not in the bundle"):
+        db.export_encrypted("posts.rsnb", "correct horse", tables=["posts"])
+    with pytest.raises(KeyError):
+        db.export_encrypted("ghosts.rsnb", "correct horse", tables=["ghosts"])
+
+    other = Database("dest.rsndb")
+    with pytest.raises(EncryptionKeyError, match="incorrect passphrase"):
+        other.import_encrypted("extract.rsnb", "wrong horse")
+    other.create_table("notes", {"body": {"type": "string"}})
+    other.import_encrypted("extract.rsnb", "correct horse")
+    assert sorted(other.execute_sql("SHOW TABLES")) == ["notes", "posts", "users"]
+    assert [(r.id, r.data["email"]) for r in other.fetch_all("users")] == [(1, "a@x"), (3, "c@x")]
+    assert other.insert("users", {"email": "d@x"}) == 4
+    assert "No relevant" in other.graph_query("Alice")
+    other.import_encrypted("extract.rsnb", "correct horse", mode="replace")
+    assert sorted(other.execute_sql("SHOW TABLES")) == ["posts", "users"]
+
+    (tmp_path / "damaged.rsnb").write_bytes(raw[:-1] + bytes([raw[-1] ^ 1]))
+    with pytest.raises(ValueError, match="damaged"):
+        other.import_encrypted("damaged.rsnb", "correct horse")
+    with pytest.raises(ValueError, match="this is a database file"):
+        other.import_encrypted("src.rsndb", "correct horse")
+    with pytest.raises(ValueError, match="passphrase cannot be empty"):
+        db.export_encrypted("all.rsnb", "")
+
+
+def test_diff_table_compares_against_another_database(tmp_path, monkeypatch):
+    monkeypatch.chdir(tmp_path)
+    base = Database("base.rsndb", encryption_key="hunter2")
+    base.create_table("users", {"email": {"type": "string"}, "age": {"type": "integer"}})
+    for email, age in [("a@x", 30), ("b@x", 40), ("c@x", 50)]:
+        base.insert("users", {"email": email, "age": age})
+    base.close()
+
+    db = Database()
+    db.create_table("people", {"email": {"type": "string"}, "name": {"type": "string"}})
+    db.insert("people", {"email": "c@x", "name": "Cy"})
+    db.insert("people", {"email": "b@x", "name": "Bo"})
+    db.insert("people", {"email": "d@x"})
+    db.insert("people", {"email": "z@x"})
+    db.delete("people", 4)
+
+    by_id = db.diff_table("people", "base", other_table="users", other_key="hunter2")
+    assert list(by_id) == ["schema", "added", "removed", "modified"]
+    assert by_id["schema"] == {"added": ["name"], "removed": ["age"]}
+    assert (by_id["added"], by_id["removed"]) == ([], [])
+    assert by_id["modified"][0] == {
+        "id": 1,
+        "other_id": 1,
+        "fields": {"email": {"old": "a@x", "new": "c@x"}},
+    }
+
+    by_email = db.diff_table("people", "base", "users", key="email", other_key="hunter2")
+    assert (by_email["added"], by_email["removed"], by_email["modified"]) == ([3], [1], [])
+    db.update("people", 2, {"email": "a@x"})
+    by_email = db.diff_table("people", "base", "users", key="email", other_key="hunter2")
+    assert (by_email["added"], by_email["removed"]) == ([3], [2])
+    summary = db.diff_table("people", "base", "users", summary=True, other_key="hunter2")
+    assert summary["modified"] == [1, 2, 3]
+
+    db.update("people", 1, {"email": "a@x"})
+    with pytest.raises(ValueError, match="key 'email' is not unique: records 1 and 2"):
+        db.diff_table("people", "base", "users", key="email", other_key="hunter2")
+    with pytest.raises(ValueError, match="key 'name' is not a field of both tables"):
+        db.diff_table("people", "base", "users", key="name", other_key="hunter2")
+    with pytest.raises(EncryptionKeyError):
+        db.diff_table("people", "base", "users")
+    with pytest.raises(KeyError):
+        db.diff_table("people", "base", other_key="hunter2")
+    with pytest.raises(IOError, match="no database at 'missing'"):
+        db.diff_table("people", "missing")
+
+
+def test_merge_combines_tables_rows_and_graph_atomically(tmp_path, monkeypatch):
+    monkeypatch.chdir(tmp_path)
+    field = Database("field.rsndb", encryption_key="hunter2")
+    field.create_table("sites", {"code": {"type": "string", "unique": True}, "name": {"type": "string"}})
+    field.create_table("readings", {"site": {"type": "integer", "references": "sites"}, "value": {"type": "float"}})
+    for code, name in [("A", "Alder"), ("B", "Birch"), ("C", "Cedar")]:
+        field.insert("sites", {"code": code, "name": name})
+    field.insert("readings", {"site": 3, "value": 1.5})
+    field.insert("readings", {"site": 1, "value": 2.5})
+    field.ingest("In Paris, Alice met Bob.", "field notes")
+    field.close()
+
+    db = Database()
+    db.create_table("sites", {"code": {"type": "string", "unique": True}, "name": {"type": "string"}})
+    db.insert("sites", {"code": "C", "name": "Old cedar"})
+    db.insert("sites", {"code": "Z", "name": "Zelkova"})
+    db.ingest("In Paris, Alice met Bob.", "office notes")
+
+    with pytest.raises(ValueError, match="record 3 of 'sites' in 'field' matches record 1 by 'code'"):
+        db.merge("field", strategy="error", key_fields={"sites": "code"}, other_key="hunter2")
+    assert sorted(db.execute_sql("SHOW TABLES")) == ["sites"]
+
+    report = db.merge("field", key_fields={"sites": "code"}, other_key="hunter2")
+    assert report == {
+        "readings": {"inserted": 2, "replaced": 0, "skipped": 0},
+        "sites": {"inserted": 2, "replaced": 0, "skipped": 1},
+    }
+    names = {r.id: r.data["name"] for r in db.fetch_all("sites")}
+    assert names == {1: "Old cedar", 2: "Zelkova", 3: "Alder", 4: "Birch"}
+    assert [(r.data["site"], r.data["value"]) for r in db.fetch_all("readings")] == [(1, 1.5), (3, 2.5)]
+    db.export_graph_json("graph.json")
+    graph = json.loads((tmp_path / "graph.json").read_text())
+    assert sorted(c["source"] for c in graph["chunks"]) == ["field notes", "office notes"]
+    assert {e["name"]: e["mentions"] for e in graph["entities"]}["Alice"] == 2
+
+    report = db.merge("field", strategy="replace", key_fields={"sites": "code"}, other_key="hunter2")
+    assert report["sites"] == {"inserted": 0, "replaced": 3, "skipped": 0}
+    assert db.get("sites", 1).data["name"] == "Cedar"
+    assert len(db.fetch_all("readings")) == 4
+    db.export_graph_json("graph.json")
+    assert len(json.loads((tmp_path / "graph.json").read_text())["chunks"]) == 2
+
+    clash = Database("clash.rsndb", encryption_key="hunter2")
+    clash.create_table("sites", {"code": {"type": "integer"}})
+    clash.close()
+    with pytest.raises(ValueError, match="different schema in 'clash' \\(fields code, name\\)"):
+        db.merge("clash", other_key="hunter2")
+    with pytest.raises(ValueError, match="strategy must be"):
+        db.merge("field", strategy="overwrite")
+    # Without key_fields, rows clashing on a unique field meet `strategy` too.
+    report = db.merge("field", other_key="hunter2")
+    assert report["sites"] == {"inserted": 0, "replaced": 0, "skipped": 3}
+    assert len(db.fetch_all("sites")) == 4
+
+
+def test_merge_applies_strategy_to_unique_clashes_without_key_fields(tmp_path, monkeypatch):
+    monkeypatch.chdir(tmp_path)
+    schema = {"email": {"type": "string", "unique": True}, "name": {"type": "string"}}
+    other = Database("other.rsndb")
+    other.create_table("u", schema)
+    other.insert("u", {"email": "x", "name": "X"})
+    other.insert("u", {"email": "y", "name": "Theirs"})
+    other.close()
+    db = Database()
+    db.create_table("u", schema)
+    db.insert("u", {"email": "y", "name": "Ours"})
+
+    with pytest.raises(ValueError, match="record 2 of 'u' in 'other' matches record 1 by 'email'"):
+        db.merge("other", strategy="error")
+    assert len(db.fetch_all("u")) == 1
+    assert db.merge("other")["u"] == {"inserted": 1, "replaced": 0, "skipped": 1}
+    assert {r.data["email"]: r.data["name"] for r in db.fetch_all("u")} == {"x": "X", "y": "Ours"}
+    assert db.merge("other", strategy="replace")["u"] == {"inserted": 0, "replaced": 2, "skipped": 0}
+    assert {r.data["email"]: r.data["name"] for r in db.fetch_all("u")} == {"x": "X", "y": "Theirs"}
+
+
+def test_execute_sql_select_runs_through_the_query_machinery(tmp_path):
+    db = Database(str(tmp_path / "select.rsndb"))
+    db.create_table("people", {"name": {"type": "string"}, "age": {"type": "integer"}, "city": {"type": "string"}})
+    db.insert_many(
+        "people",
+        [
+            {"name": "Ada", "age": 36, "city": "London"},
+            {"name": "Alan", "age": 41, "city": "Wilmslow"},
+            {"name": "Grace", "age": 85, "city": "New York"},
+            {"name": "Bo", "age": 17, "city": "O'Hare"},
+        ],
+    )
+    rows = db.execute_sql("SELECT name, age FROM people WHERE age >= 18 AND name LIKE 'A%' ORDER BY age DESC")
+    assert [r.data for r in rows] == [{"name": "Alan", "age": 41}, {"name": "Ada", "age": 36}]
+    assert [r.id for r in rows] == [2, 1]
+
+    everyone = db.execute_sql("select * from people where city != 'London' order by age limit 2;")
+    assert [r.data["name"] for r in everyone] == ["Bo", "Alan"]
+    assert everyone[0].data == {"name": "Bo", "age": 17, "city": "O'Hare"}
+    assert [r.data["name"] for r in db.execute_sql("SELECT name FROM people WHERE city = 'O''Hare'")] == ["Bo"]
+    assert db.execute_sql("SELECT * FROM people WHERE age < 0") == []
+
+    with pytest.raises(ValueError, match="unexpected `WHERE` at column 15; expected a table name"):
+        db.execute_sql("SELECT * FROM WHERE age > 1")
+    with pytest.raises(KeyError, match="does not exist"):
+        db.execute_sql("SELECT * FROM ghosts")
+    with pytest.raises(KeyError, match="no column 'salary'"):
+        db.execute_sql("SELECT name, salary FROM people")
+
+    snarky = Database(mode="snarky")
+    with pytest.raises(ValueError, match="unexpected end of input at column 9; expected FROM"):
+        snarky.execute_sql("SELECT *")
+
+
+def test_execute_sql_insert_values_and_json(tmp_path):
+    path = tmp_path / "insert.rsndb"
+    db = Database(str(path), mode="friendly")
+    db.create_table(
+        "users",
+        {
+            "name": {"type": "string", "required": True},
+            "age": {"type": "integer"},
+            "admin": {"type": "boolean"},
+            "email": {"type": "string", "unique": True},
+        },
+    )
+    assert db.execute_sql("INSERT INTO users (name, age, admin) VALUES ('O\\'Brien', 30, TRUE)") == 1
+    assert db.execute_sql('INSERT INTO users {"name": "Alice", "age": 31, "email": "a@x"}') == 2
+    ids = db.execute_sql("INSERT INTO users (name, email) VALUES ('Bob', 'b@x'), ('Cy', NULL);")
+    assert ids == [3, 4]
+    rows = {r.id: r.data for r in Database(str(path)).fetch_all("users")}
+    assert rows[1] == {"name": "O'Brien", "age": 30, "admin": True}
+    assert rows[4]["name"] == "Cy"
+
+    with pytest.raises(ValueError, match="unique"):
+        db.execute_sql("INSERT INTO users (name, email) VALUES ('Dee', 'd@x'), ('Eve', 'a@x')")
+    assert db.execute_sql("COUNT users") == 4
+    with pytest.raises(KeyError, match="`name` is missing"):
+        db.execute_sql("INSERT INTO users (age) VALUES (5)")
+    with pytest.raises(ValueError, match="unexpected `maybe` at column 41; expected a value"):
+        db.execute_sql("INSERT INTO users (name, admin) VALUES (maybe, 1)")
+    with pytest.raises(ValueError, match="malformed JSON at column 34"):
+        db.execute_sql('INSERT INTO users {"name": "Zed" "age": 1}')
+    with pytest.raises(KeyError):
+        db.execute_sql("INSERT INTO ghosts (name) VALUES ('x')")
+
+
+def test_execute_sql_update_sets_fields_and_guards_full_table(tmp_path):
+    path = tmp_path / "update.rsndb"
+    db = Database(str(path), mode="friendly")
+    db.create_table(
+        "users",
+        {
+            "name": {"type": "string", "required": True},
+            "age": {"type": "integer"},
+            "score": {"type": "float"},
+            "active": {"type": "boolean"},
+        },
+    )
+    db.insert_many(
+        "users",
+        [
+            {"name": "Ada", "age": 36, "active": True},
+            {"name": "Alan", "age": 41, "active": True},
+            {"name": "Grace", "age": 85, "active": False},
+        ],
+    )
+    assert db.execute_sql("UPDATE users SET age = '37', score = 2, active = FALSE WHERE name = 'Ada'") == 1
+    assert db.execute_sql("update users set score = 1.5 where age > 40 and active = true;") == 1
+    assert db.execute_sql("UPDATE users SET age = 1 WHERE age < 0") == 0
+    rows = {r.id: r.data for r in Database(str(path)).fetch_all("users")}
+    assert rows[1] == {"name": "Ada", "age": 37, "score": 2.0, "active": False}
+    assert rows[2]["score"] == 1.5
+    assert "score" not in rows[3]
+
+    with pytest.raises(ValueError, match="refusing to update every row without a WHERE clause"):
+        db.execute_sql("UPDATE users SET active = TRUE")
+    assert [r.data["active"] for r in db.fetch_all("users")] == [False, True, False]
+    assert db.execute_sql("UPDATE users SET active = TRUE ALL") == 3
+    assert all(r.data["active"] for r in Database(str(path)).fetch_all("users"))
+
+    with pytest.raises(ValueError):
+        db.execute_sql("UPDATE users SET age = 'old' ALL")
+    assert [r.data["age"] for r in db.fetch_all("users")] == [37, 41, 85]
+    with pytest.raises(KeyError, match="does not exist"):
+        db.execute_sql("UPDATE ghosts SET age = 1 ALL")
+
+
+def test_execute_sql_delete_counts_rows_and_guards_full_table(tmp_path):
+    path = tmp_path / "delete.rsndb"
+    db = Database(str(path), mode="friendly")
+    db.create_table(
+        "users",
+        {
+            "name": {"type": "string", "required": True},
+            "email": {"type": "string", "unique": True},
+            "age": {"type": "integer"},
+        },
+    )
+    db.insert_many(
+        "users",
+        [
+            {"name": "Ada", "email": "ada@x", "age": 36},
+            {"name": "Bo", "email": "bo@x", "age": 17},
+            {"name": "Cy", "email": "cy@x", "age": 12},
+        ],
+    )
+    assert db.execute_sql("DELETE FROM users WHERE age > 100") == 0
+    assert db.execute_sql("delete from users where age < 18 and name like 'B%';") == 1
+    assert sorted(r.data["name"] for r in Database(str(path)).fetch_all("users")) == ["Ada", "Cy"]
+    db.insert("users", {"name": "Bo again", "email": "bo@x"})
+    with pytest.raises(ValueError, match="unique"):
+        db.insert("users", {"name": "Ada again", "email": "ada@x"})
+
+    with pytest.raises(ValueError, match="refusing to delete every row without a WHERE clause"):
+        db.execute_sql("DELETE FROM users")
+    assert db.execute_sql("COUNT users") == 3
+    assert db.execute_sql("DELETE FROM users ALL") == 3
+    assert Database(str(path)).fetch_all("users") == []
+    with pytest.raises(KeyError, match="does not exist"):
+        db.execute_sql("DELETE FROM ghosts ALL")
+
+    # Snarky mode keeps the refusal and adds a remark after it; the wording is random.
+    snarky = Database(mode="snarky")
+    snarky.create_table("t", {"x": {"type": "integer"}})
+    with pytest.raises(ValueError) as refused:
+        snarky.execute_sql("DELETE FROM t")
+    message, remark = str(refused.value).split("\n  (", 1)
+    assert "refusing to delete every row without a WHERE clause" in message
+    assert remark.endswith(")") and len(remark) > 1
+    professional = Database(mode="professional")
+    professional.create_table("t", {"x": {"type": "integer"}})
+    with pytest.raises(ValueError) as plain:
+        professional.execute_sql("DELETE FROM t")
+    assert "\n  (" not in str(plain.value)
+
+
+def test_execute_sql_create_table_bootstraps_schema(tmp_path):
+    path = tmp_path / "ddl.rsndb"
+    db = Database(str(path))
+    script = """
+        CREATE TABLE users (name STRING REQUIRED UNIQUE, age INTEGER DEFAULT '18', profile JSON);
+        CREATE TABLE IF NOT EXISTS users (other TEXT);
+        INSERT INTO users (name) VALUES ('Ada');
+    """
+    for statement in filter(str.strip, script.split(";")):
+        db.execute_sql(statement)
+
+    fields = Database(str(path)).schema("users")["fields"]
+    assert sorted(fields) == ["age", "name", "profile"]
+    assert fields["name"]["required"] and fields["name"]["unique"]
+    assert fields["age"]["default"] == 18 and not fields["age"]["required"]
+    assert fields["profile"]["type"] == "json"
+    assert [r.data for r in db.fetch_all("users")] == [{"name": "Ada", "age": 18}]
+
+    with pytest.raises(ValueError, match="already exists"):
+        db.execute_sql("CREATE TABLE users (x INT)")
+    with pytest.raises(ValueError, match="unsupported field type money"):
+        db.execute_sql("CREATE TABLE accounts (balance money)")
+    with pytest.raises(ValueError, match="bad-name"):
+        db.execute_sql('CREATE TABLE t ("bad-name" INT)')
+    with pytest.raises(ValueError, match="unexpected `UNIQUE` at column 40"):
+        db.execute_sql("CREATE TABLE t (a INT UNIQUE DEFAULT 1 UNIQUE)")
+    assert db.execute_sql("SHOW TABLES") == ["users"]
+
+
+def test_execute_sql_count_where_and_show_tables_full(tmp_path):
+    db = Database(str(tmp_path / "count.rsndb"), mode="friendly")
+    db.create_table(
+        "users",
+        {
+            "email": {"type": "string", "unique": True},
+            "age": {"type": "integer"},
+            "city": {"type": "string", "default": "Oslo"},
+        },
+        soft_delete=True,
+    )
+    db.create_table("tags", {"label": {"type": "string"}})
+    db.create_index("tags", "label")
+    db.create_table("logs", {"line": {"type": "string"}})
+    ids = db.insert_many(
+        "users",
+        [
+            {"email": "a@x", "age": 25},
+            {"email": "b@x", "age": 35, "city": "Rome"},
+            {"email": "c@x", "age": 45},
+            {"email": "d@x", "age": 55},
+        ],
+    )
+    db.soft_delete("users", ids[3])
+
+    assert db.execute_sql("COUNT users") == 3
+    assert db.execute_sql("COUNT users WHERE age > 30") == 2
+    assert db.execute_sql("count users where age > 30 and city = 'Oslo';") == 1
+    assert db.execute_sql("COUNT users WHERE email LIKE '%@x'") == 3
+    assert db.execute_sql("COUNT users WHERE age > 100") == 0
+    with pytest.raises(ValueError, match="expected WHERE or the end of the statement"):
+        db.execute_sql("COUNT users ORDER BY age")
+    with pytest.raises(KeyError, match="did you mean 'users'"):
+        db.execute_sql("COUNT usres WHERE age > 1")
+
+    assert sorted(db.execute_sql("SHOW TABLES")) == ["logs", "tags", "users"]
+    assert db.execute_sql("SHOW TABLES FULL") == [
+        {"name": "logs", "records": 0, "fields": 1, "unique": False, "indexed": False},
+        {"name": "tags", "records": 0, "fields": 1, "unique": False, "indexed": True},
+        {"name": "users", "records": 3, "fields": 3, "unique": True, "indexed": False},
+    ]
+    assert db.execute_sql("tables full") == db.execute_sql("SHOW TABLES FULL")
+
+
+def test_format_results_and_print_render_tables(tmp_path):
+    db = Database(str(tmp_path / "print.rsndb"), mode="professional")
+    db.create_table("users", {"name": {"type": "string"}, "age": {"type": "integer"}, "bio": {"type": "string"}})
+    db.insert("users", {"name": "Ada", "age": 36, "bio": "wrote | the first\nprogram"})
+    db.insert("users", {"name": "Grace", "age": 85})
+
+    assert db.format_results(Query("users"), max_width=12) == "\n".join(
+        [
+            "+----+-----+--------------+-------+",
+            "| id | age | bio          | name  |",
+            "+----+-----+--------------+-------+",
+            "|  1 |  36 | wrote | the… | Ada   |",
+            "|  2 |  85 |              | Grace |",
+            "+----+-----+--------------+-------+",
+        ]
+    )
+    assert db.format_results(Query("users").select("name", "bio"), style="markdown") == "\n".join(
+        [
+            "| id  | name  | bio                           |",
+            "|----:|-------|-------------------------------|",
+            "|   1 | Ada   | wrote \\| the first<br>program |",
+            "|   2 | Grace |                               |",
+        ]
+    )
+    assert json.loads(db.format_results(Query("users").where_eq("name", "Grace"), style="json")) == [
+        {"id": 2, "age": 85, "bio": None, "name": "Grace"}
+    ]
+    with pytest.raises(ValueError, match="style must be"):
+        db.format_results(Query("users"), style="html")
+    with pytest.raises(ValueError, match="max_width must be at least 1"):
+        db.format_results(Query("users"), max_width=0)
+
+    assert db.execute_sql("PRINT SELECT * FROM users") == db.format_results(Query("users"))
+    assert db.execute_sql("print markdown select name from users where age > 50") == "\n".join(
+        ["| id  | name  |", "|----:|-------|", "|   2 | Grace |"]
+    )
+    assert db.execute_sql("PRINT SELECT name FROM users WHERE age > 100") == "+----+------+\n| id | name |\n+----+------+"
+    with pytest.raises(ValueError, match="PRINT format"):
+        db.execute_sql("PRINT COUNT users")
+
+
+def test_command_table_names_ignore_case_unless_quoted(tmp_path):
+    db = Database(str(tmp_path / "db"))
+    db.create_table("users", {"name": {"type": "string"}})
+    db.insert("users", {"name": "ada"})
+
+    assert db.execute_sql("COUNT Users") == 1
+    assert [r.data["name"] for r in db.execute_sql("SELECT name FROM USERS")] == ["ada"]
+    db.execute_sql("INSERT INTO Users (name) VALUES ('bob')")
+    assert db.execute_sql("DESCRIBE USERS") == ["name"]
+    with pytest.raises(KeyError):
+        db.fetch_all("USERS")
+
+    db.create_table("Users", {"name": {"type": "string"}})
+    with pytest.raises(ValueError, match="ambiguous"):
+        db.execute_sql("COUNT USERS")
+    assert db.execute_sql("COUNT `Users`") == 0
+    assert db.execute_sql("COUNT [users]") == 2
+    assert db.execute_sql('COUNT "users"') == 2
+    with pytest.raises(KeyError):
+        db.execute_sql("COUNT `USERS`")
+
+
+def test_quoted_table_names_may_be_keywords(tmp_path):
+    db = Database(str(tmp_path / "db"))
+    db.create_table("order", {"item": {"type": "string"}})
+    db.execute_sql("INSERT INTO \"order\" (item) VALUES ('tea')")
+    assert [r.data["item"] for r in db.execute_sql("SELECT item FROM `order`")] == ["tea"]
+    assert db.execute_sql("DESCRIBE [order]") == ["item"]
+    with pytest.raises(ValueError):
+        db.execute_sql("SELECT item FROM order")
@@ -0,0 +1,1015 @@
+"""Schema evolution and field-definition behaviour."""
+
+import json
+import time
+from datetime import datetime
+
+import pytest
+
+from rsn_db import Database, Query
+
+
+def _users(tmp_path, name="schema.rsndb"):
+    db = Database(str(tmp_path / name))
+    db.create_table(
+        "users",
+        {
+            "name": {"type": "string", "required": True},
+            "age": {"type": "string"},
+        },
+    )
+    return db
+
+
+def test_change_field_type_migrates_values(tmp_path):
+    db = _users(tmp_path)
+    db.insert("users", {"name": "Ana", "age": "30"})
+    db.insert("users", {"name": "Bob"})
+
+    assert db.change_field_type("users", "age", "integer") == []
+
+    rows = {r.data["name"]: r.data for r in db.fetch_all("users")}
+    assert rows["Ana"]["age"] == 30
+    assert "age" not in rows["Bob"]
+    reopened = Database(str(tmp_path / "schema.rsndb"))
+    assert db.query(Query("users").where_eq("age", 30))[0].data["name"] == "Ana"
+    assert len(reopened.query(Query("users").where_eq("age", 30))) == 1
+
+
+def test_change_field_type_strict_reports_failures(tmp_path):
+    db = _users(tmp_path)
+    db.insert("users", {"name": "Ana", "age": "30"})
+    bad = db.insert("users", {"name": "Bob", "age": "old"})
+
+    with pytest.raises(ValueError, match=rf"record ids \[{bad}\]"):
+        db.change_field_type("users", "age", "integer")
+    assert sorted(r.data["age"] for r in db.fetch_all("users")) == ["30", "old"]
+
+    assert db.change_field_type("users", "age", "integer", strict=False) == [bad]
+    ages = {r.id: r.data["age"] for r in db.fetch_all("users")}
+    assert ages[bad] is None
+
+
+def test_change_field_type_errors(tmp_path):
+    db = _users(tmp_path)
+    with pytest.raises(KeyError):
+        db.change_field_type("missing", "age", "integer")
+    with pytest.raises(ValueError, match="not part of the schema"):
+        db.change_field_type("users", "height", "integer")
+    with pytest.raises(ValueError, match="unsupported field type"):
+        db.change_field_type("users", "age", "decimal")
+
+
+def test_defaults_fill_missing_fields(tmp_path):
+    db = Database(str(tmp_path / "defaults.rsndb"))
+    db.create_table(
+        "orders",
+        {
+            "item": {"type": "string", "required": True},
+            "status": {"type": "string", "required": True, "default": "pending"},
+            "qty": {"type": "integer", "default": "1"},
+        },
+    )
+    rid = db.insert("orders", {"item": "widget"})
+    row = db.fetch_all("orders")[0]
+    assert row.id == rid
+    assert row.data["status"] == "pending"
+    assert row.data["qty"] == 1
+
+    db.insert("orders", {"item": "gadget", "status": "shipped"})
+    statuses = sorted(r.data["status"] for r in db.fetch_all("orders"))
+    assert statuses == ["pending", "shipped"]
+
+
+def test_default_must_match_field_type(tmp_path):
+    db = Database(str(tmp_path / "defaults_bad.rsndb"))
+    with pytest.raises(ValueError, match="schema type mismatch for field `qty`"):
+        db.create_table("orders", {"qty": {"type": "integer", "default": "many"}})
+    assert db.execute_sql("SHOW TABLES") == []
+
+
+def test_field_constraints_enforced_on_insert_and_update(tmp_path):
+    db = Database(str(tmp_path / "constraints.rsndb"))
+    db.create_table(
+        "products",
+        {
+            "sku": {"type": "string", "pattern": r"^[A-Z]{3}-\d+$"},
+            "name": {"type": "string", "min_length": 2, "max_length": 10},
+            "price": {"type": "float", "min": 0, "max": 1000},
+        },
+    )
+    rid = db.insert("products", {"sku": "ABC-1", "name": "Widget", "price": 9.5})
+
+    with pytest.raises(ValueError, match="field `price` violates constraint `min=0`"):
+        db.insert("products", {"price": -1})
+    with pytest.raises(ValueError, match="`max_length=10`"):
+        db.insert("products", {"name": "A very long product name"})
+    with pytest.raises(ValueError, match="`min_length=2`"):
+        db.update("products", rid, {"name": "X"})
+    with pytest.raises(ValueError, match="pattern="):
+        db.update("products", rid, {"sku": "abc-1"})
+
+    reopened = Database(str(tmp_path / "constraints.rsndb"))
+    with pytest.raises(ValueError, match="pattern="):
+        reopened.insert("products", {"sku": "nope"})
+    assert reopened.fetch_all("products")[0].data["name"] == "Widget"
+
+
+def test_invalid_constraint_combinations_rejected(tmp_path):
+    db = Database(str(tmp_path / "constraints_bad.rsndb"))
+    with pytest.raises(ValueError, match="max is less than min"):
+        db.create_table("t", {"n": {"type": "integer", "min": 5, "max": 1}})
+    with pytest.raises(ValueError, match="max_length is less than min_length"):
+        db.create_table("t", {"s": {"type": "string", "min_length": 5, "max_length": 1}})
+    with pytest.raises(ValueError, match="require a string field"):
+        db.create_table("t", {"n": {"type": "integer", "pattern": "x"}})
+    with pytest.raises(ValueError, match="bad pattern"):
+        db.create_table("t", {"s": {"type": "string", "pattern": "("}})
+    with pytest.raises(ValueError, match="violates constraint"):
+        db.create_table("t", {"n": {"type": "integer", "min": 1, "default": 0}})
+
+
+def test_datetime_fields_normalize_and_sort(tmp_path):
+    db = Database(str(tmp_path / "events.rsndb"))
+    db.create_table(
+        "events",
+        {"name": {"type": "string"}, "at": {"type": "datetime", "required": True}},
+    )
+    db.insert("events", {"name": "late", "at": "2024-03-01T10:00:00.5Z"})
+    db.insert("events", {"name": "offset", "at": "2024-03-01T12:00:00+02:00"})
+    db.insert("events", {"name": "epoch", "at": 0})
+    db.insert("events", {"name": "date", "at": "2024-02-15"})
+
+    ordered = db.query(Query("events").order_by("at"))
+    assert [r.data["name"] for r in ordered] == ["epoch", "date", "offset", "late"]
+    assert ordered[0].data["at"] == "1970-01-01T00:00:00Z"
+    assert ordered[2].data["at"] == "2024-03-01T10:00:00Z"
+
+    march = db.query(Query("events").where_between("at", "2024-03-01", "2024-03-02"))
+    assert sorted(r.data["name"] for r in march) == ["late", "offset"]
+
+    with pytest.raises(ValueError, match="expected `datetime`"):
+        db.insert("events", {"name": "bad", "at": "next tuesday"})
+
+
+def test_where_between_ignores_other_types(tmp_path):
+    db = Database(str(tmp_path / "between.rsndb"))
+    db.create_table("nums", {"n": {"type": "json"}})
+    for value in (1, 5, 10, "7", None):
+        db.insert("nums", {"n": value})
+    hits = db.query(Query("nums").where_between("n", 2, 10))
+    assert sorted(r.data["n"] for r in hits) == [5, 10]
+
+
+def test_typed_array_fields(tmp_path):
+    db = Database(str(tmp_path / "arrays.rsndb"))
+    db.create_table(
+        "posts",
+        {
+            "title": {"type": "string"},
+            "tags": {"type": "array", "of": "string"},
+            "scores": {"type": "array", "of": "integer"},
+        },
+    )
+    db.insert("posts", {"title": "a", "tags": ["rust", "db"], "scores": ["1", 2]})
+    db.insert("posts", {"title": "b", "tags": ["python"]})
+
+    rows = {r.data["title"]: r.data for r in db.fetch_all("posts")}
+    assert rows["a"]["scores"] == [1, 2]
+
+    with pytest.raises(ValueError, match="field `scores` at index 1: expected `integer`"):
+        db.insert("posts", {"title": "c", "scores": [1, "many"]})
+    with pytest.raises(ValueError, match="expected `array<string>`"):
+        db.insert("posts", {"title": "d", "tags": "rust"})
+    with pytest.raises(ValueError, match="unsupported array element type"):
+        db.create_table("bad", {"xs": {"type": "array", "of": "array"}})
+
+    hits = db.query(Query("posts").where_contains("tags", "rust"))
+    assert [r.data["title"] for r in hits] == ["a"]
+    assert db.query(Query("posts").where_contains("title", "b"))[0].data["title"] == "b"
+
+    reopened = Database(str(tmp_path / "arrays.rsndb"))
+    with pytest.raises(ValueError, match="at index 0"):
+        reopened.insert("posts", {"tags": [None]})
+
+
+def test_references_between_tables(tmp_path):
+    db = Database(str(tmp_path / "refs.rsndb"))
+    db.create_table("users", {"name": {"type": "string"}})
+    db.create_table("posts", {"owner": {"type": "integer", "references": "users"}})
+
+    with pytest.raises(ValueError, match="unknown table `ghosts`"):
+        db.create_table("likes", {"who": {"type": "integer", "references": "ghosts"}})
+    with pytest.raises(ValueError, match="integer"):
+        db.create_table("tags", {"who": {"type": "string", "references": "users"}})
+    with pytest.raises(ValueError, match="missing record 7 in table `users`"):
+        db.insert("posts", {"owner": 7})
+
+    uid = db.insert("users", {"name": "ada"})
+    pid = db.insert("posts", {"owner": uid})
+    with pytest.raises(ValueError, match="posts.owner"):
+        db.delete("users", uid)
+    with pytest.raises(ValueError, match="missing record 99"):
+        db.update("posts", pid, {"owner": 99})
+
+    db.delete("posts", pid)
+    db.delete("users", uid)
+    assert db.fetch_all("users") == []
+
+
+def test_on_delete_cascade_and_set_null(tmp_path):
+    path = str(tmp_path / "cascade.rsndb")
+    db = Database(path)
+    db.create_table("users", {"name": {"type": "string"}})
+    db.create_table("posts", {"author": {"type": "integer", "references": "users", "on_delete": "cascade"}})
+    db.create_table(
+        "comments",
+        {
+            "post": {"type": "integer", "references": "posts", "on_delete": "cascade"},
+            "mentions": {"type": "integer", "references": "users", "on_delete": "set_null"},
+        },
+    )
+    with pytest.raises(ValueError, match="on_delete requires a references field"):
+        db.create_table("bad", {"x": {"type": "integer", "on_delete": "cascade"}})
+    with pytest.raises(ValueError, match="requires an optional field"):
+        db.create_table("bad", {"x": {"type": "integer", "required": True, "references": "users", "on_delete": "set_null"}})
+    with pytest.raises(ValueError, match="unsupported on_delete rule"):
+        db.create_table("bad", {"x": {"type": "integer", "references": "users", "on_delete": "explode"}})
+
+    ada = db.insert("users", {"name": "ada"})
+    bob = db.insert("users", {"name": "bob"})
+    post = db.insert("posts", {"author": ada})
+    db.insert("comments", {"post": post, "mentions": ada})
+    kept = db.insert("comments", {"mentions": ada})
+
+    summary = db.delete("users", ada)
+    assert summary == {"deleted": {"comments": 1, "posts": 1, "users": 1}, "nulled": {"comments": 1}}
+
+    reopened = Database(path)
+    assert [r.id for r in reopened.fetch_all("users")] == [bob]
+    assert reopened.fetch_all("posts") == []
+    rows = reopened.fetch_all("comments")
+    assert [r.id for r in rows] == [kept]
+    assert rows[0].data["mentions"] is None
+
+    reopened.insert("posts", {"author": bob})
+    reopened.insert("posts", {"author": bob})
+    summary = reopened.delete_where(Query("users").where_eq("name", "bob"))
+    assert summary["deleted"] == {"posts": 2, "users": 1}
+
+
+def test_secondary_index_matches_full_scan(tmp_path):
+    path = str(tmp_path / "index.rsndb")
+    db = Database(path)
+    db.create_table("orders", {"status": {"type": "string", "index": True}, "qty": {"type": "integer"}})
+    for i in range(60):
+        db.insert("orders", {"status": ["new", "paid", "sent"][i % 3], "qty": i})
+
+    def paid():
+        return sorted(r.id for r in db.query(Query("orders").where_eq("status", "paid")))
+
+    assert len(paid()) == 20
+    db.update("orders", 2, {"status": "sent"})
+    db.delete("orders", 5)
+    assert paid() == [i + 1 for i in range(60) if i % 3 == 1 and i + 1 not in (2, 5)]
+    assert db.query(Query("orders").where_eq("status", "lost")) == []
+
+    db.create_index("orders", "qty")
+    assert [r.id for r in db.query(Query("orders").where_eq("qty", 10).where_eq("status", "paid"))] == [11]
+    with pytest.raises(ValueError, match="not part of the schema"):
+        db.create_index("orders", "nope")
+
+    reopened = Database(path)
+    assert len(reopened.query(Query("orders").where_eq("status", "paid"))) == 18
+
+
+def test_table_and_field_metadata(tmp_path):
+    path = str(tmp_path / "meta.rsndb")
+    db = Database(path)
+    db.create_table(
+        "payments",
+        {"amount": {"type": "integer", "min": 0, "description": "amount is in cents"}},
+        meta={"owner": "billing", "retention": {"days": 90}},
+    )
+    schema = db.schema("payments")
+    assert schema["fields"]["amount"] == {
+        "type": "integer",
+        "required": False,
+        "unique": False,
+        "indexed": False,
+        "min": 0.0,
+        "description": "amount is in cents",
+    }
+    assert schema["meta"] == {"owner": "billing", "retention": {"days": 90}}
+
+    db.set_table_meta("payments", "owner", None)
+    db.set_table_meta("payments", "pii", False)
+    db.set_field_meta("payments", "amount", "integer cents, never negative")
+    with pytest.raises(ValueError, match="exceeds 4096 bytes"):
+        db.set_table_meta("payments", "blob", "x" * 5000)
+    with pytest.raises(ValueError, match="not part of the schema"):
+        db.set_field_meta("payments", "nope", "?")
+
+    reopened = Database(path)
+    full = reopened.execute_sql("DESCRIBE payments FULL")
+    assert full["meta"] == {"retention": {"days": 90}, "pii": False}
+    assert full["fields"]["amount"]["description"] == "integer cents, never negative"
+    assert reopened.execute_sql("DESCRIBE payments") == ["amount"]
+
+
+def test_copy_table(tmp_path):
+    path = str(tmp_path / "copy.rsndb")
+    db = Database(path)
+    db.create_table("users", {"email": {"type": "string", "unique": True}}, meta={"owner": "ops"})
+    for email in ["a@x", "b@x", "c@x"]:
+        db.insert("users", {"email": email})
+    db.delete("users", 1)
+
+    assert db.copy_table("users", "users_copy") == 2
+    assert sorted(r.id for r in db.fetch_all("users_copy")) == [1, 2]
+    assert db.copy_table("users", "users_same", preserve_ids=True) == 2
+    assert sorted(r.id for r in db.fetch_all("users_same")) == [2, 3]
+    assert db.copy_table("users", "users_shape", with_data=False) == 0
+    assert db.schema("users_shape") == db.schema("users")
+
+    with pytest.raises(ValueError, match="already exists"):
+        db.copy_table("users", "users_copy")
+    with pytest.raises(KeyError):
+        db.copy_table("ghosts", "ghosts_copy")
+    with pytest.raises(ValueError, match="must be unique"):
+        db.insert("users_copy", {"email": "b@x"})
+
+    db.insert("users_same", {"email": "d@x"})
+    assert len(Database(path).fetch_all("users")) == 2
+
+
+def test_truncate_table(tmp_path):
+    path = str(tmp_path / "truncate.rsndb")
+    db = Database(path)
+    db.create_table("users", {"email": {"type": "string", "unique": True}})
+    db.create_table("posts", {"author": {"type": "integer", "references": "users"}})
+    for email in ["a@x", "b@x"]:
+        db.insert("users", {"email": email})
+    db.insert("posts", {"author": 1})
+
+    with pytest.raises(ValueError, match="posts.author"):
+        db.truncate_table("users")
+    assert db.truncate_table("posts") == 1
+    assert db.truncate_table("users") == 2
+    assert db.insert("users", {"email": "a@x"}) == 3
+
+    assert "1 row(s)" in db.execute_sql("TRUNCATE users RESET")
+    assert db.insert("users", {"email": "a@x"}) == 1
+    with pytest.raises(KeyError):
+        db.truncate_table("ghosts")
+    assert len(Database(path).fetch_all("users")) == 1
+
+    db.create_table("teams", {"name": {"type": "string"}})
+    db.create_table("members", {"team": {"type": "integer", "references": "teams", "on_delete": "cascade"}})
+    db.create_table("badges", {"team": {"type": "integer", "references": "teams", "on_delete": "set_null"}})
+    db.insert("teams", {"name": "red"})
+    db.insert("members", {"team": 1})
+    db.insert("badges", {"team": 1})
+    assert db.truncate_table("teams") == 1
+    assert db.fetch_all("members") == []
+    assert db.fetch_all("badges")[0].data["team"] is None
+
+    snarky = Database(str(tmp_path / "snark.rsndb"), mode="snarky")
+    snarky.create_table("t", {"x": {"type": "integer"}})
+    assert "Truncated 't' (0 row(s))." in snarky.truncate_table("t")
+
+
+def test_export_import_schema_round_trip(tmp_path, monkeypatch):
+    monkeypatch.chdir(tmp_path)
+    db = Database(str(tmp_path / "source.rsndb"))
+    db.create_table("users", {"email": {"type": "string", "unique": True, "pattern": ".+@.+"}}, meta={"owner": "ops"})
+    db.create_table(
+        "posts",
+        {
+            "author": {"type": "integer", "references": "users", "on_delete": "cascade", "index": True},
+            "tags": {"type": "array", "of": "string", "default": []},
+            "score": {"type": "float", "min": 0, "max": 5, "description": "stars"},
+            "published": {"type": "datetime", "required": True},
+        },
+    )
+    db.insert("users", {"email": "a@x"})
+    db.export_schema("schema.json")
+    doc = db.export_schema()
+    assert doc["version"] == 1
+    assert doc["tables"]["posts"]["fields"]["tags"]["type"] == "array<string>"
+
+    fresh = Database(str(tmp_path / "fresh.rsndb"))
+    assert sorted(fresh.import_schema("schema.json")) == ["posts", "users"]
+    for name in ("users", "posts"):
+        assert fresh.schema(name) == db.schema(name)
+    assert fresh.fetch_all("users") == []
+    assert fresh.export_schema() == doc
+
+    with pytest.raises(ValueError, match="already exists"):
+        fresh.import_schema(doc)
+    assert fresh.import_schema(doc, if_not_exists=True) == []
+
+    partial = Database(str(tmp_path / "partial.rsndb"))
+    partial.create_table("users", {"email": {"type": "string"}})
+    assert partial.import_schema(doc, if_not_exists=True) == ["posts"]
+    assert partial.schema("users")["fields"]["email"]["unique"] is False
+
+    broken = {"tables": {"likes": {"fields": {"who": {"type": "integer", "references": "ghosts"}}}}}
+    with pytest.raises(ValueError, match="unknown table `ghosts`"):
+        Database(str(tmp_path / "broken.rsndb")).import_schema(broken)
+
+
+def test_export_ddl_runs_back_through_execute_script(tmp_path, monkeypatch):
+    monkeypatch.chdir(tmp_path)
+    db = Database()
+    db.create_table(
+        "users",
+        {
+            "name": {"type": "string", "required": True, "unique": True},
+            "age": {"type": "integer", "default": 18, "index": True},
+            "select": {"type": "string", "default": "it's; fine"},
+            "tags": {"type": "array", "of": "string", "default": ["new"]},
+            "profile": {"type": "json", "default": {"plan": "free"}},
+            "active": {"type": "boolean", "default": True},
+        },
+    )
+    db.create_table(
+        "posts",
+        {
+            "author": {"type": "integer", "references": "users"},
+            "score": {"type": "float", "min": 0},
+            "double": {"type": "float", "computed": "score * 2"},
+        },
+        meta={"owner": "ops"},
+    )
+
+    ddl = db.export_ddl()
+    assert ddl == db.export_ddl()
+    assert ddl.startswith("-- posts: not in DDL: author (references users), double (computed), score (constraints), meta\n")
+    assert "CREATE TABLE users (\n    active BOOLEAN DEFAULT TRUE,\n    age INTEGER INDEXED DEFAULT 18," in ddl
+    db.export_ddl("schema.sql")
+    assert (tmp_path / "schema.sql").read_text() == ddl
+
+    fresh = Database()
+    assert fresh.execute_script(ddl) == [None, None]
+    assert fresh.schema("users") == db.schema("users")
+    assert sorted(fresh.schema("posts")["fields"]) == ["author", "score"]
+    assert fresh.execute_script("-- nothing here\n;") == []
+    with pytest.raises(ValueError, match="already exists"):
+        fresh.execute_script(ddl)
+
+
+def test_computed_fields(tmp_path):
+    path = str(tmp_path / "computed.rsndb")
+    db = Database(path)
+    db.create_table(
+        "people",
+        {
+            "first_name": {"type": "string"},
+            "last_name": {"type": "string"},
+            "full_name": {"type": "string", "computed": "concat(first_name, ' ', last_name)"},
+        },
+    )
+    db.create_table(
+        "lines",
+        {
+            "price": {"type": "float"},
+            "quantity": {"type": "integer"},
+            "total": {"type": "float", "computed": "price * quantity"},
+        },
+    )
+    db.insert("people", {"first_name": "Ada", "last_name": "Lovelace"})
+    db.insert("lines", {"price": 2.5, "quantity": 4})
+    db.insert("lines", {"price": 1.0, "quantity": 3})
+    db.insert("lines", {"price": 9.0})
+
+    assert db.fetch_all("people")[0].data["full_name"] == "Ada Lovelace"
+    totals = [r.data["total"] for r in db.query(Query("lines").order_by("total", True))]
+    assert totals == [10.0, 3.0, None]
+    # Nulls and missing values sort first, computed or stored alike.
+    totals = [r.data["total"] for r in db.query(Query("lines").order_by("total"))]
+    assert totals == [None, 3.0, 10.0]
+    assert [r.id for r in db.query(Query("lines").order_by("quantity"))] == [3, 2, 1]
+    assert [r.id for r in db.query(Query("lines").where_eq("total", 3.0))] == [2]
+
+    with pytest.raises(ValueError, match="is computed"):
+        db.insert("lines", {"price": 1.0, "total": 5.0})
+    with pytest.raises(ValueError, match="bad expression"):
+        db.create_table("bad", {"x": {"type": "integer", "computed": "1 +"}})
+    with pytest.raises(ValueError, match="not a stored field"):
+        db.create_table("bad", {"x": {"type": "integer", "computed": "y * 2"}})
+    with pytest.raises(ValueError, match="computed fields cannot"):
+        db.create_table("bad", {"x": {"type": "integer", "required": True, "computed": "1"}})
+
+    reopened = Database(path)
+    assert reopened.schema("lines")["fields"]["total"]["computed"] == "price * quantity"
+    assert reopened.fetch_all("people")[0].data["full_name"] == "Ada Lovelace"
+
+
+def test_versioned_tables_detect_conflicts(tmp_path):
+    path = str(tmp_path / "versioned.rsndb")
+    db = Database(path)
+    db.create_table("accounts", {"balance": {"type": "integer"}}, versioned=True)
+    db.create_table("plain", {"x": {"type": "integer"}})
+    rid = db.insert("accounts", {"balance": 10})
+    assert db.fetch_all("accounts")[0].version == 1
+
+    db.update("accounts", rid, {"balance": 20}, expected_version=1)
+    other = Database(path)
+    record = other.query(Query("accounts").where_eq("balance", 20))[0]
+    assert record.version == 2
+    assert "version=2" in repr(record)
+
+    with pytest.raises(ValueError, match="is at version 2, expected 1"):
+        db.update("accounts", rid, {"balance": 99}, expected_version=1)
+    db.update("accounts", rid, {"balance": 30})
+    with pytest.raises(ValueError, match="expected 2"):
+        db.delete("accounts", rid, expected_version=2)
+    assert Database(path).fetch_all("accounts")[0].data["balance"] == 30
+
+    pid = db.insert("plain", {"x": 1})
+    assert db.fetch_all("plain")[0].version is None
+    with pytest.raises(ValueError, match="not versioned"):
+        db.update("plain", pid, {"x": 2}, expected_version=1)
+
+    db.delete("accounts", rid, expected_version=3)
+    assert db.fetch_all("accounts") == []
+    assert db.export_schema()["tables"]["accounts"]["versioned"] is True
+
+
+def test_unique_fields_and_nulls(tmp_path):
+    path = str(tmp_path / "nulls.rsndb")
+    db = Database(path)
+    db.create_table(
+        "users",
+        {
+            "email": {"type": "string", "unique": True},
+            "badge": {"type": "string", "unique": True, "strict_unique_nulls": True},
+        },
+    )
+    db.insert("users", {"email": None, "badge": None})
+    db.insert("users", {"email": None})
+    db.insert("users", {})
+    db.insert("users", {"email": "a@x"})
+    with pytest.raises(ValueError, match="`email` must be unique"):
+        db.insert("users", {"email": "a@x"})
+    with pytest.raises(ValueError, match="`badge` must be unique"):
+        db.insert("users", {"badge": None})
+
+    reopened = Database(path)
+    reopened.insert("users", {"email": None})
+    with pytest.raises(ValueError, match="`badge` must be unique"):
+        reopened.insert("users", {"badge": None})
+    with pytest.raises(ValueError, match="requires a unique field"):
+        reopened.create_table("bad", {"x": {"type": "string", "strict_unique_nulls": True}})
+
+
+def test_strict_types_rejects_coercion(tmp_path):
+    path = str(tmp_path / "strict.rsndb")
+    lenient = Database(path)
+    lenient.create_table("events", {"count": {"type": "integer"}, "tags": {"type": "array", "of": "integer"}})
+    payload = {"count": "42", "tags": ["1", 2]}
+    rid = lenient.insert("events", payload)
+    assert lenient.fetch_all("events")[0].data == {"count": 42, "tags": [1, 2]}
+
+    strict = Database(path, strict_types=True)
+    with pytest.raises(ValueError, match="field `count`: expected `integer`"):
+        strict.insert("events", {"count": "42"})
+    with pytest.raises(ValueError, match="expected `array<integer>`"):
+        strict.insert("events", {"count": 1, "tags": ["1"]})
+    with pytest.raises(ValueError, match="expected `integer`"):
+        strict.update("events", rid, {"count": "7"})
+    strict.insert("events", {"count": 1, "tags": [1]})
+
+    reopened = Database(path)
+    with pytest.raises(ValueError, match="schema type mismatch"):
+        reopened.insert("events", payload)
+    reopened.set_strict_types(False)
+    reopened.insert("events", payload)
+    assert len(Database(path).fetch_all("events")) == 3
+
+
+def test_ttl_tables_expire_rows(tmp_path):
+    path = str(tmp_path / "ttl.rsndb")
+    db = Database(path)
+    db.create_table("cache", {"key": {"type": "string", "unique": True}}, ttl_seconds=1)
+    db.create_table("kept", {"key": {"type": "string"}})
+    db.insert("cache", {"key": "a"})
+    db.insert("kept", {"key": "a"})
+    with pytest.raises(ValueError, match="`key` must be unique"):
+        db.insert("cache", {"key": "a"})
+    assert Database(path).schema("cache")["ttl_seconds"] == 1
+
+    time.sleep(1.1)
+    assert db.fetch_all("cache") == []
+    assert db.query(Query("cache").where_eq("key", "a")) == []
+    assert db.execute_sql("COUNT cache") == 0
+    assert db.execute_sql("COUNT kept") == 1
+    assert db.purge_expired("cache") == 1
+    assert db.purge_expired("cache") == 0
+
+    db.insert("cache", {"key": "a"})
+    assert [r.data for r in Database(path).fetch_all("cache")] == [{"key": "a"}]
+    with pytest.raises(ValueError, match="ttl_seconds must be positive"):
+        db.create_table("bad", {"key": {"type": "string"}}, ttl_seconds=0)
+
+
+def test_insert_many_is_all_or_nothing(tmp_path):
+    path = str(tmp_path / "bulk.rsndb")
+    db = Database(path)
+    db.create_table(
+        "users",
+        {"email": {"type": "string", "unique": True, "required": True}, "age": {"type": "integer"}},
+    )
+    ids = db.insert_many("users", [{"email": f"u{i}@x", "age": i} for i in range(200)])
+    assert ids == list(range(1, 201))
+    assert len(Database(path).fetch_all("users")) == 200
+
+    with pytest.raises(ValueError, match="`email` must be unique"):
+        db.insert_many("users", [{"email": "new@x"}, {"email": "dup@x"}, {"email": "dup@x"}])
+    with pytest.raises(KeyError):
+        db.insert_many("users", [{"email": "new@x"}, {"age": 3}])
+    assert len(db.fetch_all("users")) == 200
+    assert db.insert_many("users", [{"email": "new@x"}, {"email": "dup@x"}]) == [201, 202]
+    assert db.insert_many("users", []) == []
+
+
+def test_upsert_inserts_then_patches(tmp_path):
+    path = str(tmp_path / "upsert.rsndb")
+    db = Database(path)
+    db.create_table(
+        "users",
+        {
+            "email": {"type": "string", "unique": True},
+            "name": {"type": "string"},
+            "team": {"type": "string", "index": True},
+            "note": {"type": "string"},
+        },
+    )
+    rid, created = db.upsert("users", {"email": "a@x", "name": "Ann"}, on="email")
+    assert created
+    assert db.upsert("users", {"email": "a@x", "name": "Annie"}, on="email") == (rid, False)
+    assert [r.data for r in Database(path).fetch_all("users")] == [{"email": "a@x", "name": "Annie"}]
+
+    db.upsert("users", {"email": "b@x", "team": "red"}, on="email")
+    with pytest.raises(ValueError, match="must be a unique or indexed field"):
+        db.upsert("users", {"note": "x"}, on="note")
+    with pytest.raises(KeyError):
+        db.upsert("users", {"name": "nobody"}, on="email")
+
+    hits = db.upsert_many(
+        "users",
+        [{"email": "b@x", "team": "blue"}, {"email": "c@x", "team": "blue"}],
+        on="email",
+    )
+    assert hits == [(2, False), (3, True)]
+    with pytest.raises(ValueError, match="matches more than one record"):
+        db.upsert("users", {"team": "blue", "note": "dup"}, on="team")
+    with pytest.raises(ValueError, match="not part of the schema"):
+        db.upsert_many(
+            "users",
+            [{"email": "d@x"}, {"email": "a@x", "name": "Ann"}, {"email": "c@x", "bogus": 1}],
+            on="email",
+        )
+    rows = {r.id: r.data for r in db.fetch_all("users")}
+    assert len(rows) == 3 and rows[1]["name"] == "Annie"
+    assert db.upsert("users", {"email": "d@x"}, on="email") == (4, True)
+
+
+def test_get_and_get_or_none(tmp_path):
+    db = _users(tmp_path)
+    rid = db.insert("users", {"name": "Ann", "age": "30"})
+    record = db.get("users", rid)
+    assert (record.id, record.data) == (rid, {"name": "Ann", "age": "30"})
+    assert db.get_or_none("users", rid).data == record.data
+    assert db.get_or_none("users", 99) is None
+    with pytest.raises(KeyError, match="record id `99` does not exist"):
+        db.get("users", 99)
+    with pytest.raises(KeyError, match="table `ghosts` does not exist"):
+        db.get("ghosts", rid)
+    with pytest.raises(KeyError, match="table `ghosts` does not exist"):
+        db.get_or_none("ghosts", rid)
+
+
+def test_replace_drops_omitted_fields(tmp_path):
+    path = str(tmp_path / "replace.rsndb")
+    db = Database(path)
+    db.create_table(
+        "users",
+        {
+            "name": {"type": "string", "required": True},
+            "email": {"type": "string", "unique": True},
+            "nick": {"type": "string"},
+        },
+        versioned=True,
+    )
+    rid = db.insert("users", {"name": "Ann", "email": "a@x", "nick": "annie"})
+    db.replace("users", rid, {"name": "Ann B"})
+    record = Database(path).get("users", rid)
+    assert record.data == {"name": "Ann B"}
+    assert record.version == 2
+    db.insert("users", {"name": "Bob", "email": "a@x"})
+
+    with pytest.raises(KeyError, match="`name` is missing"):
+        db.replace("users", rid, {"nick": "x"})
+    with pytest.raises(ValueError, match="`email` must be unique"):
+        db.replace("users", rid, {"name": "Ann", "email": "a@x"})
+    with pytest.raises(ValueError, match="at version 2, expected 1"):
+        db.replace("users", rid, {"name": "Ann"}, expected_version=1)
+    with pytest.raises(KeyError, match="record id `42` does not exist"):
+        db.replace("users", 42, {"name": "Nobody"})
+    assert db.get("users", rid).data == {"name": "Ann B"}
+
+
+def test_unset_removes_keys_unlike_null_patch(tmp_path):
+    path = str(tmp_path / "unset.rsndb")
+    db = Database(path)
+    db.create_table(
+        "users",
+        {
+            "name": {"type": "string", "required": True},
+            "email": {"type": "string", "unique": True},
+            "nick": {"type": "string"},
+        },
+    )
+    ann = db.insert("users", {"name": "Ann", "email": "a@x", "nick": "annie"})
+    bob = db.insert("users", {"name": "Bob", "nick": "bobby"})
+    db.update("users", ann, {"nick": None})
+    db.unset("users", bob, ["nick"])
+    assert db.get("users", ann).data["nick"] is None
+    assert "nick" not in db.get("users", bob).data
+    assert [r.id for r in db.query(Query("users").where_null("nick"))] == [ann]
+    assert [r.id for r in db.query(Query("users").where_missing("nick"))] == [bob]
+
+    db.unset("users", ann, ["email"])
+    db.insert("users", {"name": "Cy", "email": "a@x"})
+    with pytest.raises(KeyError, match="`name` is missing"):
+        db.unset("users", ann, ["name"])
+    with pytest.raises(ValueError, match="not part of the schema"):
+        db.unset("users", ann, ["bogus"])
+    with pytest.raises(KeyError, match="record id `9` does not exist"):
+        db.unset("users", 9, ["nick"])
+    assert Database(path).get("users", ann).data == {"name": "Ann", "nick": None}
+
+
+def test_soft_delete_hides_restores_and_purges(tmp_path):
+    path = str(tmp_path / "soft.rsndb")
+    db = Database(path)
+    db.create_table("users", {"email": {"type": "string", "unique": True}}, soft_delete=True)
+    db.create_table(
+        "tags",
+        {"label": {"type": "string", "unique": True}},
+        soft_delete=True,
+        soft_delete_releases_unique=True,
+    )
+    ann = db.insert("users", {"email": "a@x"})
+    bob = db.insert("users", {"email": "b@x"})
+    db.soft_delete("users", ann)
+
+    reopened = Database(path)
+    assert [r.id for r in reopened.fetch_all("users")] == [bob]
+    assert reopened.get_or_none("users", ann) is None
+    assert reopened.execute_sql("COUNT users") == 1
+    assert reopened.query(Query("users").where_eq("email", "a@x")) == []
+    assert [r.id for r in reopened.query(Query("users").where_eq("email", "a@x").with_deleted())] == [ann]
+    with pytest.raises(ValueError, match="`email` must be unique"):
+        db.insert("users", {"email": "a@x"})
+    with pytest.raises(KeyError, match="does not exist"):
+        db.update("users", ann, {"email": "z@x"})
+
+    db.restore("users", ann)
+    assert db.get("users", ann).data == {"email": "a@x"}
+    with pytest.raises(KeyError):
+        db.restore("users", ann)
+    db.create_table("plain", {"x": {"type": "string"}})
+    with pytest.raises(ValueError, match="does not use soft delete"):
+        db.soft_delete("plain", db.insert("plain", {"x": "y"}))
+
+    red = db.insert("tags", {"label": "red"})
+    db.soft_delete("tags", red)
+    db.insert("tags", {"label": "red"})
+    with pytest.raises(ValueError, match="`label` must be unique"):
+        db.restore("tags", red)
+    assert db.purge_deleted("tags", older_than=3600) == {"deleted": {}, "nulled": {}}
+    assert db.purge_deleted("tags") == {"deleted": {"tags": 1}, "nulled": {}}
+    assert len(Database(path).query(Query("tags").with_deleted())) == 1
+    with pytest.raises(ValueError, match="`label` must be unique"):
+        db.insert("tags", {"label": "red"})
+
+
+def test_soft_deleted_rows_are_not_upsert_matches_or_reference_targets():
+    db = Database()
+    db.create_table(
+        "users",
+        {"email": {"type": "string", "unique": True}, "team": {"type": "string", "index": True}},
+        soft_delete=True,
+        soft_delete_releases_unique=True,
+    )
+    db.create_table("posts", {"author": {"type": "integer", "references": "users"}})
+    ann = db.insert("users", {"email": "a@x", "team": "blue"})
+    bob = db.insert("users", {"email": "b@x", "team": "blue"})
+    db.soft_delete("users", ann)
+
+    assert db.upsert("users", {"team": "blue", "email": "bob@x"}, on="team") == (bob, False)
+    rid, created = db.upsert("users", {"email": "a@x", "team": "red"}, on="email")
+    assert created and rid != ann
+    assert db.query(Query("users").where_eq("email", "a@x").with_deleted())[0].data["team"] == "blue"
+
+    with pytest.raises(ValueError, match="references missing record"):
+        db.insert("posts", {"author": ann})
+    post = db.insert("posts", {"author": bob})
+    db.soft_delete("users", bob)
+    with pytest.raises(ValueError, match="references missing record"):
+        db.update("posts", post, {"author": bob})
+
+
+def test_update_if_checks_preconditions(tmp_path):
+    path = str(tmp_path / "cas.rsndb")
+    db = Database(path)
+    db.create_table(
+        "orders",
+        {"status": {"type": "string"}, "attempts": {"type": "integer"}, "note": {"type": "string"}},
+        versioned=True,
+    )
+    rid = db.insert("orders", {"status": "packed", "attempts": 1})
+    db.update_if("orders", rid, {"status": "packed", "attempts": "1", "note": None}, {"status": "shipped"})
+    assert Database(path).get("orders", rid).data["status"] == "shipped"
+
+    with pytest.raises(ValueError, match="expected value for `status`"):
+        db.update_if("orders", rid, {"status": "packed"}, {"status": "lost"})
+    with pytest.raises(ValueError, match="not part of the schema"):
+        db.update_if("orders", rid, {"bogus": 1}, {"status": "lost"})
+    with pytest.raises(KeyError):
+        db.update_if("orders", 99, {"status": "packed"}, {"status": "lost"})
+    record = db.get("orders", rid)
+    assert (record.data["status"], record.version) == ("shipped", 2)
+
+    db.create_table("tallies", {"n": {"type": "integer"}, "double": {"type": "integer", "computed": "n * 2"}})
+    tid = db.insert("tallies", {"n": 3})
+    db.update_if("tallies", tid, {"double": 6}, {"n": 4})
+    with pytest.raises(ValueError, match="expected value for `double`"):
+        db.update_if("tallies", tid, {"double": 6}, {"n": 5})
+    assert db.get("tallies", tid).data["n"] == 4
+
+
+def test_increment_and_increment_where(tmp_path):
+    path = str(tmp_path / "counters.rsndb")
+    db = Database(path)
+    db.create_table(
+        "jobs",
+        {
+            "retries": {"type": "integer", "max": 3},
+            "score": {"type": "float"},
+            "name": {"type": "string"},
+        },
+    )
+    a = db.insert("jobs", {"retries": 0, "name": "a"})
+    b = db.insert("jobs", {"retries": 2, "name": "b"})
+    assert db.increment("jobs", a, "retries") == 1
+    assert db.increment("jobs", a, "retries", delta=-1) == 0
+    assert db.increment("jobs", a, "score", delta=0.5) == 0.5
+    assert db.increment("jobs", b, "score", 1.25) == 1.25
+    assert Database(path).get("jobs", a).data == {"retries": 0, "score": 0.5, "name": "a"}
+
+    c = db.insert("jobs", {"name": "c"})
+    with pytest.raises(KeyError, match="`score` is missing"):
+        db.increment("jobs", c, "score", default_zero=False)
+    with pytest.raises(ValueError, match="expected `integer or float`"):
+        db.increment("jobs", a, "name")
+    with pytest.raises(ValueError, match="expected `integer`"):
+        db.increment("jobs", a, "retries", delta=0.5)
+    with pytest.raises(ValueError, match="violates constraint"):
+        db.increment("jobs", b, "retries", delta=2)
+
+    assert db.increment_where(Query("jobs").where_between("retries", 0, 1), "retries") == 1
+    with pytest.raises(ValueError, match="violates constraint"):
+        db.increment_where(Query("jobs"), "retries", delta=2)
+    assert sorted(r.data.get("retries") for r in db.fetch_all("jobs") if r.id != c) == [1, 2]
+
+
+def test_array_push_pull_and_add_unique(tmp_path):
+    path = str(tmp_path / "arrays.rsndb")
+    db = Database(path)
+    db.create_table(
+        "posts",
+        {
+            "tags": {"type": "array", "of": "string"},
+            "scores": {"type": "array", "of": "integer"},
+            "extra": {"type": "json"},
+            "title": {"type": "string"},
+        },
+    )
+    rid = db.insert("posts", {"title": "hi", "extra": {"k": 1}})
+    assert db.array_push("posts", rid, "tags", "rust") == 1
+    assert db.array_push("posts", rid, "tags", "rust") == 2
+    assert db.array_add_unique("posts", rid, "tags", "db") == 3
+    assert db.array_add_unique("posts", rid, "tags", "db") == 3
+    assert db.array_pull("posts", rid, "tags", "rust") == 1
+    assert db.array_push("posts", rid, "scores", "7") == 1
+    assert Database(path).get("posts", rid).data["tags"] == ["db"]
+    assert db.get("posts", rid).data["scores"] == [7]
+
+    with pytest.raises(ValueError, match="field `scores`: expected `integer`"):
+        db.array_push("posts", rid, "scores", "seven")
+    with pytest.raises(ValueError, match="field `title`: expected `array`"):
+        db.array_push("posts", rid, "title", "x")
+    with pytest.raises(ValueError, match="field `extra`: expected `array`"):
+        db.array_push("posts", rid, "extra", "x")
+    db.update("posts", rid, {"extra": [1]})
+    assert db.array_add_unique("posts", rid, "extra", {"a": 1}) == 2
+
+
+def test_find_or_create(tmp_path):
+    path = str(tmp_path / "foc.rsndb")
+    db = Database(path)
+    db.create_table(
+        "users",
+        {
+            "email": {"type": "string", "unique": True},
+            "age": {"type": "integer"},
+            "team": {"type": "string"},
+        },
+    )
+    record, created = db.find_or_create("users", {"email": "a@x"}, defaults={"age": 30, "email": "ignored"})
+    assert created and record.data == {"email": "a@x", "age": 30}
+    again, created = db.find_or_create("users", {"email": "a@x"}, {"age": 99})
+    assert not created and (again.id, again.data["age"]) == (record.id, 30)
+    assert db.find_or_create("users", {"age": "30"})[0].id == record.id
+    assert len(Database(path).fetch_all("users")) == 1
+
+    db.insert("users", {"email": "b@x", "team": "red"})
+    db.insert("users", {"email": "c@x", "team": "red"})
+    with pytest.raises(ValueError, match="ambiguous: 2 records"):
+        db.find_or_create("users", {"team": "red"})
+    with pytest.raises(ValueError, match="not part of the schema"):
+        db.find_or_create("users", {"bogus": 1})
+
+
+def test_rows_come_back_in_id_order(tmp_path):
+    path = str(tmp_path / "ordered.rsndb")
+    db = Database(path)
+    db.create_table("events", {"bucket": {"type": "integer", "index": True}, "kind": {"type": "string"}})
+    db.insert_many("events", [{"bucket": i % 3, "kind": "ab"[i % 2]} for i in range(60)])
+    db.delete("events", 5)
+    expected = [i for i in range(1, 61) if i != 5]
+    for _ in range(3):
+        assert [r.id for r in db.fetch_all("events")] == expected
+        assert [r.id for r in Database(path).fetch_all("events")] == expected
+    assert [r.id for r in db.query(Query("events").where_eq("bucket", 1))] == [i for i in expected if i % 3 == 2]
+    by_kind = [r.id for r in db.query(Query("events").order_by("kind"))]
+    assert by_kind == [i for i in expected if i % 2 == 1] + [i for i in expected if i % 2 == 0]
+
+
+def test_sequence_inspect_and_reset(tmp_path):
+    path = str(tmp_path / "seq.rsndb")
+    db = Database(path)
+    db.create_table("items", {"name": {"type": "string"}})
+    assert db.sequence("items") == 1
+    db.insert_many("items", [{"name": str(i)} for i in range(10)])
+    for rid in range(4, 11):
+        db.delete("items", rid)
+    assert db.sequence("items") == 11
+    assert db.reset_sequence("items") == 4
+    assert Database(path).sequence("items") == 4
+    assert db.insert("items", {"name": "reused"}) == 4
+
+    with pytest.raises(ValueError, match="collide with existing ids"):
+        db.reset_sequence("items", 3)
+    assert db.reset_sequence("items", 100) == 100
+    assert db.insert("items", {"name": "far"}) == 100
+    with pytest.raises(KeyError):
+        db.sequence("ghosts")
+    db.copy_table("items", "items_copy", preserve_ids=True)
+    assert db.sequence("items_copy") == 101
+
+
+def test_find_duplicates_and_dedupe(tmp_path):
+    path = str(tmp_path / "dupes.rsndb")
+    db = Database(path)
+    db.create_table(
+        "contacts",
+        {"name": {"type": "string"}, "city": {"type": "string"}, "phone": {"type": "string"}},
+    )
+    rows = [
+        {"name": "Ann", "city": "Oslo", "phone": "1"},
+        {"name": "Bob", "city": "Rome"},
+        {"name": "Ann", "city": "Oslo", "phone": "2"},
+        {"name": "Bob", "city": None},
+        {"name": "Ann", "city": "Oslo", "phone": "3"},
+        {"name": "Cy", "city": "Oslo"},
+        {"name": "Bob", "city": "Rome"},
+    ]
+    db.insert_many("contacts", rows)
+    assert db.find_duplicates("contacts", ["name", "city"]) == [[1, 3, 5], [2, 7]]
+    assert db.find_duplicates("contacts", ["city", "phone"]) == [[2, 7]]
+    assert db.find_duplicates("contacts", ["city", "phone"], nulls_equal=False) == []
+    with pytest.raises(ValueError, match="not part of the schema"):
+        db.find_duplicates("contacts", ["bogus"])
+    with pytest.raises(ValueError, match="keep must be"):
+        db.dedupe("contacts", ["name"], keep="middle")
+
+    assert db.dedupe("contacts", ["name", "city"], keep="last") == 3
+    assert [r.id for r in Database(path).fetch_all("contacts")] == [4, 5, 6, 7]
+    assert db.dedupe("contacts", ["name", "city"]) == 0
+    assert db.dedupe("contacts", ["city"], nulls_equal=False) == 1
+    assert [r.id for r in db.fetch_all("contacts")] == [4, 5, 7]
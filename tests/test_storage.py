@@ -0,0 +1,731 @@
+"""Transactions, the journal, on-disk files, encryption, locking and background writes."""
+
+import json
+import time
+import warnings
+
+import pytest
+
+from rsn_db import Database, EncryptionKeyError
+
+
+def test_transaction_commits_once_or_rolls_back(tmp_path):
+    path = str(tmp_path / "tx.rsndb")
+    db = Database(path)
+    db.create_table("users", {"email": {"type": "string", "unique": True}})
+    keep = db.insert("users", {"email": "keep@x"})
+
+    with db.transaction() as tx:
+        assert tx is db
+        db.insert("users", {"email": "a@x"})
+        db.update("users", keep, {"email": "kept@x"})
+        assert len(Database(path).fetch_all("users")) == 1
+    assert sorted(r.data["email"] for r in Database(path).fetch_all("users")) == ["a@x", "kept@x"]
+
+    with pytest.raises(ValueError, match="must be unique"):
+        with db.transaction():
+            db.insert("users", {"email": "b@x"})
+            db.delete("users", keep)
+            db.insert("users", {"email": "a@x"})
+    assert sorted(r.data["email"] for r in db.fetch_all("users")) == ["a@x", "kept@x"]
+    db.insert("users", {"email": "b@x"})
+
+    with pytest.raises(RuntimeError, match="already open"):
+        with db.transaction():
+            with db.transaction():
+                pass
+    assert len(Database(path).fetch_all("users")) == 3
+
+
+def test_autosave_off_defers_writes_until_flush(tmp_path):
+    path = str(tmp_path / "autosave.rsndb")
+    db = Database(path)
+    db.create_table("events", {"n": {"type": "integer"}})
+    assert not db.is_dirty()
+    db.set_autosave(False)
+    db.insert_many("events", [{"n": 1}, {"n": 2}])
+    db.insert("events", {"n": 3})
+    assert db.is_dirty()
+    assert Database(path).fetch_all("events") == []
+    db.flush()
+    assert not db.is_dirty()
+    assert len(Database(path).fetch_all("events")) == 3
+
+    db.insert("events", {"n": 4})
+    db.close(flush=True)
+    assert len(Database(path).fetch_all("events")) == 4
+    db = Database(path)
+    db.set_autosave(False)
+    db.insert("events", {"n": 5})
+    with warnings.catch_warnings(record=True) as caught:
+        warnings.simplefilter("always")
+        db.close(flush=False)
+    assert "closed with unsaved changes" in str(caught[0].message)
+    assert len(Database(path).fetch_all("events")) == 4
+
+    db = Database(path)
+    db.set_autosave(False)
+    db.insert("events", {"n": 6})
+    db.set_autosave(True)
+    assert len(Database(path).fetch_all("events")) == 5
+
+    # A rolled-back transaction leaves nothing to write.
+    with pytest.raises(KeyError):
+        with db.transaction():
+            db.insert("events", {"n": 7})
+            db.fetch_all("missing")
+    assert not db.is_dirty()
+    assert not Database().is_dirty()
+
+    db.set_autosave(False)
+    db.insert("events", {"n": 8})
+    with warnings.catch_warnings(record=True) as caught:
+        warnings.simplefilter("always")
+        del db
+    assert "dropped with unsaved changes" in str(caught[0].message)
+
+
+def test_journal_appends_and_replays(tmp_path):
+    path = tmp_path / "wal.rsndb"
+    journal = tmp_path / "wal.rsndb.journal"
+    db = Database(str(path), journal=True)
+    db.create_table("notes", {"text": {"type": "string"}})
+    snapshot = path.read_bytes()
+    header_len = journal.stat().st_size
+    first = db.insert("notes", {"text": "a"})
+    db.insert("notes", {"text": "b"})
+    db.update("notes", first, {"text": "A"})
+    assert path.read_bytes() == snapshot
+    assert journal.stat().st_size > header_len
+
+    with open(journal, "ab") as f:
+        f.write(b"\x40\x00\x00\x00torn")
+    reopened = Database(str(path))
+    assert [r.data["text"] for r in reopened.fetch_all("notes")] == ["A", "b"]
+    assert not journal.exists()
+
+    db = Database(str(path), journal=True)
+    db.delete("notes", first)
+    db.flush()
+    assert journal.stat().st_size == header_len
+    assert [r.data["text"] for r in Database(str(path)).fetch_all("notes")] == ["b"]
+
+
+def test_journal_replay_keeps_the_original_insert_time(tmp_path):
+    path = tmp_path / "wal-ttl.rsndb"
+    db = Database(str(path), journal=True)
+    db.create_table("cache", {"key": {"type": "string"}}, ttl_seconds=1)
+    db.insert("cache", {"key": "k"})
+    time.sleep(1.1)
+    assert Database(str(path)).fetch_all("cache") == []
+
+
+def test_directory_layout_writes_only_dirty_tables(tmp_path):
+    path = tmp_path / "split.rsndb"
+    db = Database(str(path), layout="directory")
+    db.create_table("small", {"n": {"type": "integer"}})
+    db.create_table("other", {"n": {"type": "integer"}})
+    db.insert("other", {"n": 1})
+    db.ingest("graph chunks live in their own file", source="doc")
+    other = path / ("table-" + "other".encode().hex() + ".rsn")
+    graph = path / "graph.rsn"
+    before = (other.stat().st_ino, graph.stat().st_ino)
+    db.insert("small", {"n": 2})
+    assert (other.stat().st_ino, graph.stat().st_ino) == before
+
+    reopened = Database(str(path))
+    assert reopened.load_errors() == {}
+    assert [r.data["n"] for r in reopened.fetch_all("small")] == [2]
+
+    other.write_bytes(b"garbage")
+    with warnings.catch_warnings(record=True) as caught:
+        warnings.simplefilter("always")
+        damaged = Database(str(path))
+    assert "could not load other" in str(caught[0].message)
+    assert list(damaged.load_errors()) == ["other"]
+    damaged.insert("small", {"n": 3})
+    assert other.read_bytes() == b"garbage"
+    assert [r.data["n"] for r in Database(str(path)).fetch_all("small")] == [2, 3]
+
+
+def test_migrate_single_file_to_directory(tmp_path):
+    single = tmp_path / "one.rsndb"
+    db = Database(str(single), compression="lz4")
+    db.create_table("items", {"name": {"type": "string"}})
+    db.insert("items", {"name": "kept"})
+    with pytest.raises(ValueError, match="migrate_to_directory"):
+        Database(str(single), layout="directory")
+    with pytest.raises(ValueError, match="unknown layout"):
+        Database(str(tmp_path / "x"), layout="sharded")
+
+    db.migrate_to_directory(str(tmp_path / "many"))
+    db.insert("items", {"name": "after"})
+    assert single.is_file()
+    moved = Database(str(tmp_path / "many"), compression="lz4")
+    assert (tmp_path / "many.rsndb" / "manifest.rsn").is_file()
+    assert [r.data["name"] for r in moved.fetch_all("items")] == ["kept", "after"]
+    assert len(Database(str(single), compression="lz4").fetch_all("items")) == 1
+    with pytest.raises(ValueError, match="already exists"):
+        moved.migrate_to_directory(str(single))
+
+
+def _strip_header(path):
+    import hashlib
+
+    raw = path.read_bytes()
+    body = raw[32 + 16 :]
+    path.write_bytes(hashlib.sha256(body).digest() + body)
+
+
+def test_header_overrides_constructor_compression(tmp_path):
+    algos = ["zstd", "lz4", "none"]
+    for written in algos:
+        path = tmp_path / f"{written}.rsndb"
+        db = Database(str(path), compression=written)
+        db.create_table("t", {"n": {"type": "integer"}})
+        db.insert("t", {"n": 1})
+        assert path.read_bytes()[32:37] == b"RSNDB"
+        for opened in algos:
+            other = Database(str(path), compression=opened)
+            assert [r.data["n"] for r in other.fetch_all("t")] == [1]
+
+    secret = tmp_path / "secret.rsndb"
+    db = Database(str(secret), encryption_key="k", compression="lz4")
+    db.create_table("t", {"n": {"type": "integer"}})
+    assert len(Database(str(secret), encryption_key="k").fetch_all("t")) == 0
+    with pytest.raises(ValueError, match="provide encryption_key"):
+        Database(str(secret))
+
+
+def test_headerless_files_fall_back_to_constructor_settings(tmp_path):
+    for algo in ["zstd", "lz4", "none"]:
+        path = tmp_path / f"legacy-{algo}.rsndb"
+        db = Database(str(path), compression=algo)
+        db.create_table("t", {"n": {"type": "integer"}})
+        db.insert("t", {"n": 7})
+        _strip_header(path)
+        assert [r.data["n"] for r in Database(str(path), compression=algo).fetch_all("t")] == [7]
+    with pytest.raises((OSError, ValueError)):
+        Database(str(tmp_path / "legacy-lz4.rsndb"), compression="zstd")
+
+
+def test_compression_level_and_storage_stats(tmp_path):
+    path = tmp_path / "levels.rsndb"
+    db = Database(str(path), compression_level=1)
+    assert db.storage_stats() is None
+    db.create_table("docs", {"body": {"type": "string"}})
+    db.insert_many("docs", [{"body": "lorem ipsum dolor sit amet " * 40} for _ in range(50)])
+    fast = db.storage_stats()
+    assert fast["compression_level"] == 1
+    assert fast["stored_bytes"] == path.stat().st_size
+    assert fast["ratio"] > 1
+
+    db.set_compression_level(19)
+    db.save()
+    small = db.storage_stats()
+    assert small["raw_bytes"] == fast["raw_bytes"]
+    assert small["stored_bytes"] <= fast["stored_bytes"]
+    assert len(Database(str(path)).fetch_all("docs")) == 50
+
+    with pytest.raises(ValueError, match="compression_level must be between"):
+        db.set_compression_level(99)
+    with pytest.raises(ValueError, match="compression_level must be between"):
+        Database(compression_level=23)
+
+
+def test_recompress_round_trips_between_algorithms(tmp_path):
+    path = tmp_path / "recompress.rsndb"
+    db = Database(str(path), compression="none")
+    db.create_table("logs", {"line": {"type": "string"}, "n": {"type": "integer"}})
+    db.insert_many("logs", [{"line": f"entry {i} " * 10, "n": i} for i in range(30)])
+    expected = [(r.id, r.data) for r in db.fetch_all("logs")]
+    sizes = [path.stat().st_size]
+    for algo, tag in [("lz4", 2), ("zstd", 1)]:
+        db.recompress(algo, level=9 if algo == "zstd" else None)
+        assert path.read_bytes()[32 + 6] == tag
+        sizes.append(path.stat().st_size)
+        reopened = Database(str(path))
+        assert [(r.id, r.data) for r in reopened.fetch_all("logs")] == expected
+    assert sizes[2] < sizes[0]
+    assert db.storage_stats()["compression"] == "zstd"
+    with pytest.raises(ValueError, match='"zstd", "lz4", "none"'):
+        db.recompress("brotli")
+
+
+def test_rekey_rotates_adds_and_removes_encryption(tmp_path):
+    path = tmp_path / "vault.rsndb"
+    db = Database(str(path), encryption_key="old")
+    db.create_table("secrets", {"v": {"type": "string"}})
+    db.insert("secrets", {"v": "hunter2"})
+
+    db.rekey("new")
+    assert Database(str(path), encryption_key="new").fetch_all("secrets")[0].data == {"v": "hunter2"}
+    with pytest.raises(ValueError, match="incorrect encryption key"):
+        Database(str(path), encryption_key="old")
+
+    db.rekey(None)
+    assert path.read_bytes()[32 + 7] == 0
+    assert len(Database(str(path)).fetch_all("secrets")) == 1
+
+    plain = Database(str(path))
+    plain.rekey("fresh")
+    with pytest.raises(ValueError, match="provide encryption_key"):
+        Database(str(path))
+    assert len(Database(str(path), encryption_key="fresh").fetch_all("secrets")) == 1
+
+
+def test_passphrase_kdf_and_unsalted_key_upgrade(tmp_path):
+    import hashlib
+
+    pytest.importorskip("cryptography")
+    from cryptography.hazmat.primitives.ciphers.aead import AESGCM
+
+    path = tmp_path / "legacy.rsndb"
+    db = Database(str(path), compression="none")
+    db.create_table("t", {"n": {"type": "integer"}})
+    db.insert("t", {"n": 1})
+    plain = path.read_bytes()[32 + 16 :]
+    nonce = b"\x00" * 12
+    key = hashlib.sha256(b"pw").digest()
+    body = nonce + AESGCM(key).encrypt(nonce, plain, None)
+    path.write_bytes(hashlib.sha256(body).digest() + body)
+
+    legacy = Database(str(path), encryption_key="pw", compression="none")
+    assert [r.data["n"] for r in legacy.fetch_all("t")] == [1]
+    legacy.insert("t", {"n": 2})
+    header = path.read_bytes()[32:]
+    assert (header[5], header[7]) == (4, 1)
+    assert int.from_bytes(header[8:12], "little") >= 100_000
+    assert len(Database(str(path), encryption_key="pw").fetch_all("t")) == 2
+    with pytest.raises(ValueError, match="incorrect encryption key"):
+        Database(str(path), encryption_key="not-pw")
+
+
+def test_encryption_key_combinations(tmp_path):
+    plain, secret = tmp_path / "plain.rsndb", tmp_path / "secret.rsndb"
+    Database(str(plain)).create_table("t", {"n": {"type": "integer"}})
+    Database(str(secret), encryption_key="right").create_table("t", {"n": {"type": "integer"}})
+
+    assert Database(str(plain)).fetch_all("t") == []
+    assert Database(str(secret), encryption_key="right").fetch_all("t") == []
+    with pytest.raises(EncryptionKeyError, match="database is encrypted, provide encryption_key"):
+        Database(str(secret))
+    with pytest.raises(EncryptionKeyError, match="incorrect encryption key"):
+        Database(str(secret), encryption_key="wrong")
+    with warnings.catch_warnings(record=True) as caught:
+        warnings.simplefilter("always")
+        keyed = Database(str(plain), encryption_key="new")
+    assert "not encrypted" in str(caught[0].message)
+    keyed.save()
+    with pytest.raises(EncryptionKeyError):
+        Database(str(plain))
+
+    legacy = tmp_path / "legacy.rsndb"
+    Database(str(legacy), encryption_key="right").create_table("t", {"n": {"type": "integer"}})
+    _strip_header(legacy)
+    with pytest.raises(EncryptionKeyError, match="appears to be encrypted"):
+        Database(str(legacy))
+
+
+_OPEN_SCRIPT = """
+import sys
+from rsn_db import Database, DatabaseLockedError
+try:
+    Database(sys.argv[1], **eval(sys.argv[2]))
+except DatabaseLockedError as e:
+    print("locked", e)
+else:
+    print("opened")
+"""
+
+
+def _open_elsewhere(path, **kwargs):
+    import subprocess
+    import sys
+
+    cmd = [sys.executable, "-c", _OPEN_SCRIPT, path, repr(kwargs)]
+    return subprocess.run(cmd, capture_output=True, text=True, timeout=60).stdout
+
+
+def test_second_writer_process_is_locked_out(tmp_path):
+    path = str(tmp_path / "locked.rsndb")
+    db = Database(path)
+    db.create_table("t", {"n": {"type": "integer"}})
+    out = _open_elsewhere(path)
+    assert out.startswith("locked") and "read_only=True" in out
+    assert _open_elsewhere(path, lock_timeout=0.2).startswith("locked")
+    assert _open_elsewhere(path, read_only=True).startswith("locked")
+    assert Database(path).fetch_all("t") == []
+    db.close()
+    assert _open_elsewhere(path) == "opened\n"
+
+    def fail_while_open():
+        handle = Database(path)
+        handle.insert("t", {"n": 1})
+        raise RuntimeError("boom")
+
+    with pytest.raises(RuntimeError, match="boom"):
+        fail_while_open()
+    assert _open_elsewhere(path) == "opened\n"
+
+    reader = Database(path, read_only=True)
+    assert _open_elsewhere(path, read_only=True) == "opened\n"
+    assert _open_elsewhere(path).startswith("locked")
+    [row] = reader.fetch_all("t")
+    for write in (
+        lambda: reader.insert("t", {"n": 2}),
+        lambda: reader.update("t", row.id, {"n": 2}),
+        lambda: reader.create_table("more", {"n": {"type": "integer"}}),
+        lambda: reader.execute_sql("UPDATE t SET n = 2 ALL"),
+        reader.save,
+    ):
+        with pytest.raises(RuntimeError, match="read_only"):
+            write()
+    assert [r.data for r in reader.fetch_all("t")] == [{"n": 1}]
+    assert reader.execute_sql("COUNT t") == 1
+    assert not reader.is_dirty()
+    assert [r.data for r in Database(path).fetch_all("t")] == [{"n": 1}]
+
+
+def test_unchanged_state_skips_the_write(tmp_path):
+    path = tmp_path / "idle.rsndb"
+    db = Database(str(path))
+    db.create_table("kv", {"k": {"type": "string"}, "v": {"type": "integer"}})
+    rid = db.insert("kv", {"k": "a", "v": 1})
+    written = db.storage_stats()["persists_written"]
+    inode = path.stat().st_ino
+
+    db.update("kv", rid, {"v": 1})
+    db.save()
+    stats = db.storage_stats()
+    assert (stats["persists_written"], stats["persists_skipped"]) == (written, 2)
+    assert path.stat().st_ino == inode
+
+    db.update("kv", rid, {"v": 2})
+    assert db.storage_stats()["persists_written"] == written + 1
+    assert Database(str(path)).fetch_all("kv")[0].data["v"] == 2
+
+
+_CRASH_SCRIPT = """
+import os, sys
+from rsn_db import Database
+db = Database(sys.argv[1], background=True)
+db.create_table("t", {"n": {"type": "integer"}})
+db.flush()
+for i in range(200):
+    db.insert("t", {"n": i})
+os._exit(0)
+"""
+
+
+def test_background_persistence_drains_and_survives_crashes(tmp_path):
+    import subprocess
+    import sys
+
+    path = tmp_path / "bg.rsndb"
+    db = Database(str(path), background=True)
+    db.create_table("t", {"n": {"type": "integer"}})
+    for i in range(100):
+        db.insert("t", {"n": i})
+    db.flush()
+    assert len(Database(str(path)).fetch_all("t")) == 100
+    stats = db.storage_stats()
+    assert stats["persists_written"] + stats["persists_coalesced"] >= 2
+    assert stats["stored_bytes"] == path.stat().st_size
+    db.insert("t", {"n": 100})
+    db.close()
+    assert len(Database(str(path)).fetch_all("t")) == 101
+
+    db = Database(str(path), background=True)
+    db.insert("t", {"n": 101})
+    del db
+    assert len(Database(str(path)).fetch_all("t")) == 102
+    with pytest.raises(ValueError, match="single-file layout"):
+        Database(str(tmp_path / "bgj"), background=True, journal=True)
+
+    crashed = str(tmp_path / "crash.rsndb")
+    subprocess.run([sys.executable, "-c", _CRASH_SCRIPT, crashed], check=True, timeout=60)
+    survivors = [r.data["n"] for r in Database(crashed).fetch_all("t")]
+    assert survivors == list(range(len(survivors)))
+
+
+def test_failed_background_writes_leave_the_database_dirty(tmp_path):
+    path = tmp_path / "bg.rsndb"
+    db = Database(str(path), background=True)
+    db.create_table("t", {"n": {"type": "integer"}})
+    db.flush()
+    assert not db.is_dirty()
+    # A directory where the file belongs makes the writer's rename fail.
+    path.unlink()
+    path.mkdir()
+    db.insert("t", {"n": 1})
+    with pytest.raises(OSError, match="background write failed"):
+        db.flush()
+    assert db.is_dirty()
+    path.rmdir()
+    db.flush()
+    assert not db.is_dirty()
+    assert len(Database(str(path)).fetch_all("t")) == 1
+
+
+def test_storage_stats_break_down_tables_and_graph(tmp_path):
+    path = tmp_path / "usage.rsndb"
+    db = Database(str(path), encryption_key="hunter2")
+    db.create_table("small", {"n": {"type": "integer"}})
+    db.create_table("big", {"body": {"type": "string"}})
+    db.insert("small", {"n": 1})
+    db.insert_many("big", [{"body": "lorem ipsum " * 50} for _ in range(20)])
+    db.ingest("Alice met Bob in Paris.", "notes")
+    stats = db.storage_stats()
+    assert stats["encrypted"] is True
+    graph_file = tmp_path / "usage.rsndb.graph"
+    assert stats["file_bytes"] == path.stat().st_size + graph_file.stat().st_size
+    assert stats["tables"]["big"]["records"] == 20
+    assert stats["tables"]["big"]["bytes"] > stats["tables"]["small"]["bytes"] > 0
+    assert stats["graph"]["chunks"] > 0 and stats["graph"]["entities"] > 0
+
+    reopened = Database(str(path), encryption_key="hunter2").storage_stats()
+    assert reopened["file_bytes"] == stats["file_bytes"]
+    assert reopened["tables"] == stats["tables"]
+    assert reopened["raw_bytes"] > 0
+
+    layout = tmp_path / "usage-dir.rsndb"
+    Database(str(layout), layout="directory").create_table("t", {"n": {"type": "integer"}})
+    stats = Database(str(layout), layout="directory").storage_stats()
+    assert stats["file_bytes"] == sum(p.stat().st_size for p in layout.iterdir() if p.is_file())
+
+
+def test_save_as_attaches_and_moves_databases(tmp_path):
+    db = Database()
+    db.create_table("t", {"n": {"type": "integer"}})
+    db.insert("t", {"n": 1})
+    first = tmp_path / "first"
+    db.save_as(str(first))
+    assert (tmp_path / "first.rsndb").exists()
+    db.insert("t", {"n": 2})
+    assert len(Database(str(tmp_path / "first.rsndb")).fetch_all("t")) == 2
+
+    (tmp_path / "taken.rsndb").write_bytes(b"occupied")
+    with pytest.raises(ValueError, match="overwrite=True"):
+        db.save_as(str(tmp_path / "taken.rsndb"))
+    db.save_as(str(tmp_path / "taken.rsndb"), overwrite=True)
+    assert (tmp_path / "first.rsndb").exists()
+
+    db.save_as(str(tmp_path / "moved.rsndb"), move=True)
+    assert not (tmp_path / "taken.rsndb").exists()
+    assert not (tmp_path / "taken.rsndb.lock").exists()
+    db.insert("t", {"n": 3})
+    assert len(Database(str(tmp_path / "moved.rsndb")).fetch_all("t")) == 3
+    assert len(Database(str(tmp_path / "first.rsndb")).fetch_all("t")) == 2
+
+
+def test_verify_reports_damage_and_recovers_intact_tables(tmp_path):
+    path = tmp_path / "damaged.rsndb"
+    db = Database(str(path), compression="none")
+    db.create_table("kept", {"note": {"type": "string"}})
+    db.create_table("hit", {"note": {"type": "string"}})
+    db.insert("kept", {"note": "fine"})
+    db.insert("hit", {"note": "needle"})
+    healthy = db.verify()
+    assert healthy["ok"] and healthy["tables"]["hit"] == {
+        "ok": True, "records": 1, "checksum_ok": True, "error": None,
+    }
+    assert db.execute_sql("VERIFY")["ok"] is True
+    del db
+
+    data = path.read_bytes()
+    path.write_bytes(data.replace(b"needle", b"noodle"))
+    with pytest.raises(ValueError, match="checksum mismatch"):
+        Database(str(path))
+    report = Database().verify(str(path))
+    assert not report["ok"] and not report["checksum_ok"]
+    assert report["header_ok"] and report["decode_ok"] and report["parse_ok"]
+    assert report["tables"]["kept"]["ok"]
+    assert report["tables"]["hit"]["checksum_ok"] is False
+
+    salvage = Database()
+    report = salvage.verify(str(path), recover=True)
+    assert (report["recovered"], report["lost"]) == (["kept"], ["hit"])
+    assert [r.data["note"] for r in salvage.fetch_all("kept")] == ["fine"]
+    with pytest.raises(KeyError):
+        salvage.fetch_all("hit")
+
+    split = tmp_path / "split.rsndb"
+    db = Database(str(split), layout="directory")
+    db.create_table("kept", {"n": {"type": "integer"}})
+    db.create_table("hit", {"n": {"type": "integer"}})
+    del db
+    table_file = split / ("table-" + b"hit".hex() + ".rsn")
+    table_file.write_bytes(table_file.read_bytes()[:-3])
+    db = Database(str(split), layout="directory")
+    report = db.execute_sql("VERIFY RECOVER")
+    assert report["manifest_ok"] and not report["ok"]
+    assert report["tables"]["hit"]["checksum_ok"] is False
+    assert (report["recovered"], report["lost"]) == (["kept"], ["hit"])
+    assert db.verify()["ok"]
+
+
+def test_tampering_is_caught_by_the_integrity_check(tmp_path):
+    path = tmp_path / "tamper.rsndb"
+    db = Database(str(path), encryption_key="pw")
+    db.create_table("t", {"n": {"type": "integer"}})
+    db.insert("t", {"n": 1})
+    del db
+    sealed = path.read_bytes()
+    assert sealed[32 + 5] == 4
+
+    def flipped(offset, mask=0xFF):
+        data = bytearray(sealed)
+        data[offset] ^= mask
+        path.write_bytes(bytes(data))
+
+    compression_byte = 32 + 6
+    for offset, mask in [(0, 0xFF), (len(sealed) - 1, 0xFF), (len(sealed) - 40, 0x01), (compression_byte, 0x03)]:
+        flipped(offset, mask)
+        with pytest.raises(ValueError, match="integrity check failed"):
+            Database(str(path), encryption_key="pw")
+    path.write_bytes(sealed)
+    with pytest.raises(EncryptionKeyError, match="incorrect encryption key"):
+        Database(str(path), encryption_key="nope")
+    assert len(Database(str(path), encryption_key="pw").fetch_all("t")) == 1
+
+    plain = tmp_path / "plain.rsndb"
+    Database(str(plain)).create_table("t", {"n": {"type": "integer"}})
+    data = bytearray(plain.read_bytes())
+    data[-1] ^= 0xFF
+    plain.write_bytes(bytes(data))
+    with pytest.raises(ValueError, match="checksum mismatch"):
+        Database(str(plain))
+
+
+def test_graph_lives_in_its_own_file_and_loads_on_first_use(tmp_path):
+    import hashlib
+    import json
+
+    path = tmp_path / "lazy.rsndb"
+    graph_file = tmp_path / "lazy.rsndb.graph"
+    db = Database(str(path), compression="none")
+    db.create_table("t", {"n": {"type": "integer"}})
+    db.insert("t", {"n": 1})
+    assert not graph_file.exists()
+    db.ingest("Alice met Bob in Paris. Bob lives in Paris.", "notes")
+    answer = db.graph_query("Paris")
+    before = (graph_file.stat().st_ino, graph_file.stat().st_mtime_ns)
+    db.insert("t", {"n": 2})
+    assert (graph_file.stat().st_ino, graph_file.stat().st_mtime_ns) == before
+    assert b"graph_rag" not in path.read_bytes()
+
+    reopened = Database(str(path), compression="none")
+    assert reopened.graph_query("Paris") == answer
+    with pytest.raises(RuntimeError):
+        with reopened.transaction():
+            reopened.ingest("Carol visited Rome.", "more")
+            raise RuntimeError("roll back")
+    assert reopened.graph_query("Paris") == answer
+    assert "Rome" not in reopened.graph_query("Rome")
+
+    # Files from before the split keep the graph inline; it moves out on the next write.
+    engine = json.loads(path.read_bytes()[32 + 16 :])
+    engine["graph_rag"] = json.loads(graph_file.read_bytes()[32 + 16 :])
+    body = json.dumps(engine).encode()
+    path.write_bytes(hashlib.sha256(body).digest() + body)
+    graph_file.unlink()
+    legacy = Database(str(path), compression="none")
+    assert legacy.graph_query("Paris") == answer
+    legacy.insert("t", {"n": 3})
+    assert graph_file.exists() and b"graph_rag" not in path.read_bytes()
+    assert Database(str(path)).graph_query("Paris") == answer
+
+
+def test_truncated_files_trailing_bytes_and_leftover_temp_files(tmp_path):
+    path = tmp_path / "cut.rsndb"
+    db = Database(str(path), compression="none")
+    db.create_table("t", {"n": {"type": "integer"}})
+    db.insert("t", {"n": 1})
+    db.close()
+    sealed = path.read_bytes()
+
+    path.write_bytes(sealed + b"garbage after the payload")
+    assert len(Database(str(path)).fetch_all("t")) == 1
+
+    path.write_bytes(sealed[:-10])
+    expected = f"file appears truncated at byte {len(sealed) - 10} of expected {len(sealed)}"
+    with pytest.raises(ValueError, match=expected):
+        Database(str(path))
+    path.write_bytes(sealed[:32 + 6])
+    with pytest.raises(ValueError, match="truncated at byte 38 of expected 40"):
+        Database(str(path))
+    path.write_bytes(sealed[:20])
+    with pytest.raises(ValueError, match="truncated at byte 20 of expected 40"):
+        Database(str(path))
+    for version in (3, 9):
+        data = bytearray(sealed)
+        data[32 + 5] = version
+        path.write_bytes(bytes(data))
+        with pytest.raises(ValueError, match=f"unsupported file format version {version}"):
+            Database(str(path))
+
+    secret = tmp_path / "secret.rsndb"
+    Database(str(secret), encryption_key="pw").create_table("t", {"n": {"type": "integer"}})
+    data = bytearray(secret.read_bytes())
+    data[32 + 8 : 32 + 12] = bytes(4)
+    secret.write_bytes(bytes(data))
+    with pytest.raises(ValueError, match="invalid header: KDF iteration count is 0"):
+        Database(str(secret), encryption_key="pw")
+
+    # A complete temp file is a write that was about to be renamed into place.
+    tmp = tmp_path / ".cut.rsndb.tmp"
+    newer = Database(str(tmp_path / "newer.rsndb"), compression="none")
+    newer.create_table("t", {"n": {"type": "integer"}})
+    newer.insert_many("t", [{"n": 1}, {"n": 2}])
+    newer.close()
+    path.write_bytes(sealed)
+    tmp.write_bytes((tmp_path / "newer.rsndb").read_bytes())
+    assert len(Database(str(path)).fetch_all("t")) == 2
+    assert not tmp.exists()
+
+    tmp.write_bytes(sealed[:-10])
+    assert len(Database(str(path)).fetch_all("t")) == 2
+    assert not tmp.exists()
+
+    tmp.write_bytes(sealed)
+    assert len(Database(str(path), read_only=True).fetch_all("t")) == 2
+    assert tmp.exists()
+
+
+def test_close_invalidates_the_handle_and_context_manager_closes(tmp_path):
+    path = str(tmp_path / "lifecycle.rsndb")
+    with Database(path) as db:
+        db.create_table("t", {"n": {"type": "integer"}})
+        db.set_autosave(False)
+        db.insert("t", {"n": 1})
+    assert db.closed
+    assert len(Database(path).fetch_all("t")) == 1
+    for call in (lambda: db.insert("t", {"n": 2}), lambda: db.fetch_all("t"), db.is_dirty):
+        with pytest.raises(RuntimeError, match="database is closed"):
+            call()
+    with pytest.raises(RuntimeError, match="database is closed"):
+        with db.transaction():
+            pass
+    db.close()
+    assert _open_elsewhere(path) == "opened\n"
+
+    with pytest.raises(KeyError):
+        with Database(path) as db:
+            db.set_autosave(False)
+            db.insert("t", {"n": 2})
+            db.fetch_all("missing")
+    assert db.closed
+    assert len(Database(path).fetch_all("t")) == 1
+
+    db = Database(path, background=True)
+    with db.transaction():
+        db.insert("t", {"n": 3})
+        db.close()
+    assert len(Database(path).fetch_all("t")) == 1
+
+    db = Database(path)
+    db.set_autosave(False)
+    db.insert("t", {"n": 4})
+    db.close()
+    assert [r.data["n"] for r in Database(path).fetch_all("t")] == [1, 4]